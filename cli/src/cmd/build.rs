@@ -92,6 +92,14 @@ impl Cmd for BuildArgs {
 }
 
 impl BuildArgs {
+    /// The project's root, the root of the current Git repository or the current working directory
+    /// if not set
+    pub fn root(&self) -> PathBuf {
+        self.root.clone().unwrap_or_else(|| {
+            utils::find_git_root_path().unwrap_or_else(|_| std::env::current_dir().unwrap())
+        })
+    }
+
     /// Determines the source directory within the given root
     fn contracts_path(&self, root: impl AsRef<Path>) -> PathBuf {
         let root = root.as_ref();
@@ -143,10 +151,7 @@ impl BuildArgs {
     /// Defaults to DAppTools-style repo layout, but can be customized.
     pub fn project(&self) -> eyre::Result<Project> {
         // 1. Set the root dir
-        let root = self.root.clone().unwrap_or_else(|| {
-            utils::find_git_root_path().unwrap_or_else(|_| std::env::current_dir().unwrap())
-        });
-        let root = dunce::canonicalize(&root)?;
+        let root = dunce::canonicalize(&self.root())?;
 
         // 2. Set the contracts dir
         let contracts = self.contracts_path(&root);
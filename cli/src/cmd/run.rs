@@ -80,7 +80,8 @@ impl Cmd for RunArgs {
         let bytecode = bin.into_bytes().unwrap();
         let needs_setup = abi.functions().any(|func| func.name == "setUp");
 
-        let cfg = crate::utils::sputnik_cfg(&self.opts.compiler.evm_version);
+        let mut cfg = crate::utils::sputnik_cfg(&self.opts.compiler.evm_version);
+        cfg.call_stack_limit = evm_opts.max_call_depth;
         let vicinity = evm_opts.vicinity()?;
         let backend = evm_opts.backend(&vicinity)?;
 
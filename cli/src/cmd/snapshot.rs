@@ -49,28 +49,77 @@ pub struct SnapshotArgs {
         value_hint = ValueHint::FilePath
     )]
     check: Option<Option<PathBuf>>,
+    #[clap(
+        help = "Compare against this snapshot file like --check, or write it like --snap if it does not exist yet",
+        conflicts_with_all = &["diff", "check"],
+        long,
+        value_hint = ValueHint::FilePath
+    )]
+    benchmark_baseline: Option<PathBuf>,
+    #[clap(
+        help = "Fail before running any tests if the snapshot file to compare against with --check, --diff or --benchmark-baseline does not exist, instead of creating it.",
+        long
+    )]
+    require_gas_snapshot: bool,
+    #[clap(
+        help = "The percentage by which the gas of a test may change in 'check' mode before it counts as a diff.",
+        default_value = "0",
+        long
+    )]
+    tolerance: f64,
     #[clap(help = "How to format the output.", long)]
     format: Option<Format>,
     #[clap(help = "Output file for the snapshot.", default_value = ".gas-snapshot", long)]
     snap: PathBuf,
 }
 
+impl SnapshotArgs {
+    /// The arguments the tests are run with
+    pub fn test_args(&self) -> &test::TestArgs {
+        &self.test
+    }
+}
+
 impl Cmd for SnapshotArgs {
     type Output = ();
 
     fn run(self) -> eyre::Result<()> {
+        // the snapshot file that is compared against, if any
+        let compared = match (&self.benchmark_baseline, &self.check, &self.diff) {
+            (Some(path), _, _) => Some(path),
+            (_, Some(path), _) | (_, _, Some(path)) => Some(path.as_ref().unwrap_or(&self.snap)),
+            _ => None,
+        };
+        if let (true, Some(snap)) = (self.require_gas_snapshot, compared) {
+            if !snap.exists() {
+                eyre::bail!(
+                    "snapshot file \"{}\" does not exist, but --require-gas-snapshot is set",
+                    snap.display()
+                )
+            }
+        }
         let outcome = self.test.run()?;
         outcome.ensure_ok()?;
         let tests = self.config.apply(outcome);
 
-        if let Some(path) = self.diff {
+        if let Some(path) = self.benchmark_baseline {
+            if path.exists() {
+                let snaps = read_snapshot(&path)?;
+                if !check(tests, snaps, self.tolerance) {
+                    std::process::exit(1)
+                }
+            } else {
+                write_to_snapshot_file(&tests, &path, self.format)?;
+                println!("Wrote the benchmark baseline to {}", path.display());
+            }
+        } else if let Some(path) = self.diff {
             let snap = path.as_ref().unwrap_or(&self.snap);
             let snaps = read_snapshot(snap)?;
             diff(tests, snaps)?;
         } else if let Some(path) = self.check {
             let snap = path.as_ref().unwrap_or(&self.snap);
             let snaps = read_snapshot(snap)?;
-            if check(tests, snaps) {
+            if check(tests, snaps, self.tolerance) {
                 std::process::exit(0)
             } else {
                 std::process::exit(1)
@@ -240,8 +289,8 @@ impl SnapshotDiff {
 
 /// Compares the set of tests with an existing snapshot
 ///
-/// Returns true all tests match
-fn check(tests: Vec<Test>, snaps: Vec<SnapshotEntry>) -> bool {
+/// Returns true if the gas of all tests changed by at most `tolerance` percent
+fn check(tests: Vec<Test>, snaps: Vec<SnapshotEntry>, tolerance: f64) -> bool {
     let snaps = snaps.into_iter().map(|s| (s.signature, s.gas_used)).collect::<HashMap<_, _>>();
     let mut has_diff = false;
 
@@ -249,11 +298,23 @@ fn check(tests: Vec<Test>, snaps: Vec<SnapshotEntry>) -> bool {
         if let Some(target_gas) = snaps.get(&test.signature).cloned() {
             let source_gas = test.result.kind.gas_used();
             if source_gas.gas() != target_gas.gas() {
+                let diff = SnapshotDiff {
+                    signature: test.signature,
+                    source_gas_used: source_gas,
+                    target_gas_used: target_gas,
+                };
+                let gas_diff = diff.gas_diff() * 100.0;
                 println!(
-                    "Diff in \"{}\": consumed \"{}\" gas, expected \"{}\" gas ",
-                    test.signature, source_gas, target_gas
+                    "Diff in \"{}\": consumed \"{}\" gas, expected \"{}\" gas, change: {} ({})",
+                    diff.signature,
+                    diff.source_gas_used,
+                    diff.target_gas_used,
+                    fmt_change(diff.gas_change()),
+                    fmt_pct_change(gas_diff)
                 );
-                has_diff = true;
+                if gas_diff.abs() > tolerance {
+                    has_diff = true;
+                }
             }
         } else {
             println!(
@@ -0,0 +1,408 @@
+//! The files next to the solc cache which carry tests and their results over to the next run
+
+use super::{paint, Filter};
+use ansi_term::Colour;
+use ethers::{
+    solc::EvmVersion,
+    types::{Address, U256},
+};
+use evm_adapters::{
+    evm_opts::Env,
+    sputnik::{
+        cheatcodes::cheatcode_handler::{CHEATCODE_ADDRESS, CONSOLE_ADDRESS},
+        PrecompileStub,
+    },
+};
+use forge::TestFilter;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// The file in which the contracts each test touched are recorded, next to the solc cache
+pub(super) fn touch_cache_file(solc_cache: &Path) -> PathBuf {
+    solc_cache.with_file_name("touched-contracts.json")
+}
+
+/// Returns the file in which failing fuzz inputs are persisted, next to the solc cache.
+///
+/// The cache directory is created if it does not exist yet, a persistence file which can not be
+/// parsed is removed with a warning, so that it is replaced by the next failure
+pub(super) fn fuzz_failures_file(solc_cache: &Path, color: bool) -> eyre::Result<PathBuf> {
+    let file = solc_cache.with_file_name("fuzz-failures");
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if file.exists() {
+        let valid = std::fs::read_to_string(&file)
+            .map(|content| {
+                content
+                    .lines()
+                    .map(str::trim)
+                    .all(|line| line.is_empty() || line.starts_with('#') || line.starts_with("cc "))
+            })
+            .unwrap_or_default();
+        if !valid {
+            eprintln!(
+                "{} ignoring corrupt fuzz failure file {}",
+                paint(color, Colour::Yellow, "Warning:"),
+                file.display()
+            );
+            std::fs::remove_file(&file)?;
+        }
+    }
+    Ok(file)
+}
+
+/// The file in which the tests which failed in the last run are recorded, next to the solc cache
+pub(super) fn failures_cache_file(solc_cache: &Path) -> PathBuf {
+    solc_cache.with_file_name("failed-tests.json")
+}
+
+/// The settings besides the compiled code which the results cached by `--cache-tests` depend on,
+/// a cached result is only used while they are unchanged
+#[derive(Debug, Clone)]
+pub(super) struct TestCacheSettings {
+    pub(super) env: Env,
+    pub(super) fork_url: Option<String>,
+    pub(super) fork_block_number: Option<u64>,
+    pub(super) initial_balance: U256,
+    pub(super) sender: Address,
+    pub(super) ffi: bool,
+    pub(super) max_call_depth: usize,
+    pub(super) evm_version: EvmVersion,
+    pub(super) require_setup: bool,
+    pub(super) isolate: bool,
+    pub(super) constructor_args: Vec<(String, String)>,
+    pub(super) timeout: Option<u64>,
+    pub(super) test_function_prefix: String,
+    pub(super) test_contract_suffix: Option<String>,
+    /// The content of the `--seed-accounts` file
+    pub(super) seed_accounts: Option<String>,
+    pub(super) precompile_overrides: Vec<(Address, PrecompileStub)>,
+}
+
+impl TestCacheSettings {
+    /// The settings as the json string which is hashed together with the code of the contracts
+    pub(super) fn key(&self) -> String {
+        let env = &self.env;
+        let precompile_overrides = self
+            .precompile_overrides
+            .iter()
+            .map(|(address, stub)| match stub {
+                PrecompileStub::Return(data) => (address, "return", hex::encode(data)),
+                PrecompileStub::Revert(data) => (address, "revert", hex::encode(data)),
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "env": {
+                "gas_limit": env.gas_limit,
+                "chain_id": env.chain_id,
+                "gas_price": env.gas_price,
+                "block_base_fee_per_gas": env.block_base_fee_per_gas,
+                "tx_origin": env.tx_origin,
+                "block_coinbase": env.block_coinbase,
+                "block_timestamp": env.block_timestamp,
+                "block_number": env.block_number,
+                "block_difficulty": env.block_difficulty,
+                "block_gas_limit": env.block_gas_limit,
+            },
+            "fork_url": self.fork_url,
+            "fork_block_number": self.fork_block_number,
+            "initial_balance": self.initial_balance,
+            "sender": self.sender,
+            "ffi": self.ffi,
+            "max_call_depth": self.max_call_depth,
+            "evm_version": self.evm_version,
+            "require_setup": self.require_setup,
+            "isolate": self.isolate,
+            "constructor_args": self.constructor_args,
+            "timeout": self.timeout,
+            "test_function_prefix": self.test_function_prefix,
+            "test_contract_suffix": self.test_contract_suffix,
+            "seed_accounts": self.seed_accounts,
+            "precompile_overrides": precompile_overrides,
+        })
+        .to_string()
+    }
+}
+
+/// Returns the file in which `--cache-tests` keeps the passing results, next to the solc cache
+pub(super) fn test_cache_file(solc_cache: &Path) -> PathBuf {
+    solc_cache.with_file_name("cached-tests.json")
+}
+
+/// The keccak256 of the evm `settings`, the creation code of a test contract and the runtime code
+/// of the contracts its tests called, `None` if one of them is not compiled anymore
+fn test_inputs_hash(
+    runner: &forge::MultiContractRunner,
+    contract_name: &str,
+    dependencies: &BTreeSet<String>,
+    settings: &str,
+) -> Option<String> {
+    let code = runner.creation_code(contract_name).ok()?;
+    let mut inputs = settings.as_bytes().to_vec();
+    inputs.extend_from_slice(&code);
+    for name in dependencies {
+        let (_, runtime_code) = runner.known_contracts.get(name)?;
+        inputs.extend_from_slice(name.as_bytes());
+        inputs.extend_from_slice(runtime_code);
+    }
+    Some(format!("0x{}", hex::encode(ethers::utils::keccak256(inputs))))
+}
+
+/// Returns the cached results of the contracts matched by the filter whose inputs did not change
+/// since they were recorded in `file` and which have a cached result for every matched test
+pub(super) fn read_test_cache(
+    runner: &forge::MultiContractRunner,
+    filter: &Filter,
+    file: &Path,
+    settings: &str,
+) -> BTreeMap<String, BTreeMap<String, forge::TestResult>> {
+    let cache: BTreeMap<String, serde_json::Value> =
+        match std::fs::read(file).ok().and_then(|file| serde_json::from_slice(&file).ok()) {
+            Some(cache) => cache,
+            None => return BTreeMap::new(),
+        };
+    let mut cached = BTreeMap::new();
+    for (contract_name, entry) in cache {
+        if !filter.matches_contract(&contract_name) {
+            continue
+        }
+        let dependencies: BTreeSet<String> =
+            serde_json::from_value(entry["dependencies"].clone()).unwrap_or_default();
+        let hash = test_inputs_hash(runner, &contract_name, &dependencies, settings);
+        if hash.is_none() || entry["hash"].as_str() != hash.as_deref() {
+            continue
+        }
+        let mut results: BTreeMap<String, forge::TestResult> =
+            match serde_json::from_value(entry["results"].clone()) {
+                Ok(results) => results,
+                Err(_) => continue,
+            };
+        let abi = match runner.contracts.get(&contract_name) {
+            Some((abi, _)) => abi,
+            None => continue,
+        };
+        let tests = abi
+            .functions()
+            .filter(|func| {
+                func.name.starts_with(&runner.test_function_prefix) &&
+                    filter.matches_contract_test(&contract_name, &func.name)
+            })
+            .map(|func| func.signature())
+            .collect::<BTreeSet<_>>();
+        if !tests.iter().all(|test| results.contains_key(test)) {
+            continue
+        }
+        results.retain(|name, _| tests.contains(name));
+        for result in results.values_mut() {
+            result.cached = true;
+        }
+        cached.insert(contract_name, results);
+    }
+    cached
+}
+
+/// Records the results of the contracts whose tests all passed in `file`, together with the hash
+/// of their inputs, and drops the contracts which had a failing test.
+///
+/// Contracts with fuzz or invariant tests, which both have fuzz results, are never cached, their
+/// results are not deterministic. Neither are contracts whose tests called code which is no
+/// compiled contract and which they did not create, nothing tells when that code changes
+pub(super) fn record_test_cache(
+    runner: &forge::MultiContractRunner,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    file: &Path,
+    settings: &str,
+) -> eyre::Result<()> {
+    let mut cache: BTreeMap<String, serde_json::Value> = std::fs::read(file)
+        .ok()
+        .and_then(|file| serde_json::from_slice(&file).ok())
+        .unwrap_or_default();
+    for (contract_name, tests) in results {
+        cache.remove(contract_name);
+        if tests.values().any(|result| !result.success || result.is_fuzz()) ||
+            tests.values().any(calls_unknown_code)
+        {
+            continue
+        }
+        let dependencies = tests
+            .values()
+            .flat_map(|result| result.identified_contracts.iter().flat_map(|ids| ids.values()))
+            .map(|(name, _)| name.clone())
+            .filter(|name| runner.known_contracts.contains_key(name))
+            .collect::<BTreeSet<_>>();
+        let hash = match test_inputs_hash(runner, contract_name, &dependencies, settings) {
+            Some(hash) => hash,
+            None => continue,
+        };
+        // the traces are only collected to find the called contracts
+        let tests = tests
+            .iter()
+            .map(|(name, result)| {
+                let mut result = result.clone();
+                result.traces = None;
+                result.identified_contracts = None;
+                (name.clone(), result)
+            })
+            .collect::<BTreeMap<_, _>>();
+        cache.insert(
+            contract_name.clone(),
+            serde_json::json!({
+                "hash": hash,
+                "dependencies": dependencies,
+                "results": tests,
+            }),
+        );
+    }
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(file, serde_json::to_string(&cache)?)?;
+    Ok(())
+}
+
+/// Whether a test called an address which is none of the identified contracts, the cheatcodes or
+/// the precompiles, and which it did not create itself
+fn calls_unknown_code(result: &forge::TestResult) -> bool {
+    let identified = result.identified_contracts.as_ref();
+    let calls = result.traces.iter().flatten().flat_map(|arena| arena.arena.iter());
+    let created = calls
+        .clone()
+        .filter(|node| node.trace.created)
+        .map(|node| node.trace.addr)
+        .collect::<Vec<_>>();
+    calls.map(|node| node.trace.addr).any(|address| {
+        !identified.map_or(false, |ids| ids.contains_key(&address)) &&
+            !created.contains(&address) &&
+            address != *CHEATCODE_ADDRESS &&
+            address != *CONSOLE_ADDRESS &&
+            address >= Address::from_low_u64_be(0x100)
+    })
+}
+
+/// Records the names of the failing tests by contract in `file`, for the next `--rerun`, and only
+/// warns if it cannot be written
+pub(super) fn record_failures(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    file: &Path,
+    color: bool,
+) {
+    let failures = results
+        .iter()
+        .map(|(contract_name, tests)| {
+            let failed = tests
+                .iter()
+                .filter(|(_, result)| !result.success)
+                .map(|(name, _)| name.split('(').next().unwrap_or(name).to_string())
+                .collect::<BTreeSet<_>>();
+            (contract_name, failed)
+        })
+        .filter(|(_, failed)| !failed.is_empty())
+        .collect::<BTreeMap<_, _>>();
+    write_json_or_warn(file, &serde_json::json!(failures), color);
+}
+
+/// Writes `value` to `file`, creating its directory, and warns instead of failing the run if that
+/// is not possible
+pub(super) fn write_json_or_warn(file: &Path, value: &serde_json::Value, color: bool) {
+    let written = file
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(file, value.to_string()));
+    if let Err(err) = written {
+        eprintln!(
+            "{} failed to write {}: {}",
+            paint(color, Colour::Yellow, "Warning:"),
+            file.display(),
+            err
+        );
+    }
+}
+
+/// Returns the names of the contracts every test called into, identified from its traces
+pub(super) fn touched_contracts(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> BTreeMap<String, BTreeMap<String, BTreeSet<String>>> {
+    results
+        .iter()
+        .map(|(contract_name, tests)| {
+            let tests = tests
+                .iter()
+                .map(|(name, result)| {
+                    let touched = result
+                        .identified_contracts
+                        .iter()
+                        .flat_map(|contracts| contracts.values())
+                        .map(|(name, _)| name.clone())
+                        .collect();
+                    (name.clone(), touched)
+                })
+                .collect();
+            (contract_name.clone(), tests)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::test::tests::{counter_project, evm_opts};
+    use clap::Parser;
+    use forge::MultiContractRunnerBuilder;
+
+    #[test]
+    fn reuses_cached_results_until_their_inputs_change() {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .force_tracing(true)
+            .build(counter_project(), evm_opts())
+            .unwrap();
+        let filter = Filter::parse_from(["forge"]);
+        let results = runner.test(&filter).unwrap();
+        let settings = TestCacheSettings {
+            env: evm_opts().env,
+            fork_url: None,
+            fork_block_number: None,
+            initial_balance: U256::MAX,
+            sender: Address::zero(),
+            ffi: false,
+            max_call_depth: 1024,
+            evm_version: EvmVersion::London,
+            require_setup: false,
+            isolate: false,
+            constructor_args: vec![],
+            timeout: None,
+            test_function_prefix: "test".to_string(),
+            test_contract_suffix: None,
+            seed_accounts: None,
+            precompile_overrides: vec![],
+        };
+        let file = std::env::temp_dir().join("forge-cached-tests.json");
+        let _ = std::fs::remove_file(&file);
+        record_test_cache(&runner, &results, &file, &settings.key()).unwrap();
+
+        let cached = read_test_cache(&runner, &filter, &file, &settings.key());
+        assert_eq!(cached.keys().collect::<Vec<_>>(), results.keys().collect::<Vec<_>>());
+        assert!(cached.values().flat_map(|tests| tests.values()).all(|result| result.cached));
+
+        // any setting the results depend on invalidates them
+        for changed in [
+            TestCacheSettings { isolate: true, ..settings.clone() },
+            TestCacheSettings { sender: Address::repeat_byte(1), ..settings.clone() },
+            TestCacheSettings { ffi: true, ..settings.clone() },
+            TestCacheSettings { timeout: Some(100), ..settings.clone() },
+            TestCacheSettings {
+                constructor_args: vec![("CounterTest".to_string(), "1".to_string())],
+                ..settings.clone()
+            },
+        ] {
+            assert!(read_test_cache(&runner, &filter, &file, &changed.key()).is_empty());
+        }
+
+        // so does a change of the code of a contract the tests called
+        let (_, code) = runner.known_contracts.get_mut("Counter").unwrap();
+        code.push(0);
+        assert!(read_test_cache(&runner, &filter, &file, &settings.key()).is_empty());
+    }
+}
@@ -0,0 +1,471 @@
+//! Line coverage and the functions of the tested contracts no test called
+
+use ethers::{
+    abi::Abi,
+    prelude::artifacts::ContractBytecode,
+    solc::{artifacts::ContractBytecodeSome, sourcemap::SourceElement, CompilerOutput},
+};
+use eyre::WrapErr;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// Collects the functions of every compiled non-test contract and of every other contract that
+/// was called or created in the call traces of the tests which were never called themselves,
+/// `contract -> [function signature]`. Compiled contracts which no test called at all are listed
+/// with all of their functions.
+///
+/// Only external calls are traced, so functions which are only called internally are also listed
+pub(super) fn collect_coverage_gaps<'a>(
+    results: &'a BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    known_contracts: &'a BTreeMap<String, (Abi, Vec<u8>)>,
+) -> BTreeMap<String, Vec<String>> {
+    let test_contracts =
+        results.keys().map(|name| name.rsplit(':').next().unwrap_or(name)).collect::<BTreeSet<_>>();
+    let is_test_contract = |name: &str, abi: &Abi| {
+        test_contracts.contains(name) || abi.functions().any(|func| func.name == "IS_TEST")
+    };
+    let mut contracts: BTreeMap<&String, &Abi> = known_contracts
+        .iter()
+        .filter(|(name, (abi, _))| !is_test_contract(name, abi))
+        .map(|(name, (abi, _))| (name, abi))
+        .collect();
+    let mut called: BTreeSet<(&String, [u8; 4])> = BTreeSet::new();
+    for tests in results.values() {
+        for result in tests.values() {
+            let identified = match result.identified_contracts {
+                Some(ref identified) => identified,
+                None => continue,
+            };
+            for node in result.traces.iter().flatten().flat_map(|arena| arena.arena.iter()) {
+                let trace = &node.trace;
+                let (name, abi) = match identified.get(&trace.addr) {
+                    // the test contracts and the cheatcodes are not covered
+                    Some((name, abi)) if name == "VM" || is_test_contract(name, abi) => continue,
+                    Some((name, abi)) => (name, abi),
+                    None => continue,
+                };
+                contracts.insert(name, abi);
+                if let (false, Some(selector)) = (trace.created, trace.data.get(..4)) {
+                    let mut bytes = [0u8; 4];
+                    bytes.copy_from_slice(selector);
+                    called.insert((name, bytes));
+                }
+            }
+        }
+    }
+    contracts
+        .into_iter()
+        .map(|(name, abi)| {
+            let uncalled = abi
+                .functions()
+                .filter(|func| !called.contains(&(name, func.short_signature())))
+                .map(|func| func.signature())
+                .collect::<Vec<_>>();
+            (name.clone(), uncalled)
+        })
+        .filter(|(_, uncalled)| !uncalled.is_empty())
+        .collect()
+}
+
+/// Prints the functions of the compiled non-test contracts which no test called
+pub(super) fn print_coverage_gaps_summary(gaps: &BTreeMap<String, Vec<String>>) {
+    if gaps.is_empty() {
+        println!("Coverage gaps: every function of the compiled contracts was called");
+        return
+    }
+    println!("Coverage gaps:");
+    for (contract_name, functions) in gaps {
+        println!("  {}", contract_name);
+        for func in functions {
+            println!("    {}", func);
+        }
+    }
+}
+
+/// The assertions every unit test executed, counted as the distinct calls of `assert*` functions
+/// and `require` in the test contract whose instructions it executed
+pub(super) struct AssertionCounts {
+    /// The number of assertions by contract and test
+    counts: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl AssertionCounts {
+    pub(super) fn new(
+        results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+        sources: &CoverageSources,
+    ) -> Self {
+        let mut counts: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+        for (contract_name, tests) in results {
+            // artifact names look like `Greeter.json:GreeterTest`
+            let contract = contract_name.rsplit(':').next().unwrap_or(contract_name);
+            // cached results did not record the instructions they executed
+            for (name, result) in tests.iter().filter(|(_, result)| !result.cached) {
+                let calls = result
+                    .coverage
+                    .get(&(result.contract_address, false))
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|ic| sources.assertion_call(contract, *ic))
+                    .collect::<BTreeSet<_>>();
+                counts.entry(contract_name.clone()).or_default().insert(name.clone(), calls.len());
+            }
+        }
+        Self { counts }
+    }
+
+    /// Returns the number of assertions a test executed, `None` for fuzz tests, whose assertions
+    /// are checked once per case, and for cached tests
+    pub(super) fn get(
+        &self,
+        contract_name: &str,
+        name: &str,
+        result: &forge::TestResult,
+    ) -> Option<usize> {
+        if result.is_fuzz() {
+            return None
+        }
+        self.counts.get(contract_name)?.get(name).copied()
+    }
+}
+
+/// The file `--coverage` writes the executed source lines to, in the project root
+pub(super) const LCOV_FILE: &str = "lcov.info";
+
+/// The source maps of the compiled contracts, which `--coverage` maps the executed instructions to
+/// source lines with
+#[derive(Debug, Clone)]
+pub(super) struct CoverageSources {
+    /// The project root, which the paths of the source files are relative to
+    pub(super) root: PathBuf,
+    /// The source index and byte offset of every instruction of the creation and the runtime code
+    /// of every contract by name
+    source_maps: BTreeMap<String, (SourceOffsets, SourceOffsets)>,
+    /// The path and the content of every source file by its index in the source maps
+    sources: BTreeMap<u32, (String, String)>,
+}
+
+/// The source index, byte offset and length of every instruction, indexed by instruction counter
+type SourceOffsets = Vec<Option<(u32, usize, usize)>>;
+
+impl CoverageSources {
+    /// Reads the source maps from the output of a compilation without the cache, which does not
+    /// keep them, and the source files they refer to from the project `root`
+    pub(super) fn new(root: PathBuf, output: CompilerOutput) -> Self {
+        let (sources, contracts) = output.split();
+
+        let offsets = |source_map: Option<Vec<SourceElement>>| -> SourceOffsets {
+            source_map
+                .unwrap_or_default()
+                .iter()
+                .map(|element| element.index.map(|index| (index, element.offset, element.length)))
+                .collect()
+        };
+        let mut source_maps = BTreeMap::new();
+        for (name, contract) in contracts.0.into_values().flatten() {
+            let contract: ContractBytecode = contract.into();
+            let contract = match ContractBytecodeSome::try_from(contract) {
+                Ok(contract) => contract,
+                Err(_) => continue,
+            };
+            let creation = offsets(contract.bytecode.source_map().and_then(Result::ok));
+            let runtime = offsets(
+                contract
+                    .deployed_bytecode
+                    .bytecode
+                    .as_ref()
+                    .and_then(|code| code.source_map())
+                    .and_then(Result::ok),
+            );
+            // the first contract wins if several share a name, like the contracts' identification
+            source_maps.entry(name).or_insert((creation, runtime));
+        }
+
+        let sources = sources
+            .into_ids()
+            .filter_map(|(index, path)| {
+                let file = root.join(path);
+                let content = std::fs::read_to_string(&file).ok()?;
+                let path = file.strip_prefix(&root).unwrap_or(&file).display().to_string();
+                Some((index, (path, content)))
+            })
+            .collect();
+        CoverageSources { root, source_maps, sources }
+    }
+
+    /// The file and 1-based line of the instruction with the given counter in the creation or
+    /// runtime code of a contract
+    fn line(&self, contract_name: &str, creation: bool, ic: usize) -> Option<(&str, usize)> {
+        let (creation_offsets, runtime_offsets) = self.source_maps.get(contract_name)?;
+        let offsets = if creation { creation_offsets } else { runtime_offsets };
+        let (index, offset, _) = (*offsets.get(ic)?)?;
+        self.source_line(index, offset)
+    }
+
+    /// The source index and byte offset of the call of an `assert*` function or `require` which
+    /// the instruction with the given counter in the runtime code of a contract belongs to
+    fn assertion_call(&self, contract_name: &str, ic: usize) -> Option<(u32, usize)> {
+        let (_, runtime_offsets) = self.source_maps.get(contract_name)?;
+        let (index, offset, length) = (*runtime_offsets.get(ic)?)?;
+        let (_, content) = self.sources.get(&index)?;
+        let code = content.get(offset..offset + length)?;
+        let name = code
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+            .map_or(code, |end| &code[..end]);
+        // the instructions of the call and of its statement both start at the call
+        let is_call = code[name.len()..].trim_start().starts_with('(');
+        ((name.starts_with("assert") || name == "require") && is_call).then(|| (index, offset))
+    }
+
+    /// The file and 1-based line of a byte offset into the source file with the given index
+    fn source_line(&self, index: u32, offset: usize) -> Option<(&str, usize)> {
+        let (path, content) = self.sources.get(&index)?;
+        let line = content.as_bytes().get(..offset)?.iter().filter(|byte| **byte == b'\n').count();
+        Some((path.as_str(), line + 1))
+    }
+}
+
+/// Counts the tests which executed every line of the sources, `file -> (line -> tests)`.
+///
+/// The lines are those which any instruction of a compiled contract maps to, so the lines of
+/// inherited contracts and libraries are attributed to their own files
+pub(super) fn collect_line_coverage(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    sources: &CoverageSources,
+) -> BTreeMap<String, BTreeMap<usize, usize>> {
+    let mut lines: BTreeMap<String, BTreeMap<usize, usize>> = BTreeMap::new();
+    for (creation_offsets, runtime_offsets) in sources.source_maps.values() {
+        for (index, offset, _) in creation_offsets.iter().chain(runtime_offsets).flatten() {
+            if let Some((path, line)) = sources.source_line(*index, *offset) {
+                lines.entry(path.to_string()).or_default().entry(line).or_default();
+            }
+        }
+    }
+    for result in results.values().flat_map(|tests| tests.values()) {
+        let identified = match result.identified_contracts {
+            Some(ref identified) => identified,
+            None => continue,
+        };
+        let executed = result
+            .coverage
+            .iter()
+            .filter_map(|((address, creation), ics)| {
+                let (name, _) = identified.get(address)?;
+                Some(ics.iter().filter_map(move |ic| sources.line(name, *creation, *ic)))
+            })
+            .flatten()
+            .collect::<BTreeSet<_>>();
+        for (path, line) in executed {
+            *lines.entry(path.to_string()).or_default().entry(line).or_default() += 1;
+        }
+    }
+    lines
+}
+
+/// Writes the executed lines of every source file to `file` in the lcov tracefile format
+pub(super) fn write_lcov(
+    lines: &BTreeMap<String, BTreeMap<usize, usize>>,
+    file: &Path,
+) -> eyre::Result<()> {
+    let mut lcov = String::from("TN:\n");
+    for (path, hits) in lines {
+        lcov.push_str(&format!("SF:{}\n", path));
+        for (line, tests) in hits {
+            lcov.push_str(&format!("DA:{},{}\n", line, tests));
+        }
+        lcov.push_str(&format!("LF:{}\n", hits.len()));
+        lcov.push_str(&format!("LH:{}\n", hits.values().filter(|tests| **tests > 0).count()));
+        lcov.push_str("end_of_record\n");
+    }
+    std::fs::write(file, lcov).wrap_err_with(|| format!("failed to write {}", file.display()))
+}
+
+/// The lines, the lines hit and their share of every source file and of all of them, as in the
+/// summary printed after the results
+pub(super) fn line_coverage_json(
+    lines: &BTreeMap<String, BTreeMap<usize, usize>>,
+) -> serde_json::Value {
+    let stats = |hit: usize, total: usize| {
+        serde_json::json!({
+            "lines": total,
+            "hit": hit,
+            "percent": hit as f64 * 100.0 / total.max(1) as f64,
+        })
+    };
+    let (mut total_hit, mut total) = (0, 0);
+    let files = lines
+        .iter()
+        .map(|(path, hits)| {
+            let hit = hits.values().filter(|tests| **tests > 0).count();
+            total_hit += hit;
+            total += hits.len();
+            (path.clone(), stats(hit, hits.len()))
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let mut coverage = stats(total_hit, total);
+    coverage["files"] = files.into();
+    coverage
+}
+
+/// Prints the share of the lines of every source file which the tests executed
+pub(super) fn print_line_coverage_summary(
+    lines: &BTreeMap<String, BTreeMap<usize, usize>>,
+    file: &Path,
+) {
+    let percent = |hit: usize, total: usize| hit as f64 * 100.0 / total.max(1) as f64;
+    println!("Coverage:");
+    let (mut total_hit, mut total) = (0, 0);
+    for (path, hits) in lines {
+        let hit = hits.values().filter(|tests| **tests > 0).count();
+        println!("  {}: {}/{} lines ({:.1}%)", path, hit, hits.len(), percent(hit, hits.len()));
+        total_hit += hit;
+        total += hits.len();
+    }
+    println!(
+        "  total: {}/{} lines ({:.1}%), written to {}",
+        total_hit,
+        total,
+        percent(total_hit, total),
+        file.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::test::{
+        report::test_warnings,
+        tests::{counter_project, evm_opts, testdata_project},
+        without_cache, Filter,
+    };
+    use clap::Parser;
+    use forge::MultiContractRunnerBuilder;
+
+    #[test]
+    fn writes_lcov_tracefiles() {
+        let lines = BTreeMap::from([
+            ("src/A.sol".to_string(), BTreeMap::from([(3, 2), (4, 0)])),
+            ("src/B.sol".to_string(), BTreeMap::from([(7, 1)])),
+        ]);
+        let file = std::env::temp_dir().join("forge-lcov-test.info");
+        write_lcov(&lines, &file).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "TN:\nSF:src/A.sol\nDA:3,2\nDA:4,0\nLF:2\nLH:1\nend_of_record\nSF:src/B.sol\nDA:7,1\nLF:1\nLH:1\nend_of_record\n"
+        );
+    }
+
+    #[test]
+    fn summarizes_line_coverage_as_json() {
+        let lines = BTreeMap::from([
+            ("src/A.sol".to_string(), BTreeMap::from([(3, 2), (4, 0)])),
+            ("src/B.sol".to_string(), BTreeMap::from([(7, 1)])),
+            ("src/C.sol".to_string(), BTreeMap::new()),
+        ]);
+        assert_eq!(
+            line_coverage_json(&lines),
+            serde_json::json!({
+                "lines": 3,
+                "hit": 2,
+                "percent": 200.0 / 3.0,
+                "files": {
+                    "src/A.sol": { "lines": 2, "hit": 1, "percent": 50.0 },
+                    "src/B.sol": { "lines": 1, "hit": 1, "percent": 100.0 },
+                    "src/C.sol": { "lines": 0, "hit": 0, "percent": 0.0 },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn maps_instructions_to_source_lines() {
+        let sources = CoverageSources {
+            root: PathBuf::new(),
+            source_maps: BTreeMap::from([(
+                "A".to_string(),
+                (vec![Some((0, 0, 1)), None], vec![Some((0, 12, 1)), Some((1, 3, 1))]),
+            )]),
+            sources: BTreeMap::from([(
+                0,
+                ("A.sol".to_string(), "contract A {\n\n}\n".to_string()),
+            )]),
+        };
+        assert_eq!(sources.line("A", true, 0), Some(("A.sol", 1)));
+        assert_eq!(sources.line("A", true, 1), None);
+        assert_eq!(sources.line("A", false, 0), Some(("A.sol", 2)));
+        // the source file is unknown, the instruction counter and the contract are out of range
+        assert_eq!(sources.line("A", false, 1), None);
+        assert_eq!(sources.line("A", false, 2), None);
+        assert_eq!(sources.line("B", false, 0), None);
+    }
+
+    #[test]
+    fn collects_the_executed_lines() {
+        let project = counter_project();
+        let root = project.paths.root.clone();
+        let (mut runner, output) = MultiContractRunnerBuilder::default()
+            .force_tracing(true)
+            .coverage(true)
+            .build_with_output(without_cache(project), evm_opts())
+            .unwrap();
+        let results = runner.test(&Filter::parse_from(["forge"])).unwrap();
+        assert!(results.values().flat_map(|tests| tests.values()).all(|result| result.success));
+
+        let lines = collect_line_coverage(&results, &CoverageSources::new(root, output));
+        let hits = &lines["Coverage.sol"];
+        // `count += 1` runs in both tests, `count = 0` never
+        assert_eq!(hits.get(&8), Some(&2));
+        assert_eq!(hits.get(&12), Some(&0));
+        // comments and declarations without code are not lines
+        assert_eq!(hits.get(&1), None);
+
+        let file = std::env::temp_dir().join("forge-lcov-coverage.info");
+        write_lcov(&lines, &file).unwrap();
+        let lcov = std::fs::read_to_string(&file).unwrap();
+        assert!(lcov.starts_with("TN:\nSF:Coverage.sol\n"));
+        assert!(lcov.contains("DA:8,2\n"));
+        assert!(lcov.contains("DA:12,0\n"));
+    }
+
+    #[test]
+    fn lists_the_uncalled_functions_of_compiled_contracts() {
+        let (mut runner, _) = MultiContractRunnerBuilder::default()
+            .force_tracing(true)
+            .build_with_output(without_cache(counter_project()), evm_opts())
+            .unwrap();
+        let results = runner.test(&Filter::parse_from(["forge"])).unwrap();
+        let gaps = collect_coverage_gaps(&results, &runner.known_contracts);
+        // the test contracts are not listed
+        assert_eq!(gaps, BTreeMap::from([("Counter".to_string(), vec!["reset()".to_string()])]));
+
+        // `Untested` is compiled, but no test calls or creates it
+        let (mut runner, _) = MultiContractRunnerBuilder::default()
+            .force_tracing(true)
+            .build_with_output(without_cache(testdata_project("assembly")), evm_opts())
+            .unwrap();
+        let results = runner.test(&Filter::parse_from(["forge"])).unwrap();
+        let gaps = collect_coverage_gaps(&results, &runner.known_contracts);
+        assert_eq!(gaps, BTreeMap::from([("Untested".to_string(), vec!["load()".to_string()])]));
+    }
+
+    #[test]
+    fn counts_the_executed_assertions() {
+        let project = counter_project();
+        let root = project.paths.root.clone();
+        let (mut runner, output) = MultiContractRunnerBuilder::default()
+            .coverage(true)
+            .build_with_output(without_cache(project), evm_opts())
+            .unwrap();
+        let results = runner.test(&Filter::parse_from(["forge"])).unwrap();
+        let assertions = AssertionCounts::new(&results, &CoverageSources::new(root, output));
+        let (contract_name, tests) = results.iter().next().unwrap();
+        let count = |name: &str| assertions.get(contract_name, name, &tests[name]);
+        assert_eq!(count("testIncrement()"), Some(1));
+        assert_eq!(count("testIncrementWithoutAssertions()"), Some(0));
+        assert_eq!(
+            test_warnings(&tests["testIncrementWithoutAssertions()"], Some(0), &BTreeSet::new()),
+            vec!["made no assertions".to_string()]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! The outcomes of the last runs of every test, to tell flaky tests apart
+
+use super::{cache::write_json_or_warn, paint};
+use ansi_term::Colour;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The number of most recent outcomes of every test which are kept in its history
+const TEST_HISTORY_LEN: usize = 20;
+
+/// The file in which the recent outcomes of every test are recorded, next to the solc cache
+pub(super) fn test_history_file(solc_cache: &Path) -> PathBuf {
+    solc_cache.with_file_name("test-history.json")
+}
+
+/// Appends the outcome of every test to the history in `file` and returns the updated history.
+///
+/// Every test keeps its last [`TEST_HISTORY_LEN`] outcomes, its total number of failures and the
+/// unix time of its last failure. Entries which are not as recorded are started over, and the
+/// run only warns if the history cannot be written
+pub(super) fn record_test_history(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    file: &Path,
+    color: bool,
+) -> serde_json::Value {
+    let mut history = std::fs::read(file)
+        .ok()
+        .and_then(|file| serde_json::from_slice::<serde_json::Value>(&file).ok())
+        .filter(serde_json::Value::is_object)
+        .unwrap_or_else(|| serde_json::json!({}));
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    for (contract_name, tests) in results {
+        let contract = &mut history[contract_name];
+        if !contract.is_object() {
+            *contract = serde_json::json!({});
+        }
+        for (name, result) in tests {
+            let entry = &mut contract[name];
+            if !entry.is_object() {
+                *entry = serde_json::json!({});
+            }
+            let mut outcomes = entry["outcomes"].as_array().cloned().unwrap_or_default();
+            outcomes.push(result.success.into());
+            let expired = outcomes.len().saturating_sub(TEST_HISTORY_LEN);
+            outcomes.drain(..expired);
+            entry["outcomes"] = outcomes.into();
+            if !result.success {
+                entry["failures"] = (entry["failures"].as_u64().unwrap_or_default() + 1).into();
+                entry["last_failure"] = now.into();
+            }
+        }
+    }
+    write_json_or_warn(file, &history, color);
+    history
+}
+
+/// Prints every test which both passed and failed within its recorded outcomes, most frequently
+/// failing first
+pub(super) fn print_flaky_report(history: &serde_json::Value, color: bool) {
+    let mut flaky = Vec::new();
+    for (contract_name, tests) in history.as_object().into_iter().flatten() {
+        for (name, entry) in tests.as_object().into_iter().flatten() {
+            let outcomes = entry["outcomes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(serde_json::Value::as_bool)
+                .collect::<Vec<_>>();
+            let failed = outcomes.iter().filter(|success| !**success).count();
+            if failed > 0 && failed < outcomes.len() {
+                flaky.push((failed, outcomes.len(), contract_name, name, entry));
+            }
+        }
+    }
+    if flaky.is_empty() {
+        println!("No flaky tests in the recorded runs");
+        return
+    }
+    flaky.sort_by(|a, b| b.0.cmp(&a.0));
+    println!("{}", paint(color, Colour::Yellow, "Flaky tests:"));
+    for (failed, runs, contract_name, name, entry) in flaky {
+        println!(
+            "  {}::{}: failed {} of the last {} runs, {} failures in total, last failure at unix time {}",
+            contract_name,
+            name,
+            failed,
+            runs,
+            entry["failures"].as_u64().unwrap_or_default(),
+            entry["last_failure"].as_u64().unwrap_or_default()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::test::cache::record_failures;
+
+    #[test]
+    fn starts_over_malformed_history_entries() {
+        let dir = std::env::temp_dir().join("forge-test-history");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("test-history.json");
+        std::fs::write(&file, r#"{"A": {"testA()": 1, "testB()": {"failures": 2}}, "B": []}"#)
+            .unwrap();
+        let results = BTreeMap::from([
+            (
+                "A".to_string(),
+                BTreeMap::from([
+                    ("testA()".to_string(), forge::TestResult::default()),
+                    ("testB()".to_string(), forge::TestResult::default()),
+                ]),
+            ),
+            (
+                "B".to_string(),
+                BTreeMap::from([(
+                    "testC()".to_string(),
+                    forge::TestResult { success: true, ..Default::default() },
+                )]),
+            ),
+        ]);
+        let history = record_test_history(&results, &file, false);
+        assert_eq!(history["A"]["testA()"]["outcomes"], serde_json::json!([false]));
+        assert_eq!(history["A"]["testA()"]["failures"], 1);
+        assert_eq!(history["A"]["testB()"]["failures"], 3);
+        assert_eq!(history["B"]["testC()"]["outcomes"], serde_json::json!([true]));
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&file).unwrap()).unwrap();
+        assert_eq!(written, history);
+
+        // a history which cannot be written is still returned
+        let unwritable = file.join("test-history.json");
+        assert_eq!(
+            record_test_history(&results, &unwritable, false)["A"]["testA()"]["failures"],
+            1
+        );
+        record_failures(&results, &unwritable, false);
+    }
+}
@@ -0,0 +1,4444 @@
+//! Test command
+
+use crate::{
+    cmd::{build::BuildArgs, Cmd},
+    utils::Template,
+};
+use ansi_term::{Colour, Style};
+use clap::{AppSettings, IntoApp, Parser, ValueHint};
+use ethers::{
+    abi::{Abi, ParamType},
+    contract::Abigen,
+    solc::{artifacts::Severity, ArtifactOutput, CompilerOutput, EvmVersion, Project},
+    types::{Address, Bytes, I256, U256},
+};
+use evm_adapters::{
+    call_tracing::TracePrintOpts,
+    evm_opts::EvmOpts,
+    fuzz::ParamRanges,
+    sputnik::{helpers::vm, new_shared_cache, MemCache, PrecompileStub},
+};
+use eyre::WrapErr;
+use forge::{MultiContractRunnerBuilder, TestFilter};
+use indicatif::{ProgressBar, ProgressStyle};
+use proptest::test_runner::{FileFailurePersistence, RngAlgorithm, TestRng, TestRunner};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+mod cache;
+use cache::*;
+mod coverage;
+use coverage::*;
+mod history;
+use history::*;
+mod repro;
+use repro::*;
+mod report;
+use report::*;
+
+#[derive(Debug, Clone, Parser)]
+pub struct Filter {
+    #[clap(
+        long = "match",
+        short = 'm',
+        help = "only run test methods matching regex (deprecated, see --match-test, --match-contract)"
+    )]
+    pattern: Option<regex::Regex>,
+
+    #[clap(
+        long = "match-test",
+        help = "only run test methods matching regex, if repeated the methods matching any of them are run. Tests matching `--no-match-test` are excluded regardless",
+        conflicts_with = "pattern",
+        multiple_occurrences = true
+    )]
+    test_pattern: Vec<regex::Regex>,
+
+    #[clap(
+        long = "no-match-test",
+        help = "only run test methods not matching regex",
+        conflicts_with = "pattern"
+    )]
+    test_pattern_inverse: Option<regex::Regex>,
+
+    #[clap(
+        long = "match-contract",
+        help = "only run test methods in contracts matching regex, if repeated the contracts matching any of them are run. Contracts matching `--no-match-contract` are excluded regardless",
+        conflicts_with = "pattern",
+        multiple_occurrences = true
+    )]
+    contract_pattern: Vec<regex::Regex>,
+
+    #[clap(
+        long = "no-match-contract",
+        help = "only run test methods in contracts not matching regex",
+        conflicts_with = "pattern"
+    )]
+    contract_pattern_inverse: Option<regex::Regex>,
+
+    #[clap(
+        long = "inherits-from",
+        alias = "only-contracts-matching-inheritance",
+        help = "only run test methods in contracts which (transitively) inherit from the given contract"
+    )]
+    inherits_from: Option<String>,
+
+    #[clap(
+        long = "filter-file",
+        help = "a TOML file with `match_test`, `no_match_test`, `match_contract`, `no_match_contract`, `match_path` and `no_match_path` patterns, overridden by the flags",
+        value_hint = ValueHint::FilePath
+    )]
+    filter_file: Option<PathBuf>,
+
+    #[clap(
+        long = "partition",
+        help = "only run the tests of the i-th of n partitions, e.g. `1/4`, split by the hash of the test's contract and name"
+    )]
+    partition: Option<Partition>,
+
+    #[clap(
+        long = "shard",
+        help = "only run the test contracts of the i-th of n shards, e.g. `1/4`, split by the hash of the contract's name. Composes with the other filters, so that the shards of a run with the same filters cover every matched contract exactly once"
+    )]
+    shard: Option<Partition>,
+
+    #[clap(
+        long = "only-tests-touching",
+        help = "only run tests which call into the given contract, as recorded by a traced run"
+    )]
+    only_tests_touching: Option<String>,
+
+    /// Only run the test methods of exactly this contract, used by `--isolate-contracts` to run
+    /// every contract in its own process
+    #[clap(long = "isolated-contract", hide = true)]
+    isolated_contract: Option<String>,
+
+    /// Contracts none of whose tests touched `only_tests_touching` in the last run
+    #[clap(skip)]
+    untouched_contracts: BTreeSet<String>,
+
+    #[clap(
+        long = "rerun",
+        help = "only run the tests which failed in the last run, or all tests if none failed"
+    )]
+    rerun: bool,
+
+    /// The names of the tests which failed in the last run by contract, loaded for `--rerun`
+    #[clap(skip)]
+    last_failures: Option<BTreeMap<String, BTreeSet<String>>>,
+
+    /// Contracts whose results are reused from the last run with `--cache-tests`
+    #[clap(skip)]
+    cached_contracts: BTreeSet<String>,
+
+    #[clap(
+        long = "match-path",
+        help = "only run test methods in contracts whose source file path matches regex"
+    )]
+    path_pattern: Option<regex::Regex>,
+
+    #[clap(
+        long = "no-match-path",
+        help = "only run test methods in contracts whose source file path does not match regex"
+    )]
+    path_pattern_inverse: Option<regex::Regex>,
+
+    /// The contracts inheriting from `inherits_from`, resolved from the project's sources
+    #[clap(skip)]
+    inheriting_contracts: Option<BTreeSet<String>>,
+
+    /// The source file of every contract, resolved if there are path patterns
+    #[clap(skip)]
+    contract_files: Option<BTreeMap<String, PathBuf>>,
+}
+
+impl Filter {
+    /// Skips the contracts whose tests did not touch the `--only-tests-touching` contract in the
+    /// last run, as recorded in the `cache` file
+    fn load_touch_cache(&mut self, cache: &Path) {
+        let target = match self.only_tests_touching {
+            Some(ref target) => target,
+            None => return,
+        };
+        let touched: BTreeMap<String, BTreeMap<String, BTreeSet<String>>> =
+            match std::fs::read(cache).ok().and_then(|f| serde_json::from_slice(&f).ok()) {
+                Some(touched) => touched,
+                None => return,
+            };
+        self.untouched_contracts = touched
+            .into_iter()
+            .filter(|(_, tests)| !tests.values().any(|touched| touched.contains(target)))
+            .map(|(contract_name, _)| contract_name)
+            .collect();
+    }
+
+    /// Restricts the tests to those which failed in the last run, as recorded in the `cache` file.
+    ///
+    /// Returns `false` if there is no record or no test failed, in which case all tests are run
+    fn load_last_failures(&mut self, cache: &Path) -> bool {
+        let failures: BTreeMap<String, BTreeSet<String>> =
+            match std::fs::read(cache).ok().and_then(|f| serde_json::from_slice(&f).ok()) {
+                Some(failures) => failures,
+                None => return false,
+            };
+        if failures.is_empty() {
+            return false
+        }
+        self.last_failures = Some(failures);
+        true
+    }
+
+    /// Fills in all patterns of the `--filter-file` which are not set via the command line
+    fn load_filter_file(&mut self) -> eyre::Result<()> {
+        let path = match self.filter_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let content = std::fs::read_to_string(path)?;
+        let table = match content.parse::<toml::Value>()? {
+            toml::Value::Table(table) => table,
+            _ => eyre::bail!("filter file {} is not a table", path.display()),
+        };
+        for (key, value) in table {
+            let pattern = match value.as_str() {
+                Some(pattern) => regex::Regex::new(pattern)?,
+                None => eyre::bail!("filter `{}` in {} is not a string", key, path.display()),
+            };
+            let patterns = match key.as_str() {
+                "match_test" => Some(&mut self.test_pattern),
+                "match_contract" => Some(&mut self.contract_pattern),
+                _ => None,
+            };
+            if let Some(patterns) = patterns {
+                if patterns.is_empty() {
+                    patterns.push(pattern);
+                }
+                continue
+            }
+            let field = match key.as_str() {
+                "no_match_test" => &mut self.test_pattern_inverse,
+                "no_match_contract" => &mut self.contract_pattern_inverse,
+                "match_path" => &mut self.path_pattern,
+                "no_match_path" => &mut self.path_pattern_inverse,
+                other => eyre::bail!("unknown filter `{}` in {}", other, path.display()),
+            };
+            // patterns passed on the command line take precedence
+            field.get_or_insert(pattern);
+        }
+        Ok(())
+    }
+
+    /// Whether the filter needs the compiler output to resolve the contracts it matches
+    fn needs_compiler_output(&self) -> bool {
+        self.inherits_from.is_some() ||
+            self.path_pattern.is_some() ||
+            self.path_pattern_inverse.is_some()
+    }
+
+    /// Resolves the contracts which inherit from the `--inherits-from` base contract from the
+    /// compiler output and the source files of all contracts if they are filtered by path
+    fn resolve_inheritance(&mut self, root: &Path, output: &CompilerOutput) {
+        if let Some(ref base) = self.inherits_from {
+            let graph = crate::utils::inheritance_graph(output);
+            self.inheriting_contracts = Some(crate::utils::inheriting_contracts(&graph, base));
+        }
+        if self.path_pattern.is_some() || self.path_pattern_inverse.is_some() {
+            self.contract_files = Some(crate::utils::contract_files(root, output));
+        }
+    }
+
+    /// The filter patterns that are in effect, as a JSON object
+    fn to_json(&self) -> serde_json::Value {
+        let pattern = |re: &Option<regex::Regex>| re.as_ref().map(|re| re.as_str().to_string());
+        serde_json::json!({
+            "match": pattern(&self.pattern),
+            "match_test": self.test_pattern.iter().map(|re| re.as_str()).collect::<Vec<_>>(),
+            "no_match_test": pattern(&self.test_pattern_inverse),
+            "match_contract": self.contract_pattern.iter().map(|re| re.as_str()).collect::<Vec<_>>(),
+            "no_match_contract": pattern(&self.contract_pattern_inverse),
+            "inherits_from": self.inherits_from,
+            "only_tests_touching": self.only_tests_touching,
+            "rerun": self.rerun,
+            "isolated_contract": self.isolated_contract,
+            "filter_file": self.filter_file,
+            "partition": self.partition.map(|p| p.to_string()),
+            "shard": self.shard.map(|s| s.to_string()),
+            "match_path": pattern(&self.path_pattern),
+            "no_match_path": pattern(&self.path_pattern_inverse),
+        })
+    }
+}
+
+impl Filter {
+    /// The filters the test of the contract, if it is known, was included and excluded by
+    fn test_reasons(&self, contract_name: Option<&str>, test_name: &str) -> MatchReasons {
+        let mut reasons = MatchReasons::default();
+        // Handle the deprecated option match
+        if let Some(re) = &self.pattern {
+            reasons.record(re.is_match(test_name), format!("match:{}", re));
+        }
+        reasons.record_any(&self.test_pattern, test_name, "match-test");
+        if let Some(re) = &self.test_pattern_inverse {
+            reasons.exclude_if(re.is_match(test_name), format!("no-match-test:{}", re));
+        }
+        if let Some(partition) = &self.partition {
+            // tests of the same name in different contracts are partitioned independently
+            let contains = match contract_name {
+                Some(contract_name) => {
+                    partition.contains(&format!("{}::{}", contract_name, test_name))
+                }
+                None => partition.contains(test_name),
+            };
+            reasons.record(contains, format!("partition:{}", partition));
+        }
+        // a test whose contract is not known is matched by name only
+        if let Some(failures) = &self.last_failures {
+            let failed = match contract_name {
+                Some(contract_name) => {
+                    failures.get(contract_name).map_or(false, |tests| tests.contains(test_name))
+                }
+                None => failures.values().any(|tests| tests.contains(test_name)),
+            };
+            reasons.record(failed, "rerun".to_string());
+        }
+        reasons
+    }
+
+    /// The filters the contract was included and excluded by
+    fn contract_reasons(&self, contract_name: &str) -> MatchReasons {
+        let mut reasons = MatchReasons::default();
+        reasons.record_any(&self.contract_pattern, contract_name, "match-contract");
+        if let Some(re) = &self.contract_pattern_inverse {
+            reasons.exclude_if(re.is_match(contract_name), format!("no-match-contract:{}", re));
+        }
+        reasons.exclude_if(
+            self.untouched_contracts.contains(contract_name),
+            format!(
+                "only-tests-touching:{}",
+                self.only_tests_touching.as_deref().unwrap_or_default()
+            ),
+        );
+        reasons
+            .exclude_if(self.cached_contracts.contains(contract_name), "cache-tests".to_string());
+        if let Some(shard) = &self.shard {
+            reasons.record(shard.contains(contract_name), format!("shard:{}", shard));
+        }
+        if let Some(ref name) = self.isolated_contract {
+            reasons.record(contract_name == name, format!("isolated-contract:{}", name));
+        }
+        if let Some(failures) = &self.last_failures {
+            reasons.record(failures.contains_key(contract_name), "rerun".to_string());
+        }
+        // the contract name is qualified by its artifact file, e.g. `Greeter.json:GreeterTest`
+        let name = contract_name.rsplit(':').next().unwrap_or(contract_name);
+        if let Some(contracts) = &self.inheriting_contracts {
+            reasons.record(
+                contracts.contains(name),
+                format!("inherits-from:{}", self.inherits_from.as_deref().unwrap_or_default()),
+            );
+        }
+        if let Some(files) = &self.contract_files {
+            match files.get(name) {
+                Some(file) => reasons.extend(self.path_reasons(file)),
+                // a contract without a known source file only passes an inverse pattern
+                None => {
+                    if let Some(re) = &self.path_pattern {
+                        reasons.record(false, format!("match-path:{}", re));
+                    }
+                }
+            }
+        }
+        reasons
+    }
+
+    /// The filters the source file path was included and excluded by
+    fn path_reasons(&self, path: &Path) -> MatchReasons {
+        let path = path.to_string_lossy();
+        let mut reasons = MatchReasons::default();
+        if let Some(re) = &self.path_pattern {
+            reasons.record(re.is_match(&path), format!("match-path:{}", re));
+        }
+        if let Some(re) = &self.path_pattern_inverse {
+            reasons.exclude_if(re.is_match(&path), format!("no-match-path:{}", re));
+        }
+        reasons
+    }
+}
+
+impl TestFilter for Filter {
+    fn matches_test(&self, test_name: &str) -> bool {
+        self.test_reasons(None, test_name).matches()
+    }
+
+    fn matches_contract_test(&self, contract_name: &str, test_name: &str) -> bool {
+        self.test_reasons(Some(contract_name), test_name).matches()
+    }
+
+    fn matches_contract(&self, contract_name: &str) -> bool {
+        self.contract_reasons(contract_name).matches()
+    }
+
+    fn matches_path(&self, path: &Path) -> bool {
+        self.path_reasons(path).matches()
+    }
+}
+
+/// The filters a test or contract was included and excluded by, as `<flag>:<value>`, e.g.
+/// `no-match-test:^skip`
+#[derive(Debug, Clone, Default)]
+struct MatchReasons {
+    included_by: Vec<String>,
+    excluded_by: Vec<String>,
+}
+
+impl MatchReasons {
+    /// Records a filter which the test or contract either passed or failed
+    fn record(&mut self, passed: bool, reason: String) {
+        if passed {
+            self.included_by.push(reason);
+        } else {
+            self.excluded_by.push(reason);
+        }
+    }
+
+    /// Records the patterns of a repeatable filter, which the name passes if it matches any of
+    /// them
+    fn record_any(&mut self, patterns: &[regex::Regex], name: &str, flag: &str) {
+        if patterns.is_empty() {
+            return
+        }
+        match patterns.iter().find(|re| re.is_match(name)) {
+            Some(re) => self.record(true, format!("{}:{}", flag, re)),
+            None => {
+                let patterns = patterns.iter().map(|re| re.as_str()).collect::<Vec<_>>();
+                self.record(false, format!("{}:{}", flag, patterns.join(",")))
+            }
+        }
+    }
+
+    /// Records a filter which can only exclude, like the inverse patterns
+    fn exclude_if(&mut self, excluded: bool, reason: String) {
+        if excluded {
+            self.excluded_by.push(reason);
+        }
+    }
+
+    fn extend(&mut self, other: MatchReasons) {
+        self.included_by.extend(other.included_by);
+        self.excluded_by.extend(other.excluded_by);
+    }
+
+    /// Returns `true` if no filter excluded the test or contract
+    fn matches(&self) -> bool {
+        self.excluded_by.is_empty()
+    }
+}
+
+/// The `index`-th of `count` disjoint partitions of all tests, or of all test contracts for
+/// `--shard`, 1-based
+#[derive(Debug, Clone, Copy)]
+pub struct Partition {
+    index: u64,
+    count: u64,
+}
+
+impl Partition {
+    /// Returns `true` if the test or contract with this name belongs to this partition
+    fn contains(&self, name: &str) -> bool {
+        // FNV-1a, which unlike the std hasher is guaranteed to be stable across releases
+        let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        hash % self.count == self.index - 1
+    }
+}
+
+impl FromStr for Partition {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| eyre::eyre!("partition `{}` is not of the form `<i>/<n>`", s))?;
+        let (index, count) = (index.trim().parse::<u64>()?, count.trim().parse::<u64>()?);
+        if index == 0 || index > count {
+            eyre::bail!("partition index must be between 1 and {}, got {}", count, index)
+        }
+        Ok(Partition { index, count })
+    }
+}
+
+impl fmt::Display for Partition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+// This is required to group Filter options in help output
+#[clap(global_setting = AppSettings::DeriveDisplayOrder)]
+pub struct TestArgs {
+    #[clap(help = "print the test results in json format", long, short)]
+    json: bool,
+
+    #[clap(
+        help = "print every test result as a separate json record per line",
+        long,
+        conflicts_with = "json"
+    )]
+    json_stream: bool,
+
+    #[clap(
+        help = "validate the json output against its schema, erroring on unexpected output",
+        long,
+        requires = "json"
+    )]
+    json_validate: bool,
+
+    #[clap(
+        help = "count the assertions every test executed for the `assertions` of the json output, which records the instructions of every call and compiles the project without the cache",
+        long
+    )]
+    count_assertions: bool,
+
+    #[clap(flatten)]
+    evm_opts: EvmOpts,
+
+    #[clap(flatten)]
+    filter: Filter,
+
+    #[clap(flatten)]
+    opts: BuildArgs,
+
+    #[clap(
+        help = "if set to true, the process will exit with an exit code = 0, even if the tests fail",
+        long,
+        env = "FORGE_ALLOW_FAILURE"
+    )]
+    allow_failure: bool,
+
+    #[clap(
+        help = "expect the tests whose `Contract::test` signature matches the regex to fail, the run fails if any other test fails or if one of them passes",
+        long,
+        multiple_occurrences = true
+    )]
+    allow_failure_for: Vec<regex::Regex>,
+
+    #[clap(help = "exit with an error if the filters did not match any test", long)]
+    fail_on_skip: bool,
+
+    #[clap(
+        help = "do not exit with an error because of fuzz or invariant tests which gave up after too many of their inputs were rejected",
+        long
+    )]
+    allow_rejects: bool,
+
+    #[clap(
+        help = "the number of threads the test contracts and their tests are run on, defaults to the number of CPUs",
+        long
+    )]
+    jobs: Option<usize>,
+
+    #[clap(
+        help = "run this shell command if the test run fails, the number of failing tests is passed in the FORGE_FAILURES environment variable",
+        long
+    )]
+    on_failure_command: Option<String>,
+
+    #[clap(
+        help = "stop starting new test contracts once a contract had a failing test, contracts which are already running are finished",
+        long
+    )]
+    exit_first_contract_failure: bool,
+
+    #[clap(help = "run failing tests again up to this many times", long, default_value = "0")]
+    retries: usize,
+
+    #[clap(
+        help = "only run failing tests again if their reason or error matches the regex, requires `--retries`",
+        long
+    )]
+    retry_on_reason: Option<regex::Regex>,
+
+    #[clap(
+        help = "run failing fuzz tests again up to this many times with a fresh seed, a fuzz test which passes on any of these runs is reported as flaky instead of failed",
+        long,
+        value_name = "N",
+        default_value = "0"
+    )]
+    fuzz_retries: usize,
+
+    #[clap(
+        help = "call setUp() once per contract and run every unit test from a snapshot of the resulting state, the unit tests of a contract then run one after the other instead of in parallel",
+        long
+    )]
+    isolate: bool,
+
+    #[clap(
+        help = "stop starting new tests once any test failed and report the results so far, tests which are already running (see --jobs) are finished",
+        long
+    )]
+    fail_fast: bool,
+
+    #[clap(
+        help = "stop starting new tests once this many fuzz or invariant tests gave up after too many of their inputs were rejected",
+        long,
+        value_name = "TESTS"
+    )]
+    max_test_rejects: Option<usize>,
+
+    #[clap(
+        help = "print the tests which both passed and failed in their recently recorded runs",
+        long
+    )]
+    flaky_report: bool,
+
+    #[clap(
+        help = "run the tests of every contract in a separate forge process, so that no global state is shared between contracts",
+        long,
+        conflicts_with = "json-stream"
+    )]
+    isolate_contracts: bool,
+
+    /// The compiled contracts of the process which spawned this one with `--isolate-contracts`,
+    /// which the runner is built from instead of compiling the project again
+    #[clap(long = "isolated-build", hide = true, requires = "isolated-results")]
+    isolated_build: Option<PathBuf>,
+
+    /// The file the results are written to for the process which spawned this one with
+    /// `--isolate-contracts`
+    #[clap(long = "isolated-results", hide = true)]
+    isolated_results: Option<PathBuf>,
+
+    #[clap(
+        help = "do not show the progress of the test run, which is shown on a terminal unless the output is json",
+        long
+    )]
+    no_progress: bool,
+
+    #[clap(
+        help = "only report the tests which used at least this amount of gas, all tests are still run",
+        long
+    )]
+    report_min_gas: Option<u64>,
+
+    #[clap(
+        help = "leave the tests matching regex out of the report, like --no-match-test, but all tests are still run",
+        long
+    )]
+    report_no_match_test: Option<regex::Regex>,
+
+    #[clap(
+        help = "fail the run if any matched test contract or a contract it inherits from contains an inline assembly block which is not annotated as memory-safe",
+        long
+    )]
+    strict_memory_safety: bool,
+
+    #[clap(help = "fail any matched test contract which does not define a setUp() function", long)]
+    require_setup: bool,
+
+    #[clap(help = "annotate each trace frame with the file:line of the called function", long)]
+    trace_source_locations: bool,
+
+    #[clap(
+        help = "persist fuzz inputs which reached new execution paths in the given directory and replay them first on future runs",
+        long,
+        value_hint = ValueHint::DirPath
+    )]
+    fuzz_persist_success: Option<PathBuf>,
+
+    #[clap(
+        help = "persist failing fuzz inputs in the cache directory and replay them first on future runs",
+        long
+    )]
+    fuzz_persist: bool,
+
+    #[clap(
+        help = "also generate fuzz inputs by mutating the inputs which reached new execution paths, instead of only at random",
+        long
+    )]
+    fuzz_coverage_guided: bool,
+
+    #[clap(
+        help = "fail fuzz tests with a counterexample if a single fuzz case uses more than this amount of gas",
+        long
+    )]
+    max_fuzz_gas: Option<u64>,
+
+    #[clap(
+        help = "instead of stopping at the first failing case, keep fuzzing and report up to this many distinct revert reasons with an input for each",
+        long,
+        value_name = "N"
+    )]
+    fuzz_report_unique_reverts: Option<usize>,
+
+    #[clap(
+        help = "fail a test if it, or a single one of its fuzz cases, executes for longer than this many milliseconds",
+        long
+    )]
+    timeout: Option<u64>,
+
+    #[clap(
+        help = "write the call traces of every test as json into the given directory",
+        long,
+        value_hint = ValueHint::DirPath
+    )]
+    trace_out: Option<PathBuf>,
+
+    #[clap(
+        help = "compare the call traces of every test against a baseline directory written by --trace-out",
+        long,
+        value_hint = ValueHint::DirPath
+    )]
+    diff_traces: Option<PathBuf>,
+
+    #[clap(
+        help = "write a flamegraph of the gas used by the calls of the named test as svg into the given file",
+        long,
+        number_of_values = 2,
+        value_names = &["TEST", "FILE"]
+    )]
+    emit_flamegraph: Option<Vec<String>>,
+
+    #[clap(help = "list all matching tests without running them", long)]
+    list: bool,
+
+    #[clap(
+        help = "with --list, also list the tests the filters excluded, together with the filters that excluded them",
+        long,
+        requires = "list"
+    )]
+    show_skipped: bool,
+
+    #[clap(
+        help = "only keep this many levels of nested calls below a reverted call in the traces",
+        long
+    )]
+    max_revert_depth: Option<usize>,
+
+    #[clap(
+        help = "the number of cases run for each fuzz test, defaults to 256",
+        long,
+        env = "FORGE_FUZZ_RUNS",
+        parse(try_from_str = parse_fuzz_runs)
+    )]
+    fuzz_runs: Option<u32>,
+
+    #[clap(
+        help = "the total number of fuzz cases, distributed equally across all matching fuzz tests",
+        long
+    )]
+    fuzz_runs_budget: Option<u32>,
+
+    #[clap(
+        help = "the number of random call sequences run for each `invariant` function, defaults to the number of fuzz runs",
+        long,
+        parse(try_from_str = parse_fuzz_runs)
+    )]
+    invariant_runs: Option<u32>,
+
+    #[clap(
+        help = "the maximum number of calls in a random call sequence of an `invariant` function, defaults to 15",
+        long
+    )]
+    invariant_depth: Option<usize>,
+
+    #[clap(
+        help = "minimize the call sequence which broke an invariant to calls that are all needed to break it, beyond the shrinking of the fuzzer",
+        long
+    )]
+    invariant_shrink_sequence: bool,
+
+    #[clap(help = "print a summary of the cheatcodes invoked by each test", long)]
+    print_cheatcode_usage: bool,
+
+    #[clap(
+        help = "report the gas used by the calls to every function of the non-test contracts, gathered from the call traces",
+        long
+    )]
+    gas_report: bool,
+
+    #[clap(
+        help = "the comma separated percentiles of the gas of the calls to every function computed by --gas-report",
+        long,
+        default_value = "50,90,99",
+        use_delimiter = true,
+        parse(try_from_str = parse_percentile)
+    )]
+    gas_report_percentiles: Vec<u8>,
+
+    #[clap(
+        help = "print only the failing tests and the summary, and nothing while compiling. Traces of failing tests are still printed with -vvv",
+        long,
+        short = 'q'
+    )]
+    quiet: bool,
+
+    #[clap(
+        help = "read the creation code of the test contracts from disk as they are run instead of keeping all of it in memory, of which at most this many bytes are kept",
+        long,
+        value_name = "BYTES"
+    )]
+    max_artifacts_memory: Option<usize>,
+
+    #[clap(
+        help = "only deploy every test contract with matching tests and run its setUp(), reporting per contract whether it succeeded instead of running the tests",
+        long,
+        conflicts_with = "cache-tests"
+    )]
+    only_setup: bool,
+
+    #[clap(
+        help = "reuse the passing results of the last run for the test contracts without fuzz or invariant tests whose bytecode, the bytecode of the contracts their tests called and the evm options did not change",
+        long
+    )]
+    cache_tests: bool,
+
+    #[clap(
+        help = "record which source lines the tests executed, across all tests and fuzz cases, and write them as lcov.info to the project root",
+        long
+    )]
+    coverage: bool,
+
+    #[clap(
+        help = "print the functions of the compiled non-test contracts which no test called, gathered from the call traces",
+        long
+    )]
+    print_coverage_gaps: bool,
+
+    #[clap(help = "print the number of SLOADs and SSTOREs executed by each test", long)]
+    print_storage_reads: bool,
+
+    #[clap(
+        help = "print the N tests which took the longest to run, slowest first",
+        long,
+        value_name = "N"
+    )]
+    slowest: Option<usize>,
+
+    #[clap(help = "print the number of logs emitted by each test, without their contents", long)]
+    print_log_counts: bool,
+
+    #[clap(
+        help = "list the revert selectors which could not be decoded as a revert reason or a known custom error, with the tests which reverted with them",
+        long
+    )]
+    print_revert_selectors_table: bool,
+
+    #[clap(
+        help = "print the contracts deployed by the setUp() of every test contract in the order they were created, with their addresses and gas",
+        long
+    )]
+    print_deployment_order: bool,
+
+    #[clap(
+        help = "print the public functions of the test contracts which are neither tests nor setUp and were not called by any test",
+        long
+    )]
+    print_unused_test_helpers: bool,
+
+    #[clap(
+        help = "print the failing tests of all contracts first, then the passing ones, instead of grouping them by contract",
+        long
+    )]
+    group_results_by_status: bool,
+
+    #[clap(
+        help = "print the keccak256 hash of the creation bytecode of each matched test contract",
+        long
+    )]
+    print_creation_code_hash: bool,
+
+    #[clap(
+        help = "print the gross gas, refund and net gas of each test, warning about tests whose refunds exceed the mainnet refund cap",
+        long
+    )]
+    print_gas_refund_summary: bool,
+
+    #[clap(
+        help = "print a tree of the test files, their test contracts and their tests before running them",
+        long
+    )]
+    print_test_tree: bool,
+
+    #[clap(
+        help = "print how many unit, fuzz and invariant tests in how many contracts matched the filters before running them",
+        long
+    )]
+    print_test_counts_summary: bool,
+
+    #[clap(help = "print the values of the immutables of every deployed test contract", long)]
+    print_immutables: bool,
+
+    #[clap(
+        help = "treat tests failing with a solidity Panic (assert, overflow, ...) as hard errors, exiting with code 2",
+        long
+    )]
+    abort_on_panic: bool,
+
+    #[clap(help = "emphasize trace frames whose `Contract::function` matches the regex", long)]
+    trace_highlight: Option<regex::Regex>,
+
+    #[clap(
+        help = "fold consecutive identical calls in traces into a single `Call (×N)` frame",
+        long
+    )]
+    trace_collapse_repeated: bool,
+
+    #[clap(help = "only print the traces of the tests themselves, without those of setUp()", long)]
+    trace_hide_setup: bool,
+
+    #[clap(
+        help = "name the contract at an address in traces, can be repeated",
+        long = "label",
+        value_name = "ADDRESS=NAME",
+        parse(try_from_str = parse_label),
+        multiple_occurrences = true
+    )]
+    labels: Vec<(Address, String)>,
+
+    #[clap(
+        help = "run every test this many times and report the mean and standard deviation of its gas usage",
+        long,
+        alias = "run-n-times-and-average"
+    )]
+    bench: Option<usize>,
+
+    #[clap(
+        help = "the order in which the tests are run: `alpha` as before, in parallel from contracts in alphabetical order, `source` one after the other in the order they are declared in and `random` one after the other shuffled with --randomize-order-seed, or the fuzz seed",
+        long,
+        default_value = "alpha",
+        possible_values = &["alpha", "source", "random"]
+    )]
+    order: TestOrder,
+
+    #[clap(
+        help = "run the tests with the evm version and chain id of this chain",
+        long,
+        possible_values = &["mainnet", "optimism", "arbitrum", "polygon", "bsc"]
+    )]
+    run_on: Option<ChainPreset>,
+
+    #[clap(
+        help = "whether the output is colored, `auto` colors it if stdout is a terminal and the NO_COLOR environment variable is not set",
+        long,
+        default_value = "auto",
+        possible_values = &["auto", "always", "never"]
+    )]
+    color: ColorChoice,
+
+    #[clap(help = "the seed of the random test order, the fuzz seed if omitted", long)]
+    randomize_order_seed: Option<u64>,
+
+    #[clap(
+        help = "generate typed bindings for all compiled contracts into this directory",
+        long,
+        value_hint = ValueHint::DirPath
+    )]
+    export_bindings: Option<PathBuf>,
+
+    #[clap(
+        help = "the language of the generated bindings",
+        long,
+        default_value = "rust",
+        possible_values = &["rust", "ts"]
+    )]
+    bindings_lang: BindingsLang,
+
+    #[clap(
+        help = "only generate the bindings, without running any tests",
+        long,
+        requires = "export-bindings"
+    )]
+    bindings_only: bool,
+
+    #[clap(help = "the number of rejected fuzz inputs after which a fuzz test fails", long)]
+    fuzz_max_rejects_before_error: Option<u32>,
+
+    #[clap(
+        help = "the number of rejected inputs while generating a single fuzz case after which a fuzz test fails, defaults to 65536",
+        long
+    )]
+    fuzz_max_local_rejects: Option<u32>,
+
+    #[clap(
+        help = "the total number of rejected fuzz inputs after which a fuzz test fails, defaults to 1024",
+        long
+    )]
+    fuzz_max_global_rejects: Option<u32>,
+
+    #[clap(
+        help = "only fuzz the integer parameter at this index of the test with values in the inclusive range, can be repeated",
+        long,
+        value_name = "TEST:INDEX=LO..HI",
+        multiple_occurrences = true
+    )]
+    fuzz_param_range: Vec<FuzzParamRange>,
+
+    #[clap(
+        help = "write every input tried while shrinking failing fuzz cases to this json file",
+        long,
+        value_hint = ValueHint::FilePath
+    )]
+    fuzz_shrink_to_file: Option<PathBuf>,
+
+    #[clap(
+        help = "seed the fuzzer of every fuzz and invariant test from the fuzz seed and shrink failing cases without a time limit, so that the same --fuzz-seed reports the same counterexample on every run and machine",
+        long
+    )]
+    fuzz_deterministic_shrink: bool,
+
+    #[clap(
+        help = "replay the fuzz inputs in this json file first and overwrite it with the inputs of every fuzz case of the run",
+        long,
+        value_hint = ValueHint::FilePath
+    )]
+    fuzz_inputs_as_json: Option<PathBuf>,
+
+    #[clap(
+        help = "write a unit test calling every failing fuzz test with its shrunk counterexample to this directory",
+        long,
+        value_hint = ValueHint::DirPath,
+        value_name = "DIR"
+    )]
+    write_fuzz_regression: Option<PathBuf>,
+
+    #[clap(
+        help = "fund the accounts in this file, one `address=balance` per line, with ETH before the tests are run",
+        long,
+        value_hint = ValueHint::FilePath
+    )]
+    seed_accounts: Option<PathBuf>,
+
+    #[clap(
+        help = "replace the precompile at an address with a stub which returns or reverts with fixed data, can be repeated",
+        long = "precompile-override",
+        value_name = "ADDRESS=return:DATA|revert[:DATA]",
+        parse(try_from_str = parse_precompile_override),
+        multiple_occurrences = true
+    )]
+    precompile_overrides: Vec<(Address, PrecompileStub)>,
+
+    #[clap(
+        help = "deploy the test contract with this constructor argument, repeated once per argument in the order of the constructor's parameters",
+        long = "constructor-args",
+        value_name = "CONTRACT=ARG",
+        parse(try_from_str = parse_constructor_arg),
+        multiple_occurrences = true
+    )]
+    constructor_args: Vec<(String, String)>,
+
+    #[clap(
+        help = "rerun the tests whenever a solidity file in the sources changed, until interrupted",
+        long,
+        conflicts_with = "list"
+    )]
+    watch: bool,
+
+    #[clap(
+        help = "render the test results with this handlebars-like template instead of the built-in report",
+        long,
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = &["json", "json-stream"]
+    )]
+    output_template: Option<PathBuf>,
+
+    #[clap(
+        help = "write the results as a JUnit XML report to this file",
+        long,
+        value_hint = ValueHint::FilePath
+    )]
+    junit: Option<PathBuf>,
+
+    #[clap(
+        help = "write the runtime and creation code size of every contract as CSV to this file after the build",
+        long,
+        value_hint = ValueHint::FilePath
+    )]
+    print_contract_sizes_csv: Option<PathBuf>,
+
+    #[clap(
+        help = "write the command, its resolved configuration, the compiler settings, the seeds and the state fetched from the fork into the given directory, to rerun the tests with --from-repro-bundle",
+        long,
+        value_hint = ValueHint::DirPath
+    )]
+    repro_bundle: Option<PathBuf>,
+
+    #[clap(
+        help = "rerun the tests recorded in a directory written by --repro-bundle with the same seeds, against the recorded fork state instead of the fork",
+        long,
+        value_hint = ValueHint::DirPath,
+        conflicts_with = "repro-bundle"
+    )]
+    from_repro_bundle: Option<PathBuf>,
+
+    #[clap(
+        help = "the profile of the foundry.toml at the project root whose options are used where they are neither passed on the command line nor set via the environment, `default` if omitted",
+        long,
+        env = "FOUNDRY_PROFILE",
+        value_name = "NAME"
+    )]
+    profile: Option<String>,
+
+    /// The compiler settings recorded in the `--from-repro-bundle`, which the project must be
+    /// compiled with
+    #[clap(skip)]
+    repro_compiler: Option<serde_json::Value>,
+
+    #[clap(
+        help = "the seed of the fuzzer as a decimal or 0x-prefixed hex number, a random seed is picked and printed if omitted",
+        long,
+        parse(try_from_str = parse_fuzz_seed)
+    )]
+    fuzz_seed: Option<u64>,
+
+    #[clap(
+        help = "the prefix of the functions which are run as tests, `<prefix>Fail` tests are expected to fail",
+        long,
+        default_value = "test"
+    )]
+    test_function_prefix: String,
+
+    #[clap(help = "only run tests in contracts whose name ends with this suffix", long)]
+    test_contract_suffix: Option<String>,
+
+    #[clap(
+        help = "warn about contracts whose runtime code exceeds this percentage of the 24KB limit",
+        long,
+        default_value = "100"
+    )]
+    deploy_size_threshold_warn: u64,
+}
+
+/// The language of the bindings generated by `--export-bindings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingsLang {
+    /// Rust bindings generated by ethers' abigen
+    Rust,
+    /// TypeScript interfaces
+    Ts,
+}
+
+impl FromStr for BindingsLang {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "rust" => BindingsLang::Rust,
+            "ts" => BindingsLang::Ts,
+            other => eyre::bail!("unknown bindings language {}", other),
+        })
+    }
+}
+
+/// The order in which tests are executed, the results are reported alphabetically regardless
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestOrder {
+    /// Tests are run in parallel, taken from the contracts and functions in alphabetical order
+    Alpha,
+    /// Tests are run sequentially in the order their functions are declared in the source
+    Source,
+    /// Tests are run sequentially in a seeded random order
+    Random,
+}
+
+impl FromStr for TestOrder {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "alpha" => TestOrder::Alpha,
+            "source" => TestOrder::Source,
+            "random" => TestOrder::Random,
+            other => eyre::bail!("unknown test order {}", other),
+        })
+    }
+}
+
+/// Whether the output is colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Colored if stdout is a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns `true` if the output should be colored
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").map_or(true, |value| value.is_empty()) &&
+                    atty::is(atty::Stream::Stdout)
+            }
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => ColorChoice::Auto,
+            "always" => ColorChoice::Always,
+            "never" => ColorChoice::Never,
+            other => eyre::bail!("unknown color choice {}", other),
+        })
+    }
+}
+
+/// Paints `text` with `style`, or leaves it plain if colors are disabled
+fn paint(color: bool, style: impl Into<Style>, text: impl fmt::Display) -> String {
+    if color {
+        style.into().paint(text.to_string()).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// A chain whose evm version and chain id the tests can be run with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainPreset {
+    Mainnet,
+    Optimism,
+    Arbitrum,
+    Polygon,
+    Bsc,
+}
+
+impl ChainPreset {
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            ChainPreset::Mainnet => 1,
+            ChainPreset::Optimism => 10,
+            ChainPreset::Arbitrum => 42161,
+            ChainPreset::Polygon => 137,
+            ChainPreset::Bsc => 56,
+        }
+    }
+
+    pub fn evm_version(&self) -> EvmVersion {
+        match self {
+            ChainPreset::Bsc => EvmVersion::Berlin,
+            _ => EvmVersion::London,
+        }
+    }
+}
+
+impl FromStr for ChainPreset {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "mainnet" => ChainPreset::Mainnet,
+            "optimism" => ChainPreset::Optimism,
+            "arbitrum" => ChainPreset::Arbitrum,
+            "polygon" => ChainPreset::Polygon,
+            "bsc" => ChainPreset::Bsc,
+            other => eyre::bail!("unknown chain preset {}", other),
+        })
+    }
+}
+
+impl Cmd for TestArgs {
+    type Output = TestOutcome;
+
+    fn run(self) -> eyre::Result<Self::Output> {
+        if let Some(ref dir) = self.from_repro_bundle {
+            return read_repro_bundle(dir)?.run()
+        }
+        if let Some(jobs) = self.jobs {
+            // contracts and their tests are run in parallel on a rayon pool of their own, unlike
+            // the global one it can be sized anew by every run of the process
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+            return pool.install(|| TestArgs { jobs: None, ..self }.run())
+        }
+        let started = Instant::now();
+        // identifies this invocation in the json output, also across `--watch` reruns
+        let run_id = new_run_id();
+        let run_timestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let TestArgs {
+            opts,
+            mut evm_opts,
+            json,
+            json_stream,
+            json_validate,
+            count_assertions,
+            mut filter,
+            allow_failure,
+            allow_failure_for,
+            fail_on_skip,
+            allow_rejects,
+            jobs: _,
+            on_failure_command,
+            exit_first_contract_failure,
+            retries,
+            retry_on_reason,
+            fuzz_retries,
+            isolate,
+            fail_fast,
+            max_test_rejects,
+            flaky_report,
+            isolate_contracts,
+            isolated_build,
+            isolated_results,
+            no_progress,
+            report_min_gas,
+            report_no_match_test,
+            strict_memory_safety,
+            require_setup,
+            trace_source_locations,
+            fuzz_persist_success,
+            fuzz_persist,
+            fuzz_coverage_guided,
+            max_fuzz_gas,
+            fuzz_report_unique_reverts,
+            timeout,
+            trace_out,
+            diff_traces,
+            emit_flamegraph,
+            list,
+            show_skipped,
+            max_revert_depth,
+            fuzz_runs,
+            fuzz_runs_budget,
+            invariant_runs,
+            invariant_depth,
+            invariant_shrink_sequence,
+            print_cheatcode_usage,
+            gas_report,
+            gas_report_percentiles,
+            quiet,
+            max_artifacts_memory,
+            only_setup,
+            cache_tests,
+            coverage,
+            print_coverage_gaps,
+            print_storage_reads,
+            slowest,
+            print_log_counts,
+            print_revert_selectors_table,
+            print_deployment_order,
+            print_unused_test_helpers,
+            group_results_by_status,
+            print_creation_code_hash,
+            print_gas_refund_summary,
+            print_test_tree,
+            print_test_counts_summary,
+            print_immutables,
+            abort_on_panic,
+            trace_highlight,
+            trace_collapse_repeated,
+            trace_hide_setup,
+            labels,
+            bench,
+            order,
+            run_on,
+            color,
+            randomize_order_seed,
+            export_bindings,
+            bindings_lang,
+            bindings_only,
+            fuzz_max_rejects_before_error,
+            fuzz_max_local_rejects,
+            fuzz_max_global_rejects,
+            fuzz_param_range,
+            fuzz_shrink_to_file,
+            fuzz_deterministic_shrink,
+            fuzz_inputs_as_json,
+            write_fuzz_regression,
+            seed_accounts,
+            precompile_overrides,
+            constructor_args,
+            watch,
+            output_template,
+            junit,
+            print_contract_sizes_csv,
+            repro_bundle,
+            from_repro_bundle: _,
+            profile,
+            repro_compiler,
+            fuzz_seed,
+            deploy_size_threshold_warn,
+            test_function_prefix,
+            test_contract_suffix,
+        } = self;
+        let color = color.enabled();
+
+        // Setup the fuzzer
+        let mut cfg =
+            proptest::test_runner::Config { failure_persistence: None, ..Default::default() };
+        if let Some(runs) = fuzz_runs {
+            cfg.cases = runs;
+        }
+        if let Some(max_rejects) = fuzz_max_rejects_before_error {
+            cfg.max_global_rejects = max_rejects;
+            cfg.max_local_rejects = max_rejects;
+        }
+        if let Some(max_rejects) = fuzz_max_local_rejects {
+            cfg.max_local_rejects = max_rejects;
+        }
+        if let Some(max_rejects) = fuzz_max_global_rejects {
+            cfg.max_global_rejects = max_rejects;
+        }
+        // the seed is picked here rather than by proptest, so that it can be reported
+        let fuzz_seed = fuzz_seed.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+        });
+        let mut seed = [0u8; 32];
+        seed[..8].copy_from_slice(&fuzz_seed.to_le_bytes());
+
+        // from -vvvv on the traces of passing tests are printed, which --quiet omits
+        if quiet && evm_opts.verbosity > 3 {
+            eyre::bail!("--quiet cannot be combined with a verbosity above -vvv")
+        }
+        // `--retries` defaults to 0, so clap cannot require it
+        if retry_on_reason.is_some() && retries == 0 {
+            eyre::bail!("--retry-on-reason requires --retries to be at least 1")
+        }
+
+        // Set up the project
+        let project = opts.project().map_err(|err| report_build_error(err, json))?;
+        if let Some(ref recorded) = repro_compiler {
+            check_repro_compiler(recorded, &compiler_info(&project))?;
+        }
+        filter.load_filter_file()?;
+        filter.load_touch_cache(&touch_cache_file(&project.paths.cache));
+        if filter.rerun &&
+            !filter.load_last_failures(&failures_cache_file(&project.paths.cache)) &&
+            !json
+        {
+            println!("No failing tests recorded in the last run, running all tests");
+        }
+        if fuzz_persist {
+            let file = fuzz_failures_file(&project.paths.cache, color)?;
+            // proptest only keeps a `&'static str` to the file, which lives for the whole run
+            let file = Box::leak(file.to_string_lossy().into_owned().into_boxed_str());
+            cfg.failure_persistence = Some(Box::new(FileFailurePersistence::Direct(file)));
+        }
+        let fuzzer =
+            TestRunner::new_with_rng(cfg.clone(), TestRng::from_seed(RngAlgorithm::ChaCha, &seed));
+
+        // prepare the test builder
+        let evm_version =
+            run_on.map(|chain| chain.evm_version()).unwrap_or(opts.compiler.evm_version);
+        if let Some(chain) = run_on {
+            evm_opts.env.chain_id = chain.chain_id();
+        }
+        let mut evm_cfg = crate::utils::sputnik_cfg(&evm_version);
+        evm_cfg.create_contract_limit = None;
+        evm_cfg.call_stack_limit = evm_opts.max_call_depth;
+        let refund_quotient = print_gas_refund_summary.then(|| evm_cfg.max_refund_quotient);
+
+        let mut builder = MultiContractRunnerBuilder::default()
+            .fuzzer(fuzzer)
+            .initial_balance(evm_opts.initial_balance)
+            .evm_cfg(evm_cfg)
+            .sender(evm_opts.sender)
+            .require_setup(require_setup)
+            // traces need to be collected regardless of verbosity if they are written or compared
+            .force_tracing(
+                trace_out.is_some() ||
+                    diff_traces.is_some() ||
+                    emit_flamegraph.is_some() ||
+                    gas_report ||
+                    print_coverage_gaps ||
+                    print_unused_test_helpers ||
+                    print_deployment_order ||
+                    cache_tests ||
+                    coverage ||
+                    filter.only_tests_touching.is_some(),
+            )
+            .max_revert_depth(max_revert_depth)
+            // the assertions are counted in the executed instructions
+            .coverage(coverage || count_assertions)
+            .fuzz_runs_budget(fuzz_runs_budget)
+            .invariant_runs(invariant_runs)
+            .invariant_depth(invariant_depth)
+            .invariant_shrink_sequence(invariant_shrink_sequence)
+            .fuzz_coverage_guided(fuzz_coverage_guided)
+            .exit_first_contract_failure(exit_first_contract_failure)
+            .fail_fast(fail_fast)
+            .max_test_rejects(max_test_rejects)
+            .max_fuzz_gas(max_fuzz_gas)
+            .fuzz_unique_reverts(fuzz_report_unique_reverts)
+            .timeout(timeout.map(Duration::from_millis))
+            .test_function_prefix(test_function_prefix.clone())
+            .retries(retries, retry_on_reason.clone())
+            .fuzz_retries(fuzz_retries, Some(fuzz_seed))
+            .isolate(isolate)
+            .only_setup(only_setup)
+            .max_artifacts_memory(max_artifacts_memory)
+            .deterministic_shrink_seed(fuzz_deterministic_shrink.then(|| fuzz_seed));
+        if let Some(ref dir) = fuzz_persist_success {
+            builder = builder.fuzz_corpus(dir.clone());
+        }
+        let mut param_ranges: BTreeMap<String, ParamRanges> = BTreeMap::new();
+        for range in &fuzz_param_range {
+            param_ranges
+                .entry(range.test.clone())
+                .or_default()
+                .insert(range.index, (range.lo, range.hi));
+        }
+        builder = builder.fuzz_param_ranges(param_ranges);
+        if let Some(ref file) = seed_accounts {
+            builder = builder.seed_accounts(read_seed_accounts(file)?);
+        }
+        builder = builder.precompile_overrides(precompile_overrides.iter().cloned().collect());
+        let mut args_by_contract: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (contract, arg) in &constructor_args {
+            args_by_contract.entry(contract.clone()).or_default().push(arg.clone());
+        }
+        builder = builder.constructor_args(args_by_contract);
+        if let Some(ref file) = fuzz_inputs_as_json {
+            if file.exists() {
+                builder = builder.fuzz_inputs(read_fuzz_inputs(file)?);
+            }
+        }
+        if let Some(ref suffix) = test_contract_suffix {
+            builder = builder.test_contract_suffix(suffix.clone());
+        }
+        // the fuzz seed is reported, so that the order can be reproduced from it as well
+        let order_seed =
+            (order == TestOrder::Random).then(|| randomize_order_seed.unwrap_or(fuzz_seed));
+        if let Some(seed) = order_seed {
+            if !json {
+                println!("Running tests in random order, seed: {}", seed);
+            }
+            builder = builder.order_seed(Some(seed));
+        }
+        if order == TestOrder::Source {
+            if !json {
+                println!("Running tests one after the other in source order");
+            }
+            builder = builder.sequential(true);
+        }
+
+        // the effective configuration of the run is recorded in the json output, and in the
+        // reproducibility bundle, which restores the seeds from it
+        let resolved_config = (json || repro_bundle.is_some()).then(|| {
+            serde_json::json!({
+                "fuzz_runs": cfg.cases,
+                "fuzz_seed": fuzz_seed,
+                "order_seed": order_seed,
+                "sender": evm_opts.sender,
+                "gas_limit": evm_opts.env.gas_limit,
+                "fork_url": evm_opts.fork_url,
+                "fork_block_number": evm_opts.fork_block_number,
+                "verbosity": evm_opts.verbosity,
+                "filter": filter.to_json(),
+                "profile": profile,
+            })
+        });
+        let json_config = resolved_config.clone().filter(|_| json);
+        let repro_bundle = match repro_bundle {
+            Some(dir) => {
+                // record the fork's block and everything fetched from it to replay it offline
+                let fork = match evm_opts.fork_url {
+                    Some(_) => {
+                        let cache = new_shared_cache(MemCache::default());
+                        evm_opts.fork_cache = Some(cache.clone());
+                        Some((evm_opts.vicinity()?, cache))
+                    }
+                    None => None,
+                };
+                Some(ReproBundle {
+                    dir,
+                    argv: std::env::args().collect(),
+                    cwd: std::env::current_dir()?,
+                    paths: repro_paths(&project),
+                    config: resolved_config.unwrap_or_default(),
+                    fork,
+                })
+            }
+            None => None,
+        };
+
+        let output_template = output_template
+            .map(|file| {
+                let template = std::fs::read_to_string(&file)
+                    .wrap_err_with(|| format!("failed to read {}", file.display()))?;
+                Template::parse(&template)
+                    .wrap_err_with(|| format!("invalid output template {}", file.display()))
+            })
+            .transpose()?;
+
+        if list {
+            return list_tests(
+                builder,
+                project,
+                evm_opts,
+                &mut filter,
+                json,
+                show_skipped,
+                allow_failure,
+            )
+        }
+
+        let cache_tests = match cache_tests {
+            true => Some(TestCacheSettings {
+                env: evm_opts.env.clone(),
+                fork_url: evm_opts.fork_url.clone(),
+                fork_block_number: evm_opts.fork_block_number,
+                initial_balance: evm_opts.initial_balance,
+                sender: evm_opts.sender,
+                ffi: evm_opts.ffi,
+                max_call_depth: evm_opts.max_call_depth,
+                evm_version,
+                require_setup,
+                isolate,
+                constructor_args: constructor_args.clone(),
+                timeout,
+                test_function_prefix: test_function_prefix.clone(),
+                test_contract_suffix: test_contract_suffix.clone(),
+                seed_accounts: match seed_accounts {
+                    Some(ref file) => Some(std::fs::read_to_string(file)?),
+                    None => None,
+                },
+                precompile_overrides: precompile_overrides.clone(),
+            }),
+            false => None,
+        };
+        let report = ReportOpts {
+            started,
+            run_id,
+            run_timestamp,
+            color,
+            json_config,
+            json_validate,
+            count_assertions,
+            json_stream,
+            allow_failure,
+            allow_failure_for,
+            fail_on_skip,
+            allow_rejects,
+            flaky_report,
+            isolate_contracts,
+            isolated_build,
+            isolated_results,
+            progress: !no_progress && !json && !json_stream && atty::is(atty::Stream::Stdout),
+            report_min_gas,
+            report_no_match_test,
+            trace_source_locations,
+            trace_out,
+            diff_traces,
+            emit_flamegraph: emit_flamegraph
+                .map(|values| (values[0].clone(), PathBuf::from(&values[1]))),
+            print_cheatcode_usage,
+            gas_report,
+            gas_report_percentiles,
+            quiet,
+            only_setup,
+            cache_tests,
+            coverage,
+            print_coverage_gaps,
+            print_storage_reads,
+            slowest,
+            print_log_counts,
+            print_revert_selectors_table,
+            print_deployment_order,
+            print_unused_test_helpers,
+            group_results_by_status,
+            print_creation_code_hash,
+            refund_quotient,
+            print_test_tree,
+            print_test_counts_summary,
+            print_immutables,
+            strict_memory_safety,
+            source_order: order == TestOrder::Source,
+            abort_on_panic,
+            trace_highlight,
+            trace_collapse_repeated,
+            trace_hide_setup,
+            labels,
+            bench,
+            bindings: export_bindings.map(|dir| (dir, bindings_lang)),
+            bindings_only,
+            deploy_size_threshold_warn,
+            fuzz_seed,
+            fuzz_shrink_to_file,
+            fuzz_inputs_as_json,
+            write_fuzz_regression,
+            output_template,
+            junit,
+            print_contract_sizes_csv,
+            repro_bundle,
+        };
+        if watch {
+            return watch_tests(&opts, builder, project, evm_opts, filter, report)
+        }
+        test(builder, project, evm_opts, filter, report)
+            .map(|outcome| outcome.on_failure_command(on_failure_command))
+    }
+}
+
+/// How often the sources are checked for changes in `--watch` mode
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the sources must be unchanged before the tests are rerun, so that a burst of saves
+/// only triggers a single run
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Runs the tests and reruns them with the same filter whenever a solidity file in the sources
+/// changed, recompiling the project every time. Only returns if the process is interrupted
+fn watch_tests(
+    opts: &BuildArgs,
+    builder: MultiContractRunnerBuilder,
+    project: Project,
+    evm_opts: EvmOpts,
+    filter: Filter,
+    mut report: ReportOpts,
+) -> eyre::Result<TestOutcome> {
+    let sources = project.paths.sources.clone();
+    let mut modified = sources_modified(&sources);
+    let mut project = Some(project);
+    loop {
+        let result = project
+            .take()
+            .map_or_else(|| opts.project(), Ok)
+            .and_then(|project| {
+                test(builder.clone(), project, evm_opts.clone(), filter.clone(), report.clone())
+            })
+            .and_then(|outcome| outcome.ensure_ok());
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
+        println!("\nwatching {} for changes...", sources.display());
+
+        modified = wait_for_changes(&sources, modified, WATCH_POLL_INTERVAL, WATCH_DEBOUNCE);
+
+        // clear the screen and move the cursor to the top left
+        print!("\x1B[2J\x1B[1;1H");
+        report.started = Instant::now();
+    }
+}
+
+/// Blocks until a solidity file in the sources was modified since `modified`, checking every
+/// `poll`, and then until no further changes were made for `debounce`. Returns the modification
+/// times of the sources by then
+fn wait_for_changes(
+    sources: &Path,
+    mut modified: BTreeMap<PathBuf, SystemTime>,
+    poll: Duration,
+    debounce: Duration,
+) -> BTreeMap<PathBuf, SystemTime> {
+    while sources_modified(sources) == modified {
+        std::thread::sleep(poll);
+    }
+    loop {
+        std::thread::sleep(debounce);
+        let current = sources_modified(sources);
+        if current == modified {
+            return current
+        }
+        modified = current;
+    }
+}
+
+/// Returns the last modification time of every solidity file in the sources
+fn sources_modified(sources: &Path) -> BTreeMap<PathBuf, SystemTime> {
+    ethers::solc::utils::source_files(sources)
+        .into_iter()
+        .filter_map(|file| {
+            let modified = std::fs::metadata(&file).and_then(|meta| meta.modified()).ok()?;
+            Some((file, modified))
+        })
+        .collect()
+}
+
+/// The file at the project root whose profiles hold defaults for the options of `forge test`
+const CONFIG_FILE: &str = "foundry.toml";
+
+/// The profile of the [`CONFIG_FILE`] which is used if no `--profile` is given
+const DEFAULT_PROFILE: &str = "default";
+
+impl TestArgs {
+    /// Returns the arguments of the options of the `[profile.<name>]` table of the `foundry.toml`
+    /// at the project root which are neither passed in `argv`, the command line these arguments
+    /// were parsed from, nor set via the environment.
+    ///
+    /// Without a `--profile`, the `default` profile is used if there is one
+    pub fn read_profile(&self, argv: &[String]) -> eyre::Result<Vec<String>> {
+        let file = self.opts.root().join(CONFIG_FILE);
+        let content = match std::fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(_) if self.profile.is_none() => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| format!("failed to read {}", file.display()))
+            }
+        };
+        let config = content
+            .parse::<toml::Value>()
+            .wrap_err_with(|| format!("failed to parse {}", file.display()))?;
+        let name = self.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+        let profile = match config.get("profile").and_then(|profiles| profiles.get(name)) {
+            Some(toml::Value::Table(profile)) => profile,
+            Some(_) => eyre::bail!("profile `{}` in {} is not a table", name, file.display()),
+            None if self.profile.is_none() => return Ok(Vec::new()),
+            None => eyre::bail!("there is no profile `{}` in {}", name, file.display()),
+        };
+        profile_args(&TestArgs::into_app(), profile, argv, self.color.enabled(), |var| {
+            std::env::var_os(var).is_some()
+        })
+        .wrap_err_with(|| format!("invalid profile `{}` in {}", name, file.display()))
+    }
+}
+
+/// Turns the options of a `foundry.toml` profile into command line arguments of `app`, skipping
+/// those which are already passed in `argv` or set via their environment variable, so that the
+/// options are taken from the defaults, the profile, the environment and the command line, in
+/// increasing order of precedence.
+///
+/// Keys are the long names of the options, in snake or kebab case. A flag is set by `true` or, if
+/// it can occur multiple times, by its number of occurrences, and an option is passed once for
+/// every value of an array. Unknown keys, e.g. of another version of forge, are skipped with a
+/// warning
+fn profile_args(
+    app: &clap::App,
+    profile: &toml::value::Table,
+    argv: &[String],
+    color: bool,
+    is_env_set: impl Fn(&std::ffi::OsStr) -> bool,
+) -> eyre::Result<Vec<String>> {
+    let mut args = Vec::new();
+    for (key, value) in profile {
+        let long = key.replace('_', "-");
+        let arg = match app.get_arguments().find(|arg| arg.get_long() == Some(long.as_str())) {
+            Some(arg) => arg,
+            None => {
+                eprintln!(
+                    "{} ignoring unknown option `{}` of the profile",
+                    paint(color, Colour::Yellow, "Warning:"),
+                    key
+                );
+                continue
+            }
+        };
+        let passed = argv.iter().any(|given| {
+            given
+                .strip_prefix("--")
+                .map_or(false, |given| given == long || given.starts_with(&format!("{}=", long))) ||
+                arg.get_short().map_or(false, |short| {
+                    !given.starts_with("--") &&
+                        given.strip_prefix('-').map_or(false, |given| given.starts_with(short))
+                })
+        });
+        if passed || arg.get_env().map_or(false, &is_env_set) {
+            continue
+        }
+        let flag = format!("--{}", long);
+        if !arg.is_set(clap::ArgSettings::TakesValue) {
+            let occurrences = match value {
+                toml::Value::Boolean(set) => *set as i64,
+                toml::Value::Integer(occurrences) => *occurrences,
+                _ => eyre::bail!("flag `{}` must be a boolean", key),
+            };
+            args.extend((0..occurrences).map(|_| flag.clone()));
+            continue
+        }
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => eyre::bail!("option `{}` must be a string, a number or a boolean", key),
+            };
+            args.push(flag.clone());
+            args.push(value);
+        }
+    }
+    Ok(args)
+}
+
+/// Parses a `--gas-report-percentiles` value, a percentile between 0 and 100
+fn parse_percentile(s: &str) -> eyre::Result<u8> {
+    let percentile = s.trim().parse::<u8>()?;
+    if percentile > 100 {
+        eyre::bail!("a percentile must be between 0 and 100")
+    }
+    Ok(percentile)
+}
+
+/// Parses the `--fuzz-runs` value, a fuzz test without any cases would pass vacuously
+fn parse_fuzz_runs(s: &str) -> eyre::Result<u32> {
+    let runs = s.parse::<u32>()?;
+    if runs == 0 {
+        eyre::bail!("the number of fuzz runs must be at least 1")
+    }
+    Ok(runs)
+}
+
+/// An inclusive range of values the integer parameter at `index` of a fuzz test is fuzzed with,
+/// given as `<test>:<index>=<lo>..<hi>`
+#[derive(Debug, Clone)]
+pub struct FuzzParamRange {
+    /// The name of the test function
+    test: String,
+    index: usize,
+    /// The bounds, negative ones, which are only valid for signed parameters, in two's complement
+    lo: U256,
+    hi: U256,
+}
+
+impl FromStr for FuzzParamRange {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            eyre::eyre!(
+                "fuzz parameter range `{}` is not of the form `<test>:<index>=<lo>..<hi>`",
+                s
+            )
+        };
+        let (target, range) = s.split_once('=').ok_or_else(invalid)?;
+        let (test, index) = target.split_once(':').ok_or_else(invalid)?;
+        let (lo, hi) = range.split_once("..").ok_or_else(invalid)?;
+        let (lo, hi) = (lo.trim(), hi.trim());
+        // only a negative bound makes this the range of a signed parameter, hex bounds with the
+        // high bit set are those of an unsigned one
+        let signed = lo.starts_with('-') || hi.starts_with('-');
+        let (lo, hi) = (parse_fuzz_bound(lo)?, parse_fuzz_bound(hi)?);
+        let ordered = if signed { I256::from_raw(lo) <= I256::from_raw(hi) } else { lo <= hi };
+        if !ordered {
+            eyre::bail!("the lower bound of fuzz parameter range `{}` exceeds its upper bound", s)
+        }
+        Ok(FuzzParamRange { test: test.trim().to_string(), index: index.trim().parse()?, lo, hi })
+    }
+}
+
+/// Parses a bound of a `--fuzz-param-range`, which is either decimal, possibly negative, or
+/// `0x`-prefixed hex
+fn parse_fuzz_bound(s: &str) -> eyre::Result<U256> {
+    Ok(match s.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16)?,
+        None => I256::from_dec_str(s)?.into_raw(),
+    })
+}
+
+/// Parses a `--label` value of the form `ADDRESS=NAME`
+fn parse_label(s: &str) -> eyre::Result<(Address, String)> {
+    let (addr, name) =
+        s.split_once('=').ok_or_else(|| eyre::eyre!("expected `ADDRESS=NAME`, got `{}`", s))?;
+    if name.is_empty() {
+        eyre::bail!("the label of {} is empty", addr)
+    }
+    Ok((addr.trim().parse()?, name.to_string()))
+}
+
+/// Parses a `--constructor-args` value of the form `CONTRACT=ARG`
+fn parse_constructor_arg(s: &str) -> eyre::Result<(String, String)> {
+    let (contract, arg) =
+        s.split_once('=').ok_or_else(|| eyre::eyre!("expected `CONTRACT=ARG`, got `{}`", s))?;
+    if contract.trim().is_empty() {
+        eyre::bail!("the contract of the constructor argument `{}` is empty", arg)
+    }
+    Ok((contract.trim().to_string(), arg.to_string()))
+}
+
+/// Parses a `--precompile-override` value of the form `ADDRESS=return:DATA`, `ADDRESS=revert` or
+/// `ADDRESS=revert:DATA`, where the data is hex encoded and the address may be given as the
+/// number of the precompile, e.g. `1` for ecrecover
+fn parse_precompile_override(s: &str) -> eyre::Result<(Address, PrecompileStub)> {
+    let (addr, behavior) = s.split_once('=').ok_or_else(|| {
+        eyre::eyre!("expected `ADDRESS=return:DATA` or `ADDRESS=revert`, got `{}`", s)
+    })?;
+    let addr = addr.trim();
+    let addr = match addr.parse::<u64>() {
+        Ok(index) => Address::from_low_u64_be(index),
+        Err(_) => addr.parse()?,
+    };
+    let (kind, data) = behavior.split_once(':').unwrap_or((behavior, ""));
+    let data = hex::decode(data.trim().trim_start_matches("0x"))?;
+    let stub = match kind.trim() {
+        "return" => PrecompileStub::Return(data),
+        "revert" => PrecompileStub::Revert(data),
+        kind => {
+            eyre::bail!("unknown precompile override `{}`, expected `return` or `revert`", kind)
+        }
+    };
+    Ok((addr, stub))
+}
+
+/// Parses the `--fuzz-seed` value, which is either decimal or `0x`-prefixed hex
+fn parse_fuzz_seed(s: &str) -> eyre::Result<u64> {
+    Ok(match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16)?,
+        None => s.parse()?,
+    })
+}
+
+/// Runs the tests of every contract matching the filter in a separate forge process with the
+/// same arguments and merges their results.
+///
+/// The processes build their runner from the compiled contracts of this one and write their
+/// results to a file each, which also holds what the json output leaves out
+fn isolated_test(
+    runner: &forge::MultiContractRunner,
+    filter: &Filter,
+) -> eyre::Result<BTreeMap<String, BTreeMap<String, forge::TestResult>>> {
+    let exe = std::env::current_exe()?;
+    let args =
+        std::env::args_os().skip(1).filter(|arg| arg != "--isolate-contracts").collect::<Vec<_>>();
+    let dir = std::env::temp_dir().join(format!("forge-isolated-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let build = dir.join("build.json");
+    // the arguments of counterexamples are decoded with the abis of the contracts they called
+    let abis = runner
+        .contracts
+        .values()
+        .map(|(abi, _)| abi)
+        .chain(runner.known_contracts.values().map(|(abi, _)| abi))
+        .collect::<Vec<_>>();
+
+    let run = || -> eyre::Result<_> {
+        write_isolated_build(runner, &build)?;
+        let mut results = BTreeMap::new();
+        let names = runner.contracts.keys().filter(|name| filter.matches_contract(name));
+        for (idx, name) in names.enumerate() {
+            let file = dir.join(format!("{}.json", idx));
+            let output = std::process::Command::new(&exe)
+                .args(&args)
+                .arg("--isolated-contract")
+                .arg(name)
+                .arg("--isolated-build")
+                .arg(&build)
+                .arg("--isolated-results")
+                .arg(&file)
+                .output()?;
+            let contract_results = read_isolated_results(&file, &abis).wrap_err_with(|| {
+                format!(
+                    "the process running the tests of {} did not write any results: {}",
+                    name,
+                    String::from_utf8_lossy(&output.stderr)
+                )
+            })?;
+            results.extend(contract_results);
+        }
+        Ok(results)
+    };
+    let results = run();
+    let _ = std::fs::remove_dir_all(&dir);
+    results
+}
+
+/// Writes the compiled contracts of the runner to the file, which the processes spawned by
+/// `--isolate-contracts` build their runner from
+fn write_isolated_build(runner: &forge::MultiContractRunner, file: &Path) -> eyre::Result<()> {
+    // the creation code may only be on disk
+    let contracts = runner
+        .contracts
+        .iter()
+        .map(|(name, (abi, _))| Ok((name, (abi, runner.creation_code(name)?))))
+        .collect::<eyre::Result<BTreeMap<_, _>>>()?;
+    let build = serde_json::json!({
+        "contracts": contracts,
+        "known_contracts": runner.known_contracts,
+        "creation_code_sizes": runner.creation_code_sizes,
+    });
+    std::fs::write(file, serde_json::to_vec(&build)?)?;
+    Ok(())
+}
+
+/// Builds the runner of a process spawned by `--isolate-contracts` from the compiled contracts
+/// the spawning process wrote to the file
+fn read_isolated_build(
+    builder: MultiContractRunnerBuilder,
+    file: &Path,
+    evm_opts: EvmOpts,
+) -> eyre::Result<forge::MultiContractRunner> {
+    let mut build: serde_json::Value = serde_json::from_slice(&std::fs::read(file)?)?;
+    Ok(builder.build_from_contracts(
+        serde_json::from_value(build["contracts"].take())?,
+        serde_json::from_value(build["known_contracts"].take())?,
+        serde_json::from_value(build["creation_code_sizes"].take())?,
+        evm_opts,
+    ))
+}
+
+/// Writes the results of a process spawned by `--isolate-contracts` to the file, together with
+/// the duration and the executed instructions of every test, which the json form of a result
+/// leaves out
+fn write_isolated_results(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    file: &Path,
+) -> eyre::Result<()> {
+    let results = results
+        .iter()
+        .map(|(contract_name, tests)| {
+            let tests = tests
+                .iter()
+                .map(|(name, result)| {
+                    let coverage = result
+                        .coverage
+                        .iter()
+                        .map(|((address, creation), ics)| (address, creation, ics))
+                        .collect::<Vec<_>>();
+                    let entry = serde_json::json!({
+                        "result": result,
+                        "duration_nanos": result.duration.as_nanos() as u64,
+                        "coverage": coverage,
+                    });
+                    (name, entry)
+                })
+                .collect::<BTreeMap<_, _>>();
+            (contract_name, tests)
+        })
+        .collect::<BTreeMap<_, _>>();
+    std::fs::write(file, serde_json::to_vec(&results)?)?;
+    Ok(())
+}
+
+/// Reads the results a process spawned by `--isolate-contracts` wrote to the file, the arguments
+/// of their counterexamples are decoded from the calldata with the abis
+fn read_isolated_results(
+    file: &Path,
+    abis: &[&Abi],
+) -> eyre::Result<BTreeMap<String, BTreeMap<String, forge::TestResult>>> {
+    let entries: BTreeMap<String, BTreeMap<String, serde_json::Value>> =
+        serde_json::from_slice(&std::fs::read(file)?)?;
+    let mut results = BTreeMap::new();
+    for (contract_name, tests) in entries {
+        let contract_results = results.entry(contract_name).or_insert_with(BTreeMap::new);
+        for (name, mut entry) in tests {
+            let mut result: forge::TestResult = serde_json::from_value(entry["result"].take())?;
+            result.duration =
+                Duration::from_nanos(serde_json::from_value(entry["duration_nanos"].take())?);
+            let coverage: Vec<(Address, bool, BTreeSet<usize>)> =
+                serde_json::from_value(entry["coverage"].take())?;
+            result.coverage = coverage
+                .into_iter()
+                .map(|(address, creation, ics)| ((address, creation), ics))
+                .collect();
+            if let Some(ref mut counterexample) = result.counterexample {
+                decode_counterexample_args(counterexample, abis);
+            }
+            for counterexample in result.unique_reverts.values_mut() {
+                decode_counterexample_args(counterexample, abis);
+            }
+            contract_results.insert(name, result);
+        }
+    }
+    Ok(results)
+}
+
+/// Decodes the arguments of the counterexample and of the calls of its sequence, of which only
+/// the calldata is serialized, with the function of the abis it was found with
+fn decode_counterexample_args(counterexample: &mut forge::CounterExample, abis: &[&Abi]) {
+    let func = abis
+        .iter()
+        .flat_map(|abi| abi.functions())
+        .find(|func| func.signature() == counterexample.signature);
+    if let (Some(func), Some(input)) = (func, counterexample.calldata.as_ref().get(4..)) {
+        counterexample.args = func.decode_input(input).unwrap_or_default();
+    }
+    for call in &mut counterexample.sequence {
+        decode_counterexample_args(call, abis);
+    }
+}
+
+/// Prints how many files were compiled and taken from the cache, with which solc and how long it
+/// took, together with the number of warnings, which are shown from verbosity 2 on
+fn print_compilation_summary(
+    compilation: &forge::CompilationSummary,
+    solc_version: Option<&semver::Version>,
+    verbosity: u8,
+) {
+    println!(
+        "Compiled {} files{}, {} cached, in {:.2?}, {} warnings",
+        compilation.compiled_files,
+        solc_version.map(|version| format!(" with solc {}", version)).unwrap_or_default(),
+        compilation.cached_files,
+        compilation.duration,
+        compilation.warnings.len()
+    );
+    if verbosity > 1 {
+        for warning in &compilation.warnings {
+            println!("{}\n", warning);
+        }
+    }
+}
+
+/// The json form of what the compilation of the project did
+fn compilation_json(compilation: &forge::CompilationSummary) -> serde_json::Value {
+    serde_json::json!({
+        "cached": compilation.cached,
+        "compiled_files": compilation.compiled_files,
+        "cached_files": compilation.cached_files,
+        "warnings": compilation.warnings,
+        "duration_ms": compilation.duration.as_millis() as u64,
+    })
+}
+
+/// The compiler configuration the tests were built with.
+///
+/// With solc auto detection, every source is compiled with a version matching its pragma and
+/// `solc_version` is only the version of the default `solc`
+fn compiler_info<A: ArtifactOutput>(project: &Project<A>) -> serde_json::Value {
+    let settings = &project.solc_config.settings;
+    serde_json::json!({
+        "solc_version": project.solc.version().ok().map(|version| version.to_string()),
+        "auto_detect": project.auto_detect,
+        "optimizer": settings.optimizer,
+        "evm_version": settings.evm_version,
+    })
+}
+
+/// Prints the test contracts matching the filter grouped by the file they are declared in,
+/// together with their tests and the parameters of fuzz tests
+fn print_tests_tree(
+    runner: &forge::MultiContractRunner,
+    filter: &Filter,
+    root: &Path,
+    files: &BTreeMap<String, PathBuf>,
+    color: bool,
+) {
+    let mut tree: BTreeMap<String, BTreeMap<&str, Vec<String>>> = BTreeMap::new();
+    for (name, (abi, _)) in
+        runner.contracts.iter().filter(|(name, _)| filter.matches_contract(name))
+    {
+        let tests = abi
+            .functions()
+            .filter(|func| {
+                func.name.starts_with(&runner.test_function_prefix) &&
+                    filter.matches_contract_test(name, &func.name)
+            })
+            .map(|func| {
+                if func.inputs.is_empty() {
+                    return format!("{}()", func.name)
+                }
+                let params = func
+                    .inputs
+                    .iter()
+                    .map(|param| format!("{} {}", param.kind, param.name))
+                    .collect::<Vec<_>>();
+                format!(
+                    "{}({}) {}",
+                    func.name,
+                    params.join(", "),
+                    paint(color, Colour::Cyan, "[fuzz]")
+                )
+            })
+            .collect::<Vec<_>>();
+        if tests.is_empty() {
+            continue
+        }
+        let contract = name.rsplit(':').next().unwrap_or(name);
+        let file = files.get(contract).map_or_else(
+            || "<unknown file>".to_string(),
+            |file| file.strip_prefix(root).unwrap_or(file).display().to_string(),
+        );
+        tree.entry(file).or_default().insert(contract, tests);
+    }
+
+    for (file, contracts) in &tree {
+        println!("{}", file);
+        for (i, (contract, tests)) in contracts.iter().enumerate() {
+            let last_contract = i + 1 == contracts.len();
+            println!("{} {}", if last_contract { "└──" } else { "├──" }, contract);
+            let indent = if last_contract { "    " } else { "│   " };
+            for (j, test) in tests.iter().enumerate() {
+                let branch = if j + 1 == tests.len() { "└──" } else { "├──" };
+                println!("{}{} {}", indent, branch, test);
+            }
+        }
+    }
+    println!();
+}
+
+/// The result of a single test
+#[derive(Debug, Clone)]
+pub struct Test {
+    /// The signature of the test
+    pub signature: String,
+    /// Result of the executed solidity test
+    pub result: forge::TestResult,
+}
+
+impl Test {
+    pub fn gas_used(&self) -> u64 {
+        self.result.gas_used
+    }
+}
+
+/// Represents the bundled results of all tests
+pub struct TestOutcome {
+    /// Whether failures are allowed
+    allow_failure: bool,
+    /// The `Contract::test` signatures of the tests which are expected to fail
+    allow_failure_for: Vec<regex::Regex>,
+    /// Whether running no tests at all is an error
+    fail_on_skip: bool,
+    /// Whether tests which gave up after too many rejected inputs are not an error
+    allow_rejects: bool,
+    /// Whether tests failing with a solidity Panic are hard errors
+    abort_on_panic: bool,
+    /// The shell command to run if the test run fails
+    on_failure_command: Option<String>,
+    /// All test results `contract -> (test name -> TestResult)`
+    pub results: BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+}
+
+impl TestOutcome {
+    fn new(
+        results: BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+        allow_failure: bool,
+    ) -> Self {
+        Self {
+            results,
+            allow_failure,
+            allow_failure_for: Vec::new(),
+            fail_on_skip: false,
+            allow_rejects: false,
+            abort_on_panic: false,
+            on_failure_command: None,
+        }
+    }
+
+    /// Sets the `Contract::test` signatures of the tests which are expected to fail
+    #[must_use]
+    fn allow_failure_for(mut self, allow_failure_for: Vec<regex::Regex>) -> Self {
+        self.allow_failure_for = allow_failure_for;
+        self
+    }
+
+    /// Sets whether running no tests at all is an error
+    #[must_use]
+    fn fail_on_skip(mut self, fail_on_skip: bool) -> Self {
+        self.fail_on_skip = fail_on_skip;
+        self
+    }
+
+    /// Sets whether tests which gave up after too many rejected inputs are not an error
+    #[must_use]
+    fn allow_rejects(mut self, allow_rejects: bool) -> Self {
+        self.allow_rejects = allow_rejects;
+        self
+    }
+
+    /// Sets whether tests failing with a solidity Panic are hard errors
+    #[must_use]
+    fn abort_on_panic(mut self, abort_on_panic: bool) -> Self {
+        self.abort_on_panic = abort_on_panic;
+        self
+    }
+
+    /// Sets the shell command to run if the test run fails
+    #[must_use]
+    fn on_failure_command(mut self, on_failure_command: Option<String>) -> Self {
+        self.on_failure_command = on_failure_command;
+        self
+    }
+
+    /// Runs the `--on-failure-command`, if any, with the number of failing tests in the
+    /// `FORGE_FAILURES` environment variable
+    pub fn run_on_failure_command(&self) -> eyre::Result<()> {
+        if let Some(ref cmd) = self.on_failure_command {
+            let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+            let status = std::process::Command::new(shell)
+                .arg(flag)
+                .arg(cmd)
+                .env("FORGE_FAILURES", self.failures().count().to_string())
+                .status()?;
+            if !status.success() {
+                eprintln!("on-failure command `{}` exited with {}", cmd, status);
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterator over all tests which failed with a solidity Panic
+    pub fn panics(&self) -> impl Iterator<Item = (&String, &forge::TestResult)> {
+        self.failures().filter(|(_, t)| {
+            t.reason.as_deref().map(foundry_utils::is_panic_reason).unwrap_or_default()
+        })
+    }
+
+    /// Returns `true` if panics are hard errors and any test failed with a solidity Panic
+    pub fn has_fatal_panics(&self) -> bool {
+        self.abort_on_panic && self.panics().next().is_some()
+    }
+
+    /// Iterator over all succeeding tests and their names
+    pub fn successes(&self) -> impl Iterator<Item = (&String, &forge::TestResult)> {
+        self.tests().filter(|(_, t)| t.success)
+    }
+
+    /// Iterator over all failing tests and their names, without those which gave up after too
+    /// many rejected inputs
+    pub fn failures(&self) -> impl Iterator<Item = (&String, &forge::TestResult)> {
+        self.tests().filter(|(_, t)| !t.success && !t.rejected)
+    }
+
+    /// Iterator over all fuzz and invariant tests which gave up after too many rejected inputs
+    pub fn rejections(&self) -> impl Iterator<Item = (&String, &forge::TestResult)> {
+        self.tests().filter(|(_, t)| t.rejected)
+    }
+
+    /// Iterator over all tests by their `Contract::test` signature
+    fn signatures(&self) -> impl Iterator<Item = (String, &forge::TestResult)> {
+        self.results.iter().flat_map(|(contract_name, tests)| {
+            let contract_name = contract_name.rsplit(':').next().unwrap_or(contract_name);
+            tests.iter().map(move |(name, result)| (format!("{}::{}", contract_name, name), result))
+        })
+    }
+
+    /// Returns `true` if the test with the `Contract::test` signature is expected to fail
+    fn is_expected_failure(&self, signature: &str) -> bool {
+        self.allow_failure_for.iter().any(|re| re.is_match(signature))
+    }
+
+    /// Iterator over all tests which are expected to fail by `--allow-failure-for`, by their
+    /// `Contract::test` signature
+    fn expected_failures(&self) -> impl Iterator<Item = (String, &forge::TestResult)> {
+        self.signatures().filter(move |(signature, _)| self.is_expected_failure(signature))
+    }
+
+    /// Iterator over all failing tests which are not expected to fail by `--allow-failure-for`,
+    /// by their `Contract::test` signature
+    fn unexpected_failures(&self) -> impl Iterator<Item = (String, &forge::TestResult)> {
+        self.signatures().filter(move |(signature, result)| {
+            !result.success && !result.rejected && !self.is_expected_failure(signature)
+        })
+    }
+
+    /// Iterator over all tests and their names
+    pub fn tests(&self) -> impl Iterator<Item = (&String, &forge::TestResult)> {
+        self.results.values().flat_map(|tests| tests.iter())
+    }
+
+    pub fn into_tests(self) -> impl Iterator<Item = Test> {
+        self.results
+            .into_values()
+            .flat_map(|tests| tests.into_iter())
+            .map(|(name, result)| Test { signature: name, result })
+    }
+
+    /// Checks if there are any failures and failures are disallowed
+    pub fn ensure_ok(&self) -> eyre::Result<()> {
+        if self.fail_on_skip && self.tests().next().is_none() {
+            eyre::bail!("No tests matched the provided filters")
+        }
+        if !self.allow_failure {
+            let failures = self.unexpected_failures().count();
+            if failures > 0 {
+                let successes = self.successes().count();
+                let expected = self.failures().count() - failures;
+                eyre::bail!(
+                    "Encountered a total of {} failing tests, {} tests succeeded, {} tests failed as expected",
+                    failures,
+                    successes,
+                    expected
+                );
+            }
+            if !self.allow_rejects {
+                let rejected = self.rejections().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+                if !rejected.is_empty() {
+                    eyre::bail!(
+                        "{} tests gave up after too many rejected inputs: {}",
+                        rejected.len(),
+                        rejected.join(", ")
+                    );
+                }
+            }
+            let passed = self
+                .expected_failures()
+                .filter(|(_, result)| result.success)
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>();
+            if !passed.is_empty() {
+                eyre::bail!(
+                    "{} tests are expected to fail by --allow-failure-for but passed: {}",
+                    passed.len(),
+                    passed.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prints all tests matching the filter without running them
+fn list_tests<A: ArtifactOutput + Clone + 'static>(
+    builder: MultiContractRunnerBuilder,
+    project: Project<A>,
+    evm_opts: EvmOpts,
+    filter: &mut Filter,
+    json: bool,
+    show_skipped: bool,
+    allow_failure: bool,
+) -> eyre::Result<TestOutcome> {
+    let (runner, _) = build_runner(builder, project, evm_opts, filter, false, json)?;
+    let contracts = runner
+        .contracts
+        .iter()
+        .map(|(name, (abi, _))| {
+            let contract = filter.contract_reasons(name);
+            let tests = abi
+                .functions()
+                .filter(|func| func.name.starts_with(&runner.test_function_prefix))
+                .map(|func| {
+                    let mut reasons = contract.clone();
+                    reasons.extend(filter.test_reasons(Some(name), &func.name));
+                    (func, reasons)
+                })
+                .filter(|(_, reasons)| show_skipped || reasons.matches())
+                .collect::<Vec<_>>();
+            (name, tests)
+        })
+        .filter(|(_, tests)| !tests.is_empty())
+        .collect::<BTreeMap<_, _>>();
+
+    if json {
+        let contracts = contracts
+            .iter()
+            .map(|(name, tests)| {
+                let tests = tests
+                    .iter()
+                    .map(|(func, reasons)| {
+                        let params = func
+                            .inputs
+                            .iter()
+                            .map(|param| {
+                                serde_json::json!({ "name": param.name, "type": param.kind.to_string() })
+                            })
+                            .collect::<Vec<_>>();
+                        serde_json::json!({
+                            "signature": func.signature(),
+                            "fuzz": !func.inputs.is_empty(),
+                            "params": params,
+                            "included": reasons.matches(),
+                            "included_by": reasons.included_by,
+                            "excluded_by": reasons.excluded_by,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                (name.to_string(), tests)
+            })
+            .collect::<BTreeMap<_, _>>();
+        println!("{}", serde_json::to_string(&serde_json::json!({ "contracts": contracts }))?);
+    } else {
+        for (name, tests) in &contracts {
+            println!("{}", name);
+            for (func, reasons) in tests {
+                if reasons.matches() {
+                    println!("  {}", func.signature());
+                } else {
+                    println!(
+                        "  {} (skipped by {})",
+                        func.signature(),
+                        reasons.excluded_by.join(", ")
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(TestOutcome::new(Default::default(), allow_failure))
+}
+
+/// Options which control how the test results are reported
+#[derive(Clone)]
+struct ReportOpts {
+    /// When the command started, the setup before compiling is timed from this
+    started: Instant,
+    /// The random UUID of this invocation in the json output
+    run_id: String,
+    /// When this invocation started in unix milliseconds, for the json output
+    run_timestamp: u64,
+    /// Whether the output is colored
+    color: bool,
+    /// The resolved configuration to embed in the json output, if json output is requested
+    json_config: Option<serde_json::Value>,
+    /// Whether every test result is printed as a separate json record
+    json_stream: bool,
+    /// Whether the json output is validated against its schema
+    json_validate: bool,
+    /// Whether the assertions every test executed are counted for the json output
+    count_assertions: bool,
+    /// Whether failures are allowed
+    allow_failure: bool,
+    /// The `Contract::test` signatures of the tests which are expected to fail
+    allow_failure_for: Vec<regex::Regex>,
+    /// Whether running no tests at all is an error
+    fail_on_skip: bool,
+    /// Whether tests which gave up after too many rejected inputs are not an error
+    allow_rejects: bool,
+    /// Whether to print the tests with intermittent outcomes in their recorded history
+    flaky_report: bool,
+    /// Whether the tests of every contract are run in a separate process
+    isolate_contracts: bool,
+    /// The compiled contracts the runner is built from in a process spawned by
+    /// `--isolate-contracts`
+    isolated_build: Option<PathBuf>,
+    /// The file a process spawned by `--isolate-contracts` writes its results to
+    isolated_results: Option<PathBuf>,
+    /// Whether a progress bar of the run is shown
+    progress: bool,
+    /// The gas below which tests are not reported
+    report_min_gas: Option<u64>,
+    /// The tests which are not reported
+    report_no_match_test: Option<regex::Regex>,
+    /// Whether trace frames are annotated with their source location
+    trace_source_locations: bool,
+    /// The directory to write the call traces of every test to
+    trace_out: Option<PathBuf>,
+    /// The directory of baseline call traces to compare against
+    diff_traces: Option<PathBuf>,
+    /// The test whose call traces are written as a gas flamegraph, and the svg file
+    emit_flamegraph: Option<(String, PathBuf)>,
+    /// Whether to print the cheatcodes invoked by each test
+    print_cheatcode_usage: bool,
+    /// Whether to report the gas used by the calls to every function of the non-test contracts
+    gas_report: bool,
+    /// The percentiles of the gas of the calls to every function in the gas report
+    gas_report_percentiles: Vec<u8>,
+    /// Whether only the failing tests and the summary are printed, without the compilation
+    /// progress and summary
+    quiet: bool,
+    /// Whether only the `setUp()` of every test contract was run, with one result per contract
+    only_setup: bool,
+    /// The settings the passing results of unchanged test contracts are reused from the last run
+    /// with, if they are
+    cache_tests: Option<TestCacheSettings>,
+    /// Whether the instructions executed by the tests are mapped to source lines, for which the
+    /// project is compiled without the cache
+    coverage: bool,
+    /// Whether to print the functions of the compiled non-test contracts which were never called
+    print_coverage_gaps: bool,
+    /// Whether to print the storage accesses of each test
+    print_storage_reads: bool,
+    /// The number of slowest tests to print
+    slowest: Option<usize>,
+    /// Whether to print the number of logs of each test
+    print_log_counts: bool,
+    /// Whether to print the undecoded revert selectors and the tests which reverted with them
+    print_revert_selectors_table: bool,
+    /// Whether to print the contracts deployed by every `setUp()`, in order
+    print_deployment_order: bool,
+    /// Whether to print the helper functions of the test contracts which no test called
+    print_unused_test_helpers: bool,
+    /// Whether the human readable results are grouped by status instead of by contract
+    group_results_by_status: bool,
+    /// Whether to print the hash of the creation bytecode of each test contract
+    print_creation_code_hash: bool,
+    /// The refund quotient of the evm version, if the gas refunds of each test are printed
+    refund_quotient: Option<u64>,
+    /// Whether to print the tree of files, contracts and tests before running them
+    print_test_tree: bool,
+    /// Whether to print the number of matching tests of every kind before running them
+    print_test_counts_summary: bool,
+    /// Whether to print the immutables of every test contract
+    print_immutables: bool,
+    /// Whether the run fails if a matched test contract contains an inline assembly block which
+    /// is not annotated as memory-safe
+    strict_memory_safety: bool,
+    /// Whether the tests are run one after the other in the order they are declared in
+    source_order: bool,
+    /// Whether tests failing with a solidity Panic are hard errors
+    abort_on_panic: bool,
+    /// Trace frames matching this are emphasized
+    trace_highlight: Option<regex::Regex>,
+    /// Whether consecutive identical calls are folded in traces
+    trace_collapse_repeated: bool,
+    /// Whether the traces of `setUp()` are omitted
+    trace_hide_setup: bool,
+    /// The names traces use for the contracts at these addresses
+    labels: Vec<(Address, String)>,
+    /// How often every test is run to gather gas statistics
+    bench: Option<usize>,
+    /// The directory and language of the bindings to generate after the build
+    bindings: Option<(PathBuf, BindingsLang)>,
+    /// Whether to stop after generating the bindings
+    bindings_only: bool,
+    /// The percentage of the contract size limit above which contracts are warned about
+    deploy_size_threshold_warn: u64,
+    /// The seed the fuzzer was created with
+    fuzz_seed: u64,
+    /// The file to write the shrink history of failing fuzz tests to
+    fuzz_shrink_to_file: Option<PathBuf>,
+    /// The file to write the inputs of every fuzz case to
+    fuzz_inputs_as_json: Option<PathBuf>,
+    /// The directory to write regression tests for failing fuzz tests to
+    write_fuzz_regression: Option<PathBuf>,
+    /// The template the test results are rendered with instead of the built-in report
+    output_template: Option<Template>,
+    /// The file to write a JUnit XML report of the results to
+    junit: Option<PathBuf>,
+    /// The file to write the code sizes of all contracts to as CSV
+    print_contract_sizes_csv: Option<PathBuf>,
+    /// Where and what to record to rerun the tests with `--from-repro-bundle`
+    repro_bundle: Option<ReproBundle>,
+}
+
+/// The maximum runtime code size of a contract, see EIP-170
+const CONTRACT_SIZE_LIMIT: usize = 24576;
+
+/// Warns about every contract whose runtime code exceeds `percent` of the contract size limit
+fn warn_contract_sizes(contracts: &BTreeMap<String, (Abi, Vec<u8>)>, percent: u64, color: bool) {
+    let threshold = CONTRACT_SIZE_LIMIT as u64 * percent / 100;
+    for (name, (_, code)) in contracts {
+        let size = code.len() as u64;
+        if size > threshold {
+            println!(
+                "{} {} is {} bytes, {:.1}% of the {} bytes contract size limit",
+                paint(color, Colour::Yellow, "Warning:"),
+                name,
+                size,
+                size as f64 * 100.0 / CONTRACT_SIZE_LIMIT as f64,
+                CONTRACT_SIZE_LIMIT
+            );
+        }
+    }
+}
+
+/// Writes a `contract,runtime_size,creation_size,headroom` row for every contract to `file`, the
+/// headroom being the bytes left until the contract size limit, negative if it is exceeded
+fn write_contract_sizes_csv(
+    contracts: &BTreeMap<String, (Abi, Vec<u8>)>,
+    creation_sizes: &BTreeMap<String, usize>,
+    file: &Path,
+) -> eyre::Result<()> {
+    let mut csv = String::from("contract,runtime_size,creation_size,headroom\n");
+    for (name, (_, code)) in contracts {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            name,
+            code.len(),
+            creation_sizes.get(name).copied().unwrap_or_default(),
+            CONTRACT_SIZE_LIMIT as i64 - code.len() as i64
+        ));
+    }
+    std::fs::write(file, csv)
+        .wrap_err_with(|| format!("failed to write the contract sizes to {}", file.display()))
+}
+
+/// Prints the keccak256 of the creation bytecode of every test contract matched by the filter
+fn print_creation_code_hashes(
+    runner: &forge::MultiContractRunner,
+    filter: &Filter,
+) -> eyre::Result<()> {
+    println!("Creation code hashes:");
+    for name in runner.contracts.keys().filter(|name| filter.matches_contract(name)) {
+        let code = runner.creation_code(name)?;
+        println!("  {}: 0x{}", name, hex::encode(ethers::utils::keccak256(code)));
+    }
+    Ok(())
+}
+
+/// Writes a bindings file for every contract with a non-empty abi
+fn write_bindings(
+    contracts: &BTreeMap<String, (Abi, Vec<u8>)>,
+    dir: &Path,
+    lang: BindingsLang,
+) -> eyre::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (name, (abi, _)) in contracts {
+        if abi.functions.is_empty() && abi.events.is_empty() {
+            continue
+        }
+        // names of contracts that exist in multiple files are prefixed with their path
+        let name = name.rsplit(':').next().unwrap_or(name);
+        match lang {
+            BindingsLang::Rust => {
+                Abigen::new(name, serde_json::to_string(abi)?)?
+                    .generate()?
+                    .write_to_file(dir.join(format!("{}.rs", name.to_lowercase())))?;
+            }
+            BindingsLang::Ts => {
+                std::fs::write(dir.join(format!("{}.ts", name)), ts_bindings(name, abi)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns a TypeScript module exporting the contract's abi and an interface of its functions
+fn ts_bindings(name: &str, abi: &Abi) -> eyre::Result<String> {
+    let mut out = format!(
+        "// generated by `forge test --export-bindings`\n\nexport const {}Abi = {} as const;\n\nexport interface {} {{\n",
+        name,
+        serde_json::to_string_pretty(abi)?,
+        name
+    );
+    for func in abi.functions() {
+        let params = func
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let name =
+                    if param.name.is_empty() { format!("arg{}", i) } else { param.name.clone() };
+                format!("{}: {}", name, ts_type(&param.kind))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let output = match func.outputs.as_slice() {
+            [] => "void".to_string(),
+            [output] => ts_type(&output.kind),
+            outputs => format!(
+                "[{}]",
+                outputs.iter().map(|output| ts_type(&output.kind)).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        out.push_str(&format!("  {}({}): Promise<{}>;\n", func.name, params, output));
+    }
+    out.push_str("}\n");
+    Ok(out)
+}
+
+/// The TypeScript type a solidity type is represented with
+fn ts_type(kind: &ParamType) -> String {
+    match kind {
+        ParamType::Address | ParamType::Bytes | ParamType::FixedBytes(_) | ParamType::String => {
+            "string".to_string()
+        }
+        ParamType::Int(_) | ParamType::Uint(_) => "bigint".to_string(),
+        ParamType::Bool => "boolean".to_string(),
+        ParamType::Array(kind) | ParamType::FixedArray(kind, _) => format!("{}[]", ts_type(kind)),
+        ParamType::Tuple(kinds) => {
+            format!("[{}]", kinds.iter().map(ts_type).collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+/// Writes the inputs tried while shrinking every failing fuzz test to `file`
+fn write_shrink_history(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    file: &Path,
+) -> eyre::Result<()> {
+    let history = results
+        .iter()
+        .map(|(contract_name, tests)| {
+            let tests = tests
+                .iter()
+                .filter(|(_, result)| !result.shrink_history.is_empty())
+                .map(|(name, result)| (name, &result.shrink_history))
+                .collect::<BTreeMap<_, _>>();
+            (contract_name, tests)
+        })
+        .filter(|(_, tests)| !tests.is_empty())
+        .collect::<BTreeMap<_, _>>();
+    std::fs::write(file, serde_json::to_string_pretty(&history)?)?;
+    Ok(())
+}
+
+/// Writes a `<contract>Regression.sol` file to `dir` for every contract with failing fuzz tests.
+///
+/// The generated contract inherits from the test contract and gets a `testRegression_<test>`
+/// function for every failing fuzz test, `testFailRegression_<test>` for `testFail` ones, which
+/// calls the test with its shrunk counterexample so that it reproduces deterministically. Those of
+/// invariant tests make the calls of the broken sequence before checking the invariant
+fn write_fuzz_regressions(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    contract_files: &BTreeMap<String, PathBuf>,
+    dir: &Path,
+    json_output: bool,
+) -> eyre::Result<()> {
+    for (contract_name, tests) in results {
+        // artifact names look like `Greeter.json:GreeterTest`
+        let contract_name = contract_name.rsplit(':').next().unwrap_or(contract_name);
+        let mut functions = Vec::new();
+        let mut names = BTreeSet::new();
+        for (name, result) in tests {
+            let counterexample = match result.counterexample {
+                Some(ref counterexample) if !result.success && result.is_fuzz() => counterexample,
+                _ => continue,
+            };
+            let test_name = name.split('(').next().unwrap_or(name);
+            // the calls of an invariant test's sequence are made before the invariant is checked
+            let calls = counterexample
+                .sequence
+                .iter()
+                .map(|call| Some(format!("this.{}", solidity_call(call)?)))
+                .chain(std::iter::once(solidity_call(counterexample)))
+                .collect::<Option<Vec<_>>>();
+            let calls = match calls {
+                Some(calls) => calls,
+                None => {
+                    if !json_output {
+                        println!(
+                            "Skipping fuzz regression for {}::{}: only value type parameters can be pinned",
+                            contract_name, name
+                        );
+                    }
+                    continue
+                }
+            };
+            let prefix = if test_name.starts_with("testFail") { "testFail" } else { "test" };
+            let mut regression = format!("{}Regression_{}", prefix, test_name);
+            // overloaded tests share their name
+            if !names.insert(regression.clone()) {
+                regression = format!("{}_{}", regression, names.len());
+                names.insert(regression.clone());
+            }
+            let body = calls.iter().map(|call| format!("        {};\n", call)).collect::<String>();
+            functions.push(format!("    function {}() public {{\n{}    }}\n", regression, body));
+        }
+        if functions.is_empty() {
+            continue
+        }
+        let source = contract_files.get(contract_name).ok_or_else(|| {
+            eyre::eyre!("the source file of {} is not known, it can not be imported", contract_name)
+        })?;
+        std::fs::create_dir_all(dir)?;
+        let dir = std::fs::canonicalize(dir)?;
+        let pragma = std::fs::read_to_string(source)?
+            .lines()
+            .map(str::trim)
+            .find(|line| line.starts_with("pragma solidity"))
+            .map(str::to_string)
+            .unwrap_or_else(|| "pragma solidity >=0.6.0;".to_string());
+        let contents = format!(
+            "// SPDX-License-Identifier: UNLICENSED\n{}\n\nimport \"{}\";\n\ncontract {}Regression is {} {{\n{}}}\n",
+            pragma,
+            relative_import(&dir, source),
+            contract_name,
+            contract_name,
+            functions.join("\n")
+        );
+        let file = dir.join(format!("{}Regression.sol", contract_name));
+        std::fs::write(&file, contents)
+            .wrap_err_with(|| format!("failed to write the fuzz regression {}", file.display()))?;
+        if !json_output {
+            println!("Wrote fuzz regressions for {} to {}", contract_name, file.display());
+        }
+    }
+    Ok(())
+}
+
+/// The solidity call of the counterexample's function with its arguments, e.g.
+/// `testFuzz(uint8(1))`, `None` if one of them is not of a value type
+fn solidity_call(counterexample: &forge::CounterExample) -> Option<String> {
+    let name = counterexample.signature.split('(').next()?;
+    let args = counterexample
+        .args
+        .iter()
+        .zip(&counterexample.inputs)
+        .map(|(arg, input)| solidity_literal(arg, &input.ty))
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!("{}({})", name, args.join(", ")))
+}
+
+/// The solidity literal of a value type argument of the solidity type `ty`, `None` for arrays and
+/// tuples
+fn solidity_literal(token: &ethers::abi::Token, ty: &str) -> Option<String> {
+    use ethers::abi::Token;
+    Some(match token {
+        Token::Address(addr) => ethers::utils::to_checksum(addr, None),
+        Token::Uint(num) => format!("{}({})", ty, num),
+        Token::Int(num) => format!("{}({})", ty, I256::from_raw(*num)),
+        Token::Bool(b) => b.to_string(),
+        Token::FixedBytes(bytes) => format!("bytes{}(hex\"{}\")", bytes.len(), hex::encode(bytes)),
+        Token::Bytes(bytes) => format!("hex\"{}\"", hex::encode(bytes)),
+        Token::String(s) => format!("string(hex\"{}\")", hex::encode(s)),
+        Token::FixedArray(_) | Token::Array(_) | Token::Tuple(_) => return None,
+    })
+}
+
+/// The path of `file` relative to `dir` as used in an import, both being absolute
+fn relative_import(dir: &Path, file: &Path) -> String {
+    let common = dir.components().zip(file.components()).take_while(|(a, b)| a == b).count();
+    let up = dir.components().count() - common;
+    let mut path = PathBuf::new();
+    if up == 0 {
+        path.push(".");
+    }
+    for _ in 0..up {
+        path.push("..");
+    }
+    path.extend(file.components().skip(common));
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Writes the inputs of every fuzz case as `{test, inputs, reverted, gas}` records, where `test` is
+/// `<contract>::<signature>` and `inputs` are the arguments formatted as strings. The
+/// counterexample of a failing test is recorded as reverted, without gas
+fn write_fuzz_inputs(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    file: &Path,
+) -> eyre::Result<()> {
+    let mut records = Vec::new();
+    for (contract_name, tests) in results {
+        for (name, result) in tests {
+            let cases = match result.kind {
+                forge::TestKind::Fuzz(ref cases) => cases,
+                _ => continue,
+            };
+            // tests whose signature can not be parsed back into a function are not exported
+            let func = match foundry_utils::get_func(&format!("function {}", name)) {
+                Ok(func) => func,
+                Err(_) => continue,
+            };
+            let test = format!("{}::{}", contract_name, name);
+            for case in cases.cases() {
+                let args = func.decode_input(&case.calldata.as_ref()[4..])?;
+                records.push(serde_json::json!({
+                    "test": test,
+                    "inputs": format_fuzz_args(&args),
+                    "reverted": case.reverted,
+                    "gas": case.gas,
+                }));
+            }
+            if let Some(ref counterexample) = result.counterexample {
+                records.push(serde_json::json!({
+                    "test": test,
+                    "inputs": format_fuzz_args(&counterexample.args),
+                    "reverted": true,
+                    "gas": null,
+                }));
+            }
+        }
+    }
+    std::fs::write(file, serde_json::to_string_pretty(&records)?)?;
+    Ok(())
+}
+
+/// Formats fuzz arguments so that they can be parsed again with `foundry_utils::encode_args`
+fn format_fuzz_args(args: &[ethers::abi::Token]) -> Vec<String> {
+    args.iter()
+        .map(|arg| match arg {
+            ethers::abi::Token::String(s) => s.clone(),
+            arg => foundry_utils::format_token(arg),
+        })
+        .collect()
+}
+
+/// Reads the records written by `write_fuzz_inputs`, or produced by an external fuzzer, and
+/// encodes their inputs as calldata by contract and test signature
+fn read_fuzz_inputs(file: &Path) -> eyre::Result<BTreeMap<String, BTreeMap<String, Vec<Bytes>>>> {
+    let records: Vec<serde_json::Value> = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+    let mut inputs: BTreeMap<String, BTreeMap<String, Vec<Bytes>>> = BTreeMap::new();
+    for record in records {
+        let test = record["test"].as_str().unwrap_or_default();
+        let (contract_name, name) = test
+            .split_once("::")
+            .ok_or_else(|| eyre::eyre!("invalid fuzz input test `{}`", test))?;
+        let args = record["inputs"]
+            .as_array()
+            .map(|args| {
+                args.iter()
+                    .map(|arg| arg.as_str().map(str::to_string).unwrap_or_else(|| arg.to_string()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let func = foundry_utils::get_func(&format!("function {}", name))?;
+        let calldata = foundry_utils::encode_args(&func, &args)?;
+        inputs
+            .entry(contract_name.to_string())
+            .or_default()
+            .entry(name.to_string())
+            .or_default()
+            .push(calldata.into());
+    }
+    Ok(inputs)
+}
+
+/// Reads the accounts to fund from a file with one `address=balance` line per account, the
+/// balance in wei is either decimal or `0x`-prefixed hex. Empty lines and `#` comments are skipped
+fn read_seed_accounts(file: &Path) -> eyre::Result<BTreeMap<Address, U256>> {
+    let mut accounts = BTreeMap::new();
+    for (idx, line) in std::fs::read_to_string(file)?.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue
+        }
+        let (address, balance) = line.split_once('=').ok_or_else(|| {
+            eyre::eyre!("{}:{}: expected `address=balance`", file.display(), idx + 1)
+        })?;
+        let address = address.trim().parse::<Address>().map_err(|err| {
+            eyre::eyre!("{}:{}: invalid address: {}", file.display(), idx + 1, err)
+        })?;
+        let balance = balance.trim();
+        let balance = match balance.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).ok(),
+            None => U256::from_dec_str(balance).ok(),
+        }
+        .ok_or_else(|| {
+            eyre::eyre!("{}:{}: invalid balance `{}`", file.display(), idx + 1, balance)
+        })?;
+        accounts.insert(address, balance);
+    }
+    Ok(accounts)
+}
+
+/// Builds the test runner, compiling the project without the cache if `needs_output` or if the
+/// filter needs the compiler output, and resolves the contracts the filter matches from it
+fn build_runner<A: ArtifactOutput + Clone + 'static>(
+    builder: MultiContractRunnerBuilder,
+    project: Project<A>,
+    evm_opts: EvmOpts,
+    filter: &mut Filter,
+    needs_output: bool,
+    json: bool,
+) -> eyre::Result<(forge::MultiContractRunner, Option<CompilerOutput>)> {
+    if !needs_output && !filter.needs_compiler_output() {
+        let runner =
+            builder.build(project, evm_opts).map_err(|err| report_build_error(err, json))?;
+        return Ok((runner, None))
+    }
+    let root = project.paths.root.clone();
+    let (runner, output) = builder
+        .build_with_output(without_cache(project), evm_opts)
+        .map_err(|err| report_build_error(err, json))?;
+    filter.resolve_inheritance(&root, &output);
+    Ok((runner, Some(output)))
+}
+
+/// Configures the project to be compiled without the cache, which keeps neither the source maps
+/// nor the ASTs, and to output the AST of every source
+fn without_cache<A: ArtifactOutput>(mut project: Project<A>) -> Project<A> {
+    project.cached = false;
+    project
+        .solc_config
+        .settings
+        .output_selection
+        .entry("*".to_string())
+        .or_default()
+        .insert(String::new(), vec!["ast".to_string()]);
+    project
+}
+
+/// Emits a json envelope for a project that could not be built, so that `--json` output is also
+/// produced when no test was run
+fn report_build_error(err: eyre::Report, json: bool) -> eyre::Report {
+    if json {
+        let errors = match err.downcast_ref::<forge::CompileError>() {
+            Some(compile_error) => compile_error
+                .diagnostics
+                .iter()
+                .filter(|diagnostic| matches!(diagnostic.severity, Severity::Error))
+                .map(compile_error_json)
+                .collect(),
+            // the project could not be set up, so nothing was compiled
+            None => vec![serde_json::json!({
+                "severity": Severity::Error,
+                "message": err.to_string(),
+                "source_location": null,
+            })],
+        };
+        println!(
+            "{}",
+            serde_json::json!({
+                "success": false,
+                "compile_errors": errors,
+            })
+        );
+    }
+    err
+}
+
+/// A solc diagnostic as a JSON object, `{ severity, type, error_code, message, formatted_message,
+/// source_location }`, where `source_location` is `null` or `{ file, start, end }` with the byte
+/// offsets of the location in the file
+fn compile_error_json(diagnostic: &ethers::solc::artifacts::Error) -> serde_json::Value {
+    serde_json::json!({
+        "severity": diagnostic.severity,
+        "type": diagnostic.r#type,
+        "error_code": diagnostic.error_code,
+        "message": diagnostic.message,
+        "formatted_message": diagnostic.formatted_message,
+        "source_location": diagnostic.source_location.as_ref().map(|location| serde_json::json!({
+            "file": location.file,
+            "start": location.start,
+            "end": location.end,
+        })),
+    })
+}
+
+/// Runs all the tests
+fn test<A: ArtifactOutput + Clone + 'static>(
+    builder: MultiContractRunnerBuilder,
+    project: Project<A>,
+    evm_opts: EvmOpts,
+    mut filter: Filter,
+    report: ReportOpts,
+) -> eyre::Result<TestOutcome> {
+    let ReportOpts {
+        started,
+        run_id,
+        run_timestamp,
+        color,
+        json_config,
+        json_validate,
+        count_assertions,
+        json_stream,
+        allow_failure,
+        allow_failure_for,
+        fail_on_skip,
+        allow_rejects,
+        flaky_report,
+        isolate_contracts,
+        isolated_build,
+        isolated_results,
+        progress,
+        report_min_gas,
+        report_no_match_test,
+        trace_source_locations,
+        trace_out,
+        diff_traces,
+        emit_flamegraph,
+        print_cheatcode_usage,
+        gas_report,
+        gas_report_percentiles,
+        quiet,
+        only_setup,
+        cache_tests,
+        coverage,
+        print_coverage_gaps,
+        print_storage_reads,
+        slowest,
+        print_log_counts,
+        print_revert_selectors_table,
+        print_deployment_order,
+        print_unused_test_helpers,
+        group_results_by_status,
+        print_creation_code_hash,
+        refund_quotient,
+        print_test_tree,
+        print_test_counts_summary,
+        print_immutables,
+        strict_memory_safety,
+        source_order,
+        abort_on_panic,
+        trace_highlight,
+        trace_collapse_repeated,
+        trace_hide_setup,
+        labels,
+        bench,
+        bindings,
+        bindings_only,
+        deploy_size_threshold_warn,
+        fuzz_seed,
+        fuzz_shrink_to_file,
+        fuzz_inputs_as_json,
+        write_fuzz_regression,
+        output_template,
+        junit,
+        print_contract_sizes_csv,
+        repro_bundle,
+    } = report;
+    let verbosity = evm_opts.verbosity;
+    let json_output = json_config.is_some() || json_stream;
+    let touch_cache = touch_cache_file(&project.paths.cache);
+    let history_file = test_history_file(&project.paths.cache);
+    let failures_file = failures_cache_file(&project.paths.cache);
+    // cached results do not keep the executed instructions, so coverage runs every test
+    let test_cache = cache_tests
+        .filter(|_| !coverage)
+        .map(|settings| (test_cache_file(&project.paths.cache), settings.key()));
+    let compiler =
+        (json_config.is_some() || repro_bundle.is_some()).then(|| compiler_info(&project));
+    let solc_version = project.solc.version().ok();
+    let compile_start = Instant::now();
+    let builder = builder.quiet(quiet || json_output);
+    // the executed instructions are mapped to the source lines for the coverage and to the
+    // assertions with the source maps, the immutables are looked up at the
+    // code offsets the compiler output references them at, the assembly blocks and the
+    // definitions the traces point to and the order of the functions are found in the ASTs and
+    // the files of the contracts in
+    // the contracts it lists by file
+    let root = project.paths.root.clone();
+    let print_test_tree = print_test_tree && !json_output;
+    let needs_output = coverage ||
+        count_assertions ||
+        print_immutables ||
+        strict_memory_safety ||
+        source_order ||
+        trace_source_locations ||
+        write_fuzz_regression.is_some() ||
+        print_test_tree;
+    let (mut runner, output) = match isolated_build {
+        Some(ref file) => (read_isolated_build(builder, file, evm_opts)?, None),
+        None => build_runner(builder, project, evm_opts, &mut filter, needs_output, json_output)?,
+    };
+    if let (true, Some(output)) = (source_order, &output) {
+        runner.set_declaration_order(crate::utils::function_order(output));
+    }
+    let immutable_references =
+        output.as_ref().filter(|_| print_immutables).map(crate::utils::immutable_references);
+    let contract_files = output.as_ref().map(|output| crate::utils::contract_files(&root, output));
+    let regression = write_fuzz_regression.zip(contract_files.clone());
+    let tree_files = contract_files.filter(|_| print_test_tree).map(|files| (root.clone(), files));
+    let locations = output
+        .as_ref()
+        .filter(|_| trace_source_locations)
+        .map(|output| crate::utils::source_locations(&root, output));
+    let highlight = trace_highlight.map(|re| move |frame: &str| re.is_match(frame));
+    let trace_opts = TracePrintOpts {
+        locations: locations.as_ref(),
+        highlight: highlight.as_ref().map(|f| f as &dyn Fn(&str) -> bool),
+        collapse_repeated: trace_collapse_repeated,
+        ..Default::default()
+    };
+    if let (true, Some(output)) = (strict_memory_safety, &output) {
+        let contracts = runner
+            .contracts
+            .keys()
+            .filter(|name| filter.matches_contract(name))
+            .map(|name| name.rsplit(':').next().unwrap_or(name).to_string())
+            .collect();
+        let blocks = crate::utils::unsafe_assembly_blocks(&root, output, &contracts);
+        if !blocks.is_empty() {
+            eyre::bail!(
+                "found {} assembly blocks which are not annotated as memory-safe:\n  {}",
+                blocks.len(),
+                blocks.join("\n  ")
+            )
+        }
+    }
+    let sources = output
+        .filter(|_| coverage || count_assertions)
+        .map(|output| CoverageSources::new(root, output));
+    let compile_time = compile_start.elapsed();
+    if !quiet && !json_output {
+        print_compilation_summary(&runner.compilation, solc_version.as_ref(), verbosity);
+    }
+    let known_errors =
+        json_output.then(|| known_error_selectors(&runner.known_contracts)).unwrap_or_default();
+    let cached_results = match test_cache {
+        Some((ref file, ref settings)) => {
+            let cached = read_test_cache(&runner, &filter, file, settings);
+            filter.cached_contracts = cached.keys().cloned().collect();
+            cached
+        }
+        None => BTreeMap::new(),
+    };
+
+    if !json_output {
+        warn_contract_sizes(&runner.known_contracts, deploy_size_threshold_warn, color);
+        if print_creation_code_hash {
+            print_creation_code_hashes(&runner, &filter)?;
+        }
+    }
+
+    if let Some(ref file) = print_contract_sizes_csv {
+        write_contract_sizes_csv(&runner.known_contracts, &runner.creation_code_sizes, file)?;
+    }
+
+    if let Some((dir, lang)) = bindings {
+        write_bindings(&runner.known_contracts, &dir, lang)?;
+        if !json_output {
+            println!("Wrote bindings to {}", dir.display());
+        }
+        if bindings_only {
+            return Ok(TestOutcome::new(BTreeMap::new(), allow_failure))
+        }
+    }
+
+    if let Some((ref root, ref files)) = tree_files {
+        print_tests_tree(&runner, &filter, root, files, color);
+    }
+    if let (Some(shard), false) = (filter.shard, json_output) {
+        let contracts = runner
+            .contracts
+            .keys()
+            .filter(|name| filter.matches_contract(name))
+            .collect::<Vec<_>>();
+        println!("Shard {} runs {} contracts:", shard, contracts.len());
+        for name in contracts {
+            println!("  {}", name);
+        }
+    }
+    if print_test_counts_summary && !json_output {
+        let counts = runner.test_counts(&filter);
+        println!(
+            "Matched {} tests in {} contracts: {} unit, {} fuzz, {} invariant",
+            counts.tests(),
+            counts.contracts,
+            counts.unit,
+            counts.fuzz,
+            counts.invariant
+        );
+    }
+
+    let immutables = match immutable_references {
+        Some(ref references) => Some(runner.immutables(&filter, references)?),
+        None => None,
+    };
+    let start = Instant::now();
+    let mut results = if isolate_contracts {
+        isolated_test(&runner, &filter)?
+    } else if progress {
+        let bar = ProgressBar::new(runner.matching_contracts(&filter) as u64);
+        bar.set_style(
+            ProgressStyle::default_bar().template("{bar:40} {pos}/{len} contracts, {msg}"),
+        );
+        let tests = AtomicUsize::new(0);
+        let results = runner.test_with_progress(&filter, |_, results| {
+            let done = tests.fetch_add(results.len(), Ordering::Relaxed) + results.len();
+            bar.set_message(format!("{} tests done", done));
+            bar.inc(1);
+        });
+        // the bar is drawn on stderr and gone before any result is printed
+        bar.finish_and_clear();
+        results?
+    } else {
+        runner.test(&filter)?
+    };
+    let execution_time = start.elapsed();
+    if let Some((ref file, ref settings)) = test_cache {
+        record_test_cache(&runner, &results, file, settings)?;
+        results.extend(cached_results);
+    }
+    if let Some(ref file) = isolated_results {
+        write_isolated_results(&results, file)?;
+    }
+    let line_coverage = match sources {
+        Some(ref sources) if coverage => {
+            let lines = collect_line_coverage(&results, sources);
+            let file = sources.root.join(LCOV_FILE);
+            write_lcov(&lines, &file)?;
+            Some((lines, file))
+        }
+        _ => None,
+    };
+    let assertions = sources
+        .as_ref()
+        .filter(|_| count_assertions)
+        .map(|sources| AssertionCounts::new(&results, sources));
+    if let Some(ref target) = filter.only_tests_touching {
+        let touched = touched_contracts(&results);
+        // merge with the contracts which were not run this time
+        let mut cache: BTreeMap<String, BTreeMap<String, BTreeSet<String>>> =
+            std::fs::read(&touch_cache)
+                .ok()
+                .and_then(|file| serde_json::from_slice(&file).ok())
+                .unwrap_or_default();
+        cache.extend(touched.clone());
+        if let Some(dir) = touch_cache.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&touch_cache, serde_json::to_string(&cache)?)?;
+
+        for (contract_name, tests) in results.iter_mut() {
+            tests.retain(|name, _| touched[contract_name][name].contains(target));
+        }
+        results.retain(|_, tests| !tests.is_empty());
+    }
+
+    let bench = match bench {
+        Some(runs) if runs > 0 => {
+            let mut gas: BTreeMap<(String, String), Vec<u64>> = BTreeMap::new();
+            let mut record = |results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>| {
+                for (contract_name, tests) in results {
+                    for (name, result) in tests {
+                        gas.entry((contract_name.clone(), name.clone()))
+                            .or_default()
+                            .push(result.gas_used);
+                    }
+                }
+            };
+            record(&results);
+            for _ in 1..runs {
+                record(&runner.test(&filter)?);
+            }
+            // the reruns run every test, also those `--only-tests-touching` dropped from the
+            // results
+            let stats = gas
+                .into_iter()
+                .filter_map(|(test, gas)| {
+                    let fuzz = results.get(&test.0)?.get(&test.1)?.is_fuzz();
+                    Some((test, GasStats::new(&gas, fuzz)))
+                })
+                .collect::<BTreeMap<_, _>>();
+            Some((runs, stats))
+        }
+        _ => None,
+    };
+
+    let gas_report = gas_report.then(|| collect_gas_report(&results));
+    let coverage_gaps =
+        print_coverage_gaps.then(|| collect_coverage_gaps(&results, &runner.known_contracts));
+
+    // the hidden tests still count towards the outcome of the run
+    let hidden = hide_from_report(&mut results, report_min_gas, report_no_match_test.as_ref());
+
+    if json_stream {
+        stream_results(&results, fuzz_seed, assertions.as_ref(), &known_errors)?;
+    } else if let Some(config) = json_config {
+        let contracts = contract_rollups(&results);
+        let mut output = serde_json::json!({
+            "schema_version": JSON_SCHEMA_VERSION,
+            "run_id": run_id,
+            "timestamp": run_timestamp,
+            "config": config,
+            "compiler": compiler,
+            "compilation": compilation_json(&runner.compilation),
+            "contracts": contracts,
+            "results": results,
+            "tests": json_test_entries(&results),
+        });
+        for (contract_name, tests) in &results {
+            for (name, result) in tests {
+                output["results"][contract_name][name]["fuzz"] = fuzz_stats(result, fuzz_seed);
+                output["results"][contract_name][name]["selector"] = selector(name).into();
+                let assertions =
+                    assertions.as_ref().and_then(|a| a.get(contract_name, name, result));
+                output["results"][contract_name][name]["assertions"] = assertions.into();
+                output["results"][contract_name][name]["warnings"] =
+                    test_warnings(result, assertions, &known_errors).into();
+            }
+        }
+        if let Some(ref immutables) = immutables {
+            output["immutables"] = immutables
+                .iter()
+                .map(|(name, immutables)| {
+                    let values = immutables
+                        .iter()
+                        .map(|(variable, offset, value)| {
+                            (
+                                variable.clone(),
+                                serde_json::json!({ "offset": offset, "value": value }),
+                            )
+                        })
+                        .collect::<serde_json::Map<_, _>>();
+                    (name.clone(), serde_json::Value::Object(values))
+                })
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+        if let Some(ref report) = gas_report {
+            output["gas_report"] = report
+                .iter()
+                .map(|(contract_name, functions)| {
+                    let functions = functions
+                        .iter()
+                        .map(|(func, gas)| {
+                            (func.clone(), function_gas_stats(gas, &gas_report_percentiles))
+                        })
+                        .collect::<serde_json::Map<_, _>>();
+                    (contract_name.clone(), serde_json::Value::Object(functions))
+                })
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+        if let Some(ref gaps) = coverage_gaps {
+            output["coverage_gaps"] = serde_json::json!(gaps);
+        }
+        if let Some((ref lines, _)) = line_coverage {
+            output["coverage"] = line_coverage_json(lines);
+        }
+        let summary = RunSummary::new(results.values().chain(hidden.values()), start.elapsed());
+        output["summary"] = serde_json::json!({
+            "passed": summary.passed,
+            "failed": summary.failed,
+            "rejected": summary.rejected,
+            "flaky": summary.flaky,
+            "gas_used": summary.gas_used,
+            "duration_ms": summary.duration.as_millis() as u64,
+        });
+        let timing = Timing::new(started, compile_start, compile_time, execution_time);
+        output["timing"] = serde_json::json!({
+            "setup_ms": timing.setup.as_millis() as u64,
+            "compile_ms": timing.compile.as_millis() as u64,
+            "execution_ms": timing.execution.as_millis() as u64,
+            "total_ms": timing.total.as_millis() as u64,
+        });
+        if let Some((_, ref stats)) = bench {
+            let mut bench: BTreeMap<&str, BTreeMap<&str, serde_json::Value>> = BTreeMap::new();
+            for ((contract_name, name), stats) in stats {
+                bench.entry(contract_name).or_default().insert(name, stats.to_json());
+            }
+            output["bench"] = serde_json::json!(bench);
+        }
+        if json_validate {
+            let schema = serde_json::from_str(TEST_OUTPUT_SCHEMA)?;
+            if let Err(err) = validate_json(&output, &schema, "$") {
+                eyre::bail!("the json output does not match its schema: {}", err)
+            }
+        }
+        let res = serde_json::to_string(&output)?;
+        println!("{}", res);
+    } else if let Some(ref template) = output_template {
+        let summary = RunSummary::new(results.values().chain(hidden.values()), start.elapsed());
+        let timing = Timing::new(started, compile_start, compile_time, execution_time);
+        print!("{}", template.render(&template_data(&results, &summary, &timing)));
+    } else {
+        // Dapptools-style printing of test results
+        let custom_errors = known_custom_errors(&runner.known_contracts);
+        let print_result = |name: &str, result: &forge::TestResult| {
+            if quiet && result.success && !result.flaky {
+                return
+            }
+            let status = if result.flaky {
+                let txt = match (failure_reason(result, &custom_errors), &result.counterexample) {
+                    (Some(reason), Some(counterexample)) => {
+                        format!("[FLAKY. Reason: {}. Counterexample: {}]", reason, counterexample)
+                    }
+                    (None, Some(counterexample)) => {
+                        format!("[FLAKY. Counterexample: {}]", counterexample)
+                    }
+                    (Some(reason), None) => format!("[FLAKY. Reason: {}]", reason),
+                    (None, None) => "[FLAKY]".to_string(),
+                };
+                paint(color, Colour::Yellow, txt)
+            } else if result.success {
+                paint(color, Colour::Green, "[PASS]")
+            } else {
+                let reason = failure_reason(result, &custom_errors);
+                let txt = match (&reason, &result.counterexample) {
+                    _ if result.error.is_some() => {
+                        format!("[ERROR: {}]", result.error.as_deref().unwrap_or_default())
+                    }
+                    (Some(ref reason), Some(ref counterexample)) => {
+                        format!("[FAIL. Reason: {}. Counterexample: {}]", reason, counterexample)
+                    }
+                    (None, Some(ref counterexample)) => {
+                        format!("[FAIL. Counterexample: {}]", counterexample)
+                    }
+                    (Some(ref reason), None) if result.rejected => {
+                        format!("[REJECTED. Reason: {}]", reason)
+                    }
+                    (Some(ref reason), None) => {
+                        format!("[FAIL. Reason: {}]", reason)
+                    }
+                    (None, None) => "[FAIL]".to_string(),
+                };
+
+                paint(color, Colour::Red, txt)
+            };
+
+            // adds a linebreak only if there were any traces or logs, so that the
+            // output does not look like 1 big block.
+            let mut add_newline = false;
+            let duration = if result.cached {
+                " (cached)".to_string()
+            } else if verbosity > 1 {
+                format!(" ({:.2?})", result.duration)
+            } else {
+                String::new()
+            };
+            if result.retries > 0 {
+                println!(
+                    "{} {} {}{} (retried {}x)",
+                    status,
+                    name,
+                    result.kind.gas_used(),
+                    duration,
+                    result.retries
+                );
+            } else {
+                println!("{} {} {}{}", status, name, result.kind.gas_used(), duration);
+            }
+            if !result.unique_reverts.is_empty() {
+                add_newline = true;
+                println!("Revert reasons:");
+                for (reason, counterexample) in &result.unique_reverts {
+                    println!("  {}: {}", reason, counterexample);
+                }
+            }
+            if verbosity > 1 && !result.logs.is_empty() {
+                add_newline = true;
+                println!("Logs:");
+                for log in &result.logs {
+                    println!("  {}", log);
+                }
+            }
+
+            if verbosity > 2 {
+                if let (Some(traces), Some(identified_contracts)) =
+                    (&result.traces, &result.identified_contracts)
+                {
+                    if !result.success && verbosity == 3 || verbosity > 3 {
+                        // add a new line if any logs were printed & to separate them from
+                        // the traces to be printed
+                        if !result.logs.is_empty() {
+                            println!();
+                        }
+
+                        let mut ident = identified_contracts.clone();
+                        for (addr, label) in &labels {
+                            ident
+                                .entry(*addr)
+                                .and_modify(|(name, _)| *name = label.clone())
+                                .or_insert_with(|| (label.clone(), Abi::default()));
+                        }
+                        // the test's own trace is always the last one
+                        let traces = if trace_hide_setup {
+                            &traces[traces.len().saturating_sub(1)..]
+                        } else {
+                            &traces[..]
+                        };
+                        if verbosity > 4 || !result.success {
+                            add_newline = true;
+                            println!("Traces:");
+
+                            // print setup calls as well
+                            traces.iter().for_each(|trace| {
+                                trace.pretty_print_with_opts(
+                                    0,
+                                    &runner.known_contracts,
+                                    &mut ident,
+                                    &vm(),
+                                    &trace_opts,
+                                    "  ",
+                                );
+                            });
+                        } else if !traces.is_empty() {
+                            add_newline = true;
+                            println!("Traces:");
+                            traces.last().expect("no last but not empty").pretty_print_with_opts(
+                                0,
+                                &runner.known_contracts,
+                                &mut ident,
+                                &vm(),
+                                &trace_opts,
+                                "  ",
+                            );
+                        }
+                    }
+                }
+            }
+
+            if add_newline {
+                println!();
+            }
+        };
+
+        if only_setup {
+            if !quiet {
+                println!("Running setUp() for {} contracts", results.len());
+            }
+            // every contract has the single result of its `setUp()`
+            for (contract_name, tests) in &results {
+                for result in tests.values() {
+                    print_result(contract_name, result);
+                }
+            }
+        } else if group_results_by_status {
+            for (title, success) in [("FAILED", false), ("PASSED", true)] {
+                if quiet && success {
+                    continue
+                }
+                let tests = results
+                    .iter()
+                    .flat_map(|(contract_name, tests)| {
+                        tests.iter().map(move |(name, result)| (contract_name, name, result))
+                    })
+                    .filter(|(_, _, result)| result.success == success)
+                    .collect::<Vec<_>>();
+                if tests.is_empty() {
+                    continue
+                }
+                println!("{} ({})", title, tests.len());
+                for (contract_name, name, result) in tests {
+                    print_result(&format!("{}::{}", contract_name, name), result);
+                }
+                println!();
+            }
+        } else if quiet {
+            // without the banners, the failures are told apart by their contract
+            for (contract_name, tests) in &results {
+                for (name, result) in tests {
+                    print_result(&format!("{}::{}", contract_name, name), result);
+                }
+            }
+        } else {
+            for (i, (contract_name, tests)) in results.iter().enumerate() {
+                if i > 0 {
+                    println!()
+                }
+                if !tests.is_empty() {
+                    let term = if tests.len() > 1 { "tests" } else { "test" };
+                    println!("Running {} {} for {}", tests.len(), term, contract_name);
+                }
+
+                for (name, result) in tests {
+                    print_result(name, result);
+                }
+            }
+        }
+
+        let summary = RunSummary::new(results.values().chain(hidden.values()), start.elapsed());
+        println!();
+        println!("{}", summary.line(color));
+        let flaky = results
+            .iter()
+            .chain(&hidden)
+            .flat_map(|(contract_name, tests)| {
+                let contract_name = contract_name.rsplit(':').next().unwrap_or(contract_name);
+                tests.iter().filter(|(_, result)| result.flaky).map(move |(name, result)| {
+                    match result.flaky_seed {
+                        Some(seed) => format!("{}::{} (seed {:#x})", contract_name, name, seed),
+                        None => format!("{}::{}", contract_name, name),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        if !flaky.is_empty() {
+            println!(
+                "{} flaky fuzz tests failed before passing with a fresh seed: {}",
+                paint(color, Colour::Yellow, flaky.len().to_string()),
+                flaky.join(", ")
+            );
+        }
+        println!("{}", Timing::new(started, compile_start, compile_time, execution_time));
+    }
+
+    for (contract_name, tests) in hidden {
+        results.entry(contract_name).or_default().extend(tests);
+    }
+
+    if !json_output && results.values().flat_map(|tests| tests.values()).any(|r| r.is_fuzz()) {
+        println!("Fuzz seed: {0:#x} (reproduce with --fuzz-seed {0:#x})", fuzz_seed);
+    }
+    if let Some(ref file) = fuzz_shrink_to_file {
+        write_shrink_history(&results, file)?;
+    }
+    if let Some(ref file) = fuzz_inputs_as_json {
+        write_fuzz_inputs(&results, file)?;
+    }
+    if let Some((ref dir, ref contract_files)) = regression {
+        write_fuzz_regressions(&results, contract_files, dir, json_output)?;
+    }
+    if let Some(ref file) = junit {
+        write_junit_report(&results, file)?;
+    }
+    // isolated processes leave recording the history and failures to the process which spawned
+    // them
+    if filter.isolated_contract.is_none() {
+        record_failures(&results, &failures_file, color);
+        let history = record_test_history(&results, &history_file, color);
+        if flaky_report && !json_output {
+            print_flaky_report(&history, color);
+        }
+    }
+    if print_cheatcode_usage {
+        print_cheatcode_summary(&results);
+    }
+    if print_storage_reads && !json_output {
+        print_storage_summary(&results);
+    }
+    if let (false, Some(n)) = (json_output, slowest) {
+        print_slowest_tests(&results, n);
+    }
+    if print_log_counts && !json_output {
+        print_log_counts_summary(&results);
+    }
+    if print_unused_test_helpers && !json_output {
+        print_unused_test_helpers_summary(&runner, &results);
+    }
+    if print_deployment_order && !json_output {
+        print_deployment_order_summary(&runner, &results);
+    }
+    if print_revert_selectors_table && !json_output {
+        print_revert_selectors_summary(&runner, &results);
+    }
+    if let (false, Some(quotient)) = (json_output, refund_quotient) {
+        print_gas_refund_summary(&results, quotient, color);
+    }
+    if let (false, Some(immutables)) = (json_output, &immutables) {
+        print_immutables_summary(immutables);
+    }
+    if let (false, Some((runs, ref stats))) = (json_output, &bench) {
+        print_bench(*runs, stats, color);
+    }
+    if let (false, Some(report)) = (json_output, &gas_report) {
+        print_gas_report(report, &gas_report_percentiles);
+    }
+    if let (false, Some(gaps)) = (json_output, &coverage_gaps) {
+        print_coverage_gaps_summary(gaps);
+    }
+    if let (false, Some((lines, file))) = (json_output, &line_coverage) {
+        print_line_coverage_summary(lines, file);
+    }
+
+    let outcome = TestOutcome::new(results, allow_failure)
+        .allow_failure_for(allow_failure_for)
+        .fail_on_skip(fail_on_skip)
+        .allow_rejects(allow_rejects)
+        .abort_on_panic(abort_on_panic);
+    if !outcome.allow_failure_for.is_empty() && !json_output {
+        let (failed, passed): (Vec<_>, Vec<_>) =
+            outcome.expected_failures().partition(|(_, result)| !result.success);
+        if !failed.is_empty() {
+            println!();
+            println!("{}", paint(color, Colour::Yellow, "Expected failures:"));
+            for (name, _) in failed {
+                println!("  {}", name);
+            }
+        }
+        if !passed.is_empty() {
+            println!();
+            println!("{}", paint(color, Colour::Red, "Expected to fail, but passed:"));
+            for (name, _) in passed {
+                println!("  {}", name);
+            }
+        }
+        let failures = outcome.unexpected_failures().collect::<Vec<_>>();
+        if !failures.is_empty() {
+            println!();
+            println!("{}", paint(color, Colour::Red.bold(), "Unexpected failures:"));
+            for (name, _) in failures {
+                println!("  {}", name);
+            }
+        }
+    }
+    if abort_on_panic && !json_output {
+        let panics = outcome.panics().collect::<Vec<_>>();
+        if !panics.is_empty() {
+            println!();
+            println!("{}", paint(color, Colour::Red.bold(), "Tests failing with a Panic:"));
+            for (name, result) in panics {
+                println!("  {} ({})", name, result.reason.as_deref().unwrap_or_default());
+            }
+        }
+    }
+    if let Some(ref dir) = trace_out {
+        write_traces(dir, &outcome.results)?;
+    }
+    if let Some(ref dir) = diff_traces {
+        print_trace_diffs(dir, &outcome.results, color)?;
+    }
+    if let Some((ref test, ref file)) = emit_flamegraph {
+        write_flamegraph(test, file, &outcome.results)?;
+    }
+    if let Some(ref bundle) = repro_bundle {
+        write_repro_bundle(bundle, compiler.as_ref(), &outcome.results)?;
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{solc::ProjectPathsConfig, types::H256};
+    use evm_adapters::evm_opts::{Env, EvmType};
+
+    fn profile(content: &str) -> toml::value::Table {
+        content.parse::<toml::Value>().unwrap().as_table().unwrap().clone()
+    }
+
+    #[test]
+    fn turns_profile_into_args() {
+        let profile = profile(
+            r#"
+            fuzz_runs = 100
+            json = true
+            match-test = ["testA", "testB"]
+            "#,
+        );
+        let args = profile_args(&TestArgs::into_app(), &profile, &[], false, |_| false).unwrap();
+        assert_eq!(
+            args,
+            vec!["--fuzz-runs", "100", "--json", "--match-test", "testA", "--match-test", "testB"]
+        );
+    }
+
+    #[test]
+    fn command_line_and_env_take_precedence_over_profile() {
+        let profile = profile("fuzz_runs = 100\njson = true\nmatch_contract = \"Foo\"");
+        let argv = ["forge", "test", "-j", "--match-contract=Bar"].map(String::from);
+        let args = profile_args(&TestArgs::into_app(), &profile, &argv, false, |_| false).unwrap();
+        assert_eq!(args, vec!["--fuzz-runs", "100"]);
+
+        let args = profile_args(&TestArgs::into_app(), &profile, &argv, false, |var| {
+            var == "FORGE_FUZZ_RUNS"
+        })
+        .unwrap();
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn rejected_tests_are_errors_unless_allowed() {
+        let rejected = forge::TestResult { rejected: true, rejects: 10, ..Default::default() };
+        let results = BTreeMap::from([(
+            "AssumeTest.json:AssumeTest".to_string(),
+            BTreeMap::from([("testFuzzAssumeNever(uint256)".to_string(), rejected)]),
+        )]);
+
+        let outcome = TestOutcome::new(results.clone(), false);
+        assert_eq!(outcome.failures().count(), 0);
+        let err = outcome.ensure_ok().unwrap_err();
+        assert!(err.to_string().contains("1 tests gave up after too many rejected inputs"));
+
+        assert!(TestOutcome::new(results, false).allow_rejects(true).ensure_ok().is_ok());
+    }
+
+    /// The counterexample of a call of the function with the given signature and arguments
+    fn counterexample(signature: &str, args: &[ethers::abi::Token]) -> forge::CounterExample {
+        let func = foundry_utils::IntoFunction::into(signature);
+        let calldata = func.encode_input(args).unwrap();
+        forge::CounterExample::decode(&func, calldata.into()).unwrap()
+    }
+
+    #[test]
+    fn pins_counterexamples_with_their_parameter_types() {
+        use ethers::abi::Token;
+        let call = counterexample(
+            "function testFuzz(uint8 x, int16 y, address z)",
+            &[Token::Uint(5.into()), Token::Int(U256::MAX - 2), Token::Address(Address::zero())],
+        );
+        assert_eq!(
+            solidity_call(&call).unwrap(),
+            "testFuzz(uint8(5), int16(-3), 0x0000000000000000000000000000000000000000)"
+        );
+
+        let call = counterexample("function testFuzz(uint256[] x)", &[Token::Array(vec![])]);
+        assert_eq!(solidity_call(&call), None);
+    }
+
+    #[test]
+    fn writes_the_sequence_of_invariant_regressions() {
+        let dir = std::env::temp_dir().join("forge-invariant-regressions");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("InvariantTest.sol");
+        std::fs::write(&source, "pragma solidity ^0.8.0;\ncontract InvariantTest {}\n").unwrap();
+
+        let increment = |x: u64| {
+            counterexample("function increment(uint256 x)", &[ethers::abi::Token::Uint(x.into())])
+        };
+        let result = forge::TestResult {
+            kind: forge::TestKind::Fuzz(evm_adapters::fuzz::FuzzedCases::new(vec![])),
+            counterexample: Some(forge::CounterExample {
+                sequence: vec![increment(3), increment(7)],
+                ..counterexample("function invariantCounter()", &[])
+            }),
+            ..Default::default()
+        };
+        let results = BTreeMap::from([(
+            "InvariantTest.json:InvariantTest".to_string(),
+            BTreeMap::from([("invariantCounter()".to_string(), result)]),
+        )]);
+        let contract_files = BTreeMap::from([("InvariantTest".to_string(), source)]);
+        write_fuzz_regressions(&results, &contract_files, &dir, true).unwrap();
+
+        let regression = std::fs::read_to_string(dir.join("InvariantTestRegression.sol")).unwrap();
+        assert!(regression.contains(
+            "    function testRegression_invariantCounter() public {\n        this.increment(uint256(3));\n        this.increment(uint256(7));\n        invariantCounter();\n    }\n"
+        ));
+    }
+
+    #[test]
+    fn writes_the_outcome_of_every_fuzz_case() {
+        let func = foundry_utils::get_func("function testFailFuzz(uint256 x)").unwrap();
+        let case = |x: u64, reverted: bool| evm_adapters::fuzz::FuzzCase {
+            calldata: func.encode_input(&[ethers::abi::Token::Uint(x.into())]).unwrap().into(),
+            gas: 100 + x,
+            reverted,
+        };
+        let result = forge::TestResult {
+            success: true,
+            kind: forge::TestKind::Fuzz(evm_adapters::fuzz::FuzzedCases::new(vec![
+                case(1, true),
+                case(2, false),
+            ])),
+            ..Default::default()
+        };
+        let results = BTreeMap::from([(
+            "FuzzTest.json:FuzzTest".to_string(),
+            BTreeMap::from([("testFailFuzz(uint256)".to_string(), result)]),
+        )]);
+        let file = std::env::temp_dir().join("forge-fuzz-inputs.json");
+        write_fuzz_inputs(&results, &file).unwrap();
+
+        let records: Vec<serde_json::Value> =
+            serde_json::from_slice(&std::fs::read(&file).unwrap()).unwrap();
+        let outcomes = records
+            .iter()
+            .map(|record| (record["gas"].as_u64().unwrap(), record["reverted"].as_bool().unwrap()))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(outcomes, BTreeSet::from([(101, true), (102, false)]));
+        assert!(records
+            .iter()
+            .all(|record| record["test"] == "FuzzTest.json:FuzzTest::testFailFuzz(uint256)"));
+    }
+
+    #[test]
+    fn partitions_tests_by_their_contract_and_name() {
+        let partitions = ["1/2", "2/2"].map(|p| Filter::parse_from(["forge", "--partition", p]));
+        let contracts = (0..16).map(|i| format!("Test.json:Test{}", i)).collect::<Vec<_>>();
+        for contract in &contracts {
+            let matched = partitions
+                .iter()
+                .filter(|filter| filter.matches_contract_test(contract, "testA()"))
+                .count();
+            assert_eq!(matched, 1);
+        }
+        // the same test of different contracts is not always in the same partition
+        let first = contracts
+            .iter()
+            .filter(|contract| partitions[0].matches_contract_test(contract, "testA()"))
+            .count();
+        assert!(first > 0 && first < contracts.len());
+    }
+
+    #[test]
+    fn waits_for_a_burst_of_changes_to_end() {
+        let dir = std::env::temp_dir().join("forge-watch-debounce");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("A.sol"), "contract A {}").unwrap();
+        let modified = sources_modified(&dir);
+
+        let saves = {
+            let dir = dir.clone();
+            std::thread::spawn(move || {
+                for i in 0..3 {
+                    std::thread::sleep(Duration::from_millis(30));
+                    std::fs::write(dir.join("A.sol"), format!("contract A {{ uint x{}; }}", i))
+                        .unwrap();
+                }
+                std::fs::write(dir.join("B.sol"), "contract B {}").unwrap();
+            })
+        };
+        let changed =
+            wait_for_changes(&dir, modified, Duration::from_millis(5), Duration::from_millis(300));
+        saves.join().unwrap();
+        // the changes are only picked up once the last save is done
+        assert!(changed.contains_key(&dir.join("B.sol")));
+        assert_eq!(changed, sources_modified(&dir));
+    }
+
+    #[test]
+    fn parses_fuzz_param_ranges() {
+        let range = "testFuzz:1=-5..0x10".parse::<FuzzParamRange>().unwrap();
+        assert_eq!(range.test, "testFuzz");
+        assert_eq!(range.index, 1);
+        assert_eq!(I256::from_raw(range.lo), I256::from_dec_str("-5").unwrap());
+        assert_eq!(range.hi, U256::from(16));
+
+        let range = "testFuzz:0=-10..-5".parse::<FuzzParamRange>().unwrap();
+        assert_eq!(I256::from_raw(range.hi), I256::from_dec_str("-5").unwrap());
+
+        // the full range of a uint256
+        let max = format!("0x{}", "f".repeat(64));
+        let range = format!("testFuzz:0=0x0..{}", max).parse::<FuzzParamRange>().unwrap();
+        assert_eq!((range.lo, range.hi), (U256::zero(), U256::MAX));
+        let range = format!("testFuzz:0=1..{}", max).parse::<FuzzParamRange>().unwrap();
+        assert_eq!(range.lo, U256::one());
+        assert!(format!("testFuzz:0={}..0x0", max).parse::<FuzzParamRange>().is_err());
+
+        for invalid in ["testFuzz:0=10..5", "testFuzz:0=-5..-10", "testFuzz=1..2", "testFuzz:0=1"] {
+            assert!(invalid.parse::<FuzzParamRange>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn reruns_the_failed_tests_of_their_contracts_only() {
+        let file = std::env::temp_dir().join("forge-rerun-failures.json");
+        std::fs::write(&file, r#"{"A.json:A":["testX"]}"#).unwrap();
+        let mut filter = Filter::parse_from(["forge", "--rerun"]);
+        assert!(filter.load_last_failures(&file));
+        assert!(filter.matches_contract_test("A.json:A", "testX"));
+        assert!(!filter.matches_contract_test("A.json:A", "testY"));
+        assert!(!filter.matches_contract_test("B.json:B", "testX"));
+        assert!(!filter.matches_contract("B.json:B"));
+    }
+
+    /// The project of the sources in a directory of `testdata`
+    pub(super) fn testdata_project(dir: &str) -> Project {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata").join(dir);
+        let paths = ProjectPathsConfig::builder().root(&root).sources(&root).build().unwrap();
+        Project::builder().paths(paths).ephemeral().no_artifacts().build().unwrap()
+    }
+
+    /// The project of the `Counter` contract in `testdata/coverage` and its tests
+    pub(super) fn counter_project() -> Project {
+        testdata_project("coverage")
+    }
+
+    pub(super) fn evm_opts() -> EvmOpts {
+        EvmOpts {
+            env: Env { gas_limit: u64::MAX, chain_id: 1, ..Default::default() },
+            initial_balance: U256::MAX,
+            evm_type: EvmType::Sputnik,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reports_the_diagnostics_of_a_failed_compilation() {
+        let err = MultiContractRunnerBuilder::default()
+            .build(without_cache(testdata_project("broken")), evm_opts())
+            .unwrap_err();
+        let compile_error = err.downcast_ref::<forge::CompileError>().unwrap();
+        let errors = compile_error
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| matches!(diagnostic.severity, Severity::Error))
+            .map(compile_error_json)
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["severity"], "error");
+        assert_eq!(errors[0]["type"], "TypeError");
+        let location = &errors[0]["source_location"];
+        assert!(location["file"].as_str().unwrap().ends_with("Broken.sol"));
+        // the location is the returned literal, `"42"` in line 6
+        let source = include_str!("../../../testdata/broken/Broken.sol");
+        let start = location["start"].as_u64().unwrap() as usize;
+        let end = location["end"].as_u64().unwrap() as usize;
+        assert_eq!(&source[start..end], "\"42\"");
+    }
+
+    #[test]
+    fn aborts_tests_which_exceed_the_timeout() {
+        // every case that shrinks the failing one would time out as well
+        let cfg = proptest::test_runner::Config {
+            failure_persistence: None,
+            max_shrink_iters: 0,
+            ..Default::default()
+        };
+        let (mut runner, _) = MultiContractRunnerBuilder::default()
+            .fuzzer(proptest::test_runner::TestRunner::new(cfg))
+            .timeout(Some(Duration::from_millis(100)))
+            .build_with_output(without_cache(testdata_project("timeout")), evm_opts())
+            .unwrap();
+        let results = runner.test(&Filter::parse_from(["forge"])).unwrap();
+        let tests = results.values().next().unwrap();
+        for name in ["testLoop()", "testFuzzLoop(uint256)"] {
+            let result = &tests[name];
+            assert!(!result.success, "{}", name);
+            assert!(result.timed_out, "{}", name);
+            assert_eq!(result.reason.as_deref(), Some("timeout"), "{}", name);
+        }
+        // the other tests of the suite still run
+        assert!(tests["testPasses()"].success);
+        assert!(!tests["testPasses()"].timed_out);
+    }
+
+    #[test]
+    fn reads_the_immutables_of_deployed_contracts() {
+        let sender = Address::repeat_byte(0x11);
+        let (runner, output) = MultiContractRunnerBuilder::default()
+            .sender(sender)
+            .build_with_output(without_cache(counter_project()), evm_opts())
+            .unwrap();
+        let references = crate::utils::immutable_references(&output);
+        let names = references["ImmutableTest"].iter().map(|(name, _)| name.as_str());
+        assert_eq!(names.collect::<BTreeSet<_>>(), BTreeSet::from(["answer", "deployer"]));
+
+        let immutables = runner.immutables(&Filter::parse_from(["forge"]), &references).unwrap();
+        let values = immutables["Coverage.json:ImmutableTest"]
+            .iter()
+            .map(|(name, _, value)| (name.as_str(), *value))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(values["answer"], H256::from_low_u64_be(42));
+        assert_eq!(values["deployer"], H256::from(sender));
+        assert!(immutables["Coverage.json:CounterTest"].is_empty());
+    }
+
+    #[test]
+    fn finds_unannotated_assembly_blocks_of_tested_contracts() {
+        let project = without_cache(testdata_project("assembly"));
+        let root = project.paths.root.clone();
+        let output = project.compile().unwrap().output();
+        let contracts = BTreeSet::from(["AssemblyTest".to_string()]);
+        assert_eq!(
+            crate::utils::unsafe_assembly_blocks(&root, &output, &contracts),
+            vec!["Assembly.sol:25".to_string(), "Assembly.sol:6".to_string()]
+        );
+        assert!(crate::utils::unsafe_assembly_blocks(&root, &output, &BTreeSet::new()).is_empty());
+    }
+
+    #[test]
+    fn finds_the_locations_of_definitions() {
+        let project = without_cache(counter_project());
+        let root = project.paths.root.clone();
+        let locations = crate::utils::source_locations(&root, &project.compile().unwrap().output());
+        assert_eq!(
+            locations["Counter"],
+            BTreeMap::from([
+                ("constructor".to_string(), "Coverage.sol:4".to_string()),
+                ("increment".to_string(), "Coverage.sol:7".to_string()),
+                ("reset".to_string(), "Coverage.sol:11".to_string()),
+            ])
+        );
+        assert_eq!(locations["CounterTest"]["setUp"], "Coverage.sol:19");
+        assert_eq!(locations["ImmutableTest"]["constructor"], "Coverage.sol:37");
+    }
+
+    #[test]
+    fn resolves_inheriting_contracts_from_the_ast() {
+        let project = without_cache(testdata_project("assembly"));
+        let root = project.paths.root.clone();
+        let output = project.compile().unwrap().output();
+        let graph = crate::utils::inheritance_graph(&output);
+        assert_eq!(graph["AssemblyTest"], vec!["Base".to_string()]);
+        assert!(graph["Base"].is_empty());
+
+        for flag in ["--inherits-from", "--only-contracts-matching-inheritance"] {
+            let mut filter = Filter::parse_from(["forge", flag, "Base"]);
+            assert!(filter.needs_compiler_output());
+            filter.resolve_inheritance(&root, &output);
+            assert!(filter.matches_contract("Assembly.json:AssemblyTest"));
+            assert!(!filter.matches_contract("Assembly.json:Untested"));
+        }
+    }
+
+    #[test]
+    fn orders_functions_as_they_are_declared_in_the_ast() {
+        let output = without_cache(counter_project()).compile().unwrap().output();
+        let order = crate::utils::function_order(&output);
+        assert_eq!(order["Coverage.json:Counter"], ["increment", "reset"]);
+        assert_eq!(
+            order["Coverage.json:CounterTest"],
+            ["setUp", "testIncrement", "testIncrementWithoutAssertions"]
+        );
+        // the constructor has no name
+        assert_eq!(order["Coverage.json:ImmutableTest"], ["testAnswer"]);
+        assert_eq!("alpha".parse::<TestOrder>().unwrap(), TestOrder::Alpha);
+        assert!("declaration".parse::<TestOrder>().is_err());
+    }
+
+    #[test]
+    fn passes_builds_and_results_of_isolated_contracts_through_files() {
+        let runner = MultiContractRunnerBuilder::default()
+            .coverage(true)
+            .build(counter_project(), evm_opts())
+            .unwrap();
+        let build = std::env::temp_dir().join("forge-isolated-build.json");
+        write_isolated_build(&runner, &build).unwrap();
+        let mut isolated = read_isolated_build(
+            MultiContractRunnerBuilder::default().coverage(true),
+            &build,
+            evm_opts(),
+        )
+        .unwrap();
+        assert_eq!(
+            isolated.contracts.keys().collect::<Vec<_>>(),
+            runner.contracts.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(isolated.known_contracts.len(), runner.known_contracts.len());
+
+        let mut results = isolated.test(&Filter::parse_from(["forge"])).unwrap();
+        let abi =
+            ethers::abi::AbiParser::default().parse(&["function testFuzz(uint256 x)"]).unwrap();
+        let func = abi.function("testFuzz").unwrap();
+        let args = vec![ethers::abi::Token::Uint(7u64.into())];
+        let fuzz = forge::TestResult {
+            success: false,
+            duration: Duration::from_millis(12),
+            counterexample: Some(forge::CounterExample {
+                calldata: func.encode_input(&args).unwrap().into(),
+                args: vec![],
+                signature: func.signature(),
+                inputs: vec![],
+                sequence: vec![],
+            }),
+            ..Default::default()
+        };
+        results
+            .entry("Fuzz.json:FuzzTest".to_string())
+            .or_default()
+            .insert("testFuzz(uint256)".to_string(), fuzz);
+
+        let file = std::env::temp_dir().join("forge-isolated-results.json");
+        write_isolated_results(&results, &file).unwrap();
+        let read = read_isolated_results(&file, &[&abi]).unwrap();
+        let increment = &read["Coverage.json:CounterTest"]["testIncrement()"];
+        let original = &results["Coverage.json:CounterTest"]["testIncrement()"];
+        assert!(increment.success);
+        assert_eq!(increment.duration, original.duration);
+        assert!(!increment.coverage.is_empty());
+        assert_eq!(increment.coverage, original.coverage);
+        let fuzz = &read["Fuzz.json:FuzzTest"]["testFuzz(uint256)"];
+        assert_eq!(fuzz.duration, Duration::from_millis(12));
+        assert_eq!(fuzz.counterexample.as_ref().unwrap().args, args);
+    }
+
+    #[test]
+    fn writes_the_creation_code_read_from_disk_to_isolated_builds() {
+        let runner = MultiContractRunnerBuilder::default()
+            .max_artifacts_memory(Some(1))
+            .build(counter_project(), evm_opts())
+            .unwrap();
+        let build = std::env::temp_dir().join("forge-isolated-build-from-disk.json");
+        write_isolated_build(&runner, &build).unwrap();
+        let isolated =
+            read_isolated_build(MultiContractRunnerBuilder::default(), &build, evm_opts()).unwrap();
+        for (name, (_, code)) in &isolated.contracts {
+            assert!(!code.as_ref().is_empty());
+            assert_eq!(*code, runner.creation_code(name).unwrap());
+        }
+    }
+
+    #[test]
+    fn resolves_the_files_of_contracts_from_the_compiler_output() {
+        let project = without_cache(testdata_project("assembly"));
+        let root = project.paths.root.clone();
+        let output = project.compile().unwrap().output();
+        let files = crate::utils::contract_files(&root, &output);
+        assert_eq!(files.keys().collect::<Vec<_>>(), ["AssemblyTest", "Base", "Untested"]);
+        assert!(files.values().all(|file| *file == root.join("Assembly.sol")));
+
+        let mut filter = Filter::parse_from(["forge", "--no-match-path", r"Assembly\.sol$"]);
+        assert!(filter.needs_compiler_output());
+        assert!(!filter.matches_path(&root.join("Assembly.sol")));
+        assert!(filter.matches_path(&root.join("Coverage.sol")));
+        filter.resolve_inheritance(&root, &output);
+        assert!(!filter.matches_contract("Assembly.json:AssemblyTest"));
+    }
+
+    #[test]
+    fn reads_the_profile_of_tests_run_by_snapshot() {
+        use crate::opts::forge::{Opts, Subcommands};
+        let root = std::env::temp_dir().join("forge-snapshot-profile");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(CONFIG_FILE), "[profile.default]\nfuzz_runs = 7\njson = true\n")
+            .unwrap();
+        let argv = ["forge", "snapshot", "--json", "--tolerance", "5", "--root"]
+            .into_iter()
+            .map(String::from)
+            .chain([root.display().to_string()])
+            .collect::<Vec<_>>();
+        let opts = Opts::try_parse_from(&argv).unwrap().apply_profile(argv).unwrap();
+        match opts.sub {
+            Subcommands::Snapshot(args) => {
+                assert_eq!(args.test_args().fuzz_runs, Some(7));
+                assert!(args.test_args().json);
+            }
+            sub => panic!("expected the snapshot subcommand, got {:?}", sub),
+        }
+    }
+
+    #[test]
+    fn sizes_the_thread_pool_of_every_run() {
+        let root = std::env::temp_dir().join("forge-jobs");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(
+            root.join("Coverage.sol"),
+            include_str!("../../../testdata/coverage/Coverage.sol"),
+        )
+        .unwrap();
+        let root = root.display().to_string();
+        // the second run would fail if the first one had set up the global pool
+        for jobs in ["1", "2"] {
+            let args = TestArgs::parse_from([
+                "forge",
+                "--jobs",
+                jobs,
+                "--root",
+                &root,
+                "--contracts",
+                &root,
+                "--quiet",
+            ]);
+            let outcome = args.run().unwrap();
+            assert!(outcome.successes().count() > 0);
+        }
+    }
+
+    #[test]
+    fn skips_unknown_profile_options() {
+        let profile = profile("fuzz_rounds = 100\nfuzz_runs = 10");
+        let args = profile_args(&TestArgs::into_app(), &profile, &[], false, |_| false).unwrap();
+        assert_eq!(args, vec!["--fuzz-runs", "10"]);
+    }
+}
@@ -0,0 +1,1469 @@
+//! Summaries and reports of the results of a test run
+
+use super::{coverage::AssertionCounts, paint};
+use ansi_term::Colour;
+use ethers::{
+    abi::{Abi, AbiError},
+    types::{Address, H256},
+};
+use evm_adapters::call_tracing::CallTraceArena;
+use proptest::test_runner::{RngAlgorithm, TestRng};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// The deployment and setup costs of every test contract, with the counts, gas and duration of its
+/// tests, for the `contracts` map of the json output
+pub(super) fn contract_rollups(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> BTreeMap<&String, serde_json::Value> {
+    results
+        .iter()
+        .filter_map(|(name, tests)| {
+            // every test deploys and sets up its contract anew, so any test's costs will do
+            let result = tests.values().next()?;
+            let duration = tests.values().map(|result| result.duration).sum();
+            let summary = RunSummary::new(std::iter::once(tests), duration);
+            Some((
+                name,
+                serde_json::json!({
+                    "deploy_gas": result.deploy_gas,
+                    "setup_gas": result.setup_gas,
+                    "tests": tests.len(),
+                    "passed": summary.passed,
+                    "failed": summary.failed,
+                    "rejected": summary.rejected,
+                    "flaky": summary.flaky,
+                    "gas_used": summary.gas_used,
+                    "duration_ms": summary.duration.as_millis() as u64,
+                }),
+            ))
+        })
+        .collect()
+}
+
+/// Gas statistics of a test over multiple runs
+pub(super) struct GasStats {
+    mean: f64,
+    std_dev: f64,
+    /// Whether a non-fuzz test consumed different amounts of gas across runs
+    nondeterministic: bool,
+}
+
+impl GasStats {
+    pub(super) fn new(gas: &[u64], fuzz: bool) -> Self {
+        let n = gas.len().max(1) as f64;
+        let mean = gas.iter().map(|g| *g as f64).sum::<f64>() / n;
+        let variance = gas.iter().map(|g| (*g as f64 - mean).powi(2)).sum::<f64>() / n;
+        // fuzz tests run different inputs on every run, so their gas is expected to vary
+        let nondeterministic = !fuzz && gas.windows(2).any(|w| w[0] != w[1]);
+        Self { mean, std_dev: variance.sqrt(), nondeterministic }
+    }
+
+    pub(super) fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "mean": self.mean,
+            "std_dev": self.std_dev,
+            "nondeterministic": self.nondeterministic,
+        })
+    }
+}
+
+/// Prints the gas statistics of every test
+pub(super) fn print_bench(runs: usize, stats: &BTreeMap<(String, String), GasStats>, color: bool) {
+    println!("Gas over {} runs:", runs);
+    for ((contract_name, name), stats) in stats {
+        println!(
+            "  {}::{}: μ: {:.0}, σ: {:.2}{}",
+            contract_name,
+            name,
+            stats.mean,
+            stats.std_dev,
+            if stats.nondeterministic {
+                format!(" {}", paint(color, Colour::Red, "[NONDETERMINISTIC]"))
+            } else {
+                "".to_string()
+            }
+        );
+    }
+}
+
+/// Prints the cheatcodes invoked by every test and how often they were called
+pub(super) fn print_cheatcode_summary(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) {
+    println!("Cheatcode usage:");
+    for (contract_name, tests) in results {
+        for (name, result) in tests {
+            if result.cheatcode_usage.is_empty() {
+                continue
+            }
+            let usage = result
+                .cheatcode_usage
+                .iter()
+                .map(|(cheatcode, count)| format!("{} ({})", cheatcode, count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("  {}::{}: {}", contract_name, name, usage);
+        }
+    }
+}
+
+/// The JSON Schema of the `--json` output
+pub(super) const TEST_OUTPUT_SCHEMA: &str = include_str!("test.schema.json");
+
+/// The version of the shape of the `tests` of the `--json` output, bumped on every change which
+/// is not backwards compatible, i.e. removing or renaming a key or changing its type.
+///
+/// Unlike `results`, which serializes the runner's results as they are, `tests` is built
+/// explicitly and only changes along with this version
+pub(super) const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Generates a random (version 4) UUID which identifies a run in the `--json` output
+pub(super) fn new_run_id() -> String {
+    use proptest::prelude::RngCore;
+    let mut bytes = [0u8; 16];
+    TestRng::default_rng(RngAlgorithm::ChaCha).fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// The `tests` of the `--json` output, one object per test in the order of the contracts and
+/// their tests:
+///
+/// `{ contract, signature, success, gas, setup_gas, duration_ms, reason, counterexample, logs }`
+///
+/// where `gas` is the gas of the test body, `setup_gas` the gas of the `setUp()` which ran before
+/// it, `duration_ms` the time the test took, across all cases of a fuzz test, `reason` is the
+/// revert reason or the error of a failed test, `counterexample` is `null` or `{ calldata,
+/// signature, inputs, args, sequence }` and `logs` are the decoded logs as strings
+pub(super) fn json_test_entries(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> serde_json::Value {
+    results
+        .iter()
+        .flat_map(|(contract_name, tests)| {
+            tests.iter().map(move |(name, result)| {
+                serde_json::json!({
+                    "contract": contract_name,
+                    "signature": name,
+                    "success": result.success,
+                    "gas": result.gas_used,
+                    "setup_gas": result.setup_gas,
+                    "duration_ms": result.duration.as_millis() as u64,
+                    "reason": result.reason.as_ref().or(result.error.as_ref()),
+                    "counterexample": result.counterexample.as_ref().map(json_counterexample),
+                    "logs": result.logs,
+                })
+            })
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// A counterexample of the `tests` of the `--json` output, with its arguments as `{ name, type,
+/// value }` and formatted as strings
+fn json_counterexample(counterexample: &forge::CounterExample) -> serde_json::Value {
+    serde_json::json!({
+        "calldata": counterexample.calldata,
+        "signature": counterexample.signature,
+        "inputs": counterexample.inputs,
+        "args": foundry_utils::format_tokens(&counterexample.args).collect::<Vec<_>>(),
+        "sequence": counterexample.sequence.iter().map(json_counterexample).collect::<Vec<_>>(),
+    })
+}
+
+/// Validates `value` against the subset of JSON Schema used by [`TEST_OUTPUT_SCHEMA`]: `type`,
+/// `required`, `properties`, `additionalProperties` and `items`.
+///
+/// Returns the path of the first value which does not match
+pub(super) fn validate_json(
+    value: &serde_json::Value,
+    schema: &serde_json::Value,
+    path: &str,
+) -> Result<(), String> {
+    use serde_json::Value;
+
+    if let Some(ty) = schema.get("type") {
+        let matches = |ty: &Value| match ty.as_str() {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_u64() || value.is_i64(),
+            Some("number") => value.is_number(),
+            Some("boolean") => value.is_boolean(),
+            Some("null") => value.is_null(),
+            _ => false,
+        };
+        let ok = match ty {
+            Value::Array(types) => types.iter().any(matches),
+            ty => matches(ty),
+        };
+        if !ok {
+            return Err(format!("{} is not of type {}", path, ty))
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            for key in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+                if let Some(key) = key.as_str() {
+                    if !object.contains_key(key) {
+                        return Err(format!("{} is missing `{}`", path, key))
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, value) in object {
+                let path = format!("{}.{}", path, key);
+                match (properties.and_then(|p| p.get(key)), schema.get("additionalProperties")) {
+                    (Some(schema), _) | (None, Some(schema)) => {
+                        validate_json(value, schema, &path)?
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_json(item, schema, &format!("{}[{}]", path, i))?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Prints the number of storage reads and writes of every test
+pub(super) fn print_storage_summary(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) {
+    println!("Storage accesses:");
+    for (contract_name, tests) in results {
+        for (name, result) in tests {
+            println!(
+                "  {}::{}: SLOAD: {}, SSTORE: {}",
+                contract_name, name, result.storage_reads, result.storage_writes
+            );
+        }
+    }
+}
+
+/// Moves the tests which are not reported out of `results`, those below `min_gas` and those whose
+/// name matches `no_match_test`, and returns them
+pub(super) fn hide_from_report(
+    results: &mut BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    min_gas: Option<u64>,
+    no_match_test: Option<&regex::Regex>,
+) -> BTreeMap<String, BTreeMap<String, forge::TestResult>> {
+    let mut hidden = BTreeMap::new();
+    if min_gas.is_none() && no_match_test.is_none() {
+        return hidden
+    }
+    for (contract_name, tests) in results.iter_mut() {
+        let (shown, excluded): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            std::mem::take(tests).into_iter().partition(|(name, result)| {
+                min_gas.map_or(true, |min_gas| result.gas_used >= min_gas) &&
+                    !no_match_test.map_or(false, |re| re.is_match(name))
+            });
+        *tests = shown;
+        if !excluded.is_empty() {
+            hidden.insert(contract_name.clone(), excluded);
+        }
+    }
+    results.retain(|_, tests| !tests.is_empty());
+    hidden
+}
+
+/// Prints the `n` tests which took the longest to run, slowest first. The duration of a fuzz test
+/// covers all of its cases
+pub(super) fn print_slowest_tests(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    n: usize,
+) {
+    let mut tests = results
+        .iter()
+        .flat_map(|(contract_name, tests)| {
+            tests.iter().map(move |(name, result)| (contract_name, name, result.duration))
+        })
+        .collect::<Vec<_>>();
+    tests.sort_by(|a, b| b.2.cmp(&a.2));
+    println!("{} slowest tests:", n.min(tests.len()));
+    for (contract_name, name, duration) in tests.into_iter().take(n) {
+        println!("  {:>10.2?}  {}::{}", duration, contract_name, name);
+    }
+}
+
+/// Collects the gas used by every call to a function of a non-test contract from the call traces
+/// of the tests, `contract -> (function -> gas of every call)`
+pub(super) fn collect_gas_report(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> BTreeMap<String, BTreeMap<String, Vec<u64>>> {
+    let mut report: BTreeMap<String, BTreeMap<String, Vec<u64>>> = BTreeMap::new();
+    for (contract_name, tests) in results {
+        // the identified contracts are named without their artifact file
+        let contract_name = contract_name.rsplit(':').next().unwrap_or(contract_name);
+        for result in tests.values() {
+            let identified = match result.identified_contracts {
+                Some(ref identified) => identified,
+                None => continue,
+            };
+            for node in result.traces.iter().flatten().flat_map(|arena| arena.arena.iter()) {
+                let trace = &node.trace;
+                let (name, abi) = match identified.get(&trace.addr) {
+                    // the test contract and the cheatcodes are not part of the report
+                    Some((name, _)) if name == contract_name || name == "VM" => continue,
+                    Some(contract) => contract,
+                    None => continue,
+                };
+                let func = if trace.created {
+                    "constructor".to_string()
+                } else {
+                    match abi
+                        .functions()
+                        .find(|func| trace.data.get(..4) == Some(&func.short_signature()[..]))
+                    {
+                        Some(func) => func.name.clone(),
+                        None => continue,
+                    }
+                };
+                report.entry(name.clone()).or_default().entry(func).or_default().push(trace.cost);
+            }
+        }
+    }
+    report
+}
+
+/// The call count, min, mean, median and max gas and the given percentiles (as `p<N>`) of the gas
+/// of the calls to a function
+pub(super) fn function_gas_stats(gas: &[u64], percentiles: &[u8]) -> serde_json::Value {
+    let mut sorted = gas.to_vec();
+    sorted.sort_unstable();
+    let mut stats = serde_json::json!({
+        "calls": sorted.len(),
+        "min": sorted.first().copied().unwrap_or_default(),
+        "mean": sorted.iter().map(|gas| *gas as u128).sum::<u128>() / sorted.len().max(1) as u128,
+        "median": sorted.get(sorted.len() / 2).copied().unwrap_or_default(),
+        "max": sorted.last().copied().unwrap_or_default(),
+    });
+    for percentile in percentiles {
+        stats[format!("p{}", percentile)] = gas_percentile(&sorted, *percentile).into();
+    }
+    stats
+}
+
+/// The nearest-rank percentile of the sorted gas samples
+fn gas_percentile(sorted: &[u64], percentile: u8) -> u64 {
+    let rank = (sorted.len() * percentile as usize + 99) / 100;
+    sorted.get(rank.saturating_sub(1)).copied().unwrap_or_default()
+}
+
+/// Prints the gas used by the calls to every function of the non-test contracts
+pub(super) fn print_gas_report(
+    report: &BTreeMap<String, BTreeMap<String, Vec<u64>>>,
+    percentiles: &[u8],
+) {
+    println!("Gas report:");
+    for (contract_name, functions) in report {
+        println!("  {}", contract_name);
+        for (func, gas) in functions {
+            let stats = function_gas_stats(gas, percentiles);
+            let percentiles = percentiles
+                .iter()
+                .map(|percentile| {
+                    format!(", p{0}: {1}", percentile, stats[format!("p{}", percentile)])
+                })
+                .collect::<String>();
+            println!(
+                "    {}: calls: {}, min: {}, mean: {}, median: {}, max: {}{}",
+                func,
+                stats["calls"],
+                stats["min"],
+                stats["mean"],
+                stats["median"],
+                stats["max"],
+                percentiles
+            );
+        }
+    }
+}
+
+/// Prints the number of logs every test emitted
+pub(super) fn print_log_counts_summary(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) {
+    println!("Log counts:");
+    for (contract_name, tests) in results {
+        for (name, result) in tests {
+            println!("  {}::{}: logs: {}", contract_name, name, result.logs.len());
+        }
+    }
+}
+
+/// Collects the public functions of every test contract which are neither tests nor `setUp()` and
+/// were not called by any of its tests, as `contract::signature`.
+///
+/// Calls are taken from the traces, so internal functions, which are not part of the abi and
+/// never show up as a call, are not covered
+fn collect_unused_test_helpers(
+    runner: &forge::MultiContractRunner,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> Vec<String> {
+    let mut unused = Vec::new();
+    for (contract_name, tests) in results {
+        let abi = match runner.contracts.get(contract_name) {
+            Some((abi, _)) => abi,
+            None => continue,
+        };
+        let called = tests
+            .values()
+            .flat_map(|result| {
+                result
+                    .traces
+                    .iter()
+                    .flatten()
+                    .flat_map(|arena| arena.arena.iter())
+                    .filter(move |node| node.trace.addr == result.contract_address)
+                    .filter_map(|node| node.trace.data.get(..4))
+            })
+            .collect::<BTreeSet<_>>();
+        for func in abi.functions() {
+            // `IS_TEST` and `failed` are part of ds-test and only called by the runner
+            if func.name.starts_with(&runner.test_function_prefix) ||
+                ["setUp", "IS_TEST", "failed"].contains(&func.name.as_str())
+            {
+                continue
+            }
+            if !called.contains(&func.short_signature()[..]) {
+                unused.push(format!("{}::{}", contract_name, func.signature()));
+            }
+        }
+    }
+    unused
+}
+
+/// Prints the unused test helpers, see `collect_unused_test_helpers`
+pub(super) fn print_unused_test_helpers_summary(
+    runner: &forge::MultiContractRunner,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) {
+    println!("Unused test helpers:");
+    for helper in collect_unused_test_helpers(runner, results) {
+        println!("  {}", helper);
+    }
+}
+
+/// A contract created by the `setUp()` of a test contract
+#[derive(Debug, PartialEq)]
+struct Deployment {
+    /// The name of the contract, `<unknown>` if it was not identified
+    name: String,
+    address: Address,
+    /// The gas of the deployment
+    gas: u64,
+    /// Whether the deployment reverted
+    reverted: bool,
+}
+
+/// Collects the contracts created by the `setUp()` of every test contract, in the order they were
+/// created, `test contract -> [deployment]`.
+///
+/// The deployments are taken from the setup trace of the contract's first traced test
+fn collect_deployment_order(
+    runner: &forge::MultiContractRunner,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> BTreeMap<String, Vec<Deployment>> {
+    let mut order = BTreeMap::new();
+    for (contract_name, tests) in results {
+        let has_setup = runner
+            .contracts
+            .get(contract_name)
+            .map_or(false, |(abi, _)| abi.functions().any(|func| func.name == "setUp"));
+        if !has_setup {
+            continue
+        }
+        // the setup trace is the first trace of every test of a contract with a `setUp()`
+        let setup = tests.values().find_map(|result| {
+            let arena = result.traces.as_ref()?.first()?;
+            Some((arena, result.identified_contracts.as_ref()))
+        });
+        let (arena, identified_contracts) = match setup {
+            Some(setup) => setup,
+            None => continue,
+        };
+        // nodes are added to the arena as the calls start, so this is the order of creation
+        let deployments = arena
+            .arena
+            .iter()
+            .filter(|node| node.trace.created)
+            .map(|node| Deployment {
+                name: identified_contracts
+                    .and_then(|contracts| contracts.get(&node.trace.addr))
+                    .map_or("<unknown>", |(name, _)| name.as_str())
+                    .to_string(),
+                address: node.trace.addr,
+                gas: node.trace.cost,
+                reverted: !node.trace.success,
+            })
+            .collect();
+        order.insert(contract_name.clone(), deployments);
+    }
+    order
+}
+
+/// Prints the contracts created by the `setUp()` of every test contract, see
+/// `collect_deployment_order`
+pub(super) fn print_deployment_order_summary(
+    runner: &forge::MultiContractRunner,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) {
+    println!("Deployment order:");
+    for (contract_name, deployments) in collect_deployment_order(runner, results) {
+        println!("  {}:", contract_name);
+        for (idx, deployment) in deployments.iter().enumerate() {
+            let status = if deployment.reverted { " (reverted)" } else { "" };
+            println!(
+                "    {}. {} at {:?}: {} gas{}",
+                idx + 1,
+                deployment.name,
+                deployment.address,
+                deployment.gas,
+                status
+            );
+        }
+    }
+}
+
+/// Prints the 4 byte selector of every revert which could neither be decoded as a revert reason
+/// nor matched to a custom error of a compiled contract, with the tests which reverted with it.
+///
+/// These are typically custom errors of contracts whose abi is not part of the project
+pub(super) fn print_revert_selectors_summary(
+    runner: &forge::MultiContractRunner,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) {
+    let known = known_error_selectors(&runner.known_contracts);
+    let mut unresolved: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (contract_name, tests) in results {
+        for (name, result) in tests {
+            if let Some(selector) = unresolved_revert_selector(result, &known) {
+                unresolved
+                    .entry(selector)
+                    .or_default()
+                    .push(format!("{}::{}", contract_name, name));
+            }
+        }
+    }
+
+    println!("Unresolved revert selectors:");
+    for (selector, tests) in unresolved {
+        println!("  {}: {}", selector, tests.join(", "));
+    }
+}
+
+/// Returns the hex encoded selectors of the custom errors of all compiled contracts
+pub(super) fn known_error_selectors(
+    contracts: &BTreeMap<String, (Abi, Vec<u8>)>,
+) -> BTreeSet<String> {
+    known_custom_errors(contracts).into_keys().collect()
+}
+
+/// Returns the custom errors of all compiled contracts by their hex encoded selector
+pub(super) fn known_custom_errors(
+    contracts: &BTreeMap<String, (Abi, Vec<u8>)>,
+) -> BTreeMap<String, &AbiError> {
+    contracts
+        .values()
+        .flat_map(|(abi, _)| abi.errors.values().flatten())
+        .map(|error| {
+            let params = error.inputs.iter().map(|param| param.kind.to_string());
+            (selector(&format!("{}({})", error.name, params.collect::<Vec<_>>().join(","))), error)
+        })
+        .collect()
+}
+
+/// Returns the reason a test failed with, decoding the data it reverted with as one of the
+/// `known` custom errors, e.g. `InsufficientBalance(100, 50)`.
+///
+/// Tests which ran out of gas are reported as such rather than as a revert.
+///
+/// Falls back to the hex encoded revert data if it is neither a custom error nor a revert reason.
+pub(super) fn failure_reason(
+    result: &forge::TestResult,
+    known: &BTreeMap<String, &AbiError>,
+) -> Option<String> {
+    if result.out_of_gas {
+        return Some("out of gas, see --gas-limit".to_string())
+    }
+    let data = match result.revert_data.as_ref().filter(|data| data.len() >= 4) {
+        Some(data) => data,
+        None => return result.reason.clone().filter(|reason| !reason.is_empty()),
+    };
+    let selector = format!("0x{}", hex::encode(&data[..4]));
+    // keccak(Panic(uint256))
+    if selector == "0x4e487b71" {
+        let code = data.last().copied().unwrap_or_default();
+        return Some(match foundry_utils::panic_reason(code) {
+            Some(reason) => format!("panic: {} (0x{:02x})", reason, code),
+            None => format!("panic: code 0x{:02x}", code),
+        })
+    }
+    if let Ok(reason) = foundry_utils::decode_revert(data) {
+        return Some(reason)
+    }
+    let decoded = known.get(&selector).and_then(|error| {
+        let params = error.inputs.iter().map(|param| param.kind.clone()).collect::<Vec<_>>();
+        let args = ethers::abi::decode(&params, &data[4..]).ok()?;
+        Some(format!(
+            "{}({})",
+            error.name,
+            foundry_utils::format_tokens(&args).collect::<Vec<_>>().join(", ")
+        ))
+    });
+    Some(decoded.unwrap_or_else(|| format!("0x{}", hex::encode(data))))
+}
+
+/// Returns the hex encoded selector the test reverted with, if it can neither be decoded as a
+/// revert reason nor is one of the `known` custom errors
+fn unresolved_revert_selector(
+    result: &forge::TestResult,
+    known: &BTreeSet<String>,
+) -> Option<String> {
+    let data = result.revert_data.as_ref().filter(|data| data.len() >= 4)?;
+    let selector = format!("0x{}", hex::encode(&data[..4]));
+    (foundry_utils::decode_revert(data).is_err() && !known.contains(&selector)).then(|| selector)
+}
+
+/// Passing unit tests using less gas than this likely do not exercise anything
+const LOW_GAS_WARNING_THRESHOLD: u64 = 1_000;
+
+/// Returns the advisories on a single test, for results which might pass or fail for the wrong
+/// reasons
+pub(super) fn test_warnings(
+    result: &forge::TestResult,
+    assertions: Option<usize>,
+    known_errors: &BTreeSet<String>,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if result.success && !result.is_fuzz() && result.gas_used < LOW_GAS_WARNING_THRESHOLD {
+        warnings.push(format!("used only {} gas", result.gas_used));
+    }
+    if assertions == Some(0) {
+        warnings.push("made no assertions".to_string());
+    }
+    if let Some(selector) = unresolved_revert_selector(result, known_errors) {
+        warnings.push(format!("reverted with the unknown error selector {}", selector));
+    }
+    warnings
+}
+
+/// Prints the gross gas, refund and net gas of every unit test.
+///
+/// On chain, the refund of a transaction is capped to `1 / quotient` of the gas it used, tests
+/// whose refunds exceed that cap report gas figures that would not hold in a real transaction
+pub(super) fn print_gas_refund_summary(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    quotient: u64,
+    color: bool,
+) {
+    println!("Gas refunds:");
+    for (contract_name, tests) in results {
+        for (name, result) in tests.iter().filter(|(_, result)| !result.is_fuzz()) {
+            let gross = result.gas_used;
+            let refund = result.gas_refund;
+            let cap = gross / quotient.max(1);
+            let line = format!(
+                "  {}::{}: gross: {}, refund: {}, net: {}",
+                contract_name,
+                name,
+                gross,
+                refund,
+                gross - refund.min(cap)
+            );
+            if refund > cap {
+                println!(
+                    "{}",
+                    paint(color, Colour::Yellow, format!("{} (refund capped at {})", line, cap))
+                );
+            } else {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+/// The hex encoded 4 byte selector of the test with the given signature
+pub(super) fn selector(signature: &str) -> String {
+    format!("0x{}", hex::encode(&ethers::utils::keccak256(signature.as_bytes())[..4]))
+}
+
+/// The statistics of a fuzz test as a JSON object, `null` for unit tests.
+///
+/// Fuzz tests which pass without executing a single case are marked as `vacuous`, so that they
+/// can be told apart from actual passes
+pub(super) fn fuzz_stats(result: &forge::TestResult, fuzz_seed: u64) -> serde_json::Value {
+    let cases = match result.kind {
+        forge::TestKind::Fuzz(ref cases) => cases,
+        forge::TestKind::Standard(_) => return serde_json::Value::Null,
+    };
+    serde_json::json!({
+        "runs": cases.cases().len(),
+        "rejects": result.rejects,
+        "mean_gas": cases.mean_gas(),
+        "median_gas": cases.median_gas(),
+        "min_gas": cases.lowest_gas(),
+        "max_gas": cases.highest_gas(),
+        "counterexample": result.counterexample,
+        "unique_reverts": result.unique_reverts,
+        "seed": fuzz_seed,
+        "vacuous": result.success && cases.cases().is_empty(),
+    })
+}
+
+/// Writes a json record per test to stdout, flushing after every record so that consumers see them
+/// promptly.
+///
+/// Stops silently if the consumer closes the pipe
+pub(super) fn stream_results(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    fuzz_seed: u64,
+    assertions: Option<&AssertionCounts>,
+    known_errors: &BTreeSet<String>,
+) -> eyre::Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for (contract_name, tests) in results {
+        for (name, result) in tests {
+            let assertions = assertions.and_then(|a| a.get(contract_name, name, result));
+            let record = serde_json::json!({
+                "contract": contract_name,
+                "test": name,
+                "result": result,
+                "selector": selector(name),
+                "fuzz": fuzz_stats(result, fuzz_seed),
+                "assertions": assertions,
+                "warnings": test_warnings(result, assertions, known_errors),
+            });
+            let written = writeln!(out, "{}", record).and_then(|_| out.flush());
+            match written {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the results as a JUnit XML report, with a `<testsuite>` per contract and a `<testcase>`
+/// per test
+pub(super) fn write_junit_report(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    file: &Path,
+) -> eyre::Result<()> {
+    let all = results.values().flat_map(|tests| tests.values());
+    let failures = all.clone().filter(|result| !result.success).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += &format!("<testsuites tests=\"{}\" failures=\"{}\">\n", all.count(), failures);
+    for (contract_name, tests) in results {
+        let failures = tests.values().filter(|result| !result.success).count();
+        let time = tests.values().map(|result| result.duration.as_secs_f64()).sum::<f64>();
+        xml += &format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            xml_escape(contract_name),
+            tests.len(),
+            failures,
+            time
+        );
+        for (name, result) in tests {
+            xml += &format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">\n",
+                xml_escape(name),
+                xml_escape(contract_name),
+                result.duration.as_secs_f64()
+            );
+            xml += "      <properties>\n";
+            xml +=
+                &format!("        <property name=\"gas_used\" value=\"{}\"/>\n", result.gas_used);
+            xml += &format!(
+                "        <property name=\"duration_ms\" value=\"{}\"/>\n",
+                result.duration.as_millis()
+            );
+            xml += "      </properties>\n";
+            if !result.success {
+                let mut message = result
+                    .error
+                    .clone()
+                    .or_else(|| result.reason.clone())
+                    .unwrap_or_else(|| "test failed".to_string());
+                if let Some(ref counterexample) = result.counterexample {
+                    message += &format!(". Counterexample: {}", counterexample);
+                }
+                xml += &format!("      <failure message=\"{}\"/>\n", xml_escape(&message));
+            }
+            xml += "    </testcase>\n";
+        }
+        xml += "  </testsuite>\n";
+    }
+    xml += "</testsuites>\n";
+    std::fs::write(file, xml)?;
+    Ok(())
+}
+
+/// Escapes the characters which may not appear in XML attribute values
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Prints the immutables of every test contract
+pub(super) fn print_immutables_summary(immutables: &BTreeMap<String, Vec<(String, usize, H256)>>) {
+    println!("Immutables:");
+    for (contract_name, immutables) in immutables {
+        if immutables.is_empty() {
+            continue
+        }
+        println!("  {}:", contract_name);
+        for (variable, offset, value) in immutables {
+            println!("    {} at code offset {:#x}: {:?}", variable, offset, value);
+        }
+    }
+}
+
+/// Returns the file the traces of a test are stored in, relative to the trace directory
+fn trace_file(contract_name: &str, test_name: &str) -> PathBuf {
+    PathBuf::from(contract_name.replace(':', "/")).join(format!("{}.json", test_name))
+}
+
+/// Writes the call traces of every test into `dir`
+pub(super) fn write_traces(
+    dir: &Path,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> eyre::Result<()> {
+    for (contract_name, tests) in results {
+        for (name, result) in tests {
+            if let Some(ref traces) = result.traces {
+                let file = dir.join(trace_file(contract_name, name));
+                if let Some(parent) = file.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(file, serde_json::to_string_pretty(traces)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A frame of a gas flamegraph: a call which used `gas`, starting `x` gas into its parent
+struct FlameFrame {
+    name: String,
+    depth: usize,
+    x: u64,
+    gas: u64,
+}
+
+/// Appends the frame of the call at `idx` and the frames of all its subcalls to `frames`
+fn flame_frames(
+    arena: &CallTraceArena,
+    idx: usize,
+    identified_contracts: Option<&BTreeMap<Address, (String, Abi)>>,
+    depth: usize,
+    x: u64,
+    frames: &mut Vec<FlameFrame>,
+) {
+    let node = &arena.arena[idx];
+    let trace = &node.trace;
+    let (contract, func) = match identified_contracts.and_then(|ids| ids.get(&trace.addr)) {
+        Some((name, abi)) => {
+            let func = if trace.created {
+                Some("constructor".to_string())
+            } else {
+                abi.functions()
+                    .find(|func| trace.data.get(..4) == Some(&func.short_signature()[..]))
+                    .map(|func| func.name.clone())
+            };
+            (name.clone(), func)
+        }
+        None => (format!("{:?}", trace.addr), None),
+    };
+    let func = func
+        .unwrap_or_else(|| format!("0x{}", hex::encode(&trace.data[..trace.data.len().min(4)])));
+    frames.push(FlameFrame { name: format!("{}::{}", contract, func), depth, x, gas: trace.cost });
+
+    // subcalls are laid out next to each other, the gas left over is used by the call itself
+    let mut child_x = x;
+    for child in &node.children {
+        flame_frames(arena, *child, identified_contracts, depth + 1, child_x, frames);
+        child_x += arena.arena[*child].trace.cost;
+    }
+}
+
+/// Escapes the characters of `text` which are special in svg
+fn escape_svg(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Renders `frames` as an svg flamegraph with the root calls at the bottom and their width
+/// proportional to their gas
+fn flamegraph_svg(title: &str, frames: &[FlameFrame]) -> String {
+    const WIDTH: f64 = 1200.0;
+    const FRAME_HEIGHT: usize = 16;
+    // approximate width of a character of the 12px monospace font
+    const CHAR_WIDTH: f64 = 7.2;
+
+    let total = frames.iter().filter(|frame| frame.depth == 0).map(|frame| frame.gas).sum::<u64>();
+    let max_depth = frames.iter().map(|frame| frame.depth).max().unwrap_or_default();
+    let height = (max_depth + 2) * FRAME_HEIGHT;
+    let scale = if total == 0 { 0.0 } else { WIDTH / total as f64 };
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"12\">\n",
+        WIDTH, height
+    );
+    svg.push_str(&format!("<text x=\"4\" y=\"12\">{} ({} gas)</text>\n", escape_svg(title), total));
+    for frame in frames {
+        let x = frame.x as f64 * scale;
+        let width = frame.gas as f64 * scale;
+        let y = (max_depth - frame.depth + 1) * FRAME_HEIGHT;
+        // warm colors which differ between frames of different calls
+        let hash =
+            frame.name.bytes().fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+        let percent = if total == 0 { 0.0 } else { frame.gas as f64 * 100.0 / total as f64 };
+        svg.push_str(&format!(
+            "<g><title>{} ({} gas, {:.2}%)</title><rect x=\"{:.2}\" y=\"{}\" width=\"{:.2}\" height=\"{}\" fill=\"rgb({},{},{})\"/>",
+            escape_svg(&frame.name),
+            frame.gas,
+            percent,
+            x,
+            y,
+            width,
+            FRAME_HEIGHT - 1,
+            205 + hash % 50,
+            hash / 50 % 180,
+            hash / 9000 % 55,
+        ));
+        let chars = ((width - 6.0) / CHAR_WIDTH) as usize;
+        if chars >= 3 {
+            let label = if frame.name.chars().count() > chars {
+                format!("{}..", frame.name.chars().take(chars - 2).collect::<String>())
+            } else {
+                frame.name.clone()
+            };
+            svg.push_str(&format!(
+                "<text x=\"{:.2}\" y=\"{}\">{}</text>",
+                x + 3.0,
+                y + FRAME_HEIGHT - 4,
+                escape_svg(&label)
+            ));
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Writes a flamegraph of the gas used by the calls of `test`, named by its name or signature,
+/// as svg into `file`. The setup calls and the test call are the roots of the graph
+pub(super) fn write_flamegraph(
+    test: &str,
+    file: &Path,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> eyre::Result<()> {
+    let (name, result) = results
+        .values()
+        .flat_map(|tests| tests.iter())
+        .find(|(name, _)| name.as_str() == test || name.split('(').next() == Some(test))
+        .ok_or_else(|| eyre::eyre!("no test named `{}` ran, can not emit its flamegraph", test))?;
+    let traces = result
+        .traces
+        .as_ref()
+        .ok_or_else(|| eyre::eyre!("`{}` has no call traces to emit a flamegraph of", name))?;
+
+    let mut frames = Vec::new();
+    let mut x = 0;
+    for arena in traces {
+        flame_frames(arena, arena.entry, result.identified_contracts.as_ref(), 0, x, &mut frames);
+        x += arena.arena[arena.entry].trace.cost;
+    }
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(file, flamegraph_svg(name, &frames))?;
+    Ok(())
+}
+
+/// Compares the call traces of every test against the baseline traces in `dir` and prints all
+/// structural differences
+pub(super) fn print_trace_diffs(
+    dir: &Path,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    color: bool,
+) -> eyre::Result<()> {
+    println!("Trace differences against {}:", dir.display());
+    let mut differing = 0;
+    for (contract_name, tests) in results {
+        for (name, result) in tests {
+            let traces = match result.traces {
+                Some(ref traces) => traces,
+                None => continue,
+            };
+            let file = dir.join(trace_file(contract_name, name));
+            if !file.exists() {
+                println!("{} {}: no baseline traces", paint(color, Colour::Yellow, "[NEW]"), name);
+                continue
+            }
+            let baseline: Vec<CallTraceArena> =
+                serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+
+            let mut diffs = Vec::new();
+            if baseline.len() != traces.len() {
+                diffs.push(format!(
+                    "{} traced transactions, baseline has {}",
+                    traces.len(),
+                    baseline.len()
+                ));
+            }
+            for (i, (trace, baseline)) in traces.iter().zip(baseline.iter()).enumerate() {
+                diffs.extend(trace.diff(baseline).into_iter().map(|d| format!("tx {}: {}", i, d)));
+            }
+
+            if !diffs.is_empty() {
+                differing += 1;
+                println!("{} {}", paint(color, Colour::Red, "[DIFF]"), name);
+                for diff in diffs {
+                    println!("  {}", diff);
+                }
+            }
+        }
+    }
+    if differing == 0 {
+        println!("No differences.");
+    }
+    Ok(())
+}
+
+/// Where the time of a test run went
+pub(super) struct Timing {
+    /// Resolving the config and setting up the project, before compiling
+    pub(super) setup: Duration,
+    pub(super) compile: Duration,
+    /// Running the tests, excluding any `--bench` reruns
+    pub(super) execution: Duration,
+    pub(super) total: Duration,
+}
+
+impl Timing {
+    pub(super) fn new(
+        started: Instant,
+        compile_start: Instant,
+        compile: Duration,
+        execution: Duration,
+    ) -> Self {
+        Self {
+            setup: compile_start.duration_since(started),
+            compile,
+            execution,
+            total: started.elapsed(),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Timing: setup {:.2?}, compile {:.2?}, execution {:.2?}, total {:.2?}",
+            self.setup, self.compile, self.execution, self.total
+        )
+    }
+}
+
+/// The aggregate outcome of a test run
+pub(super) struct RunSummary {
+    pub(super) passed: usize,
+    pub(super) failed: usize,
+    /// The fuzz and invariant tests which gave up after too many rejected inputs, not counted as
+    /// failed
+    pub(super) rejected: usize,
+    /// The fuzz tests which failed but passed when they were run again, counted as passed
+    pub(super) flaky: usize,
+    /// The gas used by all tests, the median of every fuzz test
+    pub(super) gas_used: u64,
+    /// The wall time it took to run all tests
+    pub(super) duration: Duration,
+}
+
+impl RunSummary {
+    pub(super) fn new<'a>(
+        results: impl Iterator<Item = &'a BTreeMap<String, forge::TestResult>>,
+        duration: Duration,
+    ) -> Self {
+        let mut summary =
+            Self { passed: 0, failed: 0, rejected: 0, flaky: 0, gas_used: 0, duration };
+        for result in results.flat_map(|tests| tests.values()) {
+            if result.success {
+                summary.passed += 1;
+                summary.flaky += result.flaky as usize;
+            } else if result.rejected {
+                summary.rejected += 1;
+            } else {
+                summary.failed += 1;
+            }
+            summary.gas_used += result.gas_used;
+        }
+        summary
+    }
+
+    /// The summary line printed after all test results
+    pub(super) fn line(&self, color: bool) -> String {
+        let status = if self.failed == 0 && self.rejected == 0 {
+            paint(color, Colour::Green, "ok")
+        } else {
+            paint(color, Colour::Red, "FAILED")
+        };
+        format!(
+            "Test result: {}. {} passed; {} failed; {} rejected; {} flaky; {} gas; finished in {:.2?}",
+            status,
+            self.passed,
+            self.failed,
+            self.rejected,
+            self.flaky,
+            self.gas_used,
+            self.duration
+        )
+    }
+}
+
+/// The data `--output-template` templates are rendered with
+pub(super) fn template_data(
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    summary: &RunSummary,
+    timing: &Timing,
+) -> serde_json::Value {
+    let contracts = results
+        .iter()
+        .map(|(contract_name, tests)| {
+            let tests = tests
+                .iter()
+                .map(|(name, result)| {
+                    serde_json::json!({
+                        "name": name,
+                        "success": result.success,
+                        "flaky": result.flaky,
+                        "status": match (result.success, result.rejected) {
+                            (true, _) => "PASS",
+                            (false, true) => "REJECTED",
+                            (false, false) => "FAIL",
+                        },
+                        "reason": result.reason,
+                        "counterexample": result.counterexample.as_ref().map(ToString::to_string),
+                        "gas": result.gas_used,
+                        "duration_ms": result.duration.as_millis() as u64,
+                        "fuzz": result.is_fuzz(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let passed = tests.iter().filter(|test| test["status"] == "PASS").count();
+            let rejected = tests.iter().filter(|test| test["status"] == "REJECTED").count();
+            serde_json::json!({
+                "name": contract_name,
+                "tests": tests,
+                "passed": passed,
+                "failed": tests.len() - passed - rejected,
+                "rejected": rejected,
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!({
+        "contracts": contracts,
+        "summary": {
+            "success": summary.failed == 0 && summary.rejected == 0,
+            "passed": summary.passed,
+            "failed": summary.failed,
+            "rejected": summary.rejected,
+            "flaky": summary.flaky,
+            "gas_used": summary.gas_used,
+            "duration": format!("{:.2?}", summary.duration),
+            "duration_ms": summary.duration.as_millis() as u64,
+        },
+        "timing": {
+            "setup_ms": timing.setup.as_millis() as u64,
+            "compile_ms": timing.compile.as_millis() as u64,
+            "execution_ms": timing.execution.as_millis() as u64,
+            "total_ms": timing.total.as_millis() as u64,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::test::{
+        tests::{counter_project, evm_opts, testdata_project},
+        without_cache, Filter,
+    };
+    use clap::Parser;
+    use forge::MultiContractRunnerBuilder;
+
+    #[test]
+    fn hides_cheap_and_excluded_tests_from_the_report() {
+        let result = |gas_used| forge::TestResult { success: true, gas_used, ..Default::default() };
+        let mut results = BTreeMap::from([
+            (
+                "A.json:A".to_string(),
+                BTreeMap::from([
+                    ("testCheap()".to_string(), result(10)),
+                    ("testExpensive()".to_string(), result(1000)),
+                    ("testSkipExpensive()".to_string(), result(1000)),
+                ]),
+            ),
+            ("B.json:B".to_string(), BTreeMap::from([("testSkip()".to_string(), result(1000))])),
+        ]);
+        let no_match = regex::Regex::new("^testSkip").unwrap();
+        let hidden = hide_from_report(&mut results, Some(100), Some(&no_match));
+        assert_eq!(
+            results.values().flat_map(|tests| tests.keys()).collect::<Vec<_>>(),
+            ["testExpensive()"]
+        );
+        assert_eq!(
+            hidden.iter().map(|(name, tests)| (name.as_str(), tests.len())).collect::<Vec<_>>(),
+            [("A.json:A", 2), ("B.json:B", 1)]
+        );
+        assert!(hide_from_report(&mut results, None, None).is_empty());
+    }
+
+    #[test]
+    fn writes_junit_reports() {
+        let passed = forge::TestResult {
+            success: true,
+            gas_used: 100,
+            duration: Duration::from_millis(1500),
+            ..Default::default()
+        };
+        let failed = forge::TestResult {
+            reason: Some("a < b & \"c\"".to_string()),
+            gas_used: 7,
+            duration: Duration::from_millis(250),
+            ..Default::default()
+        };
+        let results = BTreeMap::from([(
+            "Greeter.json:GreeterTest".to_string(),
+            BTreeMap::from([
+                ("testFail()".to_string(), failed),
+                ("testPass()".to_string(), passed),
+            ]),
+        )]);
+        let file = std::env::temp_dir().join("forge-junit.xml");
+        write_junit_report(&results, &file).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="2" failures="1">
+  <testsuite name="Greeter.json:GreeterTest" tests="2" failures="1" time="1.750">
+    <testcase name="testFail()" classname="Greeter.json:GreeterTest" time="0.250">
+      <properties>
+        <property name="gas_used" value="7"/>
+        <property name="duration_ms" value="250"/>
+      </properties>
+      <failure message="a &lt; b &amp; &quot;c&quot;"/>
+    </testcase>
+    <testcase name="testPass()" classname="Greeter.json:GreeterTest" time="1.500">
+      <properties>
+        <property name="gas_used" value="100"/>
+        <property name="duration_ms" value="1500"/>
+      </properties>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn rolls_up_the_tests_of_every_contract() {
+        let result = |success: bool, gas_used: u64, millis: u64| forge::TestResult {
+            success,
+            gas_used,
+            duration: Duration::from_millis(millis),
+            deploy_gas: 1000,
+            setup_gas: 200,
+            ..Default::default()
+        };
+        let results = BTreeMap::from([(
+            "Greeter.json:GreeterTest".to_string(),
+            BTreeMap::from([
+                ("testA()".to_string(), result(true, 10, 5)),
+                ("testB()".to_string(), result(false, 20, 7)),
+                (
+                    "testC(uint256)".to_string(),
+                    forge::TestResult { rejected: true, ..result(false, 30, 1) },
+                ),
+                (
+                    "testD(uint256)".to_string(),
+                    forge::TestResult { flaky: true, ..result(true, 40, 2) },
+                ),
+            ]),
+        )]);
+        let rollups = contract_rollups(&results);
+        assert_eq!(
+            rollups[&"Greeter.json:GreeterTest".to_string()],
+            serde_json::json!({
+                "deploy_gas": 1000,
+                "setup_gas": 200,
+                "tests": 4,
+                "passed": 2,
+                "failed": 1,
+                "rejected": 1,
+                "flaky": 1,
+                "gas_used": 100,
+                "duration_ms": 15,
+            })
+        );
+    }
+
+    #[test]
+    fn renders_templates_with_the_duration_of_every_test() {
+        let result = forge::TestResult {
+            success: true,
+            gas_used: 21000,
+            duration: Duration::from_millis(42),
+            ..Default::default()
+        };
+        let results = BTreeMap::from([(
+            "Greeter.json:GreeterTest".to_string(),
+            BTreeMap::from([("testGreet()".to_string(), result)]),
+        )]);
+        let summary = RunSummary::new(results.values(), Duration::from_millis(50));
+        let timing = Timing {
+            setup: Duration::ZERO,
+            compile: Duration::ZERO,
+            execution: Duration::from_millis(50),
+            total: Duration::from_millis(50),
+        };
+        let template = crate::utils::Template::parse(
+            "{{#each contracts}}{{#each tests}}{{name}} {{status}} {{duration_ms}}ms{{/each}}{{/each}}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&template_data(&results, &summary, &timing)),
+            "testGreet() PASS 42ms"
+        );
+    }
+
+    #[test]
+    fn reports_the_gas_of_the_calls_to_non_test_contracts() {
+        let (mut runner, _) = MultiContractRunnerBuilder::default()
+            .force_tracing(true)
+            .build_with_output(without_cache(counter_project()), evm_opts())
+            .unwrap();
+        let results =
+            runner.test(&Filter::parse_from(["forge", "--match-contract", "CounterTest"])).unwrap();
+        let report = collect_gas_report(&results);
+        // the test contract is not part of the report
+        assert_eq!(report.keys().collect::<Vec<_>>(), vec!["Counter"]);
+        let counter = &report["Counter"];
+        // both tests increment the counter and one reads it
+        assert_eq!(counter["increment"].len(), 2);
+        assert_eq!(counter["count"].len(), 1);
+        assert!(counter["increment"].iter().all(|gas| *gas > 0));
+    }
+
+    #[test]
+    fn aggregates_the_gas_of_the_calls_to_a_function() {
+        let stats = function_gas_stats(&[40, 10, 30, 20], &[50, 90]);
+        assert_eq!(
+            stats,
+            serde_json::json!({
+                "calls": 4,
+                "min": 10,
+                "mean": 25,
+                "median": 30,
+                "max": 40,
+                "p50": 20,
+                "p90": 40,
+            })
+        );
+        // there are no samples without calls
+        assert_eq!(function_gas_stats(&[], &[50])["p50"], 0);
+    }
+
+    #[test]
+    fn lists_the_test_helpers_no_test_called() {
+        let (mut runner, _) = MultiContractRunnerBuilder::default()
+            .force_tracing(true)
+            .build_with_output(without_cache(testdata_project("helpers")), evm_opts())
+            .unwrap();
+        let results = runner.test(&Filter::parse_from(["forge"])).unwrap();
+        assert!(results.values().flat_map(|tests| tests.values()).all(|result| result.success));
+        // `deposit()` is called by the test, `setUp()` and the ds-test functions are not helpers
+        assert_eq!(
+            collect_unused_test_helpers(&runner, &results),
+            vec!["Helpers.json:HelpersTest::unusedHelper()".to_string()]
+        );
+    }
+
+    #[test]
+    fn lists_the_contracts_created_in_setup_in_order() {
+        let (mut runner, _) = MultiContractRunnerBuilder::default()
+            .force_tracing(true)
+            .build_with_output(without_cache(testdata_project("helpers")), evm_opts())
+            .unwrap();
+        let results = runner.test(&Filter::parse_from(["forge"])).unwrap();
+        let order = collect_deployment_order(&runner, &results);
+        let deployments = &order["Helpers.json:HelpersTest"];
+        assert_eq!(
+            deployments.iter().map(|deployment| deployment.name.as_str()).collect::<Vec<_>>(),
+            vec!["Token", "Vault"]
+        );
+        assert!(deployments.iter().all(|deployment| !deployment.reverted && deployment.gas > 0));
+        assert_ne!(deployments[0].address, deployments[1].address);
+    }
+
+    #[test]
+    fn emits_gas_flamegraphs_of_tests() {
+        let (mut runner, _) = MultiContractRunnerBuilder::default()
+            .force_tracing(true)
+            .build_with_output(without_cache(counter_project()), evm_opts())
+            .unwrap();
+        let results = runner.test(&Filter::parse_from(["forge"])).unwrap();
+        let file = std::env::temp_dir().join("forge-flamegraph").join("testIncrement.svg");
+        write_flamegraph("testIncrement", &file, &results).unwrap();
+        let svg = std::fs::read_to_string(&file).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert!(svg.contains("<title>CounterTest::testIncrement ("));
+        assert!(svg.contains("<title>Counter::increment ("));
+        assert!(write_flamegraph("testMissing", &file, &results).is_err());
+    }
+
+    #[test]
+    fn lays_out_flamegraph_frames_by_gas() {
+        let frame =
+            |name: &str, depth, x, gas| FlameFrame { name: name.to_string(), depth, x, gas };
+        let frames = [frame("A::a", 0, 0, 100), frame("B::b", 1, 0, 30), frame("C::c", 1, 30, 20)];
+        let svg = flamegraph_svg("testA()", &frames);
+        assert!(svg.contains("<text x=\"4\" y=\"12\">testA() (100 gas)</text>"));
+        // the root spans the whole width at the bottom, its subcalls are stacked on top of it
+        assert!(svg.contains(
+            "<title>A::a (100 gas, 100.00%)</title><rect x=\"0.00\" y=\"32\" width=\"1200.00\""
+        ));
+        assert!(svg.contains(
+            "<title>B::b (30 gas, 30.00%)</title><rect x=\"0.00\" y=\"16\" width=\"360.00\""
+        ));
+        assert!(svg.contains(
+            "<title>C::c (20 gas, 20.00%)</title><rect x=\"360.00\" y=\"16\" width=\"240.00\""
+        ));
+        assert!(flamegraph_svg("<&>", &[]).contains("&lt;&amp;&gt; (0 gas)"));
+    }
+}
@@ -0,0 +1,331 @@
+//! Bundles which reproduce a test run
+
+use super::TestArgs;
+use clap::Parser;
+use ethers::{
+    solc::{ArtifactOutput, Project},
+    types::{Bytes, H256},
+};
+use evm_adapters::sputnik::{
+    new_shared_cache,
+    sputnik_evm::backend::{MemoryAccount, MemoryVicinity},
+    MemCache, SharedCache,
+};
+use eyre::WrapErr;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// What `--repro-bundle` records next to the results and the compiler settings
+#[derive(Clone)]
+pub(super) struct ReproBundle {
+    /// The directory the bundle is written to
+    pub(super) dir: PathBuf,
+    /// The arguments of the run
+    pub(super) argv: Vec<String>,
+    /// The working directory the relative paths of the arguments are relative to
+    pub(super) cwd: PathBuf,
+    /// The resolved paths of the project, see `repro_paths`
+    pub(super) paths: serde_json::Value,
+    /// The resolved configuration, including the seeds
+    pub(super) config: serde_json::Value,
+    /// The block environment of the fork and the cache of the state fetched from it
+    pub(super) fork: Option<(MemoryVicinity, SharedCache<MemCache>)>,
+}
+
+/// The file within a `--repro-bundle` directory everything is recorded in
+const REPRO_BUNDLE_FILE: &str = "bundle.json";
+
+/// Writes the reproducibility bundle of a run with the given `compiler` settings and `results`
+pub(super) fn write_repro_bundle(
+    bundle: &ReproBundle,
+    compiler: Option<&serde_json::Value>,
+    results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+) -> eyre::Result<()> {
+    let failed = results
+        .iter()
+        .flat_map(|(contract_name, tests)| {
+            tests
+                .iter()
+                .filter(|(_, result)| !result.success)
+                .map(move |(name, _)| format!("{}::{}", contract_name, name))
+        })
+        .collect::<Vec<_>>();
+    let fork = bundle.fork.as_ref().map(|(vicinity, cache)| {
+        serde_json::json!({
+            "env": {
+                "chain_id": vicinity.chain_id.low_u64(),
+                "gas_price": vicinity.gas_price.low_u64(),
+                "tx_origin": vicinity.origin,
+                "block_coinbase": vicinity.block_coinbase,
+                "block_number": vicinity.block_number.low_u64(),
+                "block_timestamp": vicinity.block_timestamp.low_u64(),
+                "block_difficulty": vicinity.block_difficulty.low_u64(),
+                "block_gas_limit": vicinity.block_gas_limit.low_u64(),
+                "block_base_fee_per_gas": vicinity.block_base_fee_per_gas.low_u64(),
+            },
+            "state": cache
+                .read()
+                .iter()
+                .map(|(address, account)| {
+                    let storage = account
+                        .storage
+                        .iter()
+                        .map(|(slot, value)| (format!("{:?}", slot), serde_json::json!(value)))
+                        .collect::<serde_json::Map<_, _>>();
+                    (
+                        format!("{:?}", address),
+                        serde_json::json!({
+                            "nonce": account.nonce,
+                            "balance": account.balance,
+                            "code": Bytes::from(account.code.clone()),
+                            "storage": storage,
+                        }),
+                    )
+                })
+                .collect::<serde_json::Map<_, _>>(),
+        })
+    });
+    let output = serde_json::json!({
+        "args": bundle.argv,
+        "cwd": bundle.cwd,
+        "paths": bundle.paths,
+        "config": bundle.config,
+        "compiler": compiler,
+        "fork": fork,
+        "failed": failed,
+    });
+    std::fs::create_dir_all(&bundle.dir)?;
+    std::fs::write(bundle.dir.join(REPRO_BUNDLE_FILE), serde_json::to_string_pretty(&output)?)?;
+    Ok(())
+}
+
+/// The absolute root, libraries and remappings of the project, which `--repro-bundle` records so
+/// that the project is found again wherever the tests are rerun from
+pub(super) fn repro_paths<A: ArtifactOutput>(project: &Project<A>) -> serde_json::Value {
+    serde_json::json!({
+        "root": project.paths.root,
+        "libraries": project.paths.libraries,
+        "remappings": project.paths.remappings.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+    })
+}
+
+/// Sets the resolved paths of the project recorded by `repro_paths`
+fn apply_repro_paths(args: &mut TestArgs, paths: &serde_json::Value) -> eyre::Result<()> {
+    args.opts.root = serde_json::from_value(paths["root"].clone())?;
+    args.opts.lib_paths = serde_json::from_value(paths["libraries"].clone())?;
+    let remappings: Vec<String> = serde_json::from_value(paths["remappings"].clone())?;
+    args.opts.remappings = remappings
+        .iter()
+        .map(|remapping| remapping.parse().map_err(|err| eyre::eyre!("{}", err)))
+        .collect::<eyre::Result<_>>()?;
+    // the remappings of the environment are part of the recorded ones
+    args.opts.remappings_env = None;
+    Ok(())
+}
+
+/// Resolves the relative file and directory arguments of a run recorded in a `--repro-bundle`
+/// against the working directory it was recorded in
+fn resolve_repro_args(args: &mut TestArgs, cwd: &Path) {
+    let paths = [
+        &mut args.filter.filter_file,
+        &mut args.fuzz_persist_success,
+        &mut args.trace_out,
+        &mut args.diff_traces,
+        &mut args.export_bindings,
+        &mut args.fuzz_shrink_to_file,
+        &mut args.fuzz_inputs_as_json,
+        &mut args.write_fuzz_regression,
+        &mut args.seed_accounts,
+        &mut args.output_template,
+        &mut args.junit,
+        &mut args.print_contract_sizes_csv,
+    ];
+    for path in paths.into_iter().flatten() {
+        if path.is_relative() {
+            *path = cwd.join(&path);
+        }
+    }
+}
+
+/// Sets the optimizer, the EVM version and whether solc is auto-detected to those of the
+/// `compiler` settings recorded by `compiler_info`
+fn apply_repro_compiler(args: &mut TestArgs, compiler: &serde_json::Value) -> eyre::Result<()> {
+    let optimizer = &compiler["optimizer"];
+    args.opts.compiler.optimize = optimizer["enabled"].as_bool().unwrap_or_default();
+    if let Some(runs) = optimizer["runs"].as_u64() {
+        args.opts.compiler.optimize_runs = runs as u32;
+    }
+    if let Some(evm_version) = serde_json::from_value(compiler["evm_version"].clone())? {
+        args.opts.compiler.evm_version = evm_version;
+    }
+    args.opts.no_auto_detect = compiler["auto_detect"].as_bool() == Some(false);
+    args.repro_compiler = Some(compiler.clone());
+    Ok(())
+}
+
+/// Fails if the compiler settings of the project differ from those recorded in a
+/// `--repro-bundle`. The solc version is only compared if it was not auto-detected, as it is then
+/// picked per source file
+pub(super) fn check_repro_compiler(
+    recorded: &serde_json::Value,
+    current: &serde_json::Value,
+) -> eyre::Result<()> {
+    let mut current = current.clone();
+    if recorded["auto_detect"].as_bool() != Some(false) {
+        current["solc_version"] = recorded["solc_version"].clone();
+    }
+    if current != *recorded {
+        eyre::bail!(
+            "the compiler settings {} differ from the settings {} recorded in the bundle",
+            current,
+            recorded
+        )
+    }
+    Ok(())
+}
+
+/// Reads the arguments of the run recorded in a `--repro-bundle` directory, with its seeds, its
+/// paths resolved as they were recorded, its compiler settings, and the recorded fork state in
+/// place of the fork
+pub(super) fn read_repro_bundle(dir: &Path) -> eyre::Result<TestArgs> {
+    let file = dir.join(REPRO_BUNDLE_FILE);
+    let bundle: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(&file)
+            .wrap_err_with(|| format!("failed to read {}", file.display()))?,
+    )?;
+    let argv: Vec<String> = serde_json::from_value(bundle["args"].clone())?;
+    let test_args = |argv: &[String]| -> eyre::Result<TestArgs> {
+        match crate::opts::forge::Opts::try_parse_from(argv)?.sub {
+            crate::opts::forge::Subcommands::Test(args) => Ok(args),
+            _ => Err(eyre::eyre!("{} does not record a `forge test` run", file.display())),
+        }
+    };
+    let mut args = test_args(&argv)?;
+    let paths = bundle.get("paths").filter(|paths| !paths.is_null());
+    // the profile is read from the recorded root, and the paths are set again if the profile
+    // adds arguments, which parses them anew
+    if let Some(paths) = paths {
+        apply_repro_paths(&mut args, paths)?;
+    }
+    let extra = args.read_profile(&argv)?;
+    if !extra.is_empty() {
+        args = test_args(&[argv, extra].concat())?;
+        if let Some(paths) = paths {
+            apply_repro_paths(&mut args, paths)?;
+        }
+    }
+    if let Some(cwd) = bundle["cwd"].as_str() {
+        resolve_repro_args(&mut args, Path::new(cwd));
+    }
+    if let Some(compiler) = bundle.get("compiler").filter(|compiler| !compiler.is_null()) {
+        apply_repro_compiler(&mut args, compiler)?;
+    }
+    args.repro_bundle = None;
+    args.from_repro_bundle = None;
+    args.fuzz_seed = bundle["config"]["fuzz_seed"].as_u64();
+    args.randomize_order_seed =
+        bundle["config"]["order_seed"].as_u64().or(args.randomize_order_seed);
+
+    if let Some(fork) = bundle.get("fork").filter(|fork| !fork.is_null()) {
+        let recorded = &fork["env"];
+        let value = |key: &str| {
+            recorded[key].as_u64().ok_or_else(|| eyre::eyre!("the fork env has no `{}`", key))
+        };
+        let env = &mut args.evm_opts.env;
+        env.chain_id = value("chain_id")?;
+        env.gas_price = value("gas_price")?;
+        env.tx_origin = serde_json::from_value(recorded["tx_origin"].clone())?;
+        env.block_coinbase = serde_json::from_value(recorded["block_coinbase"].clone())?;
+        env.block_number = value("block_number")?;
+        env.block_timestamp = value("block_timestamp")?;
+        env.block_difficulty = value("block_difficulty")?;
+        env.block_gas_limit = Some(value("block_gas_limit")?);
+        env.block_base_fee_per_gas = value("block_base_fee_per_gas")?;
+
+        let mut state = MemCache::default();
+        for (address, account) in fork["state"].as_object().into_iter().flatten() {
+            let storage = account["storage"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(slot, value)| -> eyre::Result<(H256, H256)> {
+                    Ok((slot.parse()?, serde_json::from_value(value.clone())?))
+                })
+                .collect::<eyre::Result<_>>()?;
+            let code: Bytes = serde_json::from_value(account["code"].clone())?;
+            state.insert(
+                address.parse()?,
+                MemoryAccount {
+                    nonce: serde_json::from_value(account["nonce"].clone())?,
+                    balance: serde_json::from_value(account["balance"].clone())?,
+                    storage,
+                    code: code.to_vec(),
+                },
+            );
+        }
+        args.evm_opts.fork_url = None;
+        args.evm_opts.fork_cache = Some(new_shared_cache(state));
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::test::tests::counter_project;
+    use ethers::solc::EvmVersion;
+
+    #[test]
+    fn reads_back_the_recorded_run_of_a_repro_bundle() {
+        let project = counter_project();
+        let cwd = std::env::temp_dir().join("forge-repro-cwd");
+        let bundle = ReproBundle {
+            dir: std::env::temp_dir().join("forge-repro-bundle"),
+            argv: ["forge", "test", "--junit", "junit.xml"].map(String::from).to_vec(),
+            cwd: cwd.clone(),
+            paths: repro_paths(&project),
+            config: serde_json::json!({ "fuzz_seed": 42 }),
+            fork: None,
+        };
+        let compiler = serde_json::json!({
+            "solc_version": "0.8.10",
+            "auto_detect": true,
+            "optimizer": { "enabled": true, "runs": 300 },
+            "evm_version": "berlin",
+        });
+        write_repro_bundle(&bundle, Some(&compiler), &BTreeMap::new()).unwrap();
+
+        let args = read_repro_bundle(&bundle.dir).unwrap();
+        assert_eq!(args.fuzz_seed, Some(42));
+        // the relative paths are resolved as they were when the bundle was written
+        assert_eq!(args.junit, Some(cwd.join("junit.xml")));
+        assert_eq!(args.opts.root.as_ref(), Some(&project.paths.root));
+        // the project is compiled with the recorded settings
+        assert!(args.opts.compiler.optimize);
+        assert_eq!(args.opts.compiler.optimize_runs, 300);
+        assert_eq!(args.opts.compiler.evm_version, EvmVersion::Berlin);
+        assert!(!args.opts.no_auto_detect);
+        assert_eq!(args.repro_compiler, Some(compiler));
+    }
+
+    #[test]
+    fn checks_the_compiler_settings_of_a_repro_bundle() {
+        let settings = |auto_detect: bool, solc_version: &str, runs: u64| {
+            serde_json::json!({
+                "solc_version": solc_version,
+                "auto_detect": auto_detect,
+                "optimizer": { "enabled": true, "runs": runs },
+                "evm_version": "london",
+            })
+        };
+        let recorded = settings(false, "0.8.10", 200);
+        assert!(check_repro_compiler(&recorded, &settings(false, "0.8.10", 200)).is_ok());
+        assert!(check_repro_compiler(&recorded, &settings(false, "0.8.13", 200)).is_err());
+        assert!(check_repro_compiler(&recorded, &settings(false, "0.8.10", 1)).is_err());
+        // an auto-detected solc is picked per source file
+        let recorded = settings(true, "0.8.10", 200);
+        assert!(check_repro_compiler(&recorded, &settings(true, "0.8.13", 200)).is_ok());
+    }
+}
@@ -15,11 +15,19 @@ fn main() -> eyre::Result<()> {
     color_eyre::install()?;
     utils::subscriber();
 
-    let opts = Opts::parse();
+    let opts = Opts::parse().apply_profile(std::env::args().collect())?;
     match opts.sub {
         Subcommands::Test(cmd) => {
             let outcome = cmd.run()?;
-            outcome.ensure_ok()?;
+            let result = outcome.ensure_ok();
+            if result.is_err() {
+                outcome.run_on_failure_command()?;
+            }
+            if outcome.has_fatal_panics() {
+                eprintln!("Aborting: tests failed with a solidity Panic");
+                std::process::exit(2);
+            }
+            result?;
         }
         Subcommands::Build(cmd) => {
             cmd.run()?;
@@ -13,6 +13,23 @@ pub struct Opts {
     pub sub: Subcommands,
 }
 
+impl Opts {
+    /// Reparses the command line `argv` these options were parsed from together with the options
+    /// of the `foundry.toml` profile, if the subcommand runs the tests, see
+    /// [`test::TestArgs::read_profile`]
+    pub fn apply_profile(self, argv: Vec<String>) -> eyre::Result<Self> {
+        let extra = match &self.sub {
+            Subcommands::Test(args) => args.read_profile(&argv)?,
+            Subcommands::Snapshot(args) => args.test_args().read_profile(&argv)?,
+            _ => return Ok(self),
+        };
+        if extra.is_empty() {
+            return Ok(self)
+        }
+        Ok(Opts::try_parse_from(argv.into_iter().chain(extra))?)
+    }
+}
+
 #[derive(Debug, Subcommand)]
 #[clap(name = "forge")]
 #[clap(about = "Build, test, fuzz, formally verify, debug & deploy solidity contracts.")]
@@ -1,7 +1,12 @@
-use ethers::solc::{artifacts::Contract, EvmVersion};
+use ethers::solc::{artifacts::Contract, CompilerOutput, EvmVersion};
 
+use evm_adapters::call_tracing::SourceLocations;
 use eyre::{ContextCompat, WrapErr};
-use std::{path::PathBuf, process::Command};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 #[cfg(feature = "evmodin-evm")]
 use evmodin::Revision;
@@ -65,6 +70,267 @@ pub fn find_git_root_path() -> eyre::Result<PathBuf> {
     Ok(PathBuf::from(path))
 }
 
+/// Returns every node of a solc AST, depth first in source order
+pub fn ast_nodes(ast: &serde_json::Value) -> Vec<&serde_json::Value> {
+    fn walk<'a>(value: &'a serde_json::Value, nodes: &mut Vec<&'a serde_json::Value>) {
+        match value {
+            serde_json::Value::Object(object) => {
+                if object.contains_key("nodeType") {
+                    nodes.push(value);
+                }
+                object.values().for_each(|value| walk(value, nodes));
+            }
+            serde_json::Value::Array(values) => values.iter().for_each(|value| walk(value, nodes)),
+            _ => {}
+        }
+    }
+    let mut nodes = Vec::new();
+    walk(ast, &mut nodes);
+    nodes
+}
+
+/// The path relative to `root` and the content of every source of the compiler output, by the
+/// source index the `src` ranges of its AST refer to it with
+pub fn source_contents(root: &Path, output: &CompilerOutput) -> BTreeMap<u64, (String, String)> {
+    output
+        .sources
+        .iter()
+        .filter_map(|(path, source)| {
+            let file = root.join(path);
+            let content = std::fs::read_to_string(&file).ok()?;
+            let path = file.strip_prefix(root).unwrap_or(&file).display().to_string();
+            Some((source.id as u64, (path, content)))
+        })
+        .collect()
+}
+
+/// The `file:line` at which the `src` range of an AST node starts
+pub fn src_location(
+    node: &serde_json::Value,
+    contents: &BTreeMap<u64, (String, String)>,
+) -> Option<String> {
+    let mut src = node["src"].as_str()?.split(':').map(str::parse::<usize>);
+    let (start, index) = (src.next()?.ok()?, src.nth(1)?.ok()?);
+    let (path, content) = contents.get(&(index as u64))?;
+    let line = content.as_bytes().get(..start)?.iter().filter(|byte| **byte == b'\n').count();
+    Some(format!("{}:{}", path, line + 1))
+}
+
+/// Records the `file:line` of every contract, constructor, fallback and function definition in
+/// the ASTs of the compiler output, with file paths relative to `root`. The first definition wins
+/// for overloaded functions and contracts which share a name
+pub fn source_locations(root: &Path, output: &CompilerOutput) -> SourceLocations {
+    let contents = source_contents(root, output);
+    let mut locations = SourceLocations::new();
+    let contracts = output
+        .sources
+        .values()
+        .flat_map(|source| ast_nodes(&source.ast))
+        .filter(|node| node["nodeType"] == "ContractDefinition");
+    for contract in contracts {
+        let (name, location) = match (contract["name"].as_str(), src_location(contract, &contents))
+        {
+            (Some(name), Some(location)) => (name, location),
+            _ => continue,
+        };
+        if locations.contains_key(name) {
+            continue
+        }
+        let funcs = locations.entry(name.to_string()).or_default();
+        funcs.insert("constructor".to_string(), location);
+        let definitions = contract["nodes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|node| node["nodeType"] == "FunctionDefinition");
+        for func in definitions {
+            let location = match src_location(func, &contents) {
+                Some(location) => location,
+                None => continue,
+            };
+            match func["kind"].as_str() {
+                Some(kind @ ("constructor" | "fallback")) => {
+                    funcs.insert(kind.to_string(), location);
+                }
+                _ => {
+                    if let Some(name) = func["name"].as_str().filter(|name| !name.is_empty()) {
+                        funcs.entry(name.to_string()).or_insert(location);
+                    }
+                }
+            }
+        }
+    }
+    locations
+}
+
+/// Returns the names of the functions of every contract in the ASTs of the compiler output in the
+/// order they are declared in, by the artifact name the runner knows the contract under, e.g.
+/// `Greeter.json:GreeterTest`, which keeps contracts of the same name in other files apart
+pub fn function_order(output: &CompilerOutput) -> BTreeMap<String, Vec<String>> {
+    let mut order = BTreeMap::new();
+    for (file, source) in &output.sources {
+        let stem = Path::new(file).file_stem().and_then(|stem| stem.to_str()).unwrap_or(file);
+        let contracts = ast_nodes(&source.ast)
+            .into_iter()
+            .filter(|node| node["nodeType"] == "ContractDefinition");
+        for contract in contracts {
+            let name = match contract["name"].as_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            let functions = contract["nodes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|node| node["nodeType"] == "FunctionDefinition")
+                .filter_map(|func| func["name"].as_str())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+            order.entry(format!("{}.json:{}", stem, name)).or_insert(functions);
+        }
+    }
+    order
+}
+
+/// Returns the base contracts of every contract in the ASTs of the compiler output, as the names
+/// of its `linearizedBaseContracts` without itself, most derived first
+pub fn inheritance_graph(output: &CompilerOutput) -> BTreeMap<String, Vec<String>> {
+    let definitions = output
+        .sources
+        .values()
+        .flat_map(|source| ast_nodes(&source.ast))
+        .filter(|node| node["nodeType"] == "ContractDefinition")
+        .filter_map(|node| Some((node["id"].as_u64()?, node)))
+        .collect::<BTreeMap<_, _>>();
+    let mut graph = BTreeMap::new();
+    for (id, node) in &definitions {
+        let name = match node["name"].as_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        let bases = node["linearizedBaseContracts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(serde_json::Value::as_u64)
+            .filter(|base| base != id)
+            .filter_map(|base| definitions.get(&base)?["name"].as_str())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        graph.entry(name.to_string()).or_insert(bases);
+    }
+    graph
+}
+
+/// Returns the source file every compiled contract is declared in, as the file the compiler output
+/// lists it under, relative paths are joined to `root`. The first file wins for contracts which
+/// share a name
+pub fn contract_files(root: &Path, output: &CompilerOutput) -> BTreeMap<String, PathBuf> {
+    let mut files = BTreeMap::new();
+    for (file, contracts) in &output.contracts {
+        for name in contracts.keys() {
+            files.entry(name.clone()).or_insert_with(|| root.join(file));
+        }
+    }
+    files
+}
+
+/// Returns the `file:line` of every inline assembly block of the given contracts and the
+/// contracts they inherit from which is not annotated as memory-safe, either with
+/// `assembly ("memory-safe")` or with a preceding `/// @solidity memory-safe-assembly` comment
+pub fn unsafe_assembly_blocks(
+    root: &Path,
+    output: &CompilerOutput,
+    contracts: &BTreeSet<String>,
+) -> Vec<String> {
+    let definitions = output
+        .sources
+        .values()
+        .flat_map(|source| ast_nodes(&source.ast))
+        .filter(|node| node["nodeType"] == "ContractDefinition")
+        .filter_map(|node| Some((node["id"].as_u64()?, node)))
+        .collect::<BTreeMap<_, _>>();
+    let checked = definitions
+        .values()
+        .filter(|node| node["name"].as_str().map_or(false, |name| contracts.contains(name)))
+        .flat_map(|node| node["linearizedBaseContracts"].as_array().into_iter().flatten())
+        .filter_map(serde_json::Value::as_u64)
+        .collect::<BTreeSet<_>>();
+    let contents = source_contents(root, output);
+    let mut blocks = checked
+        .iter()
+        .filter_map(|id| definitions.get(id))
+        .flat_map(|node| ast_nodes(node))
+        .filter(|node| node["nodeType"] == "InlineAssembly")
+        .filter(|node| {
+            let flagged = node["flags"]
+                .as_array()
+                .map_or(false, |flags| flags.iter().any(|flag| flag == "memory-safe"));
+            let documented = node["documentation"]
+                .as_str()
+                .map_or(false, |doc| doc.contains("@solidity memory-safe-assembly"));
+            !flagged && !documented
+        })
+        .filter_map(|node| src_location(node, &contents))
+        .collect::<Vec<_>>();
+    blocks.sort();
+    blocks.dedup();
+    blocks
+}
+
+/// Returns the immutables of every compiled contract by name, as the name of the variable and the
+/// code offset of the first 32 byte word of its runtime code it is stored at, ordered by offset
+pub fn immutable_references(output: &CompilerOutput) -> BTreeMap<String, Vec<(String, usize)>> {
+    let names = output
+        .sources
+        .values()
+        .flat_map(|source| ast_nodes(&source.ast))
+        .filter(|node| node["nodeType"] == "VariableDeclaration")
+        .filter_map(|node| Some((node["id"].as_u64()?.to_string(), node["name"].as_str()?)))
+        .collect::<BTreeMap<_, _>>();
+    let mut immutables = BTreeMap::new();
+    for (name, contract) in output.contracts.values().flatten() {
+        let references = match contract.evm.as_ref().and_then(|evm| evm.deployed_bytecode.as_ref())
+        {
+            Some(code) => &code.immutable_references,
+            None => continue,
+        };
+        let mut offsets = references
+            .iter()
+            .filter_map(|(id, offsets)| {
+                let start = offsets.iter().map(|offset| offset.start as usize).min()?;
+                let variable = names.get(id).map_or(id.as_str(), |name| *name);
+                Some((variable.to_string(), start))
+            })
+            .collect::<Vec<_>>();
+        offsets.sort_by_key(|(_, start)| *start);
+        // the first contract wins if several share a name, like the contracts' identification
+        immutables.entry(name.clone()).or_insert(offsets);
+    }
+    immutables
+}
+
+/// Returns all contracts of `graph` which transitively inherit from `base`
+#[allow(dead_code)]
+pub fn inheriting_contracts(graph: &BTreeMap<String, Vec<String>>, base: &str) -> BTreeSet<String> {
+    fn inherits(
+        graph: &BTreeMap<String, Vec<String>>,
+        contract: &str,
+        base: &str,
+        depth: usize,
+    ) -> bool {
+        // guard against cycles in malformed sources
+        depth < 64 &&
+            graph.get(contract).map_or(false, |bases| {
+                bases.iter().any(|b| b == base || inherits(graph, b, base, depth + 1))
+            })
+    }
+    graph.keys().filter(|contract| inherits(graph, contract, base, 0)).cloned().collect()
+}
+
+/// The sputnik config of the hardfork, whose gas schedule includes the EIP-2929 cold and warm
+/// access costs of `SLOAD`, `SSTORE` and account accesses from Berlin on
 #[cfg(feature = "sputnik-evm")]
 pub fn sputnik_cfg(evm: &EvmVersion) -> Config {
     match evm {
@@ -98,3 +364,190 @@ pub fn read_secret(secret: bool, unsafe_secret: Option<String>) -> eyre::Result<
         unsafe_secret.unwrap()
     })
 }
+
+/// A minimal handlebars-like template.
+///
+/// `{{path.to.value}}` is replaced by the value at that path, looked up in the innermost scope
+/// first. `{{#each path}}..{{/each}}` renders its body for every element of an array, with the
+/// element as the innermost scope, which `{{this}}` refers to. `{{#if path}}..{{/if}}` and
+/// `{{#unless path}}..{{/unless}}` render their body if the value is truthy, or falsy: neither
+/// null, false, 0, nor empty
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<TemplateNode>,
+}
+
+#[derive(Debug, Clone)]
+enum TemplateNode {
+    Text(String),
+    Value(String),
+    /// The block helper (`each`, `if` or `unless`), its path and its body
+    Block(String, String, Vec<TemplateNode>),
+}
+
+impl Template {
+    pub fn parse(template: &str) -> eyre::Result<Self> {
+        // the root and every block which is still open
+        let mut stack = vec![(String::new(), String::new(), Vec::new())];
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..]
+                .find("}}")
+                .map(|end| start + end)
+                .context("unclosed `{{` in template")?;
+            let tag = rest[start + 2..end].trim();
+            let nodes = &mut stack.last_mut().expect("root is never popped").2;
+            nodes.push(TemplateNode::Text(rest[..start].to_string()));
+            rest = &rest[end + 2..];
+
+            if let Some(open) = tag.strip_prefix('#') {
+                let (helper, path) = open.split_once(' ').unwrap_or((open, ""));
+                if !matches!(helper, "each" | "if" | "unless") {
+                    eyre::bail!("unknown block helper `{}` in template", helper)
+                }
+                stack.push((helper.to_string(), path.trim().to_string(), Vec::new()));
+            } else if let Some(close) = tag.strip_prefix('/') {
+                if stack.len() == 1 || stack[stack.len() - 1].0 != close.trim() {
+                    eyre::bail!("unexpected `{{{{{}}}}}` in template", tag)
+                }
+                let (helper, path, body) = stack.pop().expect("checked above");
+                let nodes = &mut stack.last_mut().expect("root is never popped").2;
+                nodes.push(TemplateNode::Block(helper, path, body));
+            } else {
+                nodes.push(TemplateNode::Value(tag.to_string()));
+            }
+        }
+        if stack.len() > 1 {
+            eyre::bail!("unclosed `{{{{#{}}}}}` in template", stack[stack.len() - 1].0)
+        }
+        let (_, _, mut nodes) = stack.pop().expect("root is never popped");
+        nodes.push(TemplateNode::Text(rest.to_string()));
+        Ok(Self { nodes })
+    }
+
+    pub fn render(&self, data: &serde_json::Value) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, &mut vec![data], &mut out);
+        out
+    }
+}
+
+fn render_nodes<'a>(
+    nodes: &[TemplateNode],
+    scopes: &mut Vec<&'a serde_json::Value>,
+    out: &mut String,
+) {
+    for node in nodes {
+        match node {
+            TemplateNode::Text(text) => out.push_str(text),
+            TemplateNode::Value(path) => match template_lookup(scopes, path) {
+                Some(serde_json::Value::String(s)) => out.push_str(s),
+                Some(serde_json::Value::Null) | None => {}
+                Some(value) => out.push_str(&value.to_string()),
+            },
+            TemplateNode::Block(helper, path, body) => {
+                let value = template_lookup(scopes, path);
+                match helper.as_str() {
+                    "each" => {
+                        for item in value.and_then(|v| v.as_array()).into_iter().flatten() {
+                            scopes.push(item);
+                            render_nodes(body, scopes, out);
+                            scopes.pop();
+                        }
+                    }
+                    "if" if value.map_or(false, is_truthy) => render_nodes(body, scopes, out),
+                    "unless" if !value.map_or(false, is_truthy) => render_nodes(body, scopes, out),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Looks up the dot separated `path` in the innermost scope which has it
+fn template_lookup<'a>(
+    scopes: &[&'a serde_json::Value],
+    path: &str,
+) -> Option<&'a serde_json::Value> {
+    if path == "this" {
+        return scopes.last().copied()
+    }
+    scopes.iter().rev().find_map(|scope| {
+        path.split('.').try_fold(*scope, |value, key| match key.parse::<usize>() {
+            Ok(idx) => value.get(idx),
+            Err(_) => value.get(key),
+        })
+    })
+}
+
+fn is_truthy(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => false,
+        serde_json::Value::Bool(b) => *b,
+        serde_json::Value::Number(n) => n.as_f64().map_or(true, |n| n != 0.0),
+        serde_json::Value::String(s) => !s.is_empty(),
+        serde_json::Value::Array(a) => !a.is_empty(),
+        serde_json::Value::Object(o) => !o.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_values_blocks_and_scopes() {
+        let template = Template::parse(
+            "{{title}}: {{#each contracts}}{{name}} ({{#each tests}}{{this}}{{/each}}) {{/each}}\
+             {{#if summary.failed}}failed{{/if}}{{#unless summary.failed}}ok{{/unless}} \
+             {{ missing }}{{contracts.0.name}}",
+        )
+        .unwrap();
+        let data = serde_json::json!({
+            "title": "Results",
+            "contracts": [{ "name": "A", "tests": ["a", "b"] }, { "name": "B", "tests": [1] }],
+            "summary": { "failed": 0 },
+        });
+        assert_eq!(template.render(&data), "Results: A (ab) B (1) ok A");
+
+        // inner scopes shadow outer ones, lookups fall back to the outer scopes
+        let template = Template::parse("{{#each items}}{{name}}-{{title}} {{/each}}").unwrap();
+        let data =
+            serde_json::json!({ "title": "t", "items": [{ "name": "x" }, { "title": "u" }] });
+        assert_eq!(template.render(&data), "x-t -u ");
+    }
+
+    #[test]
+    fn truthiness_of_values() {
+        let template = Template::parse("{{#if value}}yes{{/if}}").unwrap();
+        for (value, truthy) in [
+            (serde_json::json!(null), false),
+            (serde_json::json!(false), false),
+            (serde_json::json!(0), false),
+            (serde_json::json!(""), false),
+            (serde_json::json!([]), false),
+            (serde_json::json!({}), false),
+            (serde_json::json!(true), true),
+            (serde_json::json!(0.5), true),
+            (serde_json::json!("a"), true),
+            (serde_json::json!([0]), true),
+        ] {
+            let rendered = template.render(&serde_json::json!({ "value": value }));
+            assert_eq!(rendered == "yes", truthy, "{}", value);
+        }
+        assert_eq!(template.render(&serde_json::json!({})), "");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for (template, error) in [
+            ("{{name", "unclosed `{{` in template"),
+            ("{{#with a}}{{/with}}", "unknown block helper `with` in template"),
+            ("{{#if a}}{{/each}}", "unexpected `{{/each}}` in template"),
+            ("{{/if}}", "unexpected `{{/if}}` in template"),
+            ("{{#each a}}{{#if b}}{{/if}}", "unclosed `{{#each}}` in template"),
+        ] {
+            assert_eq!(Template::parse(template).unwrap_err().to_string(), error);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: Unlicense
+pragma solidity 0.8.13;
+
+contract Base {
+    function store() internal {
+        assembly {
+            mstore(0, 1)
+        }
+    }
+}
+
+contract AssemblyTest is Base {
+    function testAnnotated() public pure {
+        assembly ("memory-safe") {
+            mstore(0, 1)
+        }
+        /// @solidity memory-safe-assembly
+        assembly {
+            mstore(0, 2)
+        }
+    }
+
+    function testUnannotated() public {
+        store();
+        assembly {
+            mstore(0x80, 3)
+        }
+    }
+}
+
+contract Untested {
+    function load() public pure returns (uint256 value) {
+        assembly {
+            value := mload(0)
+        }
+    }
+}
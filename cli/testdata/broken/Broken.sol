@@ -0,0 +1,8 @@
+// SPDX-License-Identifier: Unlicense
+pragma solidity 0.8.10;
+
+contract Broken {
+    function answer() public pure returns (uint256) {
+        return "42";
+    }
+}
@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: Unlicense
+pragma solidity 0.8.10;
+
+contract Counter {
+    uint256 public count;
+
+    function increment() public {
+        count += 1;
+    }
+
+    function reset() public {
+        count = 0;
+    }
+}
+
+contract CounterTest {
+    Counter counter;
+
+    function setUp() public {
+        counter = new Counter();
+    }
+
+    function testIncrement() public {
+        counter.increment();
+        require(counter.count() == 1, "not incremented");
+    }
+
+    function testIncrementWithoutAssertions() public {
+        counter.increment();
+    }
+}
+
+contract ImmutableTest {
+    uint256 public immutable answer = 42;
+    address public immutable deployer;
+
+    constructor() {
+        deployer = msg.sender;
+    }
+
+    function testAnswer() public {
+        require(answer == 42, "wrong answer");
+    }
+}
@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: Unlicense
+pragma solidity 0.8.10;
+
+contract Token {}
+
+contract Vault {
+    Token public token;
+
+    constructor(Token _token) {
+        token = _token;
+    }
+}
+
+contract HelpersTest {
+    Token token;
+    Vault vault;
+
+    function setUp() public {
+        token = new Token();
+        vault = new Vault(token);
+    }
+
+    function deposit() public pure returns (uint256) {
+        return 1;
+    }
+
+    function unusedHelper() public pure returns (uint256) {
+        return 2;
+    }
+
+    function testDeposit() public view {
+        require(this.deposit() == 1, "not deposited");
+    }
+}
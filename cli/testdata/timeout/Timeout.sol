@@ -0,0 +1,14 @@
+// SPDX-License-Identifier: Unlicense
+pragma solidity 0.8.10;
+
+contract TimeoutTest {
+    function testLoop() public pure {
+        while (true) {}
+    }
+
+    function testFuzzLoop(uint256) public pure {
+        while (true) {}
+    }
+
+    function testPasses() public pure {}
+}
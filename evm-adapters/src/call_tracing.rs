@@ -1,11 +1,11 @@
 use ethers::{
-    abi::{Abi, FunctionExt, RawLog},
-    types::{H160, U256},
+    abi::{Abi, FunctionExt, Param, ParamType, RawLog, Token},
+    types::{H160, H256, U256},
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-use ansi_term::Colour;
+use ansi_term::{Colour, Style};
 
 use foundry_utils::format_token;
 
@@ -23,7 +23,10 @@ pub struct CallTraceArena {
 
 impl Default for CallTraceArena {
     fn default() -> Self {
-        CallTraceArena { arena: vec![Default::default()], entry: 0 }
+        CallTraceArena {
+            arena: vec![CallTraceNode { id: "0".to_string(), ..Default::default() }],
+            entry: 0,
+        }
     }
 }
 
@@ -33,6 +36,8 @@ pub enum Output {
     Token(Vec<ethers::abi::Token>),
     /// Not decoded raw bytes
     Raw(Vec<u8>),
+    /// Decoded and formatted values
+    Formatted(String),
 }
 
 impl Output {
@@ -48,6 +53,14 @@ impl Output {
                     if strings.is_empty() { "()" } else { &*strings }
                 );
             }
+            Output::Formatted(values) => {
+                println!(
+                    "{}  └─ {} {}",
+                    left.replace("├─", "│").replace("└─", "  "),
+                    color.paint("←"),
+                    if values.is_empty() { "()" } else { &*values }
+                );
+            }
             Output::Raw(bytes) => {
                 println!(
                     "{}  └─ {} {}",
@@ -81,6 +94,7 @@ impl CallTraceArena {
                 let node = CallTraceNode {
                     parent: Some(entry),
                     idx: self.arena.len(),
+                    id: format!("{}.{}", self.arena[entry].id, new_trace.location),
                     trace: new_trace.clone(),
                     ..Default::default()
                 };
@@ -101,6 +115,135 @@ impl CallTraceArena {
         node.trace.update(trace);
     }
 
+    /// Drops all calls in the subtree of the node at `idx` which are nested more than `max_depth`
+    /// levels below it, keeping the logs of the remaining calls
+    pub fn prune(&mut self, idx: usize, max_depth: usize) {
+        let limit = self.arena[idx].trace.depth + max_depth;
+        let mut pruned = false;
+        let mut stack = vec![idx];
+        while let Some(i) = stack.pop() {
+            let node = &mut self.arena[i];
+            if node.trace.depth < limit {
+                stack.extend(node.children.iter().copied());
+            } else if !node.children.is_empty() {
+                node.children.clear();
+                node.ordering.retain(|ordering| matches!(ordering, LogCallOrder::Log(_)));
+                pruned = true;
+            }
+        }
+        if pruned {
+            self.compact();
+        }
+    }
+
+    /// Removes all nodes which are no longer reachable from the entry node, re-indexing the
+    /// remaining ones
+    fn compact(&mut self) {
+        let mut reachable = vec![false; self.arena.len()];
+        let mut stack = vec![self.entry];
+        while let Some(i) = stack.pop() {
+            reachable[i] = true;
+            stack.extend(self.arena[i].children.iter().copied());
+        }
+
+        let mut new_idx = vec![0; self.arena.len()];
+        let mut next = 0;
+        for (i, reachable) in reachable.iter().enumerate() {
+            if *reachable {
+                new_idx[i] = next;
+                next += 1;
+            }
+        }
+
+        let arena = std::mem::take(&mut self.arena);
+        self.arena = arena
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| reachable[*i])
+            .map(|(_, mut node)| {
+                node.idx = new_idx[node.idx];
+                node.trace.idx = node.idx;
+                node.parent = node.parent.map(|parent| new_idx[parent]);
+                node.children.iter_mut().for_each(|child| *child = new_idx[*child]);
+                node
+            })
+            .collect();
+        self.entry = new_idx[self.entry];
+    }
+
+    /// Structurally compares this arena against a `baseline` arena, returning a description of
+    /// every difference.
+    ///
+    /// Calls are compared by callee, function selector, value, success and whether they created a
+    /// contract. Gas costs are not compared, use `forge snapshot` for that. Calls are addressed by
+    /// their path of child indices from the entry call, e.g. `0.1` is the second call made by the
+    /// entry call.
+    pub fn diff(&self, baseline: &CallTraceArena) -> Vec<String> {
+        let mut diffs = Vec::new();
+        self.diff_node(self.entry, baseline, baseline.entry, "0", &mut diffs);
+        diffs
+    }
+
+    fn diff_node(
+        &self,
+        idx: usize,
+        baseline: &CallTraceArena,
+        baseline_idx: usize,
+        path: &str,
+        diffs: &mut Vec<String>,
+    ) {
+        let (node, baseline_node) = (&self.arena[idx], &baseline.arena[baseline_idx]);
+        let (trace, baseline_trace) = (&node.trace, &baseline_node.trace);
+        let selector = |data: &[u8]| hex::encode(&data[..data.len().min(4)]);
+
+        if trace.created != baseline_trace.created {
+            diffs.push(format!(
+                "call {}: created a contract: {}, baseline: {}",
+                path, trace.created, baseline_trace.created
+            ));
+        }
+        if trace.addr != baseline_trace.addr {
+            diffs.push(format!(
+                "call {}: callee {:?}, baseline: {:?}",
+                path, trace.addr, baseline_trace.addr
+            ));
+        }
+        if !trace.created && selector(&trace.data) != selector(&baseline_trace.data) {
+            diffs.push(format!(
+                "call {}: selector 0x{}, baseline: 0x{}",
+                path,
+                selector(&trace.data),
+                selector(&baseline_trace.data)
+            ));
+        }
+        if trace.value != baseline_trace.value {
+            diffs.push(format!(
+                "call {}: value {}, baseline: {}",
+                path, trace.value, baseline_trace.value
+            ));
+        }
+        if trace.success != baseline_trace.success {
+            diffs.push(format!(
+                "call {}: success {}, baseline: {}",
+                path, trace.success, baseline_trace.success
+            ));
+        }
+        if node.children.len() != baseline_node.children.len() {
+            diffs.push(format!(
+                "call {}: {} child calls, baseline: {}",
+                path,
+                node.children.len(),
+                baseline_node.children.len()
+            ));
+        }
+
+        for (i, (child, baseline_child)) in
+            node.children.iter().zip(baseline_node.children.iter()).enumerate()
+        {
+            self.diff_node(*child, baseline, *baseline_child, &format!("{}.{}", path, i), diffs);
+        }
+    }
+
     /// Updates `identified_contracts` for future use so that after an `evm.reset_state()`, we
     /// already know which contract corresponds to which address.
     ///
@@ -181,6 +324,26 @@ impl CallTraceArena {
         identified_contracts: &mut BTreeMap<H160, (String, Abi)>,
         evm: &'a E,
         left: &str,
+    ) {
+        self.pretty_print_with_opts(
+            idx,
+            contracts,
+            identified_contracts,
+            evm,
+            &Default::default(),
+            left,
+        )
+    }
+
+    /// Same as `pretty_print`, but renders the frames according to the given `TracePrintOpts`
+    pub fn pretty_print_with_opts<'a, S: Clone, E: crate::Evm<S>>(
+        &self,
+        idx: usize,
+        contracts: &BTreeMap<String, (Abi, Vec<u8>)>,
+        identified_contracts: &mut BTreeMap<H160, (String, Abi)>,
+        evm: &'a E,
+        opts: &TracePrintOpts<'_>,
+        left: &str,
     ) {
         let trace = &self.arena[idx].trace;
 
@@ -217,13 +380,22 @@ impl CallTraceArena {
                 // found matching contract, insert and print
                 identified_contracts.insert(trace.addr, (name.to_string(), abi.clone()));
                 if trace.created {
-                    println!("{}{} {}@{}", left, Colour::Yellow.paint("→ new"), name, trace.addr);
+                    println!(
+                        "{}{} {}@{}{}{}",
+                        left,
+                        Colour::Yellow.paint("→ new"),
+                        opts.style(Style::new(), name, "constructor").paint(name),
+                        trace.addr,
+                        trace.call_options(),
+                        format_location(source_location(opts.locations, name, "constructor"))
+                    );
                     self.print_children_and_logs(
                         idx,
                         Some(abi),
                         contracts,
                         identified_contracts,
                         evm,
+                        opts,
                         left,
                     );
                     println!(
@@ -234,12 +406,33 @@ impl CallTraceArena {
                     );
                 } else {
                     // re-enter this function at the current node
-                    self.pretty_print(idx, contracts, identified_contracts, evm, left);
+                    self.pretty_print_with_opts(
+                        idx,
+                        contracts,
+                        identified_contracts,
+                        evm,
+                        opts,
+                        left,
+                    );
                 }
             } else if trace.created {
                 // we couldn't identify, print the children and logs without the abi
-                println!("{}{} <Unknown>@{}", left, Colour::Yellow.paint("→ new"), trace.addr);
-                self.print_children_and_logs(idx, None, contracts, identified_contracts, evm, left);
+                println!(
+                    "{}{} <Unknown>@{}{}",
+                    left,
+                    Colour::Yellow.paint("→ new"),
+                    trace.addr,
+                    trace.call_options()
+                );
+                self.print_children_and_logs(
+                    idx,
+                    None,
+                    contracts,
+                    identified_contracts,
+                    evm,
+                    opts,
+                    left,
+                );
                 println!(
                     "{}  └─ {} {} bytes of code",
                     left.replace("├─", "│").replace("└─", "  "),
@@ -247,19 +440,37 @@ impl CallTraceArena {
                     trace.output.len()
                 );
             } else {
-                let output = trace.print_func_call(None, None, color, left);
-                self.print_children_and_logs(idx, None, contracts, identified_contracts, evm, left);
+                let output =
+                    trace.print_func_call(None, None, identified_contracts, opts, color, left);
+                self.print_children_and_logs(
+                    idx,
+                    None,
+                    contracts,
+                    identified_contracts,
+                    evm,
+                    opts,
+                    left,
+                );
                 output.print(color, left);
             }
         } else if let Some((name, abi)) = res {
             if trace.created {
-                println!("{}{} {}@{}", left, Colour::Yellow.paint("→ new"), name, trace.addr);
+                println!(
+                    "{}{} {}@{}{}{}",
+                    left,
+                    Colour::Yellow.paint("→ new"),
+                    opts.style(Style::new(), &name, "constructor").paint(&name),
+                    trace.addr,
+                    trace.call_options(),
+                    format_location(source_location(opts.locations, &name, "constructor"))
+                );
                 self.print_children_and_logs(
                     idx,
                     Some(&abi),
                     contracts,
                     identified_contracts,
                     evm,
+                    opts,
                     left,
                 );
                 println!(
@@ -269,13 +480,21 @@ impl CallTraceArena {
                     trace.output.len()
                 );
             } else {
-                let output = trace.print_func_call(Some(&abi), Some(&name), color, left);
+                let output = trace.print_func_call(
+                    Some(&abi),
+                    Some(&name),
+                    identified_contracts,
+                    opts,
+                    color,
+                    left,
+                );
                 self.print_children_and_logs(
                     idx,
                     Some(&abi),
                     contracts,
                     identified_contracts,
                     evm,
+                    opts,
                     left,
                 );
                 output.print(color, left);
@@ -291,25 +510,70 @@ impl CallTraceArena {
         contracts: &BTreeMap<String, (Abi, Vec<u8>)>,
         identified_contracts: &mut BTreeMap<H160, (String, Abi)>,
         evm: &'a E,
+        opts: &TracePrintOpts<'_>,
         left: &str,
     ) {
         // Ordering stores a vec of `LogCallOrder` which is populated based on if
         // a log or a call was called first. This makes it such that we always print
         // logs and calls in the correct order
-        self.arena[node_idx].ordering.iter().for_each(|ordering| match ordering {
-            LogCallOrder::Log(index) => {
-                self.arena[node_idx].print_log(*index, abi, left);
-            }
-            LogCallOrder::Call(index) => {
-                self.pretty_print(
-                    self.arena[node_idx].children[*index],
+        let node = &self.arena[node_idx];
+        for (order, repeated) in self.folded_ordering(node_idx, opts.collapse_repeated) {
+            match *order {
+                LogCallOrder::Log(index) => node.print_log(index, abi, left),
+                LogCallOrder::Call(index) => self.pretty_print_with_opts(
+                    node.children[index],
                     contracts,
                     identified_contracts,
                     evm,
+                    &TracePrintOpts { repeated, ..*opts },
                     &(left.replace("├─", "│").replace("└─", "  ") + "  ├─ "),
-                );
+                ),
             }
-        });
+        }
+    }
+
+    /// The child calls and logs of the node in order, each with the number of consecutive
+    /// identical calls it stands for. Only calls are folded, and only if `collapse_repeated` is
+    /// set, otherwise every entry stands for itself
+    fn folded_ordering(
+        &self,
+        node_idx: usize,
+        collapse_repeated: bool,
+    ) -> Vec<(&LogCallOrder, usize)> {
+        let node = &self.arena[node_idx];
+        let mut folded: Vec<(&LogCallOrder, usize)> = Vec::new();
+        for order in &node.ordering {
+            if let (true, LogCallOrder::Call(index), Some((LogCallOrder::Call(last), repeated))) =
+                (collapse_repeated, order, folded.last_mut())
+            {
+                if self.same_calls(node.children[*last], node.children[*index]) {
+                    *repeated += 1;
+                    continue
+                }
+            }
+            folded.push((order, 1));
+        }
+        folded
+    }
+
+    /// Returns `true` if the calls at `a` and `b` are the same call with the same outcome, made
+    /// the same nested calls and emitted the same logs. The gas costs may differ, e.g. because
+    /// storage is only cold on the first call
+    fn same_calls(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.arena[a], &self.arena[b]);
+        let (ta, tb) = (&a.trace, &b.trace);
+        ta.addr == tb.addr &&
+            ta.created == tb.created &&
+            ta.value == tb.value &&
+            ta.data == tb.data &&
+            ta.success == tb.success &&
+            ta.output == tb.output &&
+            a.children.len() == b.children.len() &&
+            a.logs
+                .iter()
+                .map(|log| (&log.topics, &log.data))
+                .eq(b.logs.iter().map(|log| (&log.topics, &log.data))) &&
+            a.children.iter().zip(&b.children).all(|(a, b)| self.same_calls(*a, *b))
     }
 }
 
@@ -322,11 +586,15 @@ pub struct CallTraceNode {
     pub children: Vec<usize>,
     /// This node's index in the arena
     pub idx: usize,
+    /// The path of child indices from the entry node, e.g. `0.1` is the second call made by the
+    /// entry call. Unlike `idx`, this is stable across runs
+    #[serde(default)]
+    pub id: String,
     /// The call trace
     pub trace: CallTrace,
     /// Logs
-    #[serde(skip)]
-    pub logs: Vec<RawLog>,
+    #[serde(default)]
+    pub logs: Vec<TraceLog>,
     /// Ordering of child calls and logs
     pub ordering: Vec<LogCallOrder>,
 }
@@ -340,7 +608,7 @@ impl CallTraceNode {
             for (event_name, overloaded_events) in abi.events.iter() {
                 for event in overloaded_events.iter() {
                     if event.signature() == log.topics[0] {
-                        let params = event.parse_log(log.clone()).expect("Bad event").params;
+                        let params = event.parse_log(log.to_raw()).expect("Bad event").params;
                         let strings = params
                             .into_iter()
                             .map(|param| format!("{}: {}", param.name, format_token(&param.value)))
@@ -384,6 +652,22 @@ impl CallTraceNode {
     }
 }
 
+/// A log emitted by a call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceLog {
+    /// The `id` of the node which emitted the log
+    pub node: String,
+    pub topics: Vec<H256>,
+    pub data: Vec<u8>,
+}
+
+impl TraceLog {
+    /// Returns the log in the form expected by the abi decoder
+    pub fn to_raw(&self) -> RawLog {
+        RawLog { topics: self.topics.clone(), data: self.data.clone() }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// Ordering enum for calls and logs
 ///
@@ -394,6 +678,84 @@ pub enum LogCallOrder {
     Call(usize),
 }
 
+/// Decodes the abi encoded values of the params, decoding `string`s which are not valid UTF-8 as
+/// `bytes` instead, so that they are shown as hex. Returns `None` if the data does not match the
+/// params
+fn decode_params(params: &[Param], data: &[u8]) -> Option<Vec<Token>> {
+    let types = params.iter().map(|param| param.kind.clone()).collect::<Vec<_>>();
+    ethers::abi::decode(&types, data).ok().or_else(|| {
+        let types = types.iter().map(strings_as_bytes).collect::<Vec<_>>();
+        ethers::abi::decode(&types, data).ok()
+    })
+}
+
+/// Replaces every `string` in the type, including nested ones, with `bytes`
+fn strings_as_bytes(kind: &ParamType) -> ParamType {
+    match kind {
+        ParamType::String => ParamType::Bytes,
+        ParamType::Array(inner) => ParamType::Array(Box::new(strings_as_bytes(inner))),
+        ParamType::FixedArray(inner, len) => {
+            ParamType::FixedArray(Box::new(strings_as_bytes(inner)), *len)
+        }
+        ParamType::Tuple(types) => ParamType::Tuple(types.iter().map(strings_as_bytes).collect()),
+        kind => kind.clone(),
+    }
+}
+
+/// Formats decoded values of the params, e.g. `Order(100, Alice@0x..), [1, 2]`.
+///
+/// Structs are named after their type and addresses after the contract they were identified as.
+/// The abi does not keep the types of nested structs, so only structs that are a param or an
+/// array element of one are named. Fields are shown by position, since the decoded abi does not
+/// keep the names of struct members
+fn format_params(
+    params: &[Param],
+    values: &[Token],
+    identified_contracts: &BTreeMap<H160, (String, Abi)>,
+) -> String {
+    params
+        .iter()
+        .zip(values)
+        .map(|(param, value)| format_value(value, struct_name(param), identified_contracts))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The name of the struct type of the param or of its array elements, e.g. `Order` for
+/// `struct Exchange.Order[]`
+fn struct_name(param: &Param) -> Option<&str> {
+    let name = param.internal_type.as_deref()?.strip_prefix("struct ")?;
+    let name = name.split('[').next().unwrap_or(name);
+    Some(name.rsplit('.').next().unwrap_or(name))
+}
+
+fn format_value(
+    value: &Token,
+    struct_name: Option<&str>,
+    identified_contracts: &BTreeMap<H160, (String, Abi)>,
+) -> String {
+    let format_all = |values: &[Token], struct_name: Option<&str>| {
+        values
+            .iter()
+            .map(|value| format_value(value, struct_name, identified_contracts))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match value {
+        Token::Address(addr) => match identified_contracts.get(addr) {
+            Some((name, _)) => format!("{}@{:?}", name, addr),
+            None => format_token(value),
+        },
+        Token::Tuple(values) => {
+            format!("{}({})", struct_name.unwrap_or_default(), format_all(values, None))
+        }
+        Token::Array(values) | Token::FixedArray(values) => {
+            format!("[{}]", format_all(values, struct_name))
+        }
+        _ => format_token(value),
+    }
+}
+
 /// Call trace of a tx
 #[derive(Clone, Default, Debug, Deserialize, Serialize)]
 pub struct CallTrace {
@@ -414,6 +776,29 @@ pub struct CallTrace {
     pub cost: u64,
     /// Output
     pub output: Vec<u8>,
+    /// The `msg.sender` of the call if it was spoofed by a prank cheatcode
+    #[serde(default)]
+    pub pranked_caller: Option<H160>,
+    /// The change of the block environment if this is a `warp` or `roll` cheatcode call
+    #[serde(default)]
+    pub block_change: Option<BlockChange>,
+}
+
+/// A change of the block timestamp or number by a cheatcode, which is in effect for all later
+/// calls of the test
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BlockChange {
+    Timestamp { from: U256, to: U256 },
+    Number { from: U256, to: U256 },
+}
+
+impl std::fmt::Display for BlockChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockChange::Timestamp { from, to } => write!(f, "block.timestamp: {} -> {}", from, to),
+            BlockChange::Number { from, to } => write!(f, "block.number: {} -> {}", from, to),
+        }
+    }
 }
 
 impl CallTrace {
@@ -427,15 +812,39 @@ impl CallTrace {
         self.addr = new_trace.addr;
     }
 
+    /// Formats the transferred value, the pranked caller of the call and the block change of a
+    /// `warp` or `roll`, e.g. `{value: 1}`, `{value: 1, from: 0x..}` or
+    /// `{block.timestamp: 1 -> 100}`, empty if there are none
+    fn call_options(&self) -> String {
+        let mut options = Vec::new();
+        if self.value > 0.into() {
+            options.push(format!("value: {}", self.value));
+        }
+        if let Some(caller) = self.pranked_caller {
+            options.push(format!("from: {:?}", caller));
+        }
+        if let Some(change) = self.block_change {
+            options.push(change.to_string());
+        }
+        if options.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", options.join(", "))
+        }
+    }
+
     /// Prints function call, returning the decoded or raw output
     pub fn print_func_call(
         &self,
         abi: Option<&Abi>,
         name: Option<&String>,
+        identified_contracts: &BTreeMap<H160, (String, Abi)>,
+        opts: &TracePrintOpts<'_>,
         color: Colour,
         left: &str,
     ) -> Output {
         if let (Some(abi), Some(name)) = (abi, name) {
+            let locations = opts.locations.and_then(|l| l.get(name));
             // Is data longer than 4, meaning we can attempt to decode it
             if self.data.len() >= 4 {
                 for (func_name, overloaded_funcs) in abi.functions.iter() {
@@ -443,11 +852,13 @@ impl CallTrace {
                         if func.selector() == self.data[0..4] {
                             let mut strings = "".to_string();
                             if !self.data[4..].is_empty() {
-                                let params = func
-                                    .decode_input(&self.data[4..])
-                                    .expect("Bad func data decode");
-                                strings =
-                                    params.iter().map(format_token).collect::<Vec<_>>().join(", ");
+                                strings = match decode_params(&func.inputs, &self.data[4..]) {
+                                    Some(params) => {
+                                        format_params(&func.inputs, &params, identified_contracts)
+                                    }
+                                    // calldata which does not match the abi is shown as is
+                                    None => format!("0x{}", hex::encode(&self.data[4..])),
+                                };
 
                                 #[cfg(feature = "sputnik")]
                                 if self.addr == *CHEATCODE_ADDRESS && func.name == "expectRevert" {
@@ -458,25 +869,30 @@ impl CallTrace {
                                 }
                             }
 
+                            let style = opts.style(color.normal(), name, func_name);
                             println!(
-                                "{}[{}] {}::{}{}({})",
+                                "{}[{}] {}::{}{}({}){}{}",
                                 left,
                                 self.cost,
-                                color.paint(name),
-                                color.paint(func_name),
-                                if self.value > 0.into() {
-                                    format!("{{value: {}}}", self.value)
-                                } else {
-                                    "".to_string()
-                                },
+                                style.paint(name),
+                                style.paint(func_name),
+                                self.call_options(),
                                 strings,
+                                opts.format_repeated(),
+                                format_location(
+                                    locations.and_then(|l| l.get(func_name)).map(String::as_str)
+                                ),
                             );
 
                             if !self.output.is_empty() && self.success {
-                                return Output::Token(
-                                    func.decode_output(&self.output[..])
-                                        .expect("Bad func output decode"),
-                                )
+                                return match decode_params(&func.outputs, &self.output) {
+                                    Some(values) => Output::Formatted(format_params(
+                                        &func.outputs,
+                                        &values,
+                                        identified_contracts,
+                                    )),
+                                    None => Output::Raw(self.output.clone()),
+                                }
                             } else if !self.output.is_empty() && !self.success {
                                 if let Ok(decoded_error) =
                                     foundry_utils::decode_revert(&self.output[..])
@@ -496,15 +912,13 @@ impl CallTrace {
             } else {
                 // fallback function
                 println!(
-                    "{}[{}] {}::fallback{}()",
+                    "{}[{}] {}::fallback{}(){}{}",
                     left,
                     self.cost,
-                    color.paint(name),
-                    if self.value > 0.into() {
-                        format!("{{value: {}}}", self.value)
-                    } else {
-                        "".to_string()
-                    }
+                    opts.style(color.normal(), name, "fallback").paint(name),
+                    self.call_options(),
+                    opts.format_repeated(),
+                    format_location(locations.and_then(|l| l.get("fallback")).map(String::as_str))
                 );
 
                 if !self.success {
@@ -516,27 +930,25 @@ impl CallTrace {
             }
         }
 
-        // We couldn't decode the function call, so print it as an abstract call
+        // We couldn't decode the function call, so print it as an abstract call, on the name of
+        // the contract if it is known, e.g. from a label
         println!(
-            "{}[{}] {}::{}{}({})",
+            "{}[{}] {}::{}{}({}){}",
             left,
             self.cost,
-            color.paint(format!("{}", self.addr)),
+            color.paint(name.cloned().unwrap_or_else(|| format!("{}", self.addr))),
             if self.data.len() >= 4 {
                 hex::encode(&self.data[0..4])
             } else {
                 hex::encode(&self.data[..])
             },
-            if self.value > 0.into() {
-                format!("{{value: {}}}", self.value)
-            } else {
-                "".to_string()
-            },
+            self.call_options(),
             if self.data.len() >= 4 {
                 hex::encode(&self.data[4..])
             } else {
                 hex::encode(&vec![][..])
             },
+            opts.format_repeated(),
         );
 
         if !self.success {
@@ -548,6 +960,63 @@ impl CallTrace {
     }
 }
 
+/// Options for rendering call traces
+#[derive(Clone, Copy, Default)]
+pub struct TracePrintOpts<'a> {
+    /// Source locations to annotate identified frames with
+    pub locations: Option<&'a SourceLocations>,
+    /// Predicate on `Contract::function` of every identified frame, matching frames are
+    /// emphasized
+    pub highlight: Option<&'a dyn Fn(&str) -> bool>,
+    /// Whether consecutive identical calls are folded into a single frame
+    pub collapse_repeated: bool,
+    /// The number of consecutive identical calls the printed frame stands for, set while
+    /// folding them
+    pub repeated: usize,
+}
+
+impl<'a> TracePrintOpts<'a> {
+    /// Returns the style to paint the `contract::func` frame with
+    fn style(&self, style: Style, contract: &str, func: &str) -> Style {
+        match self.highlight {
+            Some(highlight) if highlight(&format!("{}::{}", contract, func)) => {
+                style.bold().underline()
+            }
+            _ => style,
+        }
+    }
+
+    /// Formats the number of calls a folded frame stands for as a ` (×N)` suffix
+    fn format_repeated(&self) -> String {
+        if self.repeated > 1 {
+            format!(" {}", Colour::Yellow.paint(format!("(×{})", self.repeated)))
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// Source locations of contract definitions, `contract name -> (function name -> "file:line")`.
+///
+/// A contract's creation is looked up under the `constructor` key
+pub type SourceLocations = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Looks up the `file:line` of `func` in `contract`
+fn source_location<'a>(
+    locations: Option<&'a SourceLocations>,
+    contract: &str,
+    func: &str,
+) -> Option<&'a str> {
+    locations.and_then(|l| l.get(contract)).and_then(|l| l.get(func)).map(String::as_str)
+}
+
+/// Formats an optional source location as a dimmed ` (file:line)` suffix
+fn format_location(location: Option<&str>) -> String {
+    location
+        .map(|location| format!(" {}", Colour::Fixed(8).paint(format!("({})", location))))
+        .unwrap_or_default()
+}
+
 // very simple fuzzy matching to account for immutables. Will fail for small contracts that are
 // basically all immutable vars
 fn diff_score(bytecode1: &[u8], bytecode2: &[u8]) -> f64 {
@@ -568,3 +1037,109 @@ fn diff_score(bytecode1: &[u8], bytecode2: &[u8]) -> f64 {
     // println!("diff_score {}", diff_chars as f64 / cutoff_len as f64);
     diff_chars as f64 / cutoff_len as f64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_nested_values() {
+        let params = vec![Param {
+            name: "orders".to_string(),
+            kind: ParamType::Array(Box::new(ParamType::Tuple(vec![
+                ParamType::Uint(256),
+                ParamType::String,
+            ]))),
+            internal_type: Some("struct Exchange.Order[]".to_string()),
+        }];
+        // the string is not valid UTF-8, so it is shown as hex
+        let data = ethers::abi::encode(&[Token::Array(vec![Token::Tuple(vec![
+            Token::Uint(100.into()),
+            Token::Bytes(vec![0xff, 0xfe]),
+        ])])]);
+        let values = decode_params(&params, &data).unwrap();
+        assert_eq!(format_params(&params, &values, &Default::default()), "[Order(100, 0xfffe)]");
+    }
+
+    #[test]
+    fn folds_consecutive_identical_calls() {
+        let call = |idx: usize, data: u8, cost: u64, children: Vec<usize>| CallTraceNode {
+            parent: Some(0),
+            idx,
+            ordering: (0..children.len()).map(LogCallOrder::Call).collect(),
+            children,
+            trace: CallTrace { success: true, data: vec![data], cost, ..Default::default() },
+            ..Default::default()
+        };
+        let entry = CallTraceNode {
+            children: vec![1, 2, 3, 4],
+            logs: vec![TraceLog { node: "0".to_string(), topics: vec![], data: vec![] }],
+            // the first two calls only differ in gas, the third makes a nested call and the last
+            // one follows a log
+            ordering: vec![
+                LogCallOrder::Call(0),
+                LogCallOrder::Call(1),
+                LogCallOrder::Call(2),
+                LogCallOrder::Log(0),
+                LogCallOrder::Call(3),
+            ],
+            ..Default::default()
+        };
+        let arena = CallTraceArena {
+            arena: vec![
+                entry,
+                call(1, 1, 100, vec![]),
+                call(2, 1, 50, vec![]),
+                call(3, 1, 50, vec![5]),
+                call(4, 1, 50, vec![]),
+                call(5, 2, 10, vec![]),
+            ],
+            entry: 0,
+        };
+        let folded = |collapse_repeated| {
+            arena
+                .folded_ordering(0, collapse_repeated)
+                .into_iter()
+                .map(|(order, repeated)| match order {
+                    LogCallOrder::Call(index) => (format!("call {}", index), repeated),
+                    LogCallOrder::Log(index) => (format!("log {}", index), repeated),
+                })
+                .collect::<Vec<_>>()
+        };
+        let entries = |entries: &[(&str, usize)]| {
+            entries.iter().map(|(name, repeated)| (name.to_string(), *repeated)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            folded(true),
+            entries(&[("call 0", 2), ("call 2", 1), ("log 0", 1), ("call 3", 1)])
+        );
+        assert_eq!(
+            folded(false),
+            entries(&[("call 0", 1), ("call 1", 1), ("call 2", 1), ("log 0", 1), ("call 3", 1)])
+        );
+
+        // the folded frame is marked with the number of calls
+        let opts = TracePrintOpts { repeated: 2, ..Default::default() };
+        assert!(opts.format_repeated().contains("(×2)"));
+        assert_eq!(TracePrintOpts::default().format_repeated(), "");
+    }
+
+    #[test]
+    fn formats_fields_of_structs_by_position() {
+        let params = vec![Param {
+            name: "order".to_string(),
+            kind: ParamType::Tuple(vec![
+                ParamType::Tuple(vec![ParamType::Uint(256), ParamType::Uint(256)]),
+                ParamType::Uint(256),
+            ]),
+            internal_type: Some("struct Exchange.Order".to_string()),
+        }];
+        let data = ethers::abi::encode(&[Token::Tuple(vec![
+            Token::Tuple(vec![Token::Uint(1.into()), Token::Uint(2.into())]),
+            Token::Uint(100.into()),
+        ])]);
+        let values = decode_params(&params, &data).unwrap();
+        // the nested struct has no name in the abi
+        assert_eq!(format_params(&params, &values, &Default::default()), "Order((1, 2), 100)");
+    }
+}
@@ -86,6 +86,9 @@ pub struct EvmOpts {
     #[clap(help = "enables the FFI cheatcode", long)]
     pub ffi: bool,
 
+    #[clap(help = "the maximum depth of nested calls and creations", long, default_value = "1024")]
+    pub max_call_depth: usize,
+
     #[clap(
         help = r#"Verbosity mode of EVM output as number of occurences of the `v` flag (-v, -vv, -vvv, etc.)
     3: print test trace for failing tests
@@ -100,6 +103,13 @@ pub struct EvmOpts {
 
     #[clap(help = "enable debugger", long)]
     pub debug: bool,
+
+    /// If set, the state fetched from the fork is cached in here so that it can be recorded
+    /// after the run. Without a `fork_url`, the tests run against this state instead, so that a
+    /// recorded fork can be replayed offline
+    #[cfg(feature = "sputnik")]
+    #[clap(skip)]
+    pub fork_cache: Option<crate::sputnik::SharedCache<crate::sputnik::MemCache>>,
 }
 
 #[cfg(feature = "sputnik")]
@@ -139,7 +149,16 @@ mod sputnik_helpers {
             let backend = if let Some(ref url) = self.fork_url {
                 let provider = Provider::try_from(url.as_str())?;
                 let init_state = backend.state().clone();
-                let cache = crate::sputnik::new_shared_cache(init_state);
+                let cache = match self.fork_cache {
+                    Some(ref cache) => {
+                        let mut state = cache.write();
+                        for (address, account) in init_state {
+                            state.entry(address).or_insert(account);
+                        }
+                        cache.clone()
+                    }
+                    None => crate::sputnik::new_shared_cache(init_state),
+                };
                 let backend = SharedBackend::new(
                     provider,
                     cache,
@@ -148,6 +167,9 @@ mod sputnik_helpers {
                 );
                 BackendKind::Shared(backend)
             } else {
+                if let Some(ref cache) = self.fork_cache {
+                    backend.state_mut().extend(cache.read().clone());
+                }
                 BackendKind::Simple(backend)
             };
 
@@ -175,7 +197,11 @@ mod sputnik_helpers {
 pub struct Env {
     // structopt does not let use `u64::MAX`:
     // https://doc.rust-lang.org/std/primitive.u64.html#associatedconstant.MAX
-    #[clap(help = "the block gas limit", long, default_value = "18446744073709551615")]
+    #[clap(
+        help = "the gas limit of every transaction, and the block gas limit unless --block-gas-limit is set",
+        long,
+        default_value = "18446744073709551615"
+    )]
     pub gas_limit: u64,
 
     #[clap(help = "the chainid opcode value", long, default_value = "1")]
@@ -1,4 +1,4 @@
-use crate::Evm;
+use crate::{Evm, ExpectedRevert};
 
 #[cfg(feature = "sputnik")]
 use crate::sputnik::cheatcodes::debugger::DebugArena;
@@ -46,7 +46,7 @@ impl<S: HostExt, Tr: Tracer> Evm<S> for EvmOdin<S, Tr> {
         StatusCode::Revert
     }
 
-    fn expected_revert(&self) -> Option<&[u8]> {
+    fn expected_revert(&self) -> Option<&ExpectedRevert> {
         None
     }
 
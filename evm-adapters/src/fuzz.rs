@@ -5,17 +5,28 @@ use ethers::{
     types::{Address, Bytes, I256, U256},
 };
 use std::{
-    cell::{RefCell, RefMut},
+    cell::{Cell, RefCell, RefMut},
+    collections::{BTreeMap, BTreeSet},
     marker::PhantomData,
+    path::{Path, PathBuf},
 };
 
 pub use proptest::test_runner::Config as FuzzConfig;
 use proptest::{
     prelude::*,
-    test_runner::{TestError, TestRunner},
+    test_runner::{TestCaseError, TestError, TestRunner},
 };
 use serde::{Deserialize, Serialize};
 
+/// The revert reason of the `assume` cheatcode if its condition does not hold. The fuzzer rejects
+/// the inputs of a case which reverts with it, instead of counting it as passing or failing
+pub const ASSUME_REVERT_REASON: &str = "FOUNDRY::ASSUME";
+
+/// Returns `true` if the returndata is the revert of a failed `assume`
+fn is_assume_revert(returndata: &[u8]) -> bool {
+    foundry_utils::decode_revert(returndata).map_or(false, |reason| reason == ASSUME_REVERT_REASON)
+}
+
 /// Wrapper around any [`Evm`](crate::Evm) implementor which provides fuzzing support using [`proptest`](https://docs.rs/proptest/1.0.0/proptest/).
 ///
 /// After instantiation, calling `fuzz` will proceed to hammer the deployed smart contract with
@@ -27,8 +38,18 @@ pub struct FuzzedExecutor<'a, E, S> {
     runner: TestRunner,
     state: PhantomData<S>,
     sender: Address,
+    corpus: Option<PathBuf>,
+    replay: Vec<Bytes>,
+    coverage_guided: bool,
+    max_gas: Option<u64>,
+    unique_reverts: Option<usize>,
+    param_ranges: ParamRanges,
+    shrink_sequence: bool,
 }
 
+/// The inclusive bounds of numeric parameters of a fuzzed function, by the parameter's index
+pub type ParamRanges = BTreeMap<usize, (U256, U256)>;
+
 impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
     pub fn into_inner(self) -> &'a mut E {
         self.evm.into_inner()
@@ -41,13 +62,92 @@ impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
 
     /// Instantiates a fuzzed executor EVM given a testrunner
     pub fn new(evm: &'a mut E, runner: TestRunner, sender: Address) -> Self {
-        Self { evm: RefCell::new(evm), runner, state: PhantomData, sender }
+        Self {
+            evm: RefCell::new(evm),
+            runner,
+            state: PhantomData,
+            sender,
+            corpus: None,
+            replay: vec![],
+            coverage_guided: false,
+            max_gas: None,
+            unique_reverts: None,
+            param_ranges: Default::default(),
+            shrink_sequence: false,
+        }
+    }
+
+    /// Sets the file of the [`FuzzCorpus`] which is replayed before any new inputs are generated
+    /// and extended with every input that reached a new execution path
+    #[must_use]
+    pub fn corpus(mut self, path: PathBuf) -> Self {
+        self.corpus = Some(path);
+        self
+    }
+
+    /// Sets inputs which are replayed, before the corpus and any new inputs
+    #[must_use]
+    pub fn replay(mut self, inputs: Vec<Bytes>) -> Self {
+        self.replay = inputs;
+        self
+    }
+
+    /// Sets whether new inputs are also generated by mutating the inputs which reached a new
+    /// execution path, instead of only generating them at random
+    #[must_use]
+    pub fn coverage_guided(mut self, coverage_guided: bool) -> Self {
+        self.coverage_guided = coverage_guided;
+        self
+    }
+
+    /// Sets the gas a single fuzz case may use, cases using more gas fail the test
+    #[must_use]
+    pub fn max_gas(mut self, max_gas: Option<u64>) -> Self {
+        self.max_gas = max_gas;
+        self
+    }
+
+    /// Sets the maximum number of distinct revert reasons which are collected. If set, fuzzing
+    /// continues past failing cases and the first failing case is reported without shrinking it
+    #[must_use]
+    pub fn unique_reverts(mut self, max: Option<usize>) -> Self {
+        self.unique_reverts = max;
+        self
+    }
+
+    /// Sets the bounds of numeric parameters, which only take values within them
+    #[must_use]
+    pub fn param_ranges(mut self, param_ranges: ParamRanges) -> Self {
+        self.param_ranges = param_ranges;
+        self
+    }
+
+    /// Sets whether the call sequence which broke an invariant is shrunk further, to a subset of
+    /// its calls from which no single call can be removed without the invariant holding
+    #[must_use]
+    pub fn shrink_sequence(mut self, shrink_sequence: bool) -> Self {
+        self.shrink_sequence = shrink_sequence;
+        self
+    }
+
+    /// The failure reason of a case which timed out or used more than the maximum gas, if it did
+    fn limit_exceeded(&self, evm: &E, gas: u64) -> Option<String> {
+        if evm.timed_out() {
+            return Some("timeout".to_string())
+        }
+        self.max_gas
+            .filter(|max| gas > *max)
+            .map(|max| format!("used {} gas, exceeding the maximum of {} per fuzz case", gas, max))
     }
 
     /// Fuzzes the provided function, assuming it is available at the contract at `address`
     /// If `should_fail` is set to `true`, then it will stop only when there's a success
     /// test case.
     ///
+    /// Every case, including replayed and corpus inputs, runs against a snapshot of the state taken
+    /// before fuzzing starts (i.e. after `setUp()`), so cases never observe each other's state
+    /// changes.
+    ///
     /// Returns a list of all the consumed gas and calldata of every fuzz case
     pub fn fuzz(
         &self,
@@ -61,7 +161,10 @@ impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
         // fuzz test run.
         S: Clone,
     {
-        let strat = fuzz_calldata(func);
+        // the instructions executed by the cases tell which of them reached new execution paths
+        if self.coverage_guided || self.corpus.is_some() {
+            self.evm.borrow_mut().record_coverage();
+        }
 
         // Snapshot the state before the test starts running
         let pre_test_state = self.evm.borrow().state().clone();
@@ -73,57 +176,429 @@ impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
         // case if the runner failed
         let return_reason: RefCell<Option<E::ReturnReason>> = RefCell::new(None);
         let revert_reason = RefCell::new(None);
+        let revert_data = RefCell::new(None);
+
+        // the number of distinct instructions executed by all cases so far. If the EVM does not
+        // record them, distinct gas consumption is used as a cheap proxy for an input reaching a
+        // new execution path
+        let covered = Cell::new(self.evm.borrow().covered_instructions().unwrap_or_default());
+        let seen_gas: RefCell<BTreeSet<u64>> = RefCell::new(Default::default());
+        let reached_new_path = |evm: &E, gas: u64| match evm.covered_instructions() {
+            Some(instructions) => instructions > covered.replace(instructions),
+            None => seen_gas.borrow_mut().insert(gas),
+        };
+        // a corpus which can not be read is not saved either, so that it is not overwritten
+        let (mut corpus, corpus_path) = match self.corpus {
+            Some(ref path) => match FuzzCorpus::load(path) {
+                Ok(corpus) => (corpus, Some(path)),
+                Err(err) => {
+                    tracing::warn!(
+                        ?err,
+                        ?path,
+                        "could not read the fuzz corpus, it is not updated"
+                    );
+                    (FuzzCorpus::default(), None)
+                }
+            },
+            None => (FuzzCorpus::default(), None),
+        };
+        let new_inputs: RefCell<Vec<Bytes>> = RefCell::new(Default::default());
+        // the decoded arguments of every input that reached a new execution path, which are
+        // mutated into new inputs if the fuzzer is coverage guided
+        let pool: RefCell<Vec<Vec<Token>>> = RefCell::new(Default::default());
+
+        // once a case failed, every further case is an attempt of the runner to shrink it
+        let failed = Cell::new(false);
+        let shrinks: RefCell<Vec<ShrinkStep>> = RefCell::new(Default::default());
+
+        // the distinct revert reasons of the failing cases and the first input which failed with
+        // each, only collected if `unique_reverts` is set
+        let unique_reverts: RefCell<Vec<(String, Bytes)>> = RefCell::new(Default::default());
+
+        // the number of cases whose inputs were rejected by `assume`
+        let rejects = Cell::new(0);
+
+        // replay the given inputs and the persisted corpus before generating any new inputs
+        for calldata in self.replay.iter().chain(&corpus.inputs) {
+            let mut evm = self.evm.borrow_mut();
+            evm.reset(pre_test_state.clone());
+
+            let (returndata, reason, gas, _) = evm
+                .call_raw(self.sender, address, calldata.clone(), 0.into(), false)
+                .expect("could not make raw evm call");
+
+            if is_assume_revert(returndata.as_ref()) {
+                rejects.set(rejects.get() + 1);
+                continue
+            }
+            let new_path = reached_new_path(&**evm, gas);
+
+            let limit_exceeded = self.limit_exceeded(&evm, gas);
+            if limit_exceeded.is_some() || !evm.check_success(address, &reason, should_fail) {
+                let revert_reason = limit_exceeded.unwrap_or_else(|| {
+                    foundry_utils::decode_revert(returndata.as_ref()).unwrap_or_default()
+                });
+                let test_error = TestError::Fail(
+                    format!("{}, replayed input failed, reason: '{}'", func.name, revert_reason)
+                        .into(),
+                    calldata.clone(),
+                );
+                return FuzzTestResult {
+                    cases: FuzzedCases::new(fuzz_cases.into_inner()),
+                    test_error: Some(FuzzError {
+                        test_error,
+                        return_reason: Some(reason),
+                        revert_reason,
+                        revert_data: returndata,
+                    }),
+                    shrinks: vec![],
+                    unique_reverts: vec![],
+                    rejects: rejects.get(),
+                }
+            }
+
+            if new_path && self.coverage_guided {
+                add_to_pool(&pool, func, calldata);
+            }
+            let reverted = !E::is_success(&reason);
+            fuzz_cases.borrow_mut().push(FuzzCase { calldata: calldata.clone(), gas, reverted });
+        }
 
         let mut runner = self.runner.clone();
         tracing::debug!(func = ?func.name, should_fail, "fuzzing");
-        let test_error = runner
-            .run(&strat, |calldata| {
-                let mut evm = self.evm.borrow_mut();
-                // Before each test, we must reset to the initial state
-                evm.reset(pre_test_state.clone());
+        let test = |calldata: Bytes| {
+            let mut evm = self.evm.borrow_mut();
+            // Before each test, we must reset to the initial state
+            evm.reset(pre_test_state.clone());
 
-                let (returndata, reason, gas, _) = evm
-                    .call_raw(self.sender, address, calldata.clone(), 0.into(), false)
-                    .expect("could not make raw evm call");
+            let (returndata, reason, gas, _) = evm
+                .call_raw(self.sender, address, calldata.clone(), 0.into(), false)
+                .expect("could not make raw evm call");
 
-                // We must check success before resetting the state, otherwise resetting the state
-                // will also reset the `failed` state variable back to false.
-                let success = evm.check_success(address, &reason, should_fail);
+            // proptest generates another input instead, and gives up after too many rejects
+            if is_assume_revert(returndata.as_ref()) {
+                rejects.set(rejects.get() + 1);
+                return Err(TestCaseError::reject(ASSUME_REVERT_REASON))
+            }
+            let new_path = reached_new_path(&**evm, gas);
+            let reverted = !E::is_success(&reason);
 
-                // store the result of this test case
-                let _ = return_reason.borrow_mut().insert(reason);
+            // We must check success before resetting the state, otherwise resetting the state
+            // will also reset the `failed` state variable back to false.
+            let limit_exceeded = self.limit_exceeded(&evm, gas);
+            let success =
+                limit_exceeded.is_none() && evm.check_success(address, &reason, should_fail);
 
-                if !success {
-                    let revert =
-                        foundry_utils::decode_revert(returndata.as_ref()).unwrap_or_default();
-                    let _ = revert_reason.borrow_mut().insert(revert);
+            if let (Some(max), false) = (self.unique_reverts, success) {
+                let revert = limit_exceeded.unwrap_or_else(|| {
+                    foundry_utils::decode_revert(returndata.as_ref()).unwrap_or_default()
+                });
+                let mut reverts = unique_reverts.borrow_mut();
+                // the first failing case is the one that is reported
+                if reverts.is_empty() {
+                    let _ = return_reason.borrow_mut().insert(reason);
+                    let _ = revert_reason.borrow_mut().insert(revert.clone());
+                    let _ = revert_data.borrow_mut().insert(returndata);
                 }
+                if reverts.len() < max && !reverts.iter().any(|(seen, _)| *seen == revert) {
+                    reverts.push((revert, calldata));
+                }
+                // keep fuzzing for further revert reasons
+                return Ok(())
+            }
+
+            // store the result of this test case, unless a failing case was already collected
+            if unique_reverts.borrow().is_empty() {
+                let _ = return_reason.borrow_mut().insert(reason);
+            }
+
+            if failed.get() {
+                let step = ShrinkStep { calldata: calldata.clone(), failed: !success };
+                shrinks.borrow_mut().push(step);
+            }
+            failed.set(failed.get() || !success);
+
+            if !success {
+                let revert = limit_exceeded.clone().unwrap_or_else(|| {
+                    foundry_utils::decode_revert(returndata.as_ref()).unwrap_or_default()
+                });
+                let _ = revert_reason.borrow_mut().insert(revert);
+                let _ = revert_data.borrow_mut().insert(returndata.clone());
+            }
 
-                // This will panic and get caught by the executor
-                proptest::prop_assert!(
-                    success,
-                    "{}, expected failure: {}, reason: '{}'",
-                    func.name,
-                    should_fail,
-                    match foundry_utils::decode_revert(returndata.as_ref()) {
+            // This will panic and get caught by the executor
+            proptest::prop_assert!(
+                success,
+                "{}, expected failure: {}, reason: '{}'",
+                func.name,
+                should_fail,
+                match limit_exceeded {
+                    Some(reason) => reason,
+                    None => match foundry_utils::decode_revert(returndata.as_ref()) {
                         Ok(e) => e,
                         Err(e) => e.to_string(),
-                    }
-                );
+                    },
+                }
+            );
 
-                // push test case to the case set
-                fuzz_cases.borrow_mut().push(FuzzCase { calldata, gas });
+            if new_path && self.coverage_guided {
+                add_to_pool(&pool, func, &calldata);
+            }
+            if new_path && self.corpus.is_some() {
+                new_inputs.borrow_mut().push(calldata.clone());
+            }
 
-                Ok(())
-            })
+            // push test case to the case set
+            fuzz_cases.borrow_mut().push(FuzzCase { calldata, gas, reverted });
+
+            Ok(())
+        };
+        let run = if self.coverage_guided {
+            // the cases run in rounds, each mutating a snapshot of the pool taken before it
+            // started, so that the strategy does not change while its cases run and
+            // shrink
+            let config = runner.config().clone();
+            let mut remaining = config.cases;
+            let mut run = Ok(());
+            while remaining > 0 && run.is_ok() {
+                let cases = remaining.min(GUIDED_ROUND_CASES);
+                remaining -= cases;
+                let max_global_rejects =
+                    config.max_global_rejects.saturating_sub(rejects.get() as u32);
+                let mut round = TestRunner::new_with_rng(
+                    FuzzConfig { cases, max_global_rejects, ..config.clone() },
+                    runner.rng().clone(),
+                );
+                let strategy = guided_calldata(func, pool.borrow().clone(), &self.param_ranges);
+                run = round.run(&strategy, &test);
+                // the next round continues with the rng, so that the run is reproducible
+                *runner.rng() = round.rng().clone();
+            }
+            run
+        } else {
+            runner.run(&bounded_calldata(func, &self.param_ranges), test)
+        };
+        let unique_reverts = unique_reverts.into_inner();
+        let test_error = run
             .err()
+            .or_else(|| {
+                // collecting the revert reasons never fails the runner, so it is failed here
+                unique_reverts.first().map(|(_, calldata)| {
+                    let reason = format!(
+                        "{}, {} distinct revert reasons found",
+                        func.name,
+                        unique_reverts.len()
+                    );
+                    TestError::Fail(reason.into(), calldata.clone())
+                })
+            })
             .map(|test_error| FuzzError {
                 test_error,
-                return_reason: return_reason.into_inner().expect("Reason must be set"),
-                revert_reason: revert_reason.into_inner().expect("Revert error string must be set"),
+                // not set if the runner aborted due to rejected inputs instead of failing
+                return_reason: return_reason.into_inner(),
+                revert_reason: revert_reason.into_inner().unwrap_or_default(),
+                revert_data: revert_data.into_inner().unwrap_or_default(),
             });
 
-        FuzzTestResult { cases: FuzzedCases::new(fuzz_cases.into_inner()), test_error }
+        if let Some(path) = corpus_path {
+            corpus.inputs.extend(new_inputs.into_inner());
+            if let Err(err) = corpus.save(path) {
+                tracing::warn!(?err, ?path, "could not persist the fuzz corpus");
+            }
+        }
+
+        FuzzTestResult {
+            cases: FuzzedCases::new(fuzz_cases.into_inner()),
+            test_error,
+            shrinks: shrinks.into_inner(),
+            unique_reverts,
+            rejects: rejects.get(),
+        }
+    }
+
+    /// Makes random sequences of 1 to `depth` calls to the `targets` of the contract at `address`
+    /// and checks after every call that the `invariant` function of that contract still holds,
+    /// i.e. that it neither reverts nor fails an assertion.
+    ///
+    /// Every sequence starts from a snapshot of the state taken before fuzzing starts (i.e. after
+    /// `setUp()`). Calls of a sequence which revert do not break the invariant, their state
+    /// changes are simply discarded. If the invariant broke, the failing sequence is shrunk, and
+    /// minimized further if [`FuzzedExecutor::shrink_sequence`] is set.
+    ///
+    /// Panics if there are no `targets`.
+    pub fn invariant(
+        &self,
+        invariant: &Function,
+        targets: &[Function],
+        address: Address,
+        depth: usize,
+    ) -> InvariantTestResult
+    where
+        S: Clone,
+    {
+        let pre_test_state = self.evm.borrow().state().clone();
+        let invariant_calldata: Bytes = invariant.encode_input(&[]).unwrap().into();
+
+        // stores the total gas of the calls of every sequence which kept the invariant
+        let fuzz_cases: RefCell<Vec<FuzzCase>> = RefCell::new(Default::default());
+        let revert_reason = RefCell::new(None);
+
+        let mut runner = self.runner.clone();
+        tracing::debug!(invariant = ?invariant.name, ?depth, "fuzzing invariant");
+        let test = |sequence: Vec<Bytes>| {
+            let (gas, broken) =
+                self.run_sequence(&pre_test_state, address, &invariant_calldata, &sequence);
+            if let Some((_, revert)) = broken {
+                let _ = revert_reason.borrow_mut().insert(revert.clone());
+                return Err(TestCaseError::fail(format!(
+                    "{} broken, reason: '{}'",
+                    invariant.name, revert
+                )))
+            }
+
+            fuzz_cases.borrow_mut().push(FuzzCase {
+                calldata: invariant_calldata.clone(),
+                gas,
+                reverted: false,
+            });
+            Ok(())
+        };
+        let calls = proptest::strategy::Union::new(targets.iter().map(fuzz_calldata));
+        let mut test_error = runner.run(&proptest::collection::vec(calls, 1..=depth), test).err();
+
+        let minimized = match (self.shrink_sequence, &test_error) {
+            (true, Some(TestError::Fail(_, sequence))) => {
+                self.minimize_sequence(&pre_test_state, address, &invariant_calldata, sequence)
+            }
+            _ => None,
+        };
+        if let Some((sequence, revert)) = minimized {
+            let reason = format!("{} broken, reason: '{}'", invariant.name, revert);
+            test_error = Some(TestError::Fail(reason.into(), sequence));
+            let _ = revert_reason.borrow_mut().insert(revert);
+        }
+
+        InvariantTestResult {
+            cases: FuzzedCases::new(fuzz_cases.into_inner()),
+            test_error,
+            revert_reason: revert_reason.into_inner().unwrap_or_default(),
+        }
+    }
+
+    /// Makes the calls of the sequence from the given state, checking the invariant after each.
+    ///
+    /// Returns the total gas of the calls and, if the invariant broke, the index of the call after
+    /// which it did and the revert reason of the invariant call
+    fn run_sequence(
+        &self,
+        state: &S,
+        address: Address,
+        invariant_calldata: &Bytes,
+        sequence: &[Bytes],
+    ) -> (u64, Option<(usize, String)>)
+    where
+        S: Clone,
+    {
+        let mut evm = self.evm.borrow_mut();
+        evm.reset(state.clone());
+
+        let mut gas = 0;
+        for (i, calldata) in sequence.iter().enumerate() {
+            let (_, _, call_gas, _) = evm
+                .call_raw(self.sender, address, calldata.clone(), 0.into(), false)
+                .expect("could not make raw evm call");
+            gas += call_gas;
+
+            let (returndata, reason, _, _) = evm
+                .call_raw(self.sender, address, invariant_calldata.clone(), 0.into(), false)
+                .expect("could not make raw evm call");
+            if !evm.check_success(address, &reason, false) {
+                let revert = foundry_utils::decode_revert(returndata.as_ref()).unwrap_or_default();
+                return (gas, Some((i, revert)))
+            }
+        }
+        (gas, None)
+    }
+
+    /// Shrinks a sequence which broke the invariant by dropping the calls after the one that broke
+    /// it and then removing single calls for as long as the invariant still breaks without them.
+    ///
+    /// Returns the minimized sequence and the revert reason of the invariant call that failed
+    /// after it, or `None` if the sequence no longer breaks the invariant, e.g. because a call
+    /// timed out only while fuzzing
+    fn minimize_sequence(
+        &self,
+        state: &S,
+        address: Address,
+        invariant_calldata: &Bytes,
+        sequence: &[Bytes],
+    ) -> Option<(Vec<Bytes>, String)>
+    where
+        S: Clone,
+    {
+        let (_, broken) = self.run_sequence(state, address, invariant_calldata, sequence);
+        let (at, mut revert) = broken?;
+        let mut sequence = sequence[..=at].to_vec();
+
+        // removing a call may allow removing calls that were tried before, so this repeats until
+        // no single call can be removed anymore
+        let mut shrunk = true;
+        while shrunk {
+            shrunk = false;
+            let mut i = 0;
+            while i < sequence.len() && sequence.len() > 1 {
+                let mut candidate = sequence.clone();
+                candidate.remove(i);
+                match self.run_sequence(state, address, invariant_calldata, &candidate) {
+                    (_, Some((at, reason))) => {
+                        candidate.truncate(at + 1);
+                        sequence = candidate;
+                        revert = reason;
+                        shrunk = true;
+                    }
+                    (_, None) => i += 1,
+                }
+            }
+        }
+        tracing::debug!(calls = sequence.len(), "minimized the failing call sequence");
+        Some((sequence, revert))
+    }
+}
+
+/// The outcome of an invariant test
+pub struct InvariantTestResult {
+    /// The total gas of the calls of every sequence which kept the invariant
+    pub cases: FuzzedCases,
+    /// If the invariant broke, this contains the shrunk sequence of calldata after which it did
+    pub test_error: Option<TestError<Vec<Bytes>>>,
+    /// The revert string of the invariant call that failed, empty if it kept holding
+    pub revert_reason: String,
+}
+
+/// A growing set of fuzz inputs which reached new execution paths, persisted across runs
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FuzzCorpus {
+    /// The calldata of every persisted input
+    pub inputs: Vec<Bytes>,
+}
+
+impl FuzzCorpus {
+    /// Reads the corpus from the given file, returning an empty corpus if it does not exist yet
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Default::default())
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the corpus to the given file, creating all parent directories
+    pub fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(std::fs::write(path, serde_json::to_string_pretty(self)?)?)
     }
 }
 
@@ -134,6 +609,24 @@ pub struct FuzzTestResult<Reason> {
     /// if there was a case that resulted in an error, this contains the error and the return
     /// reason of the failed call
     pub test_error: Option<FuzzError<Reason>>,
+    /// Every input that was tried while shrinking the first failing case
+    pub shrinks: Vec<ShrinkStep>,
+    /// The distinct revert reasons of the failing cases, in the order they were found, and the
+    /// first input which failed with each. Only collected if
+    /// [`FuzzedExecutor::unique_reverts`] is set
+    pub unique_reverts: Vec<(String, Bytes)>,
+    /// The number of cases whose inputs were rejected because they reverted with
+    /// [`ASSUME_REVERT_REASON`]
+    pub rejects: usize,
+}
+
+/// An input tried while shrinking a failing fuzz case
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShrinkStep {
+    /// The calldata of the input
+    pub calldata: Bytes,
+    /// Whether the input still failed the test
+    pub failed: bool,
 }
 
 impl<Reason> FuzzTestResult<Reason> {
@@ -151,10 +644,13 @@ impl<Reason> FuzzTestResult<Reason> {
 pub struct FuzzError<Reason> {
     /// The proptest error occurred as a result of a test case
     pub test_error: TestError<Bytes>,
-    /// The return reason of the offending call
-    pub return_reason: Reason,
+    /// The return reason of the offending call, `None` if the runner gave up after too many
+    /// rejected inputs before any case ran to completion
+    pub return_reason: Option<Reason>,
     /// The revert string of the offending call
     pub revert_reason: String,
+    /// The raw return data of the offending call
+    pub revert_data: Bytes,
 }
 
 /// Container type for all successful test cases
@@ -217,14 +713,25 @@ pub struct FuzzCase {
     pub calldata: Bytes,
     // Consumed gas
     pub gas: u64,
+    /// Whether the call reverted, which a passing case of a `testFail` test does
+    #[serde(default)]
+    pub reverted: bool,
 }
 
 /// Given a function, it returns a proptest strategy which generates valid abi-encoded calldata
 /// for that function's input types.
 pub fn fuzz_calldata(func: &Function) -> impl Strategy<Value = Bytes> + '_ {
+    bounded_calldata(func, &ParamRanges::new())
+}
+
+/// Same as [`fuzz_calldata`], but the numeric parameters with a range only take values within it
+pub fn bounded_calldata<'a>(
+    func: &'a Function,
+    ranges: &ParamRanges,
+) -> impl Strategy<Value = Bytes> + 'a {
     // We need to compose all the strategies generated for each parameter in all
     // possible combinations
-    let strats = func.inputs.iter().map(|input| fuzz_param(&input.kind)).collect::<Vec<_>>();
+    let strats = param_strategies(func, ranges);
 
     strats.prop_map(move |tokens| {
         tracing::trace!(input = ?tokens);
@@ -232,9 +739,93 @@ pub fn fuzz_calldata(func: &Function) -> impl Strategy<Value = Bytes> + '_ {
     })
 }
 
+/// The number of cases a coverage guided fuzzer runs before the inputs which reached new execution
+/// paths are mutated too
+const GUIDED_ROUND_CASES: u32 = 32;
+
+/// Given a function and the decoded arguments of the inputs which reached new execution paths,
+/// returns a proptest strategy which either generates random calldata, or replaces one argument
+/// of one of those inputs with a random value, so that the fuzzer explores the code behind them
+fn guided_calldata<'a>(
+    func: &'a Function,
+    pool: Vec<Vec<Token>>,
+    ranges: &ParamRanges,
+) -> impl Strategy<Value = Bytes> + 'a {
+    let strats = param_strategies(func, ranges);
+
+    (strats, any::<bool>(), any::<prop::sample::Index>(), any::<prop::sample::Index>()).prop_map(
+        move |(mut tokens, mutate, input, arg)| {
+            if mutate && !pool.is_empty() && !tokens.is_empty() {
+                let arg = arg.index(tokens.len());
+                let fresh = std::mem::replace(&mut tokens, pool[input.index(pool.len())].clone());
+                tokens[arg] = fresh[arg].clone();
+            }
+            tracing::trace!(input = ?tokens);
+            func.encode_input(&tokens).unwrap().into()
+        },
+    )
+}
+
+/// Adds the arguments of `calldata` to the pool of a coverage guided fuzzer, unless it does not
+/// decode, e.g. a replayed input too short to hold a selector
+fn add_to_pool(pool: &RefCell<Vec<Vec<Token>>>, func: &Function, calldata: &Bytes) {
+    if let Some(Ok(tokens)) = calldata.as_ref().get(4..).map(|args| func.decode_input(args)) {
+        pool.borrow_mut().push(tokens);
+    }
+}
+
 /// The max length of arrays we fuzz for is 256.
 const MAX_ARRAY_LEN: usize = 256;
 
+/// Returns the strategies for the parameters of `func`, bounded for those with a range
+fn param_strategies(func: &Function, ranges: &ParamRanges) -> Vec<BoxedStrategy<Token>> {
+    func.inputs
+        .iter()
+        .enumerate()
+        .map(|(idx, input)| match ranges.get(&idx) {
+            Some(&(lo, hi)) => bounded_param(&input.kind, lo, hi),
+            None => fuzz_param(&input.kind).boxed(),
+        })
+        .collect()
+}
+
+/// Returns a strategy for values of an integer parameter between `lo` and `hi` inclusive, which
+/// are in two's complement for signed integers. The bounds are capped to the range of the type,
+/// parameters of other types ignore the range
+fn bounded_param(param: &ParamType, lo: U256, hi: U256) -> BoxedStrategy<Token> {
+    let (signed, lo, hi) = match param {
+        ParamType::Uint(n) => {
+            let hi = if *n < 256 { hi.min((U256::one() << *n) - 1) } else { hi };
+            (false, lo.min(hi), hi)
+        }
+        ParamType::Int(n) => {
+            // `!max` is `-max - 1` in two's complement
+            let max = I256::from_raw((U256::one() << (*n - 1)) - 1);
+            let min = I256::from_raw(!max.into_raw());
+            let hi = I256::from_raw(hi).clamp(min, max);
+            let lo = I256::from_raw(lo).clamp(min, hi);
+            (true, lo.into_raw(), hi.into_raw())
+        }
+        _ => {
+            tracing::warn!(?param, "ignoring the range of a non-integer fuzz parameter");
+            return fuzz_param(param).boxed()
+        }
+    };
+    let span = hi.overflowing_sub(lo).0;
+    any::<[u8; 32]>()
+        .prop_map(move |x| {
+            let offset =
+                if span == U256::MAX { U256::from(&x) } else { U256::from(&x) % (span + 1) };
+            let value = lo.overflowing_add(offset).0;
+            if signed {
+                I256::from_raw(value).into_token()
+            } else {
+                value.into_token()
+            }
+        })
+        .boxed()
+}
+
 /// Given an ethabi parameter type, returns a proptest strategy for generating values for that
 /// datatype. Works with ABI Encoder v2 tuples.
 fn fuzz_param(param: &ParamType) -> impl Strategy<Value = Token> {
@@ -308,6 +899,10 @@ mod tests {
         test_helpers::COMPILED,
         Evm,
     };
+    use proptest::{
+        strategy::ValueTree,
+        test_runner::{RngAlgorithm, TestRng},
+    };
 
     #[test]
     fn prints_fuzzed_revert_reasons() {
@@ -325,4 +920,218 @@ mod tests {
         let revert_reason = error.revert_reason;
         assert_eq!(revert_reason, "fuzztest-revert");
     }
+
+    #[test]
+    fn gives_up_after_too_many_rejected_inputs() {
+        let mut evm = vm();
+
+        let compiled = COMPILED.find("FuzzTests").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let cfg =
+            FuzzConfig { failure_persistence: None, max_global_rejects: 10, ..Default::default() };
+        let evm = FuzzedExecutor::new(&mut evm, TestRunner::new(cfg), Address::zero());
+
+        // every input is rejected, so no case runs to completion
+        let func = compiled.abi.unwrap().function("testFuzzedAssume").unwrap();
+        let res = evm.fuzz(&func, addr, false);
+        assert_eq!(res.rejects, 10);
+        assert!(res.cases.cases().is_empty());
+        let error = res.test_error.unwrap();
+        assert!(matches!(error.test_error, TestError::Abort(_)));
+        assert!(error.return_reason.is_none());
+        assert!(error.revert_reason.is_empty());
+    }
+
+    /// Fuzzes `testFuzzedBranches` with a fixed seed, returning the revert reason and the input
+    /// of the failing case, if any
+    fn fuzz_branches(coverage_guided: bool) -> Option<(String, Bytes)> {
+        let mut evm = vm();
+
+        let compiled = COMPILED.find("FuzzTests").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let cfg = FuzzConfig { cases: 10_000, failure_persistence: None, ..Default::default() };
+        let rng = TestRng::from_seed(RngAlgorithm::ChaCha, &[7; 32]);
+        let evm =
+            FuzzedExecutor::new(&mut evm, TestRunner::new_with_rng(cfg, rng), Address::zero())
+                .coverage_guided(coverage_guided);
+
+        let func = compiled.abi.unwrap().function("testFuzzedBranches").unwrap();
+        let error = evm.fuzz(func, addr, false).test_error?;
+        match error.test_error {
+            TestError::Fail(_, calldata) => Some((error.revert_reason, calldata)),
+            TestError::Abort(abort) => panic!("fuzzer gave up: {}", abort),
+        }
+    }
+
+    /// Generates values of the bounded parameter, as signed integers for signed parameters
+    fn bounded_values(param: ParamType, lo: &str, hi: &str) -> Vec<I256> {
+        let bound = |s| I256::from_dec_str(s).unwrap().into_raw();
+        let strategy = bounded_param(&param, bound(lo), bound(hi));
+        let mut runner = TestRunner::deterministic();
+        (0..256)
+            .map(|_| match strategy.new_tree(&mut runner).unwrap().current() {
+                Token::Int(raw) => I256::from_raw(raw),
+                Token::Uint(value) => I256::from_raw(value),
+                token => panic!("not an integer: {:?}", token),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bounds_fuzzed_params_to_their_type() {
+        let within = |values: Vec<I256>, lo: &str, hi: &str| {
+            let (lo, hi) = (I256::from_dec_str(lo).unwrap(), I256::from_dec_str(hi).unwrap());
+            values.iter().all(|value| lo <= *value && *value <= hi)
+        };
+        assert!(within(bounded_values(ParamType::Int(8), "-1000", "1000"), "-128", "127"));
+        assert!(within(bounded_values(ParamType::Int(16), "-3", "3"), "-3", "3"));
+        assert!(within(bounded_values(ParamType::Int(256), "-3", "3"), "-3", "3"));
+        assert!(within(bounded_values(ParamType::Uint(8), "0", "1000"), "0", "255"));
+        assert!(within(bounded_values(ParamType::Uint(256), "10", "20"), "10", "20"));
+
+        // a range beyond the type is capped to its bound
+        assert!(within(bounded_values(ParamType::Int(8), "200", "300"), "127", "127"));
+        assert!(within(bounded_values(ParamType::Int(8), "-300", "-200"), "-128", "-128"));
+
+        // the full range of a uint256 includes the values with the high bit set
+        let strategy = bounded_param(&ParamType::Uint(256), U256::zero(), U256::MAX);
+        let mut runner = TestRunner::deterministic();
+        assert!((0..256).any(|_| matches!(
+            strategy.new_tree(&mut runner).unwrap().current(),
+            Token::Uint(value) if value.bit(255)
+        )));
+    }
+
+    #[test]
+    fn coverage_guided_fuzzing_reaches_nested_branches() {
+        // one in a million random inputs takes all the branches
+        assert_eq!(fuzz_branches(false), None);
+
+        // mutating the inputs which took more of them gets there
+        let (revert_reason, calldata) = fuzz_branches(true).expect("did not reach the revert");
+        assert_eq!(revert_reason, "guided-revert");
+
+        // the rounds of mutated inputs are reproducible with the same seed
+        assert_eq!(fuzz_branches(true).map(|(_, calldata)| calldata), Some(calldata));
+    }
+
+    #[test]
+    fn fails_cases_exceeding_max_gas() {
+        let mut evm = vm();
+
+        let compiled = COMPILED.find("FuzzTests").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let evm = fuzzvm(&mut evm).max_gas(Some(1));
+
+        // the reverts are expected, but every case uses more than 1 gas
+        let func = compiled.abi.unwrap().function("testFuzzedRevert").unwrap();
+        let res = evm.fuzz(&func, addr, true);
+        let reason = res.test_error.unwrap().revert_reason;
+        assert!(reason.contains("exceeding the maximum of 1 per fuzz case"), "{}", reason);
+    }
+
+    /// Fuzzes `testFuzzedRevert`, whose reverts are expected, with the corpus in the file. Returns
+    /// whether every case passed
+    fn fuzz_with_corpus(path: &Path) -> bool {
+        let mut evm = vm();
+
+        let compiled = COMPILED.find("FuzzTests").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let evm = fuzzvm(&mut evm).corpus(path.to_path_buf());
+        let func = compiled.abi.unwrap().function("testFuzzedRevert").unwrap();
+        evm.fuzz(func, addr, true).test_error.is_none()
+    }
+
+    #[test]
+    fn keeps_a_corpus_which_can_not_be_read() {
+        let dir = std::env::temp_dir().join("forge-fuzz-corpus");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (valid, invalid) = (dir.join("valid.json"), dir.join("invalid.json"));
+        let _ = std::fs::remove_file(&valid);
+        std::fs::write(&invalid, "not a corpus").unwrap();
+
+        // the cases which reached new paths are persisted
+        assert!(fuzz_with_corpus(&valid));
+        assert!(!FuzzCorpus::load(&valid).unwrap().inputs.is_empty());
+
+        assert!(fuzz_with_corpus(&invalid));
+        assert_eq!(std::fs::read_to_string(&invalid).unwrap(), "not a corpus");
+    }
+
+    #[test]
+    fn collects_unique_revert_reasons() {
+        let mut evm = vm();
+
+        let compiled = COMPILED.find("FuzzTests").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let evm = fuzzvm(&mut evm).unique_reverts(Some(5));
+
+        let func = compiled.abi.unwrap().function("testFuzzedRevert").unwrap();
+        let res = evm.fuzz(&func, addr, false);
+        assert_eq!(res.unique_reverts.len(), 1);
+        assert_eq!(res.unique_reverts[0].0, "fuzztest-revert");
+        assert_eq!(res.test_error.unwrap().revert_reason, "fuzztest-revert");
+    }
+
+    #[test]
+    fn finds_broken_invariants() {
+        let mut evm = vm();
+
+        let compiled = COMPILED.find("InvariantTests").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let evm = fuzzvm(&mut evm);
+
+        let abi = compiled.abi.unwrap();
+        let invariant = abi.function("invariantCounterBelowLimit").unwrap();
+        let increment = abi.function("increment").unwrap().clone();
+        let res = evm.invariant(invariant, &[increment], addr, 10);
+        assert_eq!(res.revert_reason, "invariant-limit");
+        match res.test_error {
+            // every call adds at most 9, so at least 3 calls are needed to reach 20
+            Some(TestError::Fail(_, sequence)) => assert!(sequence.len() >= 3),
+            _ => panic!("the invariant did not break"),
+        }
+    }
+
+    #[test]
+    fn minimizes_broken_invariant_sequences() {
+        let mut evm = vm();
+
+        let compiled = COMPILED.find("InvariantTests").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let evm = fuzzvm(&mut evm).shrink_sequence(true);
+
+        let abi = compiled.abi.unwrap();
+        let invariant = abi.function("invariantCounterBelowLimit").unwrap();
+        let increment = abi.function("increment").unwrap().clone();
+        let res = evm.invariant(invariant, &[increment], addr, 10);
+        assert_eq!(res.revert_reason, "invariant-limit");
+        let sequence = match res.test_error {
+            Some(TestError::Fail(_, sequence)) => sequence,
+            _ => panic!("the invariant did not break"),
+        };
+        // every call adds `x % 10` to the counter, which must reach 20
+        let added = sequence
+            .iter()
+            .map(|calldata| (U256::from_big_endian(&calldata[4..]) % 10).as_u64())
+            .collect::<Vec<_>>();
+        let total = added.iter().sum::<u64>();
+        assert!(total >= 20);
+        // no call can be dropped without the counter staying below the limit
+        assert!(added.iter().all(|x| total - x < 20));
+    }
 }
@@ -24,25 +24,77 @@ pub mod evm_opts;
 use ethers::{
     abi::{Detokenize, Tokenize},
     contract::{decode_function_data, encode_function_data},
-    core::types::{Address, Bytes, U256},
+    core::types::{Address, Bytes, H256, U256},
 };
 
 use foundry_utils::IntoFunction;
 
 use eyre::Result;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 
 /// The account that we use to fund all the deployed contracts
 pub static FAUCET_ACCOUNT: Lazy<Address> =
     Lazy::new(|| Address::from_slice(&ethers::utils::keccak256("turbodapp faucet")[12..]));
 
 /// Errors related to the EVM call execution
+/// Where a [`StructuredLog`] came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSource {
+    /// A `console.log` call
+    Console,
+    /// An emitted event, e.g. one of the DSTest `log_*` events
+    Event,
+}
+
+/// A log of an EVM call in a form that can be consumed by tools, next to the strings it is
+/// printed as
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StructuredLog {
+    pub source: LogSource,
+    /// The log formatted the way it is printed, `None` for events which are not DSTest logs
+    pub message: Option<String>,
+    /// The address of the contract which emitted the event
+    pub address: Option<Address>,
+    /// The topics of the event, empty for `console.log` calls
+    pub topics: Vec<H256>,
+    /// The data of the event, empty for `console.log` calls
+    pub data: Bytes,
+}
+
+/// What the next call is expected to revert with by the `expectRevert` cheatcode
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExpectedRevert {
+    /// `expectRevert()`, which any revert matches
+    Any,
+    /// `expectRevert(bytes)`, which only a revert with this reason or data matches
+    Data(Vec<u8>),
+}
+
+impl ExpectedRevert {
+    /// The reason or data the revert must have, `None` if any revert matches
+    pub fn data(&self) -> Option<&[u8]> {
+        match self {
+            ExpectedRevert::Any => None,
+            ExpectedRevert::Data(data) => Some(data),
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum EvmError {
     #[error("Execution reverted: {reason}, (gas: {gas_used})")]
     // TODO: Add proper log printing.
     /// Error which occurred during execution of an EVM transaction
-    Execution { reason: String, gas_used: u64, logs: Vec<String> },
+    Execution {
+        reason: String,
+        gas_used: u64,
+        logs: Vec<String>,
+        data: Bytes,
+        /// Whether the transaction ran out of gas instead of reverting
+        out_of_gas: bool,
+    },
     #[error(transparent)]
     /// Error which occurred during ABI encoding / decoding of data
     AbiError(#[from] ethers::contract::AbiError),
@@ -61,13 +113,18 @@ pub trait Evm<State> {
     /// Gets the revert reason type
     fn revert() -> Self::ReturnReason;
 
-    fn expected_revert(&self) -> Option<&[u8]>;
+    fn expected_revert(&self) -> Option<&ExpectedRevert>;
 
     /// Whether a return reason should be considered successful
     fn is_success(reason: &Self::ReturnReason) -> bool;
     /// Whether a return reason should be considered failing
     fn is_fail(reason: &Self::ReturnReason) -> bool;
 
+    /// Whether a return reason means that execution ran out of gas
+    fn is_out_of_gas(_reason: &Self::ReturnReason) -> bool {
+        false
+    }
+
     /// Sets the provided contract bytecode at the corresponding addresses
     fn initialize_contracts<I: IntoIterator<Item = (Address, Bytes)>>(&mut self, contracts: I);
 
@@ -95,6 +152,30 @@ pub trait Evm<State> {
     /// Gets all logs from the execution, regardless of reverts
     fn all_logs(&self) -> Vec<String>;
 
+    /// Returns the logs of the last call as [`StructuredLog`]s
+    fn structured_logs(&self) -> Vec<StructuredLog> {
+        vec![]
+    }
+
+    /// Returns the gas refunds accumulated by the EVM so far
+    fn gas_refund(&self) -> u64 {
+        0
+    }
+
+    /// Returns `true` if the last call exceeded its timeout and was aborted
+    fn timed_out(&self) -> bool {
+        false
+    }
+
+    /// Starts recording the instructions executed by all later calls, if the EVM supports it
+    fn record_coverage(&mut self) {}
+
+    /// Returns the number of distinct instructions executed since coverage is recorded, `None` if
+    /// it is not
+    fn covered_instructions(&self) -> Option<usize> {
+        None
+    }
+
     /// Performs a [`call_unchecked`](Self::call_unchecked), checks if execution reverted, and
     /// proceeds to return the decoded response to the user.
     fn call<D: Detokenize, T: Tokenize, F: IntoFunction>(
@@ -109,7 +190,8 @@ pub trait Evm<State> {
         let (retdata, status, gas, logs) = self.call_unchecked(from, to, &func, args, value)?;
         if Self::is_fail(&status) {
             let reason = foundry_utils::decode_revert(retdata.as_ref()).unwrap_or_default();
-            Err(EvmError::Execution { reason, gas_used: gas, logs })
+            let out_of_gas = Self::is_out_of_gas(&status);
+            Err(EvmError::Execution { reason, gas_used: gas, logs, data: retdata, out_of_gas })
         } else {
             let retdata = decode_function_data(&func, retdata, false)?;
             Ok((retdata, status, gas, logs))
@@ -160,13 +242,14 @@ pub trait Evm<State> {
         value: U256,
     ) -> Result<(Address, Self::ReturnReason, u64, Vec<String>)>;
 
-    /// Runs the `setUp()` function call to instantiate the contract's state
-    fn setup(&mut self, address: Address) -> Result<(Self::ReturnReason, Vec<String>)> {
+    /// Runs the `setUp()` function call to instantiate the contract's state, returning the gas it
+    /// consumed
+    fn setup(&mut self, address: Address) -> Result<(Self::ReturnReason, u64, Vec<String>)> {
         let span = tracing::trace_span!("setup", ?address);
         let _enter = span.enter();
-        let (_, status, _, logs) =
+        let (_, status, gas, logs) =
             self.call::<(), _, _>(Address::zero(), address, "setUp()", (), 0.into())?;
-        Ok((status, logs))
+        Ok((status, gas, logs))
     }
 
     /// Runs the `failed()` function call to inspect the test contract's state and
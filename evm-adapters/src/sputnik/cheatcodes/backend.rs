@@ -11,6 +11,7 @@ use sputnik::backend::{Backend, Basic};
 /// The cheatcode backend can be composed with other enhanced backends, e.g. the forking
 /// backend. You should always put the cheatcode backend on the highest layer of your
 /// stack of backend middlewares, so that it is always hit first.
+#[derive(Clone)]
 pub struct CheatcodeBackend<B> {
     /// The inner backend type.
     pub backend: B,
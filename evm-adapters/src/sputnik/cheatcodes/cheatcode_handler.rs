@@ -1,12 +1,16 @@
 //! Hooks to EVM execution
 use super::{
     backend::CheatcodeBackend, memory_stackstate_owned::MemoryStackStateOwned, ConsoleCalls,
-    HEVMCalls, HevmConsoleEvents,
+    HEVMCalls, HevmConsoleEvents, HEVM_ABI,
 };
 use crate::{
-    call_tracing::{CallTrace, CallTraceArena, LogCallOrder},
-    sputnik::{cheatcodes::memory_stackstate_owned::ExpectedEmit, Executor, SputnikExecutor},
-    Evm,
+    call_tracing::{BlockChange, CallTrace, CallTraceArena, LogCallOrder, TraceLog},
+    fuzz::ASSUME_REVERT_REASON,
+    sputnik::{
+        cheatcodes::memory_stackstate_owned::ExpectedEmit, Executor, PrecompileStub,
+        SputnikExecutor,
+    },
+    Evm, ExpectedRevert, LogSource, StructuredLog,
 };
 use std::collections::BTreeMap;
 
@@ -22,7 +26,7 @@ use sputnik::{
     gasometer, Capture, Config, Context, CreateScheme, ExitError, ExitReason, ExitRevert,
     ExitSucceed, Handler, Memory, Opcode, Runtime, Transfer,
 };
-use std::{process::Command, rc::Rc};
+use std::{cell::RefCell, process::Command, rc::Rc};
 
 use ethers::{
     abi::{RawLog, Token},
@@ -34,11 +38,11 @@ use ethers::{
     types::{Address, H160, H256, U256},
 };
 use ethers_core::types::Bytes;
-use std::{convert::Infallible, str::FromStr};
+use std::{convert::Infallible, str::FromStr, time::Instant};
 
 use crate::sputnik::cheatcodes::{
     debugger::{CheatOp, DebugArena, DebugNode, DebugStep, OpCode},
-    memory_stackstate_owned::Prank,
+    memory_stackstate_owned::{Coverage, Prank},
     patch_hardhat_console_log_selector,
 };
 use once_cell::sync::Lazy;
@@ -107,6 +111,9 @@ pub struct CheatcodeHandler<H> {
     enable_ffi: bool,
     enable_trace: bool,
     console_logs: Vec<String>,
+    /// The events and `console.log` calls of the previous call, kept when the logs are cleared
+    last_logs: Vec<Log>,
+    last_console_logs: Vec<String>,
 }
 
 pub(crate) fn convert_log(log: Log) -> Option<String> {
@@ -165,8 +172,8 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
         self.handler.state_mut()
     }
 
-    fn expected_revert(&self) -> Option<&[u8]> {
-        self.handler.state().expected_revert.as_deref()
+    fn expected_revert(&self) -> Option<&ExpectedRevert> {
+        self.handler.state().expected_revert.as_ref()
     }
 
     fn set_tracing_enabled(&mut self, enabled: bool) -> bool {
@@ -188,6 +195,24 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
         U256::from(self.state().metadata().gasometer().gas())
     }
 
+    fn gas_refund(&self) -> u64 {
+        self.state().metadata().gasometer().refunded_gas().max(0) as u64
+    }
+
+    fn timed_out(&self) -> bool {
+        self.state().timed_out
+    }
+
+    fn record_coverage(&mut self) {
+        self.state_mut().coverage.get_or_insert_with(Default::default);
+    }
+
+    fn covered_instructions(&self) -> Option<usize> {
+        let coverage = self.state().coverage.as_ref()?;
+        let covered = coverage.borrow().values().map(|instructions| instructions.len()).sum();
+        Some(covered)
+    }
+
     fn all_logs(&self) -> Vec<String> {
         self.handler.state().all_logs.clone()
     }
@@ -203,6 +228,8 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
     ) -> (ExitReason, Vec<u8>) {
         // reset all_logs because its a new call
         self.state_mut().all_logs = vec![];
+        self.state_mut().deadline = self.state().timeout.map(|timeout| Instant::now() + timeout);
+        self.state_mut().timed_out = false;
 
         let transaction_cost = gasometer::call_transaction_cost(&data, &access_list);
         match self.state_mut().metadata_mut().gasometer_mut().record_transaction(transaction_cost) {
@@ -267,6 +294,8 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
     ) -> ExitReason {
         // reset all_logs because its a new call
         self.state_mut().all_logs = vec![];
+        self.state_mut().deadline = self.state().timeout.map(|timeout| Instant::now() + timeout);
+        self.state_mut().timed_out = false;
 
         let transaction_cost = gasometer::create_transaction_cost(&init_code, &access_list);
         match self.state_mut().metadata_mut().gasometer_mut().record_transaction(transaction_cost) {
@@ -299,8 +328,26 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
     }
 
     fn clear_logs(&mut self) {
-        self.console_logs.clear();
-        self.state_mut().substate.logs_mut().clear()
+        self.last_console_logs = std::mem::take(&mut self.console_logs);
+        self.last_logs = std::mem::take(self.state_mut().substate.logs_mut());
+    }
+
+    fn structured_logs(&self) -> Vec<StructuredLog> {
+        let events = self.last_logs.iter().map(|log| StructuredLog {
+            source: LogSource::Event,
+            message: convert_log(log.clone()),
+            address: Some(log.address),
+            topics: log.topics.clone(),
+            data: log.data.clone().into(),
+        });
+        let console = self.last_console_logs.iter().map(|message| StructuredLog {
+            source: LogSource::Console,
+            message: Some(message.clone()),
+            address: None,
+            topics: vec![],
+            data: Default::default(),
+        });
+        events.chain(console).collect()
     }
 
     fn raw_logs(&self) -> Vec<RawLog> {
@@ -358,6 +405,8 @@ impl<'a, 'b, B: Backend, P: PrecompileSet>
             enable_ffi,
             enable_trace,
             console_logs: Vec::new(),
+            last_logs: Vec::new(),
+            last_console_logs: Vec::new(),
         };
 
         let mut evm = Executor::from_executor(executor, gas_limit);
@@ -382,16 +431,13 @@ fn evm_error(retdata: &str) -> Capture<(ExitReason, Vec<u8>), Infallible> {
 }
 
 // helper for creating the Expected Revert return type, based on if there was a call or a create,
-// and if there was any decoded retdata that matched the expected revert value.
+// and if the revert matched the expected revert.
 fn revert_return_evm<T: ToString>(
     call: bool,
-    result: Option<(&[u8], &[u8])>,
+    matched: bool,
     err: impl FnOnce() -> T,
 ) -> ExpectRevertReturn {
-    let success =
-        result.map(|(retdata, expected_revert)| retdata == expected_revert).unwrap_or(false);
-
-    match (success, call) {
+    match (matched, call) {
         // Success case for CALLs needs to return a dummy output value which
         // can be decoded
         (true, true) => ExpectRevertReturn::Call(Capture::Exit((
@@ -423,7 +469,7 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
     fn expected_revert(
         &mut self,
         res: ExpectRevertReturn,
-        expected_revert: Option<Vec<u8>>,
+        expected_revert: Option<ExpectedRevert>,
     ) -> ExpectRevertReturn {
         // return early if there was no revert expected
         let expected_revert = match expected_revert {
@@ -442,19 +488,28 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
             ExpectRevertReturn::Call(Capture::Exit((ExitReason::Revert(_e), revdata))) => {
                 Some(revdata)
             }
-            _ => return revert_return_evm(call, None, || "Expected revert did not revert"),
+            _ => return revert_return_evm(call, false, || "call did not revert as expected"),
+        };
+
+        // `expectRevert()` doesn't expect any specific data, so any revert matches
+        let expected_revert = match expected_revert {
+            ExpectedRevert::Any => {
+                self.state_mut().matched_expected_reverts.push(ExpectedRevert::Any);
+                return revert_return_evm(call, true, || "")
+            }
+            ExpectedRevert::Data(data) => data,
         };
 
         // if there was no revert data return an error
         let data = match data {
             Some(inner) => inner,
             None => {
-                return revert_return_evm(call, None, || "Expected revert did not revert with data")
+                return revert_return_evm(call, false, || "Expected revert did not revert with data")
             }
         };
 
         // do the actual check
-        if data.len() >= 4 && data[0..4] == [8, 195, 121, 160] {
+        let (matched, res) = if data.len() >= 4 && data[0..4] == [8, 195, 121, 160] {
             // its a revert string
             let decoded_data = ethers::abi::decode(&[ethers::abi::ParamType::Bytes], &data[4..])
                 .expect("String error code, but not actual string");
@@ -469,7 +524,8 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
                     String::from_utf8_lossy(&expected_revert)
                 )
             };
-            revert_return_evm(call, Some((&decoded_data, &expected_revert)), err)
+            let matched = decoded_data == expected_revert;
+            (matched, revert_return_evm(call, matched, err))
         } else {
             let err = || {
                 format!(
@@ -478,8 +534,13 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
                     hex::encode(&expected_revert)
                 )
             };
-            revert_return_evm(call, Some((&data, &expected_revert)), err)
+            let matched = data == expected_revert;
+            (matched, revert_return_evm(call, matched, err))
+        };
+        if matched {
+            self.state_mut().matched_expected_reverts.push(ExpectedRevert::Data(expected_revert));
         }
+        res
     }
 
     /// Given a transaction's calldata, it tries to parse it a console call and print the call
@@ -571,14 +632,22 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
             Err(err) => return evm_error(&err.to_string()),
         };
 
+        if let Some(func) = HEVM_ABI.functions().find(|func| func.short_signature() == input[..4]) {
+            *self.state_mut().cheatcode_usage.entry(func.name.clone()).or_default() += 1;
+        }
+
         match decoded {
             HEVMCalls::Warp(inner) => {
                 self.add_debug(CheatOp::WARP);
+                let from = self.handler.block_timestamp();
                 self.state_mut().backend.cheats.block_timestamp = Some(inner.0);
+                self.record_block_change(&trace, BlockChange::Timestamp { from, to: inner.0 });
             }
             HEVMCalls::Roll(inner) => {
                 self.add_debug(CheatOp::ROLL);
+                let from = self.handler.block_number();
                 self.state_mut().backend.cheats.block_number = Some(inner.0);
+                self.record_block_change(&trace, BlockChange::Number { from, to: inner.0 });
             }
             HEVMCalls::Fee(inner) => {
                 self.add_debug(CheatOp::FEE);
@@ -599,9 +668,10 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
                 // (we could have an FFI cheatcode executor instead but feels like
                 // over engineering)
                 if !self.enable_ffi {
-                    return evm_error(
-                        "ffi disabled: run again with --ffi if you want to allow tests to call external scripts",
-                    );
+                    return evm_error("ffi is disabled; pass --ffi to enable")
+                }
+                if args.is_empty() {
+                    return evm_error("ffi needs the command to run as its first argument")
                 }
 
                 // execute the command & get the stdout
@@ -610,12 +680,11 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
                     Err(err) => return evm_error(&err.to_string()),
                 };
 
-                // get the hex string & decode it
-                let output = unsafe { std::str::from_utf8_unchecked(&output) };
-                let decoded = match hex::decode(&output[2..]) {
-                    Ok(res) => res,
-                    Err(err) => return evm_error(&err.to_string()),
-                };
+                // hex output, with or without `0x`, is decoded, any other output is returned as is
+                let output = String::from_utf8_lossy(&output);
+                let output = output.trim();
+                let decoded = hex::decode(output.strip_prefix("0x").unwrap_or(output))
+                    .unwrap_or_else(|_| output.as_bytes().to_vec());
 
                 // encode the data as Bytes
                 res = ethers::abi::encode(&[Token::Bytes(decoded.to_vec())]);
@@ -734,14 +803,24 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
                 self.add_debug(CheatOp::STOPPRANK);
                 self.state_mut().prank = None;
             }
-            HEVMCalls::ExpectRevert(inner) => {
+            HEVMCalls::ExpectRevert0(inner) => {
+                self.add_debug(CheatOp::EXPECTREVERT);
+                if self.state().expected_revert.is_some() {
+                    return evm_error(
+                        "You must call another function prior to expecting a second revert.",
+                    )
+                } else {
+                    self.state_mut().expected_revert = Some(ExpectedRevert::Data(inner.0.to_vec()));
+                }
+            }
+            HEVMCalls::ExpectRevert1(_) => {
                 self.add_debug(CheatOp::EXPECTREVERT);
                 if self.state().expected_revert.is_some() {
                     return evm_error(
                         "You must call another function prior to expecting a second revert.",
                     )
                 } else {
-                    self.state_mut().expected_revert = Some(inner.0.to_vec());
+                    self.state_mut().expected_revert = Some(ExpectedRevert::Any);
                 }
             }
             HEVMCalls::Deal(inner) => {
@@ -821,6 +900,13 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
                 self.add_debug(CheatOp::EXPECTCALL);
                 self.state_mut().expected_calls.entry(inner.0).or_default().push(inner.1.to_vec());
             }
+            HEVMCalls::Assume(inner) => {
+                self.add_debug(CheatOp::ASSUME);
+                // the fuzzer discards the inputs of a case which reverts with this reason
+                if !inner.0 {
+                    return evm_error(ASSUME_REVERT_REASON)
+                }
+            }
         };
 
         self.fill_trace(&trace, true, Some(res.clone()), pre_index);
@@ -925,52 +1011,87 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
         }
     }
 
-    fn debug_run(
+    /// Returns the program counter => instruction counter mapping of the code at `address` from
+    /// memory or constructs it
+    fn instruction_counters(
         &mut self,
-        runtime: &mut Runtime,
         address: Address,
-        depth: usize,
-        code: Rc<Vec<u8>>,
+        code: &[u8],
         creation: bool,
-    ) -> Capture<ExitReason, ()> {
-        let mut done = false;
-        let mut res = Capture::Exit(ExitReason::Succeed(ExitSucceed::Returned));
-        let mut steps = Vec::new();
+    ) -> Rc<BTreeMap<usize, usize>> {
         // grab the debug instruction pointers for either construct or runtime bytecode
         let dip = if creation {
             &mut self.state_mut().debug_instruction_pointers.0
         } else {
             &mut self.state_mut().debug_instruction_pointers.1
         };
-        // get the program counter => instruction counter mapping from memory or construct it
-        let ics = if let Some(pc_ic) = dip.get(&address) {
+        if let Some(pc_ic) = dip.get(&address) {
             // grabs an Rc<BTreemap> of an already created pc -> ic mapping
-            pc_ic.clone()
-        } else {
-            // builds a program counter to instruction counter map
-            // basically this strips away bytecodes to make it work
-            // with the sourcemap output from the solc compiler
-            let mut pc_ic: BTreeMap<usize, usize> = BTreeMap::new();
-
-            let mut i = 0;
-            let mut push_ctr = 0usize;
-            while i < code.len() {
-                let wrapped_op = OpCode::from(Opcode(code[i]));
-                pc_ic.insert(i, i - push_ctr);
-
-                if let Some(push_size) = wrapped_op.push_size() {
-                    i += push_size as usize;
-                    i += 1;
-                    push_ctr += push_size as usize;
-                } else {
-                    i += 1;
+            return pc_ic.clone()
+        }
+        // builds a program counter to instruction counter map
+        // basically this strips away bytecodes to make it work
+        // with the sourcemap output from the solc compiler
+        let mut pc_ic: BTreeMap<usize, usize> = BTreeMap::new();
+
+        let mut i = 0;
+        let mut push_ctr = 0usize;
+        while i < code.len() {
+            let wrapped_op = OpCode::from(Opcode(code[i]));
+            pc_ic.insert(i, i - push_ctr);
+
+            if let Some(push_size) = wrapped_op.push_size() {
+                i += push_size as usize;
+                i += 1;
+                push_ctr += push_size as usize;
+            } else {
+                i += 1;
+            }
+        }
+        let pc_ic = Rc::new(pc_ic);
+
+        dip.insert(address, pc_ic.clone());
+        pc_ic
+    }
+
+    /// Executes the call/create while recording the instruction counter of every executed
+    /// instruction in `coverage`
+    fn coverage_execute(
+        &mut self,
+        runtime: &mut Runtime,
+        coverage: &RefCell<Coverage>,
+        address: Address,
+        code: &[u8],
+        creation: bool,
+    ) -> ExitReason {
+        let ics = self.instruction_counters(address, code, creation);
+        loop {
+            if let Ok(pc) = runtime.machine().position() {
+                if let Some(ic) = ics.get(pc) {
+                    coverage.borrow_mut().entry((address, creation)).or_default().insert(*ic);
+                }
+            }
+            if let Err(capture) = runtime.step(self) {
+                match capture {
+                    Capture::Exit(reason) => return reason,
+                    Capture::Trap(_) => unreachable!("Trap is Infallible"),
                 }
             }
-            let pc_ic = Rc::new(pc_ic);
+        }
+    }
 
-            dip.insert(address, pc_ic.clone());
-            pc_ic
-        };
+    fn debug_run(
+        &mut self,
+        runtime: &mut Runtime,
+        address: Address,
+        depth: usize,
+        code: Rc<Vec<u8>>,
+        creation: bool,
+    ) -> Capture<ExitReason, ()> {
+        let mut done = false;
+        let mut res = Capture::Exit(ExitReason::Succeed(ExitSucceed::Returned));
+        let mut steps = Vec::new();
+        let ics = self.instruction_counters(address, &code, creation);
         while !done {
             // debug step doesnt actually execute the step, it just peeks into the machine
             // will return true or false, which signifies whether to push the steps
@@ -1026,6 +1147,7 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
         transfer: U256,
         creation: bool,
     ) -> Option<CallTrace> {
+        let pranked_caller = self.state_mut().pranked_caller.take();
         if self.enable_trace {
             let mut trace: CallTrace = CallTrace {
                 // depth only starts tracking at first child substate and is 0. so add 1 when depth
@@ -1039,6 +1161,7 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
                 created: creation,
                 data: input,
                 value: transfer,
+                pranked_caller,
                 ..Default::default()
             };
 
@@ -1050,6 +1173,13 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
         }
     }
 
+    /// Notes the block change of a `warp` or `roll` on the trace of the cheatcode call
+    fn record_block_change(&mut self, trace: &Option<CallTrace>, change: BlockChange) {
+        if let Some(trace) = trace {
+            self.state_mut().trace_mut().arena[trace.idx].trace.block_change = Some(change);
+        }
+    }
+
     fn fill_trace(
         &mut self,
         new_trace: &Option<CallTrace>,
@@ -1064,6 +1194,11 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
             trace.output = output.unwrap_or_default();
             trace.cost = used_gas;
             trace.success = success;
+
+            // drop the deep call detail of reverted calls
+            if let (false, Some(max_depth)) = (success, self.state().max_revert_depth) {
+                self.state_mut().trace_mut().prune(new_trace.idx, max_depth);
+            }
         }
     }
 
@@ -1198,6 +1333,10 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
             let code = Rc::new(code);
             runtime = Runtime::new(code.clone(), Rc::new(input), context, &config);
             self.debug_execute(&mut runtime, code_address, code, false)
+        } else if let Some(coverage) = self.state().coverage.clone() {
+            let code = Rc::new(code);
+            runtime = Runtime::new(code.clone(), Rc::new(input), context, &config);
+            self.coverage_execute(&mut runtime, &coverage, code_address, &code, false)
         } else {
             runtime = Runtime::new(Rc::new(code), Rc::new(input), context, &config);
             self.execute(&mut runtime)
@@ -1345,6 +1484,10 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
             let code = Rc::new(init_code);
             runtime = Runtime::new(code.clone(), Rc::new(Vec::new()), context, &config);
             self.debug_execute(&mut runtime, address, code, true)
+        } else if let Some(coverage) = self.state().coverage.clone() {
+            let code = Rc::new(init_code);
+            runtime = Runtime::new(code.clone(), Rc::new(Vec::new()), context, &config);
+            self.coverage_execute(&mut runtime, &coverage, address, &code, true)
         } else {
             runtime = Runtime::new(Rc::new(init_code), Rc::new(Vec::new()), context, &config);
             self.execute(&mut runtime)
@@ -1444,6 +1587,7 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
 
             // modify execution context depending on the cheatcode
             let expected_revert = self.state_mut().expected_revert.take();
+            let caller = context.caller;
             let mut new_context = context;
             let mut new_transfer = transfer;
             let curr_depth =
@@ -1504,7 +1648,22 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
                 }
             }
 
-            // perform the call
+            // handle stubbed precompiles
+            if let Some(stub) = self.state().precompile_overrides.get(&code_address) {
+                return match stub {
+                    PrecompileStub::Return(data) => {
+                        Capture::Exit((ExitReason::Succeed(ExitSucceed::Returned), data.clone()))
+                    }
+                    PrecompileStub::Revert(data) => {
+                        Capture::Exit((ExitReason::Revert(ExitRevert::Reverted), data.clone()))
+                    }
+                }
+            }
+
+            // perform the call, tracing the spoofed caller if it was pranked
+            if new_context.caller != caller {
+                self.state_mut().pranked_caller = Some(new_context.caller);
+            }
             let res = self.call_inner(
                 code_address,
                 new_transfer,
@@ -1615,6 +1774,10 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
     }
 
     fn is_cold(&self, address: H160, index: Option<H256>) -> bool {
+        // EIP-2929: precompiles are always warm, only their storage slots can be cold
+        if index.is_none() && self.handler.precompiles().is_precompile(address) {
+            return false
+        }
         self.handler.is_cold(address, index)
     }
 
@@ -1627,7 +1790,9 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
             let index = self.state().trace_index;
             let node = &mut self.state_mut().traces.last_mut().expect("no traces").arena[index];
             node.ordering.push(LogCallOrder::Log(node.logs.len()));
-            node.logs.push(RawLog { topics: topics.clone(), data: data.clone() });
+            let log =
+                TraceLog { node: node.id.clone(), topics: topics.clone(), data: data.clone() };
+            node.logs.push(log);
         }
 
         if let Some(decoded) =
@@ -1726,6 +1891,8 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
                 }
                 _ => scheme,
             };
+            // trace the spoofed caller of the creation
+            self.state_mut().pranked_caller = Some(new_tx_caller);
         }
 
         let res = self.create_inner(new_tx_caller, new_scheme, value, init_code, target_gas, true);
@@ -1753,6 +1920,16 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
         opcode: sputnik::Opcode,
         stack: &sputnik::Stack,
     ) -> Result<(), ExitError> {
+        // abort every frame of a call which exceeded its timeout
+        if self.state().deadline.map_or(false, |deadline| Instant::now() > deadline) {
+            self.state_mut().timed_out = true;
+            return Err(ExitError::Other("timeout".into()))
+        }
+        match opcode {
+            Opcode::SLOAD => self.state_mut().storage_reads += 1,
+            Opcode::SSTORE => self.state_mut().storage_writes += 1,
+            _ => {}
+        }
         self.handler.pre_validate(context, opcode, stack)
     }
 }
@@ -1839,6 +2016,25 @@ mod tests {
         assert_eq!(logs, expected);
     }
 
+    #[test]
+    fn structured_console_logs() {
+        let mut evm = vm();
+
+        let compiled = COMPILED.find("ConsoleLogs").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let (_, _, _, logs) =
+            evm.call::<(), _, _>(Address::zero(), addr, "test_log()", (), 0.into()).unwrap();
+        let structured = evm.structured_logs();
+        assert_eq!(structured.len(), logs.len());
+        for (log, message) in structured.iter().zip(&logs) {
+            assert_eq!(log.source, LogSource::Console);
+            assert_eq!(log.message.as_ref(), Some(message));
+            assert!(log.topics.is_empty());
+        }
+    }
+
     #[test]
     fn console_logs_types() {
         let mut evm = vm();
@@ -1902,7 +2098,7 @@ mod tests {
         let abi = compiled.abi.as_ref().unwrap();
         for func in abi.functions().filter(|func| func.name.starts_with("test")) {
             // Skip the FFI unit test if not in a unix system
-            if func.name == "testFFI" && !cfg!(unix) {
+            if func.name.starts_with("testFFI") && !cfg!(unix) {
                 continue
             }
 
@@ -1919,6 +2115,68 @@ mod tests {
         }
     }
 
+    #[test]
+    fn access_costs_match_the_fork() {
+        use crate::sputnik::{
+            helpers::{new_backend, GAS_LIMIT, VICINITY},
+            PRECOMPILES_MAP,
+        };
+        use sputnik::backend::MemoryAccount;
+
+        // SLOAD slot 0 twice, then BALANCE of the ecrecover precompile and of an untouched account
+        let code = hex::decode("600054506000545060013150611234315000").unwrap();
+        let target = Address::from_low_u64_be(0xc0de);
+        let account =
+            MemoryAccount { nonce: 1.into(), balance: 0.into(), storage: Default::default(), code };
+
+        // every opcode costs 3 (PUSH) + the access + 2 (POP), STOP is free
+        for (cfg, gas) in [
+            // cold and warm SLOAD, warm precompile, cold account
+            (Config::london(), 2105 + 105 + 105 + 2605),
+            (Config::berlin(), 2105 + 105 + 105 + 2605),
+            // flat SLOAD and BALANCE costs before EIP-2929
+            (Config::istanbul(), 805 + 805 + 705 + 705),
+        ] {
+            let backend = new_backend(&*VICINITY, [(target, account.clone())].into());
+            let mut evm = Executor::new_with_cheatcodes(
+                backend,
+                GAS_LIMIT,
+                &cfg,
+                &*PRECOMPILES_MAP,
+                false,
+                false,
+                false,
+            );
+            let (_, reason, used, _) =
+                evm.call_raw(Address::zero(), target, Bytes::new(), 0.into(), false).unwrap();
+            assert!(matches!(reason, ExitReason::Succeed(_)), "{:?}", reason);
+            assert_eq!(used, gas);
+        }
+    }
+
+    #[test]
+    fn precompile_override() {
+        let mut evm = vm_no_limit();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let func = compiled.abi.as_ref().unwrap().function("test_sign_addr_digest").unwrap();
+        let args = (U256::from(1), [1u8; 32]);
+
+        let (_, reason, _, _) =
+            evm.call_unchecked(Address::zero(), addr, func, args, 0.into()).unwrap();
+        assert!(evm.check_success(addr, &reason, false));
+
+        // ecrecover returns the zero address instead of the signer
+        evm.executor
+            .state_mut()
+            .precompile_overrides
+            .insert(H160::from_low_u64_be(1), PrecompileStub::Return(vec![0; 32]));
+        let (_, reason, _, _) =
+            evm.call_unchecked(Address::zero(), addr, func, args, 0.into()).unwrap();
+        assert!(!evm.check_success(addr, &reason, false));
+    }
+
     #[test]
     fn ffi_fails_if_disabled() {
         let mut evm = vm_no_limit();
@@ -1934,7 +2192,7 @@ mod tests {
             crate::EvmError::Execution { reason, .. } => reason,
             _ => panic!("unexpected error"),
         };
-        assert_eq!(reason, "ffi disabled: run again with --ffi if you want to allow tests to call external scripts");
+        assert_eq!(reason, "ffi is disabled; pass --ffi to enable");
     }
 
     #[test]
@@ -2051,4 +2309,51 @@ mod tests {
         let mut identified = Default::default();
         evm.traces()[1].pretty_print(0, &mapping, &mut identified, &evm, "");
     }
+
+    #[test]
+    fn tracing_prank() {
+        let mut evm = vm_tracing(false);
+
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let (_, _, _, _) =
+            evm.call::<(), _, _>(Address::zero(), addr, "testPrank()", (), 0.into()).unwrap();
+
+        // only the call right after `prank` is made from the spoofed sender
+        let pranked = evm.traces()[1]
+            .arena
+            .iter()
+            .filter_map(|node| node.trace.pranked_caller)
+            .collect::<Vec<_>>();
+        assert_eq!(pranked, vec![Address::from_low_u64_be(1337)]);
+    }
+
+    #[test]
+    fn tracing_warp_and_roll() {
+        let mut evm = vm_tracing(false);
+
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let timestamp = evm.state().block_timestamp();
+        let number = evm.state().block_number();
+        let (_, reason, _, _) = evm
+            .call::<(), _, _>(Address::zero(), addr, "testWarpRollLaterCalls()", (), 0.into())
+            .unwrap();
+        assert!(matches!(reason, ExitReason::Succeed(_)), "{:?}", reason);
+
+        let changes = evm.traces()[1]
+            .arena
+            .iter()
+            .filter_map(|node| node.trace.block_change)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                BlockChange::Timestamp { from: timestamp, to: timestamp + 7 * 24 * 60 * 60 },
+                BlockChange::Number { from: number, to: number + 1 },
+            ]
+        );
+    }
 }
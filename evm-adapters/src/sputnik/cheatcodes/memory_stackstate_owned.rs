@@ -4,14 +4,23 @@ use sputnik::{
     ExitError, Transfer,
 };
 
-use crate::{call_tracing::CallTraceArena, sputnik::cheatcodes::debugger::DebugArena};
+use crate::{
+    call_tracing::CallTraceArena,
+    sputnik::{cheatcodes::debugger::DebugArena, PrecompileStub},
+    ExpectedRevert,
+};
 
 use ethers::{
     abi::RawLog,
     types::{H160, H256, U256},
 };
 
-use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 #[derive(Clone, Default)]
 pub struct RecordAccess {
@@ -57,12 +66,16 @@ pub struct MemoryStackStateOwned<'config, B> {
     pub trace_index: usize,
     /// Arena allocator that holds a tree of traces
     pub traces: Vec<CallTraceArena>,
-    /// Expected revert storage of bytes
-    pub expected_revert: Option<Vec<u8>>,
+    /// The number of call levels below a reverted call that are kept in the traces
+    pub max_revert_depth: Option<usize>,
+    /// What the next call is expected to revert with
+    pub expected_revert: Option<ExpectedRevert>,
     /// Next call's prank
     pub next_prank: Option<Prank>,
     /// StartPrank information
     pub prank: Option<Prank>,
+    /// The spoofed `msg.sender` of the call that is traced next, if it is pranked
+    pub pranked_caller: Option<H160>,
     /// List of accesses done during a call
     pub accesses: Option<RecordAccess>,
     /// All logs accumulated (regardless of revert status)
@@ -70,13 +83,34 @@ pub struct MemoryStackStateOwned<'config, B> {
     /// Expected events by end of the next call
     pub expected_emits: Vec<ExpectedEmit>,
     pub mocked_calls: BTreeMap<H160, BTreeMap<Vec<u8>, Vec<u8>>>,
+    /// Precompiles whose calls are answered by a stub instead
+    pub precompile_overrides: BTreeMap<H160, PrecompileStub>,
     pub expected_calls: BTreeMap<H160, Vec<Vec<u8>>>,
+    /// How often each cheatcode was called, by cheatcode name
+    pub cheatcode_usage: BTreeMap<String, usize>,
+    /// The `expectRevert`s which matched the revert of the call they expected
+    pub matched_expected_reverts: Vec<ExpectedRevert>,
+    /// The number of executed SLOAD opcodes
+    pub storage_reads: usize,
+    /// The number of executed SSTORE opcodes
+    pub storage_writes: usize,
+    /// How long a call may execute before it is aborted
+    pub timeout: Option<Duration>,
+    /// When the current call is aborted, derived from `timeout` when the call starts
+    pub deadline: Option<Instant>,
+    /// Whether the current call was aborted because it exceeded its timeout
+    pub timed_out: bool,
     /// Debug enabled
     pub debug_enabled: bool,
     /// An arena allocator of DebugNodes for debugging purposes
     pub debug_steps: Vec<DebugArena>,
     /// Instruction pointers that maps an address to a mapping of pc to ic
     pub debug_instruction_pointers: Dip,
+    /// If set, the instruction counters of every executed instruction are recorded.
+    ///
+    /// The recording is shared by all clones of the state, so that it covers every case of a
+    /// fuzz test
+    pub coverage: Option<Rc<RefCell<Coverage>>>,
 }
 
 impl<'config, B: Backend> MemoryStackStateOwned<'config, B> {
@@ -107,6 +141,10 @@ impl<'config, B: Backend> MemoryStackStateOwned<'config, B> {
     }
 }
 
+/// The instruction counters of the executed instructions by code address and whether they are
+/// part of the creation code, which index into the contract's sourcemap like [`Dip`]'s
+pub type Coverage = BTreeMap<(H160, bool), BTreeSet<usize>>;
+
 /// Debug Instruction pointers: a tuple with 2 maps, the first being for creation
 /// sourcemaps, the second for runtime sourcemaps.
 ///
@@ -132,17 +170,28 @@ impl<'config, B: Backend> MemoryStackStateOwned<'config, B> {
             call_index: 0,
             trace_index: 1,
             traces: vec![Default::default()],
+            max_revert_depth: None,
             expected_revert: None,
             next_prank: None,
             prank: None,
+            pranked_caller: None,
             accesses: None,
             all_logs: Default::default(),
             expected_emits: Default::default(),
             mocked_calls: Default::default(),
+            precompile_overrides: Default::default(),
             expected_calls: Default::default(),
+            cheatcode_usage: Default::default(),
+            matched_expected_reverts: Default::default(),
+            storage_reads: 0,
+            storage_writes: 0,
+            timeout: None,
+            deadline: None,
+            timed_out: false,
             debug_enabled,
             debug_steps: vec![Default::default()],
             debug_instruction_pointers: (BTreeMap::new(), BTreeMap::new()),
+            coverage: None,
         }
     }
 }
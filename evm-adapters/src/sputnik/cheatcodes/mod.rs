@@ -64,6 +64,7 @@ ethers::contract::abigen!(
             deal(address,uint256)
             etch(address,bytes)
             expectRevert(bytes)
+            expectRevert()
             record()
             accesses(address)(bytes32[],bytes32[])
             expectEmit(bool,bool,bool,bool)
@@ -71,6 +72,7 @@ ethers::contract::abigen!(
             clearMockedCalls()
             expectCall(address,bytes)
             getCode(string)
+            assume(bool)
     ]"#,
 );
 pub use hevm_mod::{HEVMCalls, HEVM_ABI};
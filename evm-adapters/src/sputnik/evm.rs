@@ -1,4 +1,4 @@
-use crate::{call_tracing::CallTraceArena, Evm, FAUCET_ACCOUNT};
+use crate::{call_tracing::CallTraceArena, Evm, ExpectedRevert, StructuredLog, FAUCET_ACCOUNT};
 use ethers::types::{Address, Bytes, U256};
 
 use crate::sputnik::cheatcodes::debugger::DebugArena;
@@ -8,7 +8,7 @@ use sputnik::{
     executor::stack::{
         MemoryStackState, PrecompileSet, StackExecutor, StackState, StackSubstateMetadata,
     },
-    Config, CreateScheme, ExitReason, ExitRevert, Transfer,
+    Config, CreateScheme, ExitError, ExitReason, ExitRevert, Transfer,
 };
 use std::{collections::BTreeMap, marker::PhantomData};
 
@@ -68,7 +68,7 @@ where
         ExitReason::Revert(ExitRevert::Reverted)
     }
 
-    fn expected_revert(&self) -> Option<&[u8]> {
+    fn expected_revert(&self) -> Option<&ExpectedRevert> {
         self.executor.expected_revert()
     }
 
@@ -80,6 +80,10 @@ where
         !Self::is_success(reason)
     }
 
+    fn is_out_of_gas(reason: &Self::ReturnReason) -> bool {
+        matches!(reason, ExitReason::Error(ExitError::OutOfGas))
+    }
+
     fn reset(&mut self, state: S) {
         let mut _state = self.executor.state_mut();
         *_state = state;
@@ -135,6 +139,26 @@ where
         self.executor.all_logs()
     }
 
+    fn structured_logs(&self) -> Vec<StructuredLog> {
+        self.executor.structured_logs()
+    }
+
+    fn gas_refund(&self) -> u64 {
+        self.executor.gas_refund()
+    }
+
+    fn timed_out(&self) -> bool {
+        self.executor.timed_out()
+    }
+
+    fn record_coverage(&mut self) {
+        self.executor.record_coverage()
+    }
+
+    fn covered_instructions(&self) -> Option<usize> {
+        self.executor.covered_instructions()
+    }
+
     /// Deploys the provided contract bytecode
     fn deploy(
         &mut self,
@@ -412,6 +436,23 @@ mod tests {
         assert_eq!(gas_used, 26633);
     }
 
+    #[test]
+    fn out_of_gas_is_not_a_revert() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        evm.setup(addr).unwrap();
+
+        // not enough to execute the test past its intrinsic gas
+        evm.gas_limit = 25_000;
+        let err = evm
+            .call::<(), _, _>(Address::zero(), addr, "testFailGreeting()", (), 0.into())
+            .unwrap_err();
+        assert!(matches!(err, crate::EvmError::Execution { out_of_gas: true, .. }));
+    }
+
     #[test]
     fn test_can_call_large_contract() {
         let mut evm = vm();
@@ -19,7 +19,10 @@ use sputnik::{
     Config, CreateScheme, ExitError, ExitReason, ExitSucceed,
 };
 
-use crate::{call_tracing::CallTraceArena, sputnik::cheatcodes::debugger::DebugArena};
+use crate::{
+    call_tracing::CallTraceArena, sputnik::cheatcodes::debugger::DebugArena, ExpectedRevert,
+    StructuredLog,
+};
 
 pub use sputnik as sputnik_evm;
 use sputnik_evm::executor::stack::PrecompileSet;
@@ -63,12 +66,23 @@ pub trait SputnikExecutor<S> {
     fn config(&self) -> &Config;
     fn state(&self) -> &S;
     fn state_mut(&mut self) -> &mut S;
-    fn expected_revert(&self) -> Option<&[u8]>;
+    fn expected_revert(&self) -> Option<&ExpectedRevert>;
     fn set_tracing_enabled(&mut self, enabled: bool) -> bool;
     fn tracing_enabled(&self) -> bool;
     fn debug_calls(&self) -> Vec<DebugArena>;
     fn all_logs(&self) -> Vec<String>;
     fn gas_left(&self) -> U256;
+    /// Returns the gas refunds accumulated over all the transactions executed so far
+    fn gas_refund(&self) -> u64;
+    /// Returns `true` if the last call exceeded its timeout and was aborted
+    fn timed_out(&self) -> bool;
+    /// Starts recording the instructions executed by all later calls, if the executor supports it
+    fn record_coverage(&mut self) {}
+    /// Returns the number of distinct instructions executed since coverage is recorded, `None` if
+    /// it is not
+    fn covered_instructions(&self) -> Option<usize> {
+        None
+    }
     fn transact_call(
         &mut self,
         caller: H160,
@@ -108,6 +122,11 @@ pub trait SputnikExecutor<S> {
     /// Clears all logs in the current EVM instance, so that subsequent calls to
     /// `logs` do not print duplicate logs on shared EVM instances.
     fn clear_logs(&mut self);
+
+    /// Returns the logs which were cleared last, i.e. the logs of the previous call
+    fn structured_logs(&self) -> Vec<StructuredLog> {
+        vec![]
+    }
 }
 
 // The implementation for the base Stack Executor just forwards to the internal methods.
@@ -126,7 +145,7 @@ impl<'a, 'b, S: StackState<'a>, P: PrecompileSet> SputnikExecutor<S>
         self.state_mut()
     }
 
-    fn expected_revert(&self) -> Option<&[u8]> {
+    fn expected_revert(&self) -> Option<&ExpectedRevert> {
         None
     }
 
@@ -151,6 +170,14 @@ impl<'a, 'b, S: StackState<'a>, P: PrecompileSet> SputnikExecutor<S>
         U256::from(self.state().metadata().gasometer().gas())
     }
 
+    fn gas_refund(&self) -> u64 {
+        self.state().metadata().gasometer().refunded_gas().max(0) as u64
+    }
+
+    fn timed_out(&self) -> bool {
+        false
+    }
+
     fn transact_call(
         &mut self,
         caller: H160,
@@ -195,6 +222,15 @@ use std::borrow::Cow;
 pub type PrecompileFn =
     fn(&[u8], Option<u64>, &sputnik::Context, bool) -> Result<PrecompileOutput, PrecompileFailure>;
 
+/// The behavior a precompile is replaced with by the cheatcode handler, regardless of its input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrecompileStub {
+    /// Return this data
+    Return(Vec<u8>),
+    /// Revert with this data
+    Revert(Vec<u8>),
+}
+
 /// Precompiled contracts which should be provided when instantiating the EVM.
 pub static PRECOMPILES: Lazy<revm_precompiles::Precompiles> = Lazy::new(|| {
     // We use the const to immediately choose the latest revision of available
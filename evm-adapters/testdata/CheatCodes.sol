@@ -38,6 +38,7 @@ interface Hevm {
     function etch(address, bytes calldata) external;
     // Expects an error on next call
     function expectRevert(bytes calldata) external;
+    function expectRevert() external;
     // Record all storage reads and writes
     function record() external;
     // Gets all accessed reads and write slot from a recording session, for a given address
@@ -97,6 +98,21 @@ contract CheatCodes is DSTest {
         assertEq(block.timestamp, pre + jump + 1);
     }
 
+    function blockEnv() external view returns (uint256, uint256) {
+        return (block.timestamp, block.number);
+    }
+
+    // the new timestamp and number hold for every later call of the test
+    function testWarpRollLaterCalls() public {
+        uint256 timestamp = block.timestamp + 7 days;
+        uint256 number = block.number + 1;
+        hevm.warp(timestamp);
+        hevm.roll(number);
+        (uint256 laterTimestamp, uint256 laterNumber) = this.blockEnv();
+        require(laterTimestamp == timestamp, "warp did not hold");
+        require(laterNumber == number, "roll did not hold");
+    }
+
     // Fee
 
     // Sets the basefee
@@ -184,6 +200,15 @@ contract CheatCodes is DSTest {
         assertEq(output, "acab");
     }
 
+    function testFFIText() public {
+        string[] memory inputs = new string[](2);
+        inputs[0] = "echo";
+        inputs[1] = "hello";
+
+        bytes memory res = hevm.ffi(inputs);
+        assertEq(string(res), "hello");
+    }
+
     function testDeal() public {
         address addr = address(1337);
         hevm.deal(addr, 1337);
@@ -326,6 +351,32 @@ contract CheatCodes is DSTest {
         target.stringErrCall(99);
     }
 
+    function testExpectRevertAny() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert();
+        target.stringErr(101);
+        target.stringErr(99);
+    }
+
+    function testFailExpectRevertAny() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert();
+        target.stringErr(99);
+    }
+
+    function testExpectRevertEmptyData() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert(bytes(""));
+        target.emptyErr(101);
+        target.emptyErr(99);
+    }
+
+    function testFailExpectRevertEmptyData() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert(bytes(""));
+        target.stringErr(101);
+    }
+
     function testFailExpectRevert() public {
         ExpectRevert target = new ExpectRevert();
         hevm.expectRevert("Value too large");
@@ -627,6 +678,11 @@ contract ExpectRevert {
         return a;
     }
 
+    function emptyErr(uint256 a) public returns (uint256) {
+        require(a < 100);
+        return a;
+    }
+
     function customErr(uint256 a) public returns (uint256) {
         if (a > 99) {
             revert InputTooLarge();
@@ -1,7 +1,42 @@
 pragma solidity ^0.8.10;
 
+interface Hevm {
+    function assume(bool) external;
+}
+
 contract FuzzTests {
     function testFuzzedRevert(uint256 x) public {
         require(x == 5, "fuzztest-revert");
     }
+
+    // each branch is taken by one in 16 inputs
+    function testFuzzedBranches(uint8 a, uint8 b, uint8 c, uint8 d, uint8 e) public {
+        if (a % 16 == 1) {
+            if (b % 16 == 2) {
+                if (c % 16 == 3) {
+                    if (d % 16 == 4) {
+                        if (e % 16 == 5) {
+                            revert("guided-revert");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    function testFuzzedAssume(uint256 x) public {
+        Hevm(0x7109709ECfa91a80626fF3989D68f67F5b1DD12D).assume(x != x);
+    }
+}
+
+contract InvariantTests {
+    uint256 public counter;
+
+    function increment(uint256 x) public {
+        counter += x % 10;
+    }
+
+    function invariantCounterBelowLimit() public {
+        require(counter < 20, "invariant-limit");
+    }
 }
@@ -1,12 +1,24 @@
 mod runner;
-pub use runner::{ContractRunner, TestKind, TestKindGas, TestResult};
+pub use runner::{
+    ContractRunner, CounterExample, CounterExampleInput, TestKind, TestKindGas, TestResult,
+};
 
 mod multi_runner;
-pub use multi_runner::{MultiContractRunner, MultiContractRunnerBuilder};
+pub use multi_runner::{
+    CompilationSummary, CompileError, MultiContractRunner, MultiContractRunnerBuilder, TestCounts,
+};
+
+use std::path::Path;
 
 pub trait TestFilter {
     fn matches_test(&self, test_name: &str) -> bool;
+    /// Whether the test of the contract matches, for filters which also depend on the contract
+    /// a test is declared in. Only matches the test name by default
+    fn matches_contract_test(&self, _contract_name: &str, test_name: &str) -> bool {
+        self.matches_test(test_name)
+    }
     fn matches_contract(&self, contract_name: &str) -> bool;
+    fn matches_path(&self, path: &Path) -> bool;
 }
 
 #[cfg(test)]
@@ -71,5 +83,9 @@ pub mod test_helpers {
         fn matches_contract(&self, contract_name: &str) -> bool {
             self.contract_regex.is_match(contract_name)
         }
+
+        fn matches_path(&self, _path: &Path) -> bool {
+            true
+        }
     }
 }
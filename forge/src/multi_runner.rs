@@ -1,24 +1,41 @@
-use crate::{runner::TestResult, ContractRunner, TestFilter};
-use evm_adapters::evm_opts::{BackendKind, EvmOpts};
+use crate::{
+    runner::{TestResult, DEFAULT_TEST_PREFIX, INVARIANT_PREFIX, SETUP_SIGNATURE},
+    ContractRunner, TestFilter,
+};
+use evm_adapters::{
+    evm_opts::{BackendKind, EvmOpts},
+    fuzz::ParamRanges,
+    sputnik::PrecompileStub,
+    Evm,
+};
 use sputnik::{backend::Backend, Config};
 
 use ethers::solc::Artifact;
 
 use ethers::{
-    abi::Abi,
+    abi::{Abi, Token},
     prelude::ArtifactOutput,
-    solc::Project,
-    types::{Address, U256},
+    solc::{artifacts::Severity, CompilerOutput, Project, ProjectCompileOutput},
+    types::{Address, Bytes, H256, U256},
 };
 
-use proptest::test_runner::TestRunner;
+use proptest::test_runner::{Config as FuzzConfig, TestRunner};
 
 use eyre::Result;
 use rayon::prelude::*;
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 /// Builder used for instantiating the multi-contract runner
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct MultiContractRunnerBuilder {
     /// The fuzzer to be used for running fuzz tests
     pub fuzzer: Option<TestRunner>,
@@ -29,6 +46,80 @@ pub struct MultiContractRunnerBuilder {
     pub initial_balance: U256,
     /// The EVM Configuration to use
     pub evm_cfg: Option<Config>,
+    /// Whether contracts with test functions but no `setUp()` should fail
+    pub require_setup: bool,
+    /// The directory in which fuzz inputs that reached new execution paths are persisted
+    pub fuzz_corpus: Option<PathBuf>,
+    /// Inputs replayed before any new ones by the fuzz tests, by contract and test signature
+    pub fuzz_inputs: BTreeMap<String, BTreeMap<String, Vec<Bytes>>>,
+    /// Whether fuzz inputs are also generated by mutating inputs that reached new execution paths
+    pub fuzz_coverage_guided: bool,
+    /// The gas a single fuzz case may use before it fails the test
+    pub max_fuzz_gas: Option<u64>,
+    /// If set, fuzz tests collect up to this many distinct revert reasons instead of stopping at
+    /// the first failing case
+    pub fuzz_unique_reverts: Option<usize>,
+    /// How long a test call, or a single fuzz case, may execute before it fails the test
+    pub timeout: Option<Duration>,
+    /// Whether call traces are collected regardless of the verbosity
+    pub force_tracing: bool,
+    /// The number of call levels below a reverted call that are kept in the traces
+    pub max_revert_depth: Option<usize>,
+    /// The total number of fuzz cases which is distributed equally across all fuzz tests
+    pub fuzz_runs_budget: Option<u32>,
+    /// If set, all tests are run one after the other in a random order derived from this seed
+    pub order_seed: Option<u64>,
+    /// Whether all tests are run one after the other, in the declaration order of the functions
+    /// of their contract if it is known and alphabetically otherwise
+    pub sequential: bool,
+    /// The maximum number of calls in a sequence of an invariant test
+    pub invariant_depth: Option<usize>,
+    /// The number of call sequences of an invariant test, the fuzzer's number of cases if not set
+    pub invariant_runs: Option<u32>,
+    /// Whether the call sequence which broke an invariant is minimized beyond proptest's shrinking
+    pub invariant_shrink_sequence: bool,
+    /// The prefix of test functions, `test` if not set
+    pub test_function_prefix: Option<String>,
+    /// If set, only contracts whose name ends with this are considered test contracts
+    pub test_contract_suffix: Option<String>,
+    /// Whether no further contracts are run once a contract had a failing test
+    pub exit_first_contract_failure: bool,
+    /// Whether no further tests are started once any test failed
+    pub fail_fast: bool,
+    /// The number of tests giving up after too many rejected inputs after which no further tests
+    /// are started
+    pub max_test_rejects: Option<usize>,
+    /// The source file of every contract by name, matched against the filter's paths
+    pub contract_files: BTreeMap<String, PathBuf>,
+    /// Accounts funded with the given balance before the tests of every contract are run
+    pub seed_accounts: BTreeMap<Address, U256>,
+    /// Precompiles replaced with stubs in every EVM
+    pub precompile_overrides: BTreeMap<Address, PrecompileStub>,
+    /// The constructor arguments of test contracts, by contract name
+    pub constructor_args: BTreeMap<String, Vec<String>>,
+    /// The bounds of numeric fuzz parameters by test name and parameter index
+    pub fuzz_param_ranges: BTreeMap<String, ParamRanges>,
+    /// How often failing tests are run again
+    pub retries: usize,
+    /// If set, only failing tests whose reason or error matches are run again
+    pub retry_on_reason: Option<regex::Regex>,
+    /// How often failing fuzz tests are run again with a fresh seed
+    pub fuzz_retries: usize,
+    /// The seed of the fuzzer, which the seeds of the retries of fuzz tests are derived from
+    pub fuzz_seed: Option<u64>,
+    /// Whether unit tests start from a snapshot of the post-`setUp()` state
+    pub isolate: bool,
+    /// If set, fuzz and invariant tests shrink failing cases deterministically from this seed
+    pub deterministic_shrink_seed: Option<u64>,
+    /// Whether the instruction counters executed by every test are recorded
+    pub coverage: bool,
+    /// Whether only the `setUp()` of the test contracts is run, instead of their tests
+    pub only_setup: bool,
+    /// Whether nothing is printed while compiling
+    pub quiet: bool,
+    /// If set, the creation code of the test contracts is read from disk as they are run instead
+    /// of being kept in memory, of which at most this many bytes are kept
+    pub max_artifacts_memory: Option<usize>,
 }
 
 impl MultiContractRunnerBuilder {
@@ -37,25 +128,70 @@ impl MultiContractRunnerBuilder {
     pub fn build<A>(self, project: Project<A>, evm_opts: EvmOpts) -> Result<MultiContractRunner>
     where
         // TODO: Can we remove the static? It's due to the `into_artifacts()` call below
-        A: ArtifactOutput + 'static,
+        A: ArtifactOutput + Clone + 'static,
     {
-        println!("compiling...");
+        self.build_with_output(project, evm_opts).map(|(runner, _)| runner)
+    }
+
+    /// Same as [`Self::build`], but also returns the output of the compiler, which holds the
+    /// source maps of all contracts if the project is compiled without the cache
+    pub fn build_with_output<A>(
+        self,
+        project: Project<A>,
+        evm_opts: EvmOpts,
+    ) -> Result<(MultiContractRunner, CompilerOutput)>
+    where
+        A: ArtifactOutput + Clone + 'static,
+    {
+        let (output, compiler_output, compilation) = self.compile(project)?;
+        Ok((self.build_from_output(output, compilation, evm_opts)?, compiler_output))
+    }
+
+    /// Compiles the project, returning the output, the output of the compiler and what the
+    /// compilation did
+    fn compile<A: ArtifactOutput + Clone>(
+        &self,
+        project: Project<A>,
+    ) -> Result<(ProjectCompileOutput<A>, CompilerOutput, CompilationSummary)> {
+        if !self.quiet {
+            println!("compiling...");
+        }
+        let compile_start = Instant::now();
         let output = project.compile()?;
         if output.has_compiler_errors() {
             // return the diagnostics error back to the user.
-            eyre::bail!(output.to_string())
-        } else if output.is_unchanged() {
+            let message = output.to_string();
+            return Err(CompileError { diagnostics: output.output().errors, message }.into())
+        } else if !self.quiet && output.is_unchanged() {
             println!("no files changed, compilation skipped.");
-        } else {
+        } else if !self.quiet {
             println!("success.");
         }
+        let duration = compile_start.elapsed();
+        let compiler_output = output.clone().output();
+        let compilation = CompilationSummary::new(&project, &output, &compiler_output, duration)?;
+        Ok((output, compiler_output, compilation))
+    }
 
+    fn build_from_output<A>(
+        self,
+        output: ProjectCompileOutput<A>,
+        compilation: CompilationSummary,
+        evm_opts: EvmOpts,
+    ) -> Result<MultiContractRunner>
+    where
+        A: ArtifactOutput + 'static,
+    {
         // This is just the contracts compiled, but we need to merge this with the read cached
         // artifacts
+        let test_function_prefix =
+            self.test_function_prefix.clone().unwrap_or_else(|| DEFAULT_TEST_PREFIX.to_string());
         let contracts = output.into_artifacts();
         let mut known_contracts: BTreeMap<String, (Abi, Vec<u8>)> = Default::default();
+        let mut creation_code_sizes: BTreeMap<String, usize> = Default::default();
         let mut deployable_contracts: BTreeMap<String, (Abi, ethers::prelude::Bytes)> =
             Default::default();
+        let mut artifacts = self.max_artifacts_memory.map(ArtifactStore::new).transpose()?;
 
         for (fname, contract) in contracts {
             let (maybe_abi, maybe_deploy_bytes, maybe_runtime_bytes) = contract.into_parts();
@@ -65,29 +201,122 @@ impl MultiContractRunnerBuilder {
                     continue
                 }
 
+                let split = fname.split(':').collect::<Vec<&str>>();
+                let contract_name = if split.len() > 1 { split[1] } else { split[0] };
+
                 if abi.constructor.as_ref().map(|c| c.inputs.is_empty()).unwrap_or(true) &&
-                    abi.functions().any(|func| func.name.starts_with("test"))
+                    abi.functions().any(|func| {
+                        func.name.starts_with(&test_function_prefix) ||
+                            func.name.starts_with(INVARIANT_PREFIX)
+                    }) &&
+                    self.test_contract_suffix
+                        .as_ref()
+                        .map_or(true, |suffix| contract_name.ends_with(suffix))
                 {
-                    deployable_contracts.insert(fname.clone(), (abi.clone(), bytecode.clone()));
+                    // the code is left out of the contract if it is read from disk as it is run
+                    let code = match artifacts.as_mut() {
+                        Some(artifacts) => {
+                            artifacts.insert(&fname, bytecode.as_ref())?;
+                            Default::default()
+                        }
+                        None => bytecode.clone(),
+                    };
+                    deployable_contracts.insert(fname.clone(), (abi.clone(), code));
                 }
 
-                let split = fname.split(':').collect::<Vec<&str>>();
-                let contract_name = if split.len() > 1 { split[1] } else { split[0] };
+                creation_code_sizes.insert(contract_name.to_string(), bytecode.as_ref().len());
                 if let Some(runtime_code) = maybe_runtime_bytes {
                     known_contracts.insert(contract_name.to_string(), (abi, runtime_code.to_vec()));
                 }
             }
         }
 
-        Ok(MultiContractRunner {
-            contracts: deployable_contracts,
+        Ok(self.into_runner(
+            deployable_contracts,
+            artifacts,
+            known_contracts,
+            creation_code_sizes,
+            compilation,
+            evm_opts,
+        ))
+    }
+
+    /// Builds a runner of contracts which were already compiled, e.g. by the runner of another
+    /// process, without compiling the project again
+    pub fn build_from_contracts(
+        self,
+        contracts: BTreeMap<String, (Abi, ethers::prelude::Bytes)>,
+        known_contracts: BTreeMap<String, (Abi, Vec<u8>)>,
+        creation_code_sizes: BTreeMap<String, usize>,
+        evm_opts: EvmOpts,
+    ) -> MultiContractRunner {
+        let compilation = CompilationSummary { cached: true, ..Default::default() };
+        self.into_runner(
+            contracts,
+            None,
             known_contracts,
+            creation_code_sizes,
+            compilation,
+            evm_opts,
+        )
+    }
+
+    fn into_runner(
+        self,
+        contracts: BTreeMap<String, (Abi, ethers::prelude::Bytes)>,
+        artifacts: Option<ArtifactStore>,
+        known_contracts: BTreeMap<String, (Abi, Vec<u8>)>,
+        creation_code_sizes: BTreeMap<String, usize>,
+        compilation: CompilationSummary,
+        evm_opts: EvmOpts,
+    ) -> MultiContractRunner {
+        MultiContractRunner {
+            contracts,
+            artifacts,
+            known_contracts,
+            creation_code_sizes,
             identified_contracts: Default::default(),
             evm_opts,
             evm_cfg: self.evm_cfg.unwrap_or_else(Config::london),
             sender: self.sender,
             fuzzer: self.fuzzer,
-        })
+            require_setup: self.require_setup,
+            fuzz_corpus: self.fuzz_corpus,
+            fuzz_inputs: self.fuzz_inputs,
+            fuzz_coverage_guided: self.fuzz_coverage_guided,
+            max_fuzz_gas: self.max_fuzz_gas,
+            fuzz_unique_reverts: self.fuzz_unique_reverts,
+            timeout: self.timeout,
+            force_tracing: self.force_tracing,
+            max_revert_depth: self.max_revert_depth,
+            fuzz_runs_budget: self.fuzz_runs_budget,
+            order_seed: self.order_seed,
+            sequential: self.sequential,
+            declaration_order: BTreeMap::new(),
+            invariant_depth: self.invariant_depth,
+            invariant_runs: self.invariant_runs,
+            invariant_shrink_sequence: self.invariant_shrink_sequence,
+            exit_first_contract_failure: self.exit_first_contract_failure,
+            fail_fast: self.fail_fast,
+            max_test_rejects: self.max_test_rejects,
+            contract_files: self.contract_files,
+            seed_accounts: self.seed_accounts,
+            precompile_overrides: self.precompile_overrides,
+            constructor_args: self.constructor_args,
+            fuzz_param_ranges: self.fuzz_param_ranges,
+            retries: self.retries,
+            retry_on_reason: self.retry_on_reason,
+            fuzz_retries: self.fuzz_retries,
+            fuzz_seed: self.fuzz_seed,
+            isolate: self.isolate,
+            deterministic_shrink_seed: self.deterministic_shrink_seed,
+            coverage: self.coverage,
+            only_setup: self.only_setup,
+            compilation,
+            test_function_prefix: self
+                .test_function_prefix
+                .unwrap_or_else(|| DEFAULT_TEST_PREFIX.to_string()),
+        }
     }
 
     #[must_use]
@@ -113,15 +342,223 @@ impl MultiContractRunnerBuilder {
         self.evm_cfg = Some(evm_cfg);
         self
     }
+
+    #[must_use]
+    pub fn require_setup(mut self, require_setup: bool) -> Self {
+        self.require_setup = require_setup;
+        self
+    }
+
+    #[must_use]
+    pub fn fuzz_corpus(mut self, dir: PathBuf) -> Self {
+        self.fuzz_corpus = Some(dir);
+        self
+    }
+
+    #[must_use]
+    pub fn fuzz_inputs(mut self, inputs: BTreeMap<String, BTreeMap<String, Vec<Bytes>>>) -> Self {
+        self.fuzz_inputs = inputs;
+        self
+    }
+
+    #[must_use]
+    pub fn fuzz_coverage_guided(mut self, fuzz_coverage_guided: bool) -> Self {
+        self.fuzz_coverage_guided = fuzz_coverage_guided;
+        self
+    }
+
+    #[must_use]
+    pub fn max_fuzz_gas(mut self, max_fuzz_gas: Option<u64>) -> Self {
+        self.max_fuzz_gas = max_fuzz_gas;
+        self
+    }
+
+    #[must_use]
+    pub fn fuzz_unique_reverts(mut self, max: Option<usize>) -> Self {
+        self.fuzz_unique_reverts = max;
+        self
+    }
+
+    #[must_use]
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    #[must_use]
+    pub fn force_tracing(mut self, force_tracing: bool) -> Self {
+        self.force_tracing = force_tracing;
+        self
+    }
+
+    #[must_use]
+    pub fn max_revert_depth(mut self, max_revert_depth: Option<usize>) -> Self {
+        self.max_revert_depth = max_revert_depth;
+        self
+    }
+
+    #[must_use]
+    pub fn fuzz_runs_budget(mut self, fuzz_runs_budget: Option<u32>) -> Self {
+        self.fuzz_runs_budget = fuzz_runs_budget;
+        self
+    }
+
+    #[must_use]
+    pub fn order_seed(mut self, order_seed: Option<u64>) -> Self {
+        self.order_seed = order_seed;
+        self
+    }
+
+    #[must_use]
+    pub fn sequential(mut self, sequential: bool) -> Self {
+        self.sequential = sequential;
+        self
+    }
+
+    #[must_use]
+    pub fn test_function_prefix(mut self, prefix: String) -> Self {
+        self.test_function_prefix = Some(prefix);
+        self
+    }
+
+    #[must_use]
+    pub fn test_contract_suffix(mut self, suffix: String) -> Self {
+        self.test_contract_suffix = Some(suffix);
+        self
+    }
+
+    #[must_use]
+    pub fn exit_first_contract_failure(mut self, exit_first_contract_failure: bool) -> Self {
+        self.exit_first_contract_failure = exit_first_contract_failure;
+        self
+    }
+
+    #[must_use]
+    pub fn invariant_depth(mut self, invariant_depth: Option<usize>) -> Self {
+        self.invariant_depth = invariant_depth;
+        self
+    }
+
+    #[must_use]
+    pub fn invariant_runs(mut self, invariant_runs: Option<u32>) -> Self {
+        self.invariant_runs = invariant_runs;
+        self
+    }
+
+    #[must_use]
+    pub fn invariant_shrink_sequence(mut self, shrink_sequence: bool) -> Self {
+        self.invariant_shrink_sequence = shrink_sequence;
+        self
+    }
+
+    #[must_use]
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    #[must_use]
+    pub fn max_test_rejects(mut self, max_test_rejects: Option<usize>) -> Self {
+        self.max_test_rejects = max_test_rejects;
+        self
+    }
+
+    #[must_use]
+    pub fn contract_files(mut self, contract_files: BTreeMap<String, PathBuf>) -> Self {
+        self.contract_files = contract_files;
+        self
+    }
+
+    #[must_use]
+    pub fn seed_accounts(mut self, seed_accounts: BTreeMap<Address, U256>) -> Self {
+        self.seed_accounts = seed_accounts;
+        self
+    }
+
+    #[must_use]
+    pub fn precompile_overrides(
+        mut self,
+        precompile_overrides: BTreeMap<Address, PrecompileStub>,
+    ) -> Self {
+        self.precompile_overrides = precompile_overrides;
+        self
+    }
+
+    #[must_use]
+    pub fn constructor_args(mut self, constructor_args: BTreeMap<String, Vec<String>>) -> Self {
+        self.constructor_args = constructor_args;
+        self
+    }
+
+    #[must_use]
+    pub fn fuzz_param_ranges(mut self, ranges: BTreeMap<String, ParamRanges>) -> Self {
+        self.fuzz_param_ranges = ranges;
+        self
+    }
+
+    #[must_use]
+    pub fn retries(mut self, retries: usize, on_reason: Option<regex::Regex>) -> Self {
+        self.retries = retries;
+        self.retry_on_reason = on_reason;
+        self
+    }
+
+    #[must_use]
+    pub fn fuzz_retries(mut self, fuzz_retries: usize, fuzz_seed: Option<u64>) -> Self {
+        self.fuzz_retries = fuzz_retries;
+        self.fuzz_seed = fuzz_seed;
+        self
+    }
+
+    #[must_use]
+    pub fn isolate(mut self, isolate: bool) -> Self {
+        self.isolate = isolate;
+        self
+    }
+
+    #[must_use]
+    pub fn deterministic_shrink_seed(mut self, seed: Option<u64>) -> Self {
+        self.deterministic_shrink_seed = seed;
+        self
+    }
+
+    #[must_use]
+    pub fn coverage(mut self, coverage: bool) -> Self {
+        self.coverage = coverage;
+        self
+    }
+
+    #[must_use]
+    pub fn only_setup(mut self, only_setup: bool) -> Self {
+        self.only_setup = only_setup;
+        self
+    }
+
+    #[must_use]
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    #[must_use]
+    pub fn max_artifacts_memory(mut self, max_artifacts_memory: Option<usize>) -> Self {
+        self.max_artifacts_memory = max_artifacts_memory;
+        self
+    }
 }
 
 /// A multi contract runner receives a set of contracts deployed in an EVM instance and proceeds
 /// to run all test functions in these contracts.
 pub struct MultiContractRunner {
-    /// Mapping of contract name to Abi and creation bytecode
+    /// Mapping of contract name to Abi and creation bytecode, which is empty if it is read from
+    /// disk instead, see [`Self::creation_code`]
     pub contracts: BTreeMap<String, (Abi, ethers::prelude::Bytes)>,
+    /// The creation code of the test contracts, if it is read from disk as they are run
+    artifacts: Option<ArtifactStore>,
     /// Compiled contracts by name that have an Abi and runtime bytecode
     pub known_contracts: BTreeMap<String, (Abi, Vec<u8>)>,
+    /// The size of the creation bytecode of every compiled contract by name
+    pub creation_code_sizes: BTreeMap<String, usize>,
     /// Identified contracts by test
     pub identified_contracts: BTreeMap<String, BTreeMap<Address, (String, Abi)>>,
     /// The EVM instance used in the test runner
@@ -132,58 +569,592 @@ pub struct MultiContractRunner {
     fuzzer: Option<TestRunner>,
     /// The address which will be used as the `from` field in all EVM calls
     sender: Option<Address>,
+    /// Whether contracts with test functions but no `setUp()` should fail
+    require_setup: bool,
+    /// The directory in which fuzz inputs that reached new execution paths are persisted
+    fuzz_corpus: Option<PathBuf>,
+    /// Inputs replayed before any new ones by the fuzz tests, by contract and test signature
+    fuzz_inputs: BTreeMap<String, BTreeMap<String, Vec<Bytes>>>,
+    /// Whether fuzz inputs are also generated by mutating inputs that reached new execution paths
+    fuzz_coverage_guided: bool,
+    /// The gas a single fuzz case may use before it fails the test
+    max_fuzz_gas: Option<u64>,
+    /// If set, fuzz tests collect up to this many distinct revert reasons instead of stopping at
+    /// the first failing case
+    fuzz_unique_reverts: Option<usize>,
+    /// How long a test call, or a single fuzz case, may execute before it fails the test
+    timeout: Option<Duration>,
+    /// Whether call traces are collected regardless of the verbosity
+    force_tracing: bool,
+    /// The number of call levels below a reverted call that are kept in the traces
+    max_revert_depth: Option<usize>,
+    /// The total number of fuzz cases which is distributed equally across all fuzz tests
+    fuzz_runs_budget: Option<u32>,
+    /// If set, all tests are run one after the other in a random order derived from this seed
+    order_seed: Option<u64>,
+    /// Whether all tests are run one after the other
+    sequential: bool,
+    /// The names of the functions of every contract by its artifact name in the order they are
+    /// declared in
+    declaration_order: BTreeMap<String, Vec<String>>,
+    /// The maximum number of calls in a sequence of an invariant test
+    invariant_depth: Option<usize>,
+    /// The number of call sequences of an invariant test, the fuzzer's number of cases if not set
+    invariant_runs: Option<u32>,
+    /// Whether the call sequence which broke an invariant is minimized beyond proptest's shrinking
+    invariant_shrink_sequence: bool,
+    /// Whether no further contracts are run once a contract had a failing test
+    exit_first_contract_failure: bool,
+    /// Whether no further tests are started once any test failed
+    fail_fast: bool,
+    /// The number of tests giving up after too many rejected inputs after which no further tests
+    /// are started
+    max_test_rejects: Option<usize>,
+    /// The source file of every contract by name, matched against the filter's paths
+    contract_files: BTreeMap<String, PathBuf>,
+    /// Accounts funded with the given balance before the tests of every contract are run
+    seed_accounts: BTreeMap<Address, U256>,
+    /// Precompiles replaced with stubs in every EVM
+    precompile_overrides: BTreeMap<Address, PrecompileStub>,
+    /// The constructor arguments of test contracts, by contract name
+    constructor_args: BTreeMap<String, Vec<String>>,
+    /// The bounds of numeric fuzz parameters by test name and parameter index
+    fuzz_param_ranges: BTreeMap<String, ParamRanges>,
+    /// How often failing tests are run again
+    retries: usize,
+    /// If set, only failing tests whose reason or error matches are run again
+    retry_on_reason: Option<regex::Regex>,
+    /// How often failing fuzz tests are run again with a fresh seed
+    fuzz_retries: usize,
+    /// The seed of the fuzzer, which the seeds of the retries of fuzz tests are derived from
+    fuzz_seed: Option<u64>,
+    /// Whether unit tests start from a snapshot of the post-`setUp()` state
+    isolate: bool,
+    /// If set, fuzz and invariant tests shrink failing cases deterministically from this seed
+    deterministic_shrink_seed: Option<u64>,
+    /// Whether the instruction counters executed by every test are recorded
+    coverage: bool,
+    /// Whether only the `setUp()` of the test contracts is run, its result keyed by `setUp()`
+    only_setup: bool,
+    /// What the compilation of the project did
+    pub compilation: CompilationSummary,
+    /// The prefix of test functions
+    pub test_function_prefix: String,
+}
+
+/// The filter of the tests of a single contract, which matches its tests by
+/// [`TestFilter::matches_contract_test`]
+struct ContractTestFilter<'a, F> {
+    filter: &'a F,
+    contract_name: &'a str,
+}
+
+impl<F: TestFilter> TestFilter for ContractTestFilter<'_, F> {
+    fn matches_test(&self, test_name: &str) -> bool {
+        self.filter.matches_contract_test(self.contract_name, test_name)
+    }
+
+    fn matches_contract(&self, contract_name: &str) -> bool {
+        self.filter.matches_contract(contract_name)
+    }
+
+    fn matches_path(&self, path: &Path) -> bool {
+        self.filter.matches_path(path)
+    }
+}
+
+/// What the compilation of a project did
+#[derive(Clone, Debug, Default)]
+pub struct CompilationSummary {
+    /// Whether no file changed and the cached artifacts were used
+    pub cached: bool,
+    /// The number of source files solc compiled, including the imported ones
+    pub compiled_files: usize,
+    /// The number of source files of the project whose artifacts were taken from the cache
+    /// instead, because they did not change
+    pub cached_files: usize,
+    /// The warnings solc emitted, which the project does not ignore
+    pub warnings: Vec<String>,
+    /// How long the compilation took
+    pub duration: Duration,
+}
+
+impl CompilationSummary {
+    fn new<A: ArtifactOutput>(
+        project: &Project<A>,
+        output: &ProjectCompileOutput<A>,
+        compiler_output: &CompilerOutput,
+        duration: Duration,
+    ) -> Result<Self> {
+        let compiled = compiler_output.sources.keys().map(PathBuf::from).collect::<BTreeSet<_>>();
+        let cached_files =
+            project.sources()?.keys().filter(|file| !compiled.contains(*file)).count();
+        let warnings = compiler_output
+            .errors
+            .iter()
+            .filter(|diagnostic| matches!(diagnostic.severity, Severity::Warning))
+            .filter(|diagnostic| {
+                diagnostic
+                    .error_code
+                    .map_or(true, |code| !project.ignored_error_codes.contains(&code))
+            })
+            .map(|diagnostic| {
+                diagnostic.formatted_message.clone().unwrap_or_else(|| diagnostic.message.clone())
+            })
+            .collect();
+        Ok(Self {
+            cached: output.is_unchanged(),
+            compiled_files: compiled.len(),
+            cached_files,
+            warnings,
+            duration,
+        })
+    }
+}
+
+/// The error of a compilation which failed, with the diagnostics solc emitted
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    /// The errors and warnings of the compilation
+    pub diagnostics: Vec<ethers::solc::artifacts::Error>,
+    /// The diagnostics as they are printed
+    message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// The creation code of the test contracts, which is written to a directory and read back as the
+/// contracts are run. Only the most recently used codes are kept in memory, up to a number of
+/// bytes
+#[derive(Debug)]
+struct ArtifactStore {
+    /// The directory the codes are written to, which is removed with the store
+    dir: PathBuf,
+    /// The file of the code of every contract by name
+    files: BTreeMap<String, PathBuf>,
+    /// The number of bytes of code which are kept in memory
+    max_memory: usize,
+    /// The codes in memory, the least recently used first, and their total size
+    loaded: Mutex<(VecDeque<(String, ethers::prelude::Bytes)>, usize)>,
+}
+
+impl ArtifactStore {
+    fn new(max_memory: usize) -> Result<Self> {
+        // every runner of the process gets its own directory
+        static STORES: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "forge-artifacts-{}-{}",
+            std::process::id(),
+            STORES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, files: BTreeMap::new(), max_memory, loaded: Default::default() })
+    }
+
+    /// Writes the code of the contract to the directory of the store
+    fn insert(&mut self, name: &str, code: &[u8]) -> Result<()> {
+        let file = self.dir.join(format!("{}.bin", self.files.len()));
+        std::fs::write(&file, code)?;
+        self.files.insert(name.to_string(), file);
+        Ok(())
+    }
+
+    /// Returns the code of the contract, reading it from its file if it is not in memory, after
+    /// which the least recently used codes are evicted until they fit in the maximum again
+    fn get(&self, name: &str) -> Result<ethers::prelude::Bytes> {
+        {
+            let mut loaded = self.loaded.lock().unwrap();
+            let (codes, _) = &mut *loaded;
+            if let Some(idx) = codes.iter().position(|(loaded, _)| loaded == name) {
+                let entry = codes.remove(idx).expect("the position is in bounds");
+                let code = entry.1.clone();
+                codes.push_back(entry);
+                return Ok(code)
+            }
+        }
+        let file = self.files.get(name).ok_or_else(|| eyre::eyre!("{} was not compiled", name))?;
+        let code = ethers::prelude::Bytes::from(std::fs::read(file)?);
+        let mut loaded = self.loaded.lock().unwrap();
+        let (codes, memory) = &mut *loaded;
+        // another contract run may have read it in the meantime
+        if !codes.iter().any(|(loaded, _)| loaded == name) {
+            *memory += code.as_ref().len();
+            codes.push_back((name.to_string(), code.clone()));
+        }
+        // the code which was just read is kept, even if it is larger than the maximum on its own
+        while *memory > self.max_memory && codes.len() > 1 {
+            if let Some((_, evicted)) = codes.pop_front() {
+                *memory -= evicted.as_ref().len();
+            }
+        }
+        Ok(code)
+    }
+
+    /// The names of the contracts whose code is in memory, the least recently used first
+    #[cfg(test)]
+    fn loaded(&self) -> Vec<String> {
+        self.loaded.lock().unwrap().0.iter().map(|(name, _)| name.clone()).collect()
+    }
+}
+
+impl Drop for ArtifactStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The number of tests of every kind, and the number of contracts they are declared in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TestCounts {
+    pub contracts: usize,
+    pub unit: usize,
+    pub fuzz: usize,
+    pub invariant: usize,
+}
+
+impl TestCounts {
+    /// The number of tests of all kinds
+    pub fn tests(&self) -> usize {
+        self.unit + self.fuzz + self.invariant
+    }
 }
 
 impl MultiContractRunner {
+    /// Sets the names of the functions of every contract by its artifact name in the order they
+    /// are declared in, which sequential runs execute the tests of the contract in
+    pub fn set_declaration_order(&mut self, declaration_order: BTreeMap<String, Vec<String>>) {
+        self.declaration_order = declaration_order;
+    }
+
+    /// Returns `true` if the contract and, if it is known, the source file it is declared in
+    /// match the filter
+    fn matches_contract(&self, filter: &impl TestFilter, name: &str) -> bool {
+        // artifact names look like `Greeter.json:GreeterTest`
+        let contract_name = name.rsplit(':').next().unwrap_or(name);
+        filter.matches_contract(name) &&
+            self.contract_files.get(contract_name).map_or(true, |path| filter.matches_path(path))
+    }
+
     pub fn test(
         &mut self,
         filter: &(impl TestFilter + Send + Sync),
+    ) -> Result<BTreeMap<String, BTreeMap<String, TestResult>>> {
+        self.test_with_progress(filter, |_, _| {})
+    }
+
+    /// Returns the creation code of the test contract, which is read from disk if the runner was
+    /// built with a maximum of artifact memory
+    pub fn creation_code(&self, name: &str) -> Result<ethers::prelude::Bytes> {
+        let (_, code) = self
+            .contracts
+            .get(name)
+            .ok_or_else(|| eyre::eyre!("{} is not a test contract", name))?;
+        self.deploy_code(name, code)
+    }
+
+    /// The code the test contract is deployed with, its creation code unless it is read from disk
+    fn deploy_code(
+        &self,
+        name: &str,
+        code: &ethers::prelude::Bytes,
+    ) -> Result<ethers::prelude::Bytes> {
+        match self.artifacts {
+            Some(ref artifacts) => artifacts.get(name),
+            None => Ok(code.clone()),
+        }
+    }
+
+    /// Returns the number of contracts whose tests are run with the filter
+    pub fn matching_contracts(&self, filter: &impl TestFilter) -> usize {
+        self.contracts.keys().filter(|name| self.matches_contract(filter, name)).count()
+    }
+
+    /// Counts the tests of every kind which are run with the filter, without running them.
+    /// Fuzz and invariant tests are only run, and counted, if a fuzzer is set
+    pub fn test_counts(&self, filter: &impl TestFilter) -> TestCounts {
+        let mut counts = TestCounts::default();
+        for (name, (abi, _)) in &self.contracts {
+            if !self.matches_contract(filter, name) {
+                continue
+            }
+            let before = counts.tests();
+            for func in
+                abi.functions().filter(|func| filter.matches_contract_test(name, &func.name))
+            {
+                if func.name.starts_with(&self.test_function_prefix) {
+                    if func.inputs.is_empty() {
+                        counts.unit += 1;
+                    } else if self.fuzzer.is_some() {
+                        counts.fuzz += 1;
+                    }
+                } else if func.name.starts_with(INVARIANT_PREFIX) &&
+                    func.inputs.is_empty() &&
+                    self.fuzzer.is_some()
+                {
+                    counts.invariant += 1;
+                }
+            }
+            if counts.tests() > before {
+                counts.contracts += 1;
+            }
+        }
+        counts
+    }
+
+    /// Runs the tests like [`Self::test`], calling `progress` with the name and results of every
+    /// contract once all of its tests finished
+    pub fn test_with_progress(
+        &mut self,
+        filter: &(impl TestFilter + Send + Sync),
+        progress: impl Fn(&str, &BTreeMap<String, TestResult>) + Sync,
     ) -> Result<BTreeMap<String, BTreeMap<String, TestResult>>> {
         // TODO: Convert to iterator, ideally parallel one?
         let contracts = std::mem::take(&mut self.contracts);
 
+        if let (Some(budget), Some(fuzzer)) = (self.fuzz_runs_budget, self.fuzzer.as_mut()) {
+            let fuzz_tests = contracts
+                .iter()
+                .filter(|(name, _)| self.matches_contract(filter, name))
+                .flat_map(|(name, (abi, _))| abi.functions().map(move |func| (name, func)))
+                .filter(|(name, func)| {
+                    func.name.starts_with(&self.test_function_prefix) &&
+                        !func.inputs.is_empty() &&
+                        filter.matches_contract_test(name, &func.name)
+                })
+                .count();
+            if fuzz_tests > 0 {
+                let cases = (budget / fuzz_tests as u32).max(1);
+                // keep the rng so that the fuzzer's seed is retained
+                let rng = fuzzer.rng().clone();
+                *fuzzer =
+                    TestRunner::new_with_rng(FuzzConfig { cases, ..fuzzer.config().clone() }, rng);
+            }
+        }
+
         let vicinity = self.evm_opts.vicinity()?;
         let backend = self.evm_opts.backend(&vicinity)?;
 
-        let results = contracts
-            .par_iter()
-            .filter(|(name, _)| filter.matches_contract(name))
-            .map(|(name, (abi, deploy_code))| {
-                // unavoidable duplication here?
-                let result = match backend {
-                    BackendKind::Simple(ref backend) => {
-                        self.run_tests(name, abi, backend, deploy_code.clone(), filter)?
-                    }
-                    BackendKind::Shared(ref backend) => {
-                        self.run_tests(name, abi, backend, deploy_code.clone(), filter)?
-                    }
-                };
-                Ok((name.clone(), result))
-            })
-            .filter_map(|x: Result<_>| x.ok())
-            .filter_map(|(name, res)| if res.is_empty() { None } else { Some((name, res)) })
-            .collect::<BTreeMap<_, _>>();
+        // set once any contract had a failing test, or with fail fast once any test failed
+        let failed = AtomicBool::new(false);
+        let fail_fast = if self.fail_fast { Some(&failed) } else { None };
+        // the number of tests which gave up after too many rejected inputs
+        let rejected = AtomicUsize::new(0);
+        let max_test_rejects = self.max_test_rejects.map(|max| (&rejected, max));
+        let run = |(name, (abi, deploy_code)): (&String, &(Abi, ethers::prelude::Bytes))| {
+            // contracts which are already running are finished, but no new ones are started
+            if (self.exit_first_contract_failure || self.fail_fast) &&
+                failed.load(Ordering::Relaxed)
+            {
+                return Ok((name.clone(), BTreeMap::new()))
+            }
+            if max_test_rejects
+                .map_or(false, |(rejected, max)| rejected.load(Ordering::Relaxed) >= max)
+            {
+                return Ok((name.clone(), BTreeMap::new()))
+            }
+            let deploy_code = self.deploy_code(name, deploy_code)?;
+            // unavoidable duplication here?
+            let result = match backend {
+                BackendKind::Simple(ref backend) => self.run_tests(
+                    name,
+                    abi,
+                    backend,
+                    deploy_code,
+                    filter,
+                    fail_fast,
+                    max_test_rejects,
+                )?,
+                BackendKind::Shared(ref backend) => self.run_tests(
+                    name,
+                    abi,
+                    backend,
+                    deploy_code,
+                    filter,
+                    fail_fast,
+                    max_test_rejects,
+                )?,
+            };
+            if result.values().any(|result| !result.success) {
+                failed.store(true, Ordering::Relaxed);
+            }
+            progress(name, &result);
+            Ok((name.clone(), result))
+        };
+
+        let results = if self.order_seed.is_some() || self.sequential {
+            let mut matching = contracts
+                .iter()
+                .filter(|(name, _)| self.matches_contract(filter, name))
+                .collect::<Vec<_>>();
+            if let Some(seed) = self.order_seed {
+                crate::runner::shuffle(&mut matching, seed);
+            }
+            matching
+                .into_iter()
+                .map(run)
+                .filter_map(|x: Result<_>| x.ok())
+                .filter_map(|(name, res)| if res.is_empty() { None } else { Some((name, res)) })
+                .collect::<BTreeMap<_, _>>()
+        } else {
+            contracts
+                .par_iter()
+                .filter(|(name, _)| self.matches_contract(filter, name))
+                .map(run)
+                .filter_map(|x: Result<_>| x.ok())
+                .filter_map(|(name, res)| if res.is_empty() { None } else { Some((name, res)) })
+                .collect::<BTreeMap<_, _>>()
+        };
 
         self.contracts = contracts;
 
         Ok(results)
     }
 
-    // The _name field is unused because we only want it for tracing
     #[tracing::instrument(
         name = "contract",
         skip_all,
         err,
-        fields(name = %_name)
+        fields(name = %name)
     )]
     fn run_tests<B: Backend + Clone + Send + Sync>(
         &self,
-        _name: &str,
+        name: &str,
         contract: &Abi,
         backend: &B,
         deploy_code: ethers::prelude::Bytes,
         filter: &impl TestFilter,
+        fail_fast: Option<&AtomicBool>,
+        max_test_rejects: Option<(&AtomicUsize, usize)>,
     ) -> Result<BTreeMap<String, TestResult>> {
+        let tests = contract
+            .functions()
+            .filter(|func| func.name.starts_with(&self.test_function_prefix))
+            .filter(|func| filter.matches_contract_test(name, &func.name))
+            .map(|func| func.signature())
+            .collect::<Vec<_>>();
+        // only the `setUp()` of a contract with matched tests stands in for them
+        let tests = if self.only_setup {
+            tests.into_iter().take(1).map(|_| SETUP_SIGNATURE.to_string()).collect()
+        } else {
+            tests
+        };
+        // fail every matched test with the reason instead of running them
+        let fail_tests = |reason: String| {
+            tests.iter().map(|name| (name.clone(), TestResult::failed(reason.clone()))).collect()
+        };
+        if self.require_setup && !contract.functions().any(|func| func.name == "setUp") {
+            // rather than silently running them without a fixture
+            return Ok(fail_tests(
+                "setUp() is required (--require-setup) but not defined".to_string(),
+            ))
+        }
+        let constructor_args =
+            match self.constructor_args.get(name.rsplit(':').next().unwrap_or(name)) {
+                Some(args) => match constructor_tokens(contract, args) {
+                    Ok(tokens) => tokens,
+                    Err(err) => {
+                        return Ok(fail_tests(format!("Invalid constructor arguments: {}", err)))
+                    }
+                },
+                None => vec![],
+            };
+
+        let mut runner = ContractRunner::new(
+            &self.evm_opts,
+            &self.evm_cfg,
+            backend,
+            contract,
+            deploy_code,
+            self.sender,
+        )
+        .force_tracing(self.force_tracing)
+        .max_revert_depth(self.max_revert_depth)
+        .order_seed(self.order_seed)
+        .sequential(self.sequential, self.declaration_order.get(name).cloned().unwrap_or_default())
+        .invariant_depth(self.invariant_depth)
+        .invariant_runs(self.invariant_runs)
+        .invariant_shrink_sequence(self.invariant_shrink_sequence)
+        .fuzz_coverage_guided(self.fuzz_coverage_guided)
+        .max_fuzz_gas(self.max_fuzz_gas)
+        .fuzz_unique_reverts(self.fuzz_unique_reverts)
+        .timeout(self.timeout)
+        .fail_fast(fail_fast)
+        .max_test_rejects(max_test_rejects)
+        .seed_accounts(self.seed_accounts.clone())
+        .precompile_overrides(self.precompile_overrides.clone())
+        .constructor_args(constructor_args)
+        .fuzz_param_ranges(self.fuzz_param_ranges.clone())
+        .retries(self.retries, self.retry_on_reason.clone())
+        .fuzz_retries(self.fuzz_retries, self.fuzz_seed)
+        .isolate(self.isolate)
+        .deterministic_shrink_seed(self.deterministic_shrink_seed)
+        .coverage(self.coverage)
+        .fuzz_inputs(self.fuzz_inputs.get(name).cloned().unwrap_or_default())
+        .test_prefix(self.test_function_prefix.clone());
+        if let Some(ref dir) = self.fuzz_corpus {
+            // artifact names look like `Greeter.json:GreeterTest`, which we keep as the directory
+            runner = runner.fuzz_corpus(dir.join(name.replace(':', "/")));
+        }
+        if self.only_setup {
+            if tests.is_empty() {
+                return Ok(BTreeMap::new())
+            }
+            let result = runner.run_setup(Some(&self.known_contracts))?;
+            return Ok([(SETUP_SIGNATURE.to_string(), result)].into())
+        }
+        let filter = ContractTestFilter { filter, contract_name: name };
+        runner.run_tests(&filter, self.fuzzer.clone(), Some(&self.known_contracts))
+    }
+
+    /// Deploys every test contract matching the filter and returns the name, code offset and value
+    /// of each of its immutables, which are looked up at the code offsets of the variables the
+    /// compiler output references them at, by contract name
+    pub fn immutables(
+        &self,
+        filter: &impl TestFilter,
+        references: &BTreeMap<String, Vec<(String, usize)>>,
+    ) -> Result<BTreeMap<String, Vec<(String, usize, H256)>>> {
+        let vicinity = self.evm_opts.vicinity()?;
+        let backend = self.evm_opts.backend(&vicinity)?;
+        self.contracts
+            .iter()
+            .filter(|(name, _)| self.matches_contract(filter, name))
+            .filter_map(|(name, (abi, deploy_code))| {
+                let contract_name = name.rsplit(':').next().unwrap_or(name);
+                let references = references.get(contract_name)?;
+                let deployed =
+                    self.deploy_code(name, deploy_code).and_then(|deploy_code| match backend {
+                        BackendKind::Simple(ref backend) => {
+                            self.deployed_code(contract_name, abi, backend, deploy_code)
+                        }
+                        BackendKind::Shared(ref backend) => {
+                            self.deployed_code(contract_name, abi, backend, deploy_code)
+                        }
+                    });
+                Some(
+                    deployed.map(|deployed| (name.clone(), find_immutables(references, &deployed))),
+                )
+            })
+            .collect()
+    }
+
+    fn deployed_code<B: Backend + Clone + Send + Sync>(
+        &self,
+        name: &str,
+        contract: &Abi,
+        backend: &B,
+        deploy_code: ethers::prelude::Bytes,
+    ) -> Result<Vec<u8>> {
+        let constructor_args = match self.constructor_args.get(name) {
+            Some(args) => constructor_tokens(contract, args)?,
+            None => vec![],
+        };
         let runner = ContractRunner::new(
             &self.evm_opts,
             &self.evm_cfg,
@@ -191,15 +1162,52 @@ impl MultiContractRunner {
             contract,
             deploy_code,
             self.sender,
-        );
-        runner.run_tests(filter, self.fuzzer.clone(), Some(&self.known_contracts))
+        )
+        .constructor_args(constructor_args);
+        let (address, evm, _, _) = runner.new_sputnik_evm()?;
+        let code = evm.code(address);
+        Ok(code)
     }
 }
 
+/// Parses the `--constructor-args` of a test contract against the inputs of its constructor
+fn constructor_tokens(contract: &Abi, args: &[String]) -> Result<Vec<Token>> {
+    let constructor = contract
+        .constructor
+        .as_ref()
+        .ok_or_else(|| eyre::eyre!("the contract has no constructor to pass them to"))?;
+    if constructor.inputs.len() != args.len() {
+        eyre::bail!(
+            "the constructor takes {} arguments but {} were given",
+            constructor.inputs.len(),
+            args.len()
+        )
+    }
+    foundry_utils::parse_tokens(
+        constructor.inputs.iter().map(|param| &param.kind).zip(args.iter().map(String::as_str)),
+        true,
+    )
+}
+
+/// Reads the value of every immutable from the code of a deployed contract, at the offset of the
+/// 32 byte word the constructor stored it at, skipping those which lie outside of the code
+fn find_immutables(references: &[(String, usize)], deployed: &[u8]) -> Vec<(String, usize, H256)> {
+    references
+        .iter()
+        .filter_map(|(name, start)| {
+            let word = deployed.get(*start..start + 32)?;
+            Some((name.clone(), *start, H256::from_slice(word)))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_helpers::{Filter, EVM_OPTS};
+    use crate::{
+        test_helpers::{Filter, EVM_OPTS},
+        TestKind,
+    };
     use ethers::solc::ProjectPathsConfig;
     use std::path::PathBuf;
 
@@ -223,15 +1231,76 @@ mod tests {
         MultiContractRunnerBuilder::default().build(project(), EVM_OPTS.clone()).unwrap()
     }
 
+    #[test]
+    fn summarizes_the_compilation() {
+        let compilation = runner().compilation;
+        assert!(!compilation.cached);
+        // ephemeral projects compile every source, and its imports
+        assert!(compilation.compiled_files >= project().sources().unwrap().len());
+        assert_eq!(compilation.cached_files, 0);
+        assert!(compilation.warnings.iter().all(|warning| warning.contains("Warning")));
+    }
+
+    #[test]
+    fn finds_immutables() {
+        let mut deployed = [[0x60, 0x80, 0x7f].as_slice(), &[0; 32], &[0x56]].concat();
+        deployed[34] = 0x2a;
+        let references = [("answer".to_string(), 3), ("outside".to_string(), 5)];
+        assert_eq!(find_immutables(&[], &deployed), vec![]);
+        assert_eq!(
+            find_immutables(&references, &deployed),
+            vec![("answer".to_string(), 3, H256::from_low_u64_be(42))]
+        );
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_artifacts() {
+        let mut store = ArtifactStore::new(4).unwrap();
+        store.insert("A.json:A", &[1, 2]).unwrap();
+        store.insert("B.json:B", &[3, 4]).unwrap();
+        store.insert("C.json:C", &[5, 6, 7]).unwrap();
+        assert!(store.loaded().is_empty());
+
+        assert_eq!(store.get("A.json:A").unwrap().as_ref(), &[1, 2]);
+        assert_eq!(store.get("B.json:B").unwrap().as_ref(), &[3, 4]);
+        assert_eq!(store.loaded(), ["A.json:A", "B.json:B"]);
+        // reading A again makes B the least recently used
+        store.get("A.json:A").unwrap();
+        assert_eq!(store.get("C.json:C").unwrap().as_ref(), &[5, 6, 7]);
+        assert_eq!(store.loaded(), ["C.json:C"]);
+        assert!(store.get("D.json:D").is_err());
+
+        let dir = store.dir.clone();
+        drop(store);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn reads_the_creation_code_from_disk() {
+        let mut from_disk = MultiContractRunnerBuilder::default()
+            .max_artifacts_memory(Some(1))
+            .build(project(), EVM_OPTS.clone())
+            .unwrap();
+        assert!(from_disk.contracts.values().all(|(_, code)| code.as_ref().is_empty()));
+        for (name, (_, code)) in &runner().contracts {
+            assert_eq!(from_disk.creation_code(name).unwrap(), *code);
+        }
+
+        let results = from_disk.test(&Filter::new("testGm.*", ".*")).unwrap();
+        assert!(results["GmTest.json:GmTest"]["testGm()"].success);
+    }
+
     fn test_multi_runner() {
         let mut runner = runner();
         let results = runner.test(&Filter::new(".*", ".*")).unwrap();
 
-        // 8 contracts being built
-        assert_eq!(results.keys().len(), 8);
+        // 9 contracts being built
+        assert_eq!(results.keys().len(), 9);
         for (key, contract_tests) in results {
-            // for a bad setup, we dont want a successful test
-            if key == "SetupTest.json:SetupTest" {
+            // for a bad setup or missing constructor arguments, we dont want a successful test
+            if key == "SetupTest.json:SetupTest" ||
+                key == "ConstructorArgsTest.json:ConstructorArgsTest"
+            {
                 assert!(contract_tests.iter().all(|(_, result)| !result.success));
             } else {
                 assert_ne!(contract_tests.keys().len(), 0);
@@ -291,6 +1360,116 @@ mod tests {
             );
         }
 
+        #[test]
+        fn test_sputnik_fuzz_runs_budget() {
+            let mut runner = MultiContractRunnerBuilder::default()
+                .fuzzer(TestRunner::default())
+                .fuzz_runs_budget(Some(60))
+                .build(project(), EVM_OPTS.clone())
+                .unwrap();
+            let results = runner.test(&Filter::new(".*", "GreeterTest")).unwrap();
+
+            let fuzz_results = results
+                .values()
+                .flat_map(|tests| tests.values())
+                .filter(|result| result.is_fuzz())
+                .collect::<Vec<_>>();
+            assert!(!fuzz_results.is_empty());
+            let cases = 60 / fuzz_results.len();
+            for result in fuzz_results {
+                match result.kind {
+                    TestKind::Fuzz(ref fuzzed) => assert_eq!(fuzzed.cases().len(), cases),
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        #[test]
+        fn test_sputnik_test_counts() {
+            let runner = runner();
+            assert_eq!(
+                runner.test_counts(&Filter::new("testGm.*", ".*")),
+                TestCounts { contracts: 1, unit: 1, fuzz: 0, invariant: 0 }
+            );
+
+            let mut runner = MultiContractRunnerBuilder::default()
+                .fuzzer(TestRunner::default())
+                .build(project(), EVM_OPTS.clone())
+                .unwrap();
+            let filter = Filter::new(".*", "GreeterTest");
+            let counts = runner.test_counts(&filter);
+            assert!(counts.fuzz > 0);
+            let results = runner.test(&filter).unwrap();
+            assert_eq!(counts.contracts, results.len());
+            assert_eq!(counts.tests(), results.values().map(|tests| tests.len()).sum::<usize>());
+        }
+
+        #[test]
+        fn test_sputnik_constructor_args() {
+            let filter = Filter::new(".*", "ConstructorArgsTest");
+            let results = runner().test(&filter).unwrap();
+            let result = &results["ConstructorArgsTest.json:ConstructorArgsTest"]["testValue()"];
+            assert!(!result.success);
+            assert!(result.reason.as_ref().unwrap().contains("(uint256,address)"));
+
+            let args =
+                vec!["42".to_string(), "0x0000000000000000000000000000000000001234".to_string()];
+            let mut runner = MultiContractRunnerBuilder::default()
+                .constructor_args([("ConstructorArgsTest".to_string(), args)].into())
+                .build(project(), EVM_OPTS.clone())
+                .unwrap();
+            let results = runner.test(&filter).unwrap();
+            assert!(results["ConstructorArgsTest.json:ConstructorArgsTest"]["testValue()"].success);
+
+            let mut runner = MultiContractRunnerBuilder::default()
+                .constructor_args(
+                    [("ConstructorArgsTest".to_string(), vec!["42".to_string()])].into(),
+                )
+                .build(project(), EVM_OPTS.clone())
+                .unwrap();
+            let results = runner.test(&filter).unwrap();
+            let result = &results["ConstructorArgsTest.json:ConstructorArgsTest"]["testValue()"];
+            assert!(result.reason.as_ref().unwrap().starts_with("Invalid constructor arguments"));
+        }
+
+        #[test]
+        fn test_sputnik_max_test_rejects() {
+            let mut cfg = FuzzConfig::default();
+            cfg.max_global_rejects = 10;
+            let mut runner = MultiContractRunnerBuilder::default()
+                .fuzzer(TestRunner::new(cfg))
+                .sequential(true)
+                .max_test_rejects(Some(1))
+                .build(project(), EVM_OPTS.clone())
+                .unwrap();
+            let results = runner.test(&Filter::new(".*", "AssumeTest|GreeterTest")).unwrap();
+
+            // no further tests are started once a test gave up
+            assert!(results["AssumeTest.json:AssumeTest"].values().any(|result| result.rejected));
+            assert!(results["GreeterTest.json:GreeterTest"].is_empty());
+        }
+
+        #[test]
+        fn test_sputnik_only_setup() {
+            let mut runner = MultiContractRunnerBuilder::default()
+                .only_setup(true)
+                .build(project(), EVM_OPTS.clone())
+                .unwrap();
+            let results = runner
+                .test(&Filter::new(".*", "SetupTest|GreeterTest|ConstructorArgsTest"))
+                .unwrap();
+            assert!(results.values().all(|tests| tests.keys().eq(["setUp()"])));
+
+            let greeter = &results["GreeterTest.json:GreeterTest"]["setUp()"];
+            assert!(greeter.success);
+            assert!(greeter.setup_gas > 0);
+            let bad = &results["SetupTest.json:SetupTest"]["setUp()"];
+            assert!(!bad.success);
+            assert!(bad.reason.as_ref().unwrap().starts_with("Setup failed"));
+            let undeployable = &results["ConstructorArgsTest.json:ConstructorArgsTest"]["setUp()"];
+            assert!(undeployable.reason.as_ref().unwrap().starts_with("Deployment failed"));
+        }
+
         #[test]
         fn test_sputnik_multi_runner() {
             test_multi_runner();
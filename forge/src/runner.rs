@@ -1,25 +1,42 @@
 use crate::TestFilter;
 use evm_adapters::{
     evm_opts::EvmOpts,
-    sputnik::{helpers::TestSputnikVM, Executor, PRECOMPILES_MAP},
+    sputnik::{helpers::TestSputnikVM, Executor, PrecompileStub, SputnikExecutor, PRECOMPILES_MAP},
+    ExpectedRevert,
 };
 use rayon::iter::ParallelIterator;
 use sputnik::{backend::Backend, Config};
 
 use ethers::{
-    abi::{Abi, Function, Token},
-    types::{Address, Bytes},
+    abi::{Abi, Function, StateMutability, Token},
+    types::{Address, Bytes, U256},
 };
 use evm_adapters::{
     call_tracing::CallTraceArena,
-    fuzz::{FuzzTestResult, FuzzedCases, FuzzedExecutor},
-    sputnik::cheatcodes::debugger::DebugArena,
-    Evm, EvmError,
+    fuzz::{
+        FuzzConfig, FuzzTestResult, FuzzedCases, FuzzedExecutor, InvariantTestResult, ParamRanges,
+        ShrinkStep,
+    },
+    sputnik::cheatcodes::{
+        cheatcode_handler::CheatcodeStackState, debugger::DebugArena,
+        memory_stackstate_owned::Coverage,
+    },
+    Evm, EvmError, StructuredLog,
 };
 use eyre::Result;
-use std::{collections::BTreeMap, fmt, time::Instant};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::PathBuf,
+    rc::Rc,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
-use proptest::test_runner::{TestError, TestRunner};
+use proptest::{
+    prelude::Rng,
+    test_runner::{RngAlgorithm, TestError, TestRng, TestRunner},
+};
 use rayon::iter::IntoParallelRefIterator;
 use serde::{Deserialize, Serialize};
 
@@ -29,17 +46,92 @@ pub struct CounterExample {
     // Token does not implement Serde (lol), so we just serialize the calldata
     #[serde(skip)]
     pub args: Vec<Token>,
+    /// The signature of the called function, e.g. `testFuzz(uint256,address)`
+    #[serde(default)]
+    pub signature: String,
+    /// The name, solidity type and formatted value of every argument of the call, in order
+    #[serde(default)]
+    pub inputs: Vec<CounterExampleInput>,
+    /// For invariant tests, the calls after which the invariant broke, in order. `calldata` is the
+    /// invariant call then
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sequence: Vec<CounterExample>,
+}
+
+/// An argument of the call of a [`CounterExample`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CounterExampleInput {
+    /// The name of the parameter, empty if it is unnamed
+    pub name: String,
+    /// The solidity type of the parameter, e.g. `uint256`
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The value of the argument, formatted like in the console output
+    pub value: String,
+}
+
+impl CounterExample {
+    /// Decodes the arguments of a call of `func` with the given calldata
+    pub fn decode(func: &Function, calldata: Bytes) -> Result<Self> {
+        // skip the function selector when decoding
+        let args = func.decode_input(&calldata.as_ref()[4..])?;
+        let inputs = func
+            .inputs
+            .iter()
+            .zip(&args)
+            .map(|(param, arg)| CounterExampleInput {
+                name: param.name.clone(),
+                ty: param.kind.to_string(),
+                value: foundry_utils::format_token(arg),
+            })
+            .collect();
+        Ok(Self { calldata, args, signature: func.signature(), inputs, sequence: vec![] })
+    }
 }
 
 impl fmt::Display for CounterExample {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let args = foundry_utils::format_tokens(&self.args).collect::<Vec<_>>().join(", ");
-        write!(f, "calldata=0x{}, args=[{}]", hex::encode(&self.calldata), args)
+        if !self.sequence.is_empty() {
+            let calls = self.sequence.iter().map(|call| call.to_string()).collect::<Vec<_>>();
+            return write!(f, "sequence=[{}]", calls.join("; "))
+        }
+        let args = self.inputs.iter().map(|input| input.value.as_str()).collect::<Vec<_>>();
+        write!(f, "calldata=0x{}, args=[{}]", hex::encode(&self.calldata), args.join(", "))
     }
 }
 
-/// The result of an executed solidity test
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The prefix of test functions if none is configured
+pub(crate) const DEFAULT_TEST_PREFIX: &str = "test";
+
+/// The prefix of invariant functions, which must hold after every call of a random sequence
+pub(crate) const INVARIANT_PREFIX: &str = "invariant";
+
+/// The name the result of a contract's `setUp()` is reported with when only it is run
+pub(crate) const SETUP_SIGNATURE: &str = "setUp()";
+
+/// The maximum number of calls in a sequence of an invariant test if none is configured
+const DEFAULT_INVARIANT_DEPTH: usize = 15;
+
+/// Shuffles the items into a random order that is reproducible with the same seed
+pub(crate) fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut bytes = [0u8; 32];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    let mut rng = TestRng::from_seed(RngAlgorithm::ChaCha, &bytes);
+    // Fisher-Yates
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.gen_range(0..=i));
+    }
+}
+
+/// Returns a fuzzer with the config whose rng is seeded with `seed`
+pub(crate) fn seeded_fuzzer(config: FuzzConfig, seed: u64) -> TestRunner {
+    let mut bytes = [0u8; 32];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    TestRunner::new_with_rng(config, TestRng::from_seed(RngAlgorithm::ChaCha, &bytes))
+}
+
+/// The result of an executed solidity test, a failed standard test which did not run by default
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct TestResult {
     /// Whether the test case was successful. This means that the transaction executed
     /// properly, or that there was a revert and that the test was expected to fail
@@ -50,6 +142,48 @@ pub struct TestResult {
     /// still be successful (i.e self.success == true) when it's expected to fail.
     pub reason: Option<String>,
 
+    /// If the runner or the EVM itself errored while executing the test, e.g. because a forked
+    /// backend could not be reached, this field will be populated instead of `reason`. Such
+    /// tests are always unsuccessful.
+    #[serde(default)]
+    pub error: Option<String>,
+
+    /// The raw return data of the test call, if it reverted
+    #[serde(default)]
+    pub revert_data: Option<Bytes>,
+
+    /// Whether the test passed by reverting as intended, either as a `testFail` test or because
+    /// of a matched `expectRevert`
+    #[serde(default)]
+    pub expected_revert: bool,
+
+    /// The reason the test was expected to and did revert with
+    #[serde(default)]
+    pub expected_reason: Option<String>,
+
+    /// Whether the test failed because it ran out of gas rather than reverting
+    #[serde(default)]
+    pub out_of_gas: bool,
+
+    /// How often the test was run again after failing
+    #[serde(default)]
+    pub retries: usize,
+
+    /// Whether the fuzz test failed but passed when it was run again with a fresh seed. It is
+    /// successful then and its `reason` and `counterexample` are those of the first failure
+    #[serde(default)]
+    pub flaky: bool,
+
+    /// For a flaky fuzz test, the seed of the fuzzer of the run it passed in, if it was derived
+    /// from the fuzz seed
+    #[serde(default)]
+    pub flaky_seed: Option<u64>,
+
+    /// Whether the test, or one of its fuzz cases, was aborted because it exceeded the timeout.
+    /// Its `reason` is `timeout` then
+    #[serde(default)]
+    pub timed_out: bool,
+
     /// The gas used during execution.
     ///
     /// If this is the result of a fuzz test (`TestKind::Fuzz`), then this is the median of all
@@ -63,6 +197,10 @@ pub struct TestResult {
     /// be printed to the user.
     pub logs: Vec<String>,
 
+    /// The events and `console.log` calls of the test, for tools consuming them as data
+    #[serde(default)]
+    pub structured_logs: Vec<StructuredLog>,
+
     /// What kind of test this was
     pub kind: TestKind,
 
@@ -75,9 +213,80 @@ pub struct TestResult {
     /// Debug Steps
     #[serde(skip)]
     pub debug_calls: Option<Vec<DebugArena>>,
+
+    /// The address the test contract was deployed to
+    #[serde(default)]
+    pub contract_address: Address,
+
+    /// The gas consumed by deploying the test contract
+    #[serde(default)]
+    pub deploy_gas: u64,
+
+    /// The gas consumed by the test contract's `setUp()`, 0 if it has none
+    #[serde(default)]
+    pub setup_gas: u64,
+
+    /// How often each cheatcode was called during the test, including its setup.
+    ///
+    /// For fuzz tests, this only covers the last executed case
+    #[serde(default)]
+    pub cheatcode_usage: BTreeMap<String, usize>,
+
+    /// The number of SLOADs executed by the test, including its deployment and setup.
+    ///
+    /// For fuzz tests, this only covers the last executed case
+    #[serde(default)]
+    pub storage_reads: usize,
+
+    /// The number of SSTOREs executed by the test, including its deployment and setup
+    #[serde(default)]
+    pub storage_writes: usize,
+
+    /// The gas refunded by the test call, e.g. for clearing storage slots. `gas_used` does not
+    /// account for it
+    #[serde(default)]
+    pub gas_refund: u64,
+
+    /// Every input that was tried while shrinking the counterexample of a fuzz test
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shrink_history: Vec<ShrinkStep>,
+
+    /// How long the test took to run, including the deployment and setup of its contract
+    #[serde(skip)]
+    pub duration: Duration,
+
+    /// Whether this is the passing result of an earlier run which was reused because neither the
+    /// test contract nor the contracts its tests called changed
+    #[serde(default)]
+    pub cached: bool,
+
+    /// Whether the fuzz or invariant test gave up because too many of its inputs were rejected,
+    /// without finding a failing one. It neither passed nor failed, `success` is `false` and
+    /// `reason` says how many inputs were rejected
+    #[serde(default)]
+    pub rejected: bool,
+
+    /// The number of fuzz inputs which were rejected by the `assume` cheatcode
+    #[serde(default)]
+    pub rejects: usize,
+
+    /// The instruction counters executed by the test, including the deployment and setup of its
+    /// contract and every fuzz case, if coverage is recorded
+    #[serde(skip)]
+    pub coverage: Coverage,
+
+    /// The distinct revert reasons of a fuzz test and the first input which failed with each,
+    /// only collected if the runner was configured to do so
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unique_reverts: BTreeMap<String, CounterExample>,
 }
 
 impl TestResult {
+    /// The result of a test which failed with the reason without being run
+    pub(crate) fn failed(reason: String) -> Self {
+        TestResult { reason: Some(reason), ..Default::default() }
+    }
+
     /// Returns `true` if this is the result of a fuzz test
     pub fn is_fuzz(&self) -> bool {
         matches!(self.kind, TestKind::Fuzz(_))
@@ -126,6 +335,12 @@ pub enum TestKind {
     Fuzz(FuzzedCases),
 }
 
+impl Default for TestKind {
+    fn default() -> Self {
+        TestKind::Standard(0)
+    }
+}
+
 impl TestKind {
     /// The gas consumed by this test
     pub fn gas_used(&self) -> TestKindGas {
@@ -157,6 +372,69 @@ pub struct ContractRunner<'a, B> {
     pub code: ethers::prelude::Bytes,
     /// The address which will be used as the `from` field in all EVM calls
     pub sender: Address,
+    /// The directory in which the fuzz corpus of every fuzz test is persisted
+    pub fuzz_corpus: Option<PathBuf>,
+    /// Inputs replayed before any new ones by the fuzz tests, by test signature
+    pub fuzz_inputs: BTreeMap<String, Vec<Bytes>>,
+    /// Whether fuzz inputs are also generated by mutating inputs that reached new execution paths
+    pub fuzz_coverage_guided: bool,
+    /// The gas a single fuzz case may use before it fails the test
+    pub max_fuzz_gas: Option<u64>,
+    /// If set, fuzz tests collect up to this many distinct revert reasons instead of stopping at
+    /// the first failing case
+    pub fuzz_unique_reverts: Option<usize>,
+    /// How long a test call, or a single fuzz case, may execute before it fails the test
+    pub timeout: Option<Duration>,
+    /// If set, this is set once any test failed and no further tests are started afterwards
+    pub fail_fast: Option<&'a AtomicBool>,
+    /// If set, the number of tests which gave up after too many rejected inputs, and the number
+    /// of them after which no further tests are started
+    pub max_test_rejects: Option<(&'a AtomicUsize, usize)>,
+    /// Whether call traces are collected regardless of the verbosity
+    pub force_tracing: bool,
+    /// The number of call levels below a reverted call that are kept in the traces
+    pub max_revert_depth: Option<usize>,
+    /// If set, tests are run one after the other in a random order derived from this seed
+    pub order_seed: Option<u64>,
+    /// Whether tests are run one after the other, in `declaration_order` if it is set and in
+    /// alphabetical order otherwise, instead of in parallel
+    pub sequential: bool,
+    /// The names of the contract's functions in the order they are declared in
+    pub declaration_order: Vec<String>,
+    /// The maximum number of calls in a sequence of an invariant test
+    pub invariant_depth: Option<usize>,
+    /// The number of call sequences of an invariant test, the fuzzer's number of cases if not set
+    pub invariant_runs: Option<u32>,
+    /// Whether the call sequence which broke an invariant is minimized beyond proptest's shrinking
+    pub invariant_shrink_sequence: bool,
+    /// Accounts funded with the given balance in every EVM, after the test contract was deployed
+    pub seed_accounts: BTreeMap<Address, U256>,
+    /// Precompiles replaced with stubs in every EVM
+    pub precompile_overrides: BTreeMap<Address, PrecompileStub>,
+    /// The arguments the test contract's constructor is called with when it is deployed
+    pub constructor_args: Vec<Token>,
+    /// The bounds of numeric fuzz parameters by test name and parameter index
+    pub fuzz_param_ranges: BTreeMap<String, ParamRanges>,
+    /// How often failing tests are run again
+    pub retries: usize,
+    /// If set, only failing tests whose reason or error matches are run again
+    pub retry_on_reason: Option<regex::Regex>,
+    /// How often failing fuzz tests are run again with a fresh seed before they count as failed
+    pub fuzz_retries: usize,
+    /// The seed of the fuzzer, which the seed of every retry of a fuzz test is derived from with
+    /// the number of the retry. Retries are seeded randomly if it is not set
+    pub fuzz_seed: Option<u64>,
+    /// If set, `setUp()` runs once and unit tests start from a copy of the resulting state, one
+    /// after the other
+    pub isolate: bool,
+    /// If set, every fuzz and invariant test gets its own fuzzer seeded from this seed and
+    /// shrinks its failing case without a time limit, so the reported counterexample does not
+    /// depend on timing or on which tests run concurrently
+    pub deterministic_shrink_seed: Option<u64>,
+    /// Whether the instruction counters executed by every test are recorded
+    pub coverage: bool,
+    /// The prefix of test functions, tests expected to fail are prefixed with `<prefix>Fail`
+    pub test_prefix: String,
 }
 
 impl<'a, B: Backend> ContractRunner<'a, B> {
@@ -168,7 +446,392 @@ impl<'a, B: Backend> ContractRunner<'a, B> {
         code: ethers::prelude::Bytes,
         sender: Option<Address>,
     ) -> Self {
-        Self { evm_opts, evm_cfg, backend, contract, code, sender: sender.unwrap_or_default() }
+        Self {
+            evm_opts,
+            evm_cfg,
+            backend,
+            contract,
+            code,
+            sender: sender.unwrap_or_default(),
+            fuzz_corpus: None,
+            fuzz_inputs: Default::default(),
+            fuzz_coverage_guided: false,
+            max_fuzz_gas: None,
+            fuzz_unique_reverts: None,
+            timeout: None,
+            fail_fast: None,
+            max_test_rejects: None,
+            force_tracing: false,
+            max_revert_depth: None,
+            order_seed: None,
+            sequential: false,
+            declaration_order: Vec::new(),
+            invariant_depth: None,
+            invariant_runs: None,
+            invariant_shrink_sequence: false,
+            seed_accounts: Default::default(),
+            precompile_overrides: Default::default(),
+            constructor_args: vec![],
+            fuzz_param_ranges: Default::default(),
+            retries: 0,
+            retry_on_reason: None,
+            fuzz_retries: 0,
+            fuzz_seed: None,
+            isolate: false,
+            deterministic_shrink_seed: None,
+            coverage: false,
+            test_prefix: DEFAULT_TEST_PREFIX.to_string(),
+        }
+    }
+
+    /// Sets whether call traces are collected regardless of the verbosity
+    #[must_use]
+    pub fn force_tracing(mut self, force_tracing: bool) -> Self {
+        self.force_tracing = force_tracing;
+        self
+    }
+
+    /// Sets the number of call levels below a reverted call that are kept in the traces
+    #[must_use]
+    pub fn max_revert_depth(mut self, max_revert_depth: Option<usize>) -> Self {
+        self.max_revert_depth = max_revert_depth;
+        self
+    }
+
+    /// Sets the prefix of the functions which are run as tests
+    #[must_use]
+    pub fn test_prefix(mut self, test_prefix: String) -> Self {
+        self.test_prefix = test_prefix;
+        self
+    }
+
+    /// Returns `true` if the test is expected to fail
+    fn should_fail(&self, func: &Function) -> bool {
+        func.name.strip_prefix(&self.test_prefix).map_or(false, |name| name.starts_with("Fail"))
+    }
+
+    /// Sets the seed of the random order in which the tests are run sequentially
+    #[must_use]
+    pub fn order_seed(mut self, order_seed: Option<u64>) -> Self {
+        self.order_seed = order_seed;
+        self
+    }
+
+    /// Sets whether the tests are run one after the other, in the given order of the contract's
+    /// functions if it is not empty and alphabetically otherwise
+    #[must_use]
+    pub fn sequential(mut self, sequential: bool, declaration_order: Vec<String>) -> Self {
+        self.sequential = sequential;
+        self.declaration_order = declaration_order;
+        self
+    }
+
+    /// Sets the maximum number of calls in a sequence of an invariant test
+    #[must_use]
+    pub fn invariant_depth(mut self, invariant_depth: Option<usize>) -> Self {
+        self.invariant_depth = invariant_depth;
+        self
+    }
+
+    /// Sets the number of call sequences of an invariant test
+    #[must_use]
+    pub fn invariant_runs(mut self, invariant_runs: Option<u32>) -> Self {
+        self.invariant_runs = invariant_runs;
+        self
+    }
+
+    /// Sets whether the call sequence which broke an invariant is minimized to calls which are
+    /// all needed to break it
+    #[must_use]
+    pub fn invariant_shrink_sequence(mut self, shrink_sequence: bool) -> Self {
+        self.invariant_shrink_sequence = shrink_sequence;
+        self
+    }
+
+    /// Sets the accounts funded with the given balance in every EVM
+    #[must_use]
+    pub fn seed_accounts(mut self, seed_accounts: BTreeMap<Address, U256>) -> Self {
+        self.seed_accounts = seed_accounts;
+        self
+    }
+
+    /// Sets the precompiles replaced with stubs in every EVM
+    #[must_use]
+    pub fn precompile_overrides(
+        mut self,
+        precompile_overrides: BTreeMap<Address, PrecompileStub>,
+    ) -> Self {
+        self.precompile_overrides = precompile_overrides;
+        self
+    }
+
+    /// Sets the arguments the test contract's constructor is called with when it is deployed
+    #[must_use]
+    pub fn constructor_args(mut self, constructor_args: Vec<Token>) -> Self {
+        self.constructor_args = constructor_args;
+        self
+    }
+
+    /// The reason of the tests of a contract which could not be deployed, which points at
+    /// missing constructor arguments if the constructor takes any but none were given
+    fn deployment_failure(&self, err: &eyre::Report) -> String {
+        let mut reason = format!("Deployment failed: {}", err);
+        let constructor = self.contract.constructor.as_ref();
+        if let (Some(constructor), true) = (constructor, self.constructor_args.is_empty()) {
+            if !constructor.inputs.is_empty() {
+                let types = constructor
+                    .inputs
+                    .iter()
+                    .map(|param| param.kind.to_string())
+                    .collect::<Vec<_>>();
+                reason += &format!(
+                    ". The constructor takes the arguments ({}) but none were given, pass them with \
+                     --constructor-args",
+                    types.join(",")
+                );
+            }
+        }
+        reason
+    }
+
+    /// Returns the functions which invariant tests make random sequences of calls to: all state
+    /// changing functions of the test contract which are neither tests, invariants nor `setUp()`
+    fn invariant_targets(&self) -> Vec<Function> {
+        self.contract
+            .functions()
+            .filter(|func| {
+                !func.name.starts_with(&self.test_prefix) &&
+                    !func.name.starts_with(INVARIANT_PREFIX) &&
+                    !["setUp", "IS_TEST", "failed"].contains(&func.name.as_str()) &&
+                    !matches!(
+                        func.state_mutability,
+                        StateMutability::View | StateMutability::Pure
+                    )
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Sets the directory in which the fuzz corpus of every fuzz test is persisted
+    #[must_use]
+    pub fn fuzz_corpus(mut self, dir: PathBuf) -> Self {
+        self.fuzz_corpus = Some(dir);
+        self
+    }
+
+    /// Sets the inputs replayed before any new ones by the fuzz tests, by test signature
+    #[must_use]
+    pub fn fuzz_inputs(mut self, fuzz_inputs: BTreeMap<String, Vec<Bytes>>) -> Self {
+        self.fuzz_inputs = fuzz_inputs;
+        self
+    }
+
+    /// Sets whether fuzz inputs are also generated by mutating inputs that reached new
+    /// execution paths
+    #[must_use]
+    pub fn fuzz_coverage_guided(mut self, fuzz_coverage_guided: bool) -> Self {
+        self.fuzz_coverage_guided = fuzz_coverage_guided;
+        self
+    }
+
+    /// Sets the gas a single fuzz case may use before it fails the test
+    #[must_use]
+    pub fn max_fuzz_gas(mut self, max_fuzz_gas: Option<u64>) -> Self {
+        self.max_fuzz_gas = max_fuzz_gas;
+        self
+    }
+
+    /// Sets the maximum number of distinct revert reasons fuzz tests collect, instead of stopping
+    /// at the first failing case
+    #[must_use]
+    pub fn fuzz_unique_reverts(mut self, max: Option<usize>) -> Self {
+        self.fuzz_unique_reverts = max;
+        self
+    }
+
+    /// Sets the bounds of numeric fuzz parameters by test name and parameter index
+    #[must_use]
+    pub fn fuzz_param_ranges(mut self, ranges: BTreeMap<String, ParamRanges>) -> Self {
+        self.fuzz_param_ranges = ranges;
+        self
+    }
+
+    /// Sets how often failing tests, whose reason or error matches `on_reason` if it is set, are
+    /// run again
+    #[must_use]
+    pub fn retries(mut self, retries: usize, on_reason: Option<regex::Regex>) -> Self {
+        self.retries = retries;
+        self.retry_on_reason = on_reason;
+        self
+    }
+
+    /// Sets how often failing fuzz tests are run again with a fresh seed before they count as
+    /// failed, and the seed of the fuzzer the seeds of the retries are derived from
+    #[must_use]
+    pub fn fuzz_retries(mut self, fuzz_retries: usize, fuzz_seed: Option<u64>) -> Self {
+        self.fuzz_retries = fuzz_retries;
+        self.fuzz_seed = fuzz_seed;
+        self
+    }
+
+    /// Sets whether unit tests are run from a snapshot of the post-`setUp()` state instead of
+    /// calling `setUp()` for each of them. This trades the parallelism of the tests within the
+    /// contract for running `setUp()` only once, so it pays off for expensive `setUp()`s
+    #[must_use]
+    pub fn isolate(mut self, isolate: bool) -> Self {
+        self.isolate = isolate;
+        self
+    }
+
+    /// Sets the seed from which every fuzz and invariant test's fuzzer is seeded to shrink
+    /// failing cases deterministically
+    #[must_use]
+    pub fn deterministic_shrink_seed(mut self, seed: Option<u64>) -> Self {
+        self.deterministic_shrink_seed = seed;
+        self
+    }
+
+    /// Sets whether the instruction counters executed by every test are recorded
+    #[must_use]
+    pub fn coverage(mut self, coverage: bool) -> Self {
+        self.coverage = coverage;
+        self
+    }
+
+    /// Returns the fuzzer a single test runs with.
+    ///
+    /// Clones of a fuzzer share proptest's count of regenerated values, which bounds how far a
+    /// failing case is shrunk, and the shrinking may be bounded in time, so with a deterministic
+    /// shrink seed a fresh fuzzer without a time limit is created instead. Its seed is offset by
+    /// the attempt, so that a retried fuzz test explores other inputs
+    fn test_fuzzer(&self, runner: TestRunner, attempt: usize) -> TestRunner {
+        match self.deterministic_shrink_seed {
+            Some(seed) => seeded_fuzzer(
+                FuzzConfig { max_shrink_time: 0, ..runner.config().clone() },
+                seed.wrapping_add(attempt as u64),
+            ),
+            None => runner,
+        }
+    }
+
+    /// Sets how long a test call, or a single fuzz case, may execute before it fails the test
+    #[must_use]
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the flag which is set once any test failed, after which no further tests are started
+    #[must_use]
+    pub fn fail_fast(mut self, fail_fast: Option<&'a AtomicBool>) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Sets the counter of the tests which gave up after too many rejected inputs, and the number
+    /// of them after which no further tests are started
+    #[must_use]
+    pub fn max_test_rejects(mut self, max_test_rejects: Option<(&'a AtomicUsize, usize)>) -> Self {
+        self.max_test_rejects = max_test_rejects;
+        self
+    }
+
+    /// Returns `true` if no further tests should be started, because a test already failed or too
+    /// many tests gave up after rejecting their inputs
+    fn stopped(&self) -> bool {
+        self.fail_fast.map_or(false, |failed| failed.load(Ordering::Relaxed)) ||
+            self.max_test_rejects
+                .map_or(false, |(rejected, max)| rejected.load(Ordering::Relaxed) >= max)
+    }
+
+    /// Runs a test, running it again while it fails with a retried reason until the retries are
+    /// used up. `run` is called with the number of the retry, `0` for the first run. The result of
+    /// the last run is returned
+    fn run_with_retries(&self, run: impl Fn(usize) -> Result<TestResult>) -> Result<TestResult> {
+        let mut result = run(0)?;
+        while !result.success && result.retries < self.retries && self.should_retry(&result) {
+            let retries = result.retries + 1;
+            tracing::debug!(retries, reason = ?result.reason, error = ?result.error, "retrying");
+            result = run(retries)?;
+            result.retries = retries;
+        }
+        Ok(result)
+    }
+
+    /// Returns the fuzzer of the run of a fuzz or invariant test with the number of the retry.
+    ///
+    /// The first run uses the fuzzer as it is, while every retry gets a fresh fuzzer so that it
+    /// does not replay the failing case. If there is a fuzz seed, the fresh fuzzer is seeded with
+    /// the fuzz seed offset by the retry, which makes the retries reproducible
+    fn retry_fuzzer(&self, fuzzer: &TestRunner, retry: usize) -> TestRunner {
+        match (retry, self.fuzz_seed) {
+            (0, _) => fuzzer.clone(),
+            (retry, Some(seed)) => {
+                seeded_fuzzer(fuzzer.config().clone(), seed.wrapping_add(retry as u64))
+            }
+            (_, None) => TestRunner::new(fuzzer.config().clone()),
+        }
+    }
+
+    /// Runs a fuzz test with `run_with_retries`, running it again with a fresh seed while it fails
+    /// until the fuzz retries are used up.
+    ///
+    /// If any of these runs passes, the first failure is returned as a successful but `flaky`
+    /// result, otherwise the first failure is returned as it is
+    fn run_fuzz_with_retries(
+        &self,
+        func: &Function,
+        setup: bool,
+        fuzzer: &TestRunner,
+        known_contracts: Option<&BTreeMap<String, (Abi, Vec<u8>)>>,
+    ) -> Result<TestResult> {
+        let mut result = self.run_with_retries(|retry| {
+            let fuzzer = self.retry_fuzzer(fuzzer, retry);
+            self.fuzz_test_attempt(func, setup, fuzzer, known_contracts, retry)
+        })?;
+        if result.success || result.rejected || result.error.is_some() {
+            return Ok(result)
+        }
+        for attempt in 1..=self.fuzz_retries {
+            if self.stopped() {
+                break
+            }
+            // numbered after the retries above, so that every run has a seed of its own
+            let retry = result.retries + attempt;
+            let seed = self.fuzz_seed.map(|seed| seed.wrapping_add(retry as u64));
+            tracing::debug!(attempt, ?seed, reason = ?result.reason, "retrying with a fresh seed");
+            let fresh = self.retry_fuzzer(fuzzer, retry);
+            let rerun = self.fuzz_test_attempt(func, setup, fresh, known_contracts, retry)?;
+            if rerun.success {
+                result.success = true;
+                result.flaky = true;
+                result.flaky_seed = seed;
+                result.retries = retry;
+                break
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns `true` if the failed test should be run again
+    fn should_retry(&self, result: &TestResult) -> bool {
+        match self.retry_on_reason {
+            Some(ref re) => {
+                result.reason.iter().chain(&result.error).any(|reason| re.is_match(reason))
+            }
+            None => true,
+        }
+    }
+
+    /// Sets the fail fast flag if the test failed, and counts it if it gave up after too many
+    /// rejected inputs
+    fn record_failure(&self, result: &TestResult) {
+        if let (Some(failed), false) = (self.fail_fast, result.success) {
+            failed.store(true, Ordering::Relaxed);
+        }
+        if let (Some((rejected, _)), true) = (self.max_test_rejects, result.rejected) {
+            rejected.fetch_add(1, Ordering::Relaxed);
+        }
     }
 }
 
@@ -176,8 +839,10 @@ impl<'a, B: Backend> ContractRunner<'a, B> {
 // evm-adapters which is clone-able.
 impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
     /// Creates a new EVM and deploys the test contract inside the runner
-    /// from the sending account.
-    pub fn new_sputnik_evm(&'a self) -> eyre::Result<(Address, TestSputnikVM<'a, B>, Vec<String>)> {
+    /// from the sending account, returning the gas consumed by the deployment.
+    pub fn new_sputnik_evm(
+        &'a self,
+    ) -> eyre::Result<(Address, TestSputnikVM<'a, B>, u64, Vec<String>)> {
         // create the EVM, clone the backend.
         let mut executor = Executor::new_with_cheatcodes(
             self.backend.clone(),
@@ -185,15 +850,118 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
             self.evm_cfg,
             &*PRECOMPILES_MAP,
             self.evm_opts.ffi,
-            self.evm_opts.verbosity > 2,
+            self.evm_opts.verbosity > 2 || self.force_tracing,
             self.evm_opts.debug,
         );
 
+        executor.executor.state_mut().max_revert_depth = self.max_revert_depth;
+        executor.executor.state_mut().timeout = self.timeout;
+        executor.executor.state_mut().precompile_overrides = self.precompile_overrides.clone();
+        if self.coverage {
+            executor.executor.state_mut().coverage = Some(Default::default());
+        }
+
         // deploy an instance of the contract inside the runner in the EVM
-        let (addr, _, _, logs) =
-            executor.deploy(self.sender, self.code.clone(), 0u32.into()).expect("couldn't deploy");
+        let code = match self.contract.constructor {
+            Some(ref constructor) if !self.constructor_args.is_empty() => {
+                constructor.encode_input(self.code.to_vec(), &self.constructor_args)?.into()
+            }
+            _ => self.code.clone(),
+        };
+        let (addr, _, deploy_gas, logs) = executor.deploy(self.sender, code, 0u32.into())?;
         executor.set_balance(addr, self.evm_opts.initial_balance);
-        Ok((addr, executor, logs))
+        for (account, balance) in &self.seed_accounts {
+            executor.set_balance(*account, *balance);
+        }
+        Ok((addr, executor, deploy_gas, logs))
+    }
+
+    /// Deploys the test contract like [`Self::new_sputnik_evm`], or returns the failed result
+    /// each of its tests reports if it cannot be deployed
+    fn deploy_or_fail(
+        &'a self,
+    ) -> std::result::Result<(Address, TestSputnikVM<'a, B>, u64, Vec<String>), TestResult> {
+        self.new_sputnik_evm().map_err(|err| {
+            let reason = self.deployment_failure(&err);
+            tracing::debug!(%reason, "could not deploy");
+            TestResult::failed(reason)
+        })
+    }
+
+    /// Deploys the test contract and calls `setUp()` once, returning the resulting state which
+    /// `--isolate` restores before every unit test. Returns `None` if the deployment or `setUp()`
+    /// failed, so that each test does them again and reports the failure.
+    fn setup_snapshot(&self) -> Result<Option<SetupSnapshot<'_, B>>> {
+        let (address, mut evm, _, _) = match self.new_sputnik_evm() {
+            Ok(deployed) => deployed,
+            Err(_) => return Ok(None),
+        };
+        evm.reset_traces();
+        Ok(match evm.setup(address) {
+            Ok((_reason, setup_gas, logs)) => Some(SetupSnapshot {
+                setup_gas,
+                logs,
+                structured_logs: evm.structured_logs(),
+                state: evm.executor.state().clone(),
+            }),
+            Err(err) => {
+                tracing::debug!(?err, "setUp() failed, not isolating");
+                None
+            }
+        })
+    }
+
+    /// Deploys the contract and calls its `setUp()`, if it has one, without running any of its
+    /// tests. The result is successful if both succeeded, its gas is that of the `setUp()`
+    pub fn run_setup(
+        &self,
+        known_contracts: Option<&BTreeMap<String, (Abi, Vec<u8>)>>,
+    ) -> Result<TestResult> {
+        let start = Instant::now();
+        let (address, mut evm, deploy_gas, init_logs) = match self.deploy_or_fail() {
+            Ok(deployed) => deployed,
+            Err(failed) => return Ok(failed),
+        };
+        let mut result = TestResult {
+            success: true,
+            reason: None,
+            logs: init_logs,
+            contract_address: address,
+            deploy_gas,
+            ..Default::default()
+        };
+
+        // clear out the deployment trace
+        evm.reset_traces();
+
+        let needs_setup = self.contract.functions().any(|func| func.name == "setUp");
+        if needs_setup {
+            match evm.setup(address) {
+                Ok((_reason, gas, setup_logs)) => {
+                    result.setup_gas = gas;
+                    result.kind = TestKind::Standard(gas);
+                    result.logs.extend(setup_logs);
+                }
+                Err(err) => {
+                    tracing::debug!(?err, "setUp() failed");
+                    result.success = false;
+                    result.reason = setup_failure(&err, true);
+                    result.error = setup_failure(&err, false);
+                    result.revert_data = setup_revert_data(&err);
+                }
+            }
+        }
+        result.timed_out = evm.timed_out();
+        result.structured_logs = evm.structured_logs();
+        self.update_traces(
+            &mut result.traces,
+            &mut result.identified_contracts,
+            known_contracts,
+            needs_setup,
+            &mut evm,
+        );
+        result.duration = start.elapsed();
+        Ok(result)
     }
 
     /// Runs all tests for a contract whose names match the provided regular expression
@@ -210,16 +978,87 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
             .contract
             .functions()
             .into_iter()
-            .filter(|func| func.name.starts_with("test"))
+            .filter(|func| func.name.starts_with(&self.test_prefix))
+            .filter(|func| filter.matches_test(&func.name))
+            .collect::<Vec<_>>();
+        let invariant_fns = self
+            .contract
+            .functions()
+            .filter(|func| func.name.starts_with(INVARIANT_PREFIX) && func.inputs.is_empty())
             .filter(|func| filter.matches_test(&func.name))
             .collect::<Vec<_>>();
+        let targets = if invariant_fns.is_empty() { vec![] } else { self.invariant_targets() };
+        let snapshot = if self.isolate && needs_setup { self.setup_snapshot()? } else { None };
+
+        if self.order_seed.is_some() || self.sequential || snapshot.is_some() {
+            let mut test_fns = test_fns;
+            if let Some(seed) = self.order_seed {
+                shuffle(&mut test_fns, seed);
+            } else if !self.declaration_order.is_empty() {
+                // inherited functions are not declared in the contract and keep their order last
+                test_fns.sort_by_key(|func| {
+                    self.declaration_order
+                        .iter()
+                        .position(|name| *name == func.name)
+                        .unwrap_or(usize::MAX)
+                });
+            }
+            let mut map = BTreeMap::new();
+            for func in test_fns {
+                if self.stopped() {
+                    break
+                }
+                let result = if func.inputs.is_empty() {
+                    self.run_with_retries(|_| {
+                        self.run_test_from(func, needs_setup, snapshot.as_ref(), known_contracts)
+                    })?
+                } else if let Some(ref fuzzer) = fuzzer {
+                    self.run_fuzz_with_retries(func, needs_setup, fuzzer, known_contracts)?
+                } else {
+                    continue
+                };
+                self.record_failure(&result);
+                map.insert(func.signature(), result);
+            }
+            if let Some(ref fuzzer) = fuzzer {
+                for func in invariant_fns {
+                    if self.stopped() {
+                        break
+                    }
+                    let result = self.run_with_retries(|retry| {
+                        self.run_invariant_test(
+                            func,
+                            needs_setup,
+                            self.retry_fuzzer(fuzzer, retry),
+                            &targets,
+                            known_contracts,
+                            retry,
+                        )
+                    })?;
+                    self.record_failure(&result);
+                    map.insert(func.signature(), result);
+                }
+            }
+            let duration = Instant::now().duration_since(start);
+            tracing::info!(
+                ?duration,
+                seed = ?self.order_seed,
+                isolated = snapshot.is_some(),
+                "done. ran {} tests one after the other",
+                map.len()
+            );
+            return Ok(map)
+        }
 
         // run all unit tests
         let unit_tests = test_fns
             .par_iter()
             .filter(|func| func.inputs.is_empty())
+            .filter(|_| !self.stopped())
             .map(|func| {
-                let result = self.run_test(func, needs_setup, known_contracts)?;
+                let result =
+                    self.run_with_retries(|_| self.run_test(func, needs_setup, known_contracts))?;
+                self.record_failure(&result);
                 Ok((func.signature(), result))
             })
             .collect::<Result<BTreeMap<_, _>>>()?;
@@ -228,15 +1067,37 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
             let fuzz_tests = test_fns
                 .par_iter()
                 .filter(|func| !func.inputs.is_empty())
+                .filter(|_| !self.stopped())
                 .map(|func| {
                     let result =
-                        self.run_fuzz_test(func, needs_setup, fuzzer.clone(), known_contracts)?;
+                        self.run_fuzz_with_retries(func, needs_setup, &fuzzer, known_contracts)?;
+                    self.record_failure(&result);
+                    Ok((func.signature(), result))
+                })
+                .collect::<Result<BTreeMap<_, _>>>()?;
+
+            let invariant_tests = invariant_fns
+                .par_iter()
+                .filter(|_| !self.stopped())
+                .map(|func| {
+                    let result = self.run_with_retries(|retry| {
+                        self.run_invariant_test(
+                            func,
+                            needs_setup,
+                            self.retry_fuzzer(&fuzzer, retry),
+                            &targets,
+                            known_contracts,
+                            retry,
+                        )
+                    })?;
+                    self.record_failure(&result);
                     Ok((func.signature(), result))
                 })
                 .collect::<Result<BTreeMap<_, _>>>()?;
 
             let mut map = unit_tests;
             map.extend(fuzz_tests);
+            map.extend(invariant_tests);
             map
         } else {
             unit_tests
@@ -250,36 +1111,67 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
         Ok(map)
     }
 
-    #[tracing::instrument(name = "test", skip_all, fields(name = %func.signature()))]
     pub fn run_test(
         &self,
         func: &Function,
         setup: bool,
         known_contracts: Option<&BTreeMap<String, (Abi, Vec<u8>)>>,
+    ) -> Result<TestResult> {
+        self.run_test_from(func, setup, None, known_contracts)
+    }
+
+    /// Runs a unit test, starting from `snapshot` instead of calling `setUp()` if it is set
+    #[tracing::instrument(name = "test", skip_all, fields(name = %func.signature()))]
+    fn run_test_from(
+        &self,
+        func: &Function,
+        setup: bool,
+        snapshot: Option<&SetupSnapshot<'_, B>>,
+        known_contracts: Option<&BTreeMap<String, (Abi, Vec<u8>)>>,
     ) -> Result<TestResult> {
         let start = Instant::now();
         // the expected result depends on the function name
         // DAppTools' ds-test will not revert inside its `assertEq`-like functions
         // which allows to test multiple assertions in 1 test function while also
         // preserving logs.
-        let should_fail = func.name.starts_with("testFail");
+        let should_fail = self.should_fail(func);
         tracing::debug!(func = ?func.signature(), should_fail, "unit-testing");
 
-        let (address, mut evm, init_logs) = self.new_sputnik_evm()?;
+        // a contract which cannot be deployed fails each of its tests instead of the whole run
+        let (address, mut evm, deploy_gas, init_logs) = match self.deploy_or_fail() {
+            Ok(deployed) => deployed,
+            Err(failed) => return Ok(failed),
+        };
 
         let mut logs = init_logs;
 
         let mut traces: Option<Vec<CallTraceArena>> = None;
         let mut identified_contracts: Option<BTreeMap<Address, (String, Abi)>> = None;
 
+        let mut setup_gas = 0;
+        let mut structured_logs = Vec::new();
+
         // clear out the deployment trace
         evm.reset_traces();
 
-        // call the setup function in each test to reset the test's state.
-        if setup {
+        if let Some(snapshot) = snapshot {
+            tracing::trace!("restoring the post-setup state");
+            *evm.executor.state_mut() = snapshot.state.clone();
+            // the tests share the snapshot, but each records its own coverage
+            let coverage = snapshot.state.coverage.as_ref().map(|c| Rc::new((**c).clone()));
+            evm.executor.state_mut().coverage = coverage;
+            setup_gas = snapshot.setup_gas;
+            structured_logs = snapshot.structured_logs.clone();
+            logs.extend_from_slice(&snapshot.logs);
+        } else if setup {
+            // call the setup function in each test to reset the test's state.
             tracing::trace!("setting up");
             let setup_logs = match evm.setup(address) {
-                Ok((_reason, setup_logs)) => setup_logs,
+                Ok((_reason, gas, setup_logs)) => {
+                    setup_gas = gas;
+                    structured_logs = evm.structured_logs();
+                    setup_logs
+                }
                 Err(e) => {
                     // if tracing is enabled, just return it as a failed test
                     // otherwise abort
@@ -294,12 +1186,12 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
                     }
 
                     return Ok(TestResult {
-                        success: false,
-                        reason: Some("Setup failed: ".to_string() + &e.to_string()),
-                        gas_used: 0,
-                        counterexample: None,
+                        reason: setup_failure(&e, true),
+                        error: setup_failure(&e, false),
+                        revert_data: setup_revert_data(&e),
+                        timed_out: evm.timed_out(),
                         logs,
-                        kind: TestKind::Standard(0),
+                        structured_logs: evm.structured_logs(),
                         traces,
                         identified_contracts,
                         debug_calls: if evm.state().debug_enabled {
@@ -307,31 +1199,59 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
                         } else {
                             None
                         },
+                        cheatcode_usage: evm.state().cheatcode_usage.clone(),
+                        storage_reads: evm.state().storage_reads,
+                        storage_writes: evm.state().storage_writes,
+                        duration: start.elapsed(),
+                        coverage: evm
+                            .state()
+                            .coverage
+                            .as_ref()
+                            .map(|c| c.borrow().clone())
+                            .unwrap_or_default(),
+                        contract_address: address,
+                        deploy_gas,
+                        setup_gas,
+                        ..Default::default()
                     })
                 }
             };
             logs.extend_from_slice(&setup_logs);
         }
 
-        let (status, reason, gas_used, logs) =
+        let refund_before = evm.gas_refund();
+        let mut out_of_gas = false;
+        let (status, reason, error, revert_data, gas_used, logs) =
             match evm.call::<(), _, _>(self.sender, address, func.clone(), (), 0.into()) {
                 Ok((_, status, gas_used, execution_logs)) => {
                     logs.extend(execution_logs);
-                    (status, None, gas_used, logs)
+                    structured_logs.extend(evm.structured_logs());
+                    (status, None, None, None, gas_used, logs)
                 }
                 Err(err) => match err {
-                    EvmError::Execution { reason, gas_used, logs: execution_logs } => {
+                    EvmError::Execution {
+                        reason,
+                        gas_used,
+                        logs: execution_logs,
+                        data,
+                        out_of_gas: ran_out_of_gas,
+                    } => {
+                        out_of_gas = ran_out_of_gas;
                         logs.extend(execution_logs);
+                        structured_logs.extend(evm.structured_logs());
                         // add reverted logs
                         logs.extend(evm.all_logs());
-                        (revert(&evm), Some(reason), gas_used, logs)
+                        (revert(&evm), Some(reason), None, Some(data), gas_used, logs)
                     }
+                    // the execution itself failed, which says nothing about the test's logic
                     err => {
                         tracing::error!(?err);
-                        return Err(err.into())
+                        (revert(&evm), None, Some(err.to_string()), None, 0, logs)
                     }
                 },
             };
+        let timed_out = evm.timed_out();
+        let reason = if timed_out { Some("timeout".to_string()) } else { reason };
 
         self.update_traces(
             &mut traces,
@@ -341,41 +1261,85 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
             &mut evm,
         );
 
-        let success = evm.check_success(address, &status, should_fail);
+        let gas_refund = evm.gas_refund().saturating_sub(refund_before);
+        let success =
+            !timed_out && error.is_none() && evm.check_success(address, &status, should_fail);
+        let (expected_revert, expected_reason) = match evm.state().matched_expected_reverts.last() {
+            Some(matched) if success => (true, matched.data().map(expected_revert_reason)),
+            _ if success && should_fail && revert_data.is_some() => (true, reason.clone()),
+            _ => (false, None),
+        };
         let duration = Instant::now().duration_since(start);
         tracing::debug!(?duration, %success, %gas_used);
 
         Ok(TestResult {
             success,
             reason,
+            error,
+            revert_data,
+            expected_revert,
+            expected_reason,
+            out_of_gas,
+            timed_out,
             gas_used,
-            counterexample: None,
             logs,
+            structured_logs,
             kind: TestKind::Standard(gas_used),
             traces,
             identified_contracts,
             debug_calls: if evm.state().debug_enabled { Some(evm.debug_calls()) } else { None },
+            cheatcode_usage: evm.state().cheatcode_usage.clone(),
+            storage_reads: evm.state().storage_reads,
+            storage_writes: evm.state().storage_writes,
+            gas_refund,
+            duration,
+            coverage: evm.state().coverage.as_ref().map(|c| c.borrow().clone()).unwrap_or_default(),
+            contract_address: address,
+            deploy_gas,
+            setup_gas,
+            ..Default::default()
         })
     }
 
-    #[tracing::instrument(name = "fuzz-test", skip_all, fields(name = %func.signature()))]
     pub fn run_fuzz_test(
         &self,
         func: &Function,
         setup: bool,
         runner: TestRunner,
         known_contracts: Option<&BTreeMap<String, (Abi, Vec<u8>)>>,
+    ) -> Result<TestResult> {
+        self.fuzz_test_attempt(func, setup, runner, known_contracts, 0)
+    }
+
+    /// Runs the fuzz test, `attempt` is the number of times it was retried with a fresh seed
+    #[tracing::instrument(
+        name = "fuzz-test",
+        skip_all,
+        fields(name = %func.signature(), attempt = attempt)
+    )]
+    fn fuzz_test_attempt(
+        &self,
+        func: &Function,
+        setup: bool,
+        runner: TestRunner,
+        known_contracts: Option<&BTreeMap<String, (Abi, Vec<u8>)>>,
+        attempt: usize,
     ) -> Result<TestResult> {
         // do not trace in fuzztests, as it's a big performance hit
         let start = Instant::now();
-        let should_fail = func.name.starts_with("testFail");
+        let should_fail = self.should_fail(func);
         tracing::debug!(func = ?func.signature(), should_fail, "fuzzing");
 
-        let (address, mut evm, init_logs) = self.new_sputnik_evm()?;
+        let (address, mut evm, deploy_gas, init_logs) = match self.deploy_or_fail() {
+            Ok(deployed) => deployed,
+            Err(failed) => return Ok(failed),
+        };
 
         let mut traces: Option<Vec<CallTraceArena>> = None;
         let mut identified_contracts: Option<BTreeMap<Address, (String, Abi)>> = None;
 
+        let mut setup_gas = 0;
+
         // clear out the deployment trace
         evm.reset_traces();
 
@@ -383,7 +1347,7 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
         if setup {
             tracing::trace!("setting up");
             match evm.setup(address) {
-                Ok((_reason, _setup_logs)) => {}
+                Ok((_reason, gas, _setup_logs)) => setup_gas = gas,
                 Err(e) => {
                     // if tracing is enabled, just return it as a failed test
                     // otherwise abort
@@ -397,11 +1361,10 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
                         );
                     }
                     return Ok(TestResult {
-                        success: false,
-                        reason: Some("Setup failed: ".to_string() + &e.to_string()),
-                        gas_used: 0,
-                        counterexample: None,
-                        logs: vec![],
+                        reason: setup_failure(&e, true),
+                        error: setup_failure(&e, false),
+                        revert_data: setup_revert_data(&e),
+                        timed_out: evm.timed_out(),
                         kind: TestKind::Fuzz(FuzzedCases::new(vec![])),
                         traces,
                         identified_contracts,
@@ -410,28 +1373,60 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
                         } else {
                             None
                         },
+                        cheatcode_usage: evm.state().cheatcode_usage.clone(),
+                        storage_reads: evm.state().storage_reads,
+                        storage_writes: evm.state().storage_writes,
+                        duration: start.elapsed(),
+                        coverage: evm
+                            .state()
+                            .coverage
+                            .as_ref()
+                            .map(|c| c.borrow().clone())
+                            .unwrap_or_default(),
+                        contract_address: address,
+                        deploy_gas,
+                        setup_gas,
+                        ..Default::default()
                     })
                 }
             }
         }
 
         let mut logs = init_logs;
+        let mut structured_logs = Vec::new();
+        let mut out_of_gas = false;
 
         let prev = evm.set_tracing_enabled(false);
+        // the counterexample is replayed on the state every case started from
+        let pre_fuzz_state = evm.state().clone();
 
+        let runner = self.test_fuzzer(runner, attempt);
         // instantiate the fuzzed evm in line
-        let evm = FuzzedExecutor::new(&mut evm, runner, self.sender);
-        let FuzzTestResult { cases, test_error } = evm.fuzz(func, address, should_fail);
+        let mut evm = FuzzedExecutor::new(&mut evm, runner, self.sender)
+            .replay(self.fuzz_inputs.get(&func.signature()).cloned().unwrap_or_default())
+            .coverage_guided(self.fuzz_coverage_guided)
+            .max_gas(self.max_fuzz_gas)
+            .unique_reverts(self.fuzz_unique_reverts)
+            .param_ranges(self.fuzz_param_ranges.get(&func.name).cloned().unwrap_or_default());
+        if let Some(ref dir) = self.fuzz_corpus {
+            evm = evm.corpus(dir.join(format!("{}.json", func.signature())));
+        }
+        let FuzzTestResult { cases, test_error, shrinks, unique_reverts, rejects } =
+            evm.fuzz(func, address, should_fail);
 
         let evm = evm.into_inner();
         if let Some(ref error) = test_error {
-            // we want traces for a failed fuzz
+            // we want traces for a failed fuzz, of the reported counterexample rather than of the
+            // last case run, which is usually a passing one tried while shrinking
             if let TestError::Fail(_reason, bytes) = &error.test_error {
+                evm.reset(pre_fuzz_state);
                 if prev {
                     let _ = evm.set_tracing_enabled(true);
                 }
                 let (_retdata, status, _gas, execution_logs) =
                     evm.call_raw(self.sender, address, bytes.clone(), 0.into(), false)?;
+                structured_logs = evm.structured_logs();
+                out_of_gas = is_out_of_gas(evm, &status);
                 if is_fail(evm, status) {
                     logs.extend(execution_logs);
                     // add reverted logs
@@ -449,25 +1444,38 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
             }
         }
 
+        let unique_reverts = unique_reverts
+            .into_iter()
+            .map(|(reason, calldata)| Ok((reason, CounterExample::decode(func, calldata)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
         let success = test_error.is_none();
         let mut counterexample = None;
         let mut reason = None;
+        let mut revert_data = None;
+        let mut rejected = false;
         if let Some(err) = test_error {
             match err.test_error {
                 TestError::Fail(_, value) => {
-                    // skip the function selector when decoding
-                    let args = func.decode_input(&value.as_ref()[4..])?;
-                    let counter = CounterExample { calldata: value.clone(), args };
-                    counterexample = Some(counter);
+                    counterexample = Some(CounterExample::decode(func, value.clone())?);
                     tracing::info!("Found minimal failing case: {}", hex::encode(&value));
+                    revert_data = Some(err.revert_data);
+                    if !err.revert_reason.is_empty() {
+                        reason = Some(err.revert_reason);
+                    }
+                }
+                // proptest gives up once too many inputs were rejected
+                TestError::Abort(_) => {
+                    rejected = true;
+                    reason = Some(rejected_reason(&func.name, rejects));
                 }
-                result => panic!("Unexpected test result: {:?}", result),
-            }
-            if !err.revert_reason.is_empty() {
-                reason = Some(err.revert_reason);
             }
         }
 
+        let timed_out = reason.as_deref() == Some("timeout");
+        let matched = evm.state().matched_expected_reverts.last();
+        let expected_reason = matched.and_then(ExpectedRevert::data).map(expected_revert_reason);
+        let expected_revert = success && (should_fail || matched.is_some());
         let duration = Instant::now().duration_since(start);
         tracing::debug!(?duration, %success);
 
@@ -475,13 +1483,174 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
         Ok(TestResult {
             success,
             reason,
+            timed_out,
+            revert_data,
+            expected_revert,
+            expected_reason: expected_reason.filter(|_| success),
+            out_of_gas,
             gas_used: cases.median_gas(),
             counterexample,
             logs,
+            structured_logs,
             kind: TestKind::Fuzz(cases),
             traces,
             identified_contracts,
             debug_calls: if evm.state().debug_enabled { Some(evm.debug_calls()) } else { None },
+            cheatcode_usage: evm.state().cheatcode_usage.clone(),
+            storage_reads: evm.state().storage_reads,
+            storage_writes: evm.state().storage_writes,
+            shrink_history: shrinks,
+            unique_reverts,
+            duration,
+            rejected,
+            rejects,
+            coverage: evm.state().coverage.as_ref().map(|c| c.borrow().clone()).unwrap_or_default(),
+            contract_address: address,
+            deploy_gas,
+            setup_gas,
+            ..Default::default()
+        })
+    }
+
+    #[tracing::instrument(name = "invariant-test", skip_all, fields(name = %func.signature()))]
+    pub fn run_invariant_test(
+        &self,
+        func: &Function,
+        setup: bool,
+        runner: TestRunner,
+        targets: &[Function],
+        known_contracts: Option<&BTreeMap<String, (Abi, Vec<u8>)>>,
+        attempt: usize,
+    ) -> Result<TestResult> {
+        let start = Instant::now();
+        let (address, mut evm, deploy_gas, logs) = match self.deploy_or_fail() {
+            Ok(deployed) => deployed,
+            Err(failed) => return Ok(failed),
+        };
+
+        let mut traces: Option<Vec<CallTraceArena>> = None;
+        let mut identified_contracts: Option<BTreeMap<Address, (String, Abi)>> = None;
+        let mut setup_gas = 0;
+
+        // clear out the deployment trace
+        evm.reset_traces();
+
+        if setup {
+            tracing::trace!("setting up");
+            match evm.setup(address) {
+                Ok((_reason, gas, _setup_logs)) => setup_gas = gas,
+                Err(e) => {
+                    if evm.tracing_enabled() {
+                        self.update_traces(
+                            &mut traces,
+                            &mut identified_contracts,
+                            known_contracts,
+                            setup,
+                            &mut evm,
+                        );
+                    }
+                    return Ok(TestResult {
+                        reason: setup_failure(&e, true),
+                        error: setup_failure(&e, false),
+                        revert_data: setup_revert_data(&e),
+                        timed_out: evm.timed_out(),
+                        kind: TestKind::Fuzz(FuzzedCases::new(vec![])),
+                        traces,
+                        identified_contracts,
+                        cheatcode_usage: evm.state().cheatcode_usage.clone(),
+                        storage_reads: evm.state().storage_reads,
+                        storage_writes: evm.state().storage_writes,
+                        duration: start.elapsed(),
+                        coverage: evm
+                            .state()
+                            .coverage
+                            .as_ref()
+                            .map(|c| c.borrow().clone())
+                            .unwrap_or_default(),
+                        contract_address: address,
+                        deploy_gas,
+                        setup_gas,
+                        ..Default::default()
+                    })
+                }
+            }
+        }
+
+        let mut cases = FuzzedCases::new(vec![]);
+        let mut counterexample = None;
+        let mut reason = None;
+        let mut rejected = false;
+        if targets.is_empty() {
+            reason = Some("the test contract has no state changing functions to call".to_string());
+        } else {
+            let _ = evm.set_tracing_enabled(false);
+            let mut runner = self.test_fuzzer(runner, attempt);
+            let runner = match self.invariant_runs {
+                // keep the rng so that the fuzzer's seed is retained
+                Some(cases) => TestRunner::new_with_rng(
+                    FuzzConfig { cases, ..runner.config().clone() },
+                    runner.rng().clone(),
+                ),
+                None => runner,
+            };
+            let depth = self.invariant_depth.unwrap_or(DEFAULT_INVARIANT_DEPTH);
+            let config = runner.config().clone();
+            let executor = FuzzedExecutor::new(&mut evm, runner, self.sender)
+                .shrink_sequence(self.invariant_shrink_sequence);
+            let InvariantTestResult { cases: invariant_cases, test_error, revert_reason } =
+                executor.invariant(func, targets, address, depth);
+            cases = invariant_cases;
+            match test_error {
+                Some(TestError::Fail(_, sequence)) => {
+                    let sequence = sequence
+                        .into_iter()
+                        .map(|calldata| {
+                            let target = targets
+                                .iter()
+                                .find(|target| calldata.as_ref()[..4] == target.short_signature())
+                                .expect("calls are only made to the targets");
+                            CounterExample::decode(target, calldata)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    counterexample = Some(CounterExample {
+                        sequence,
+                        ..CounterExample::decode(func, func.encode_input(&[])?.into())?
+                    });
+                    reason = Some(if revert_reason.is_empty() {
+                        format!("{} broken", func.name)
+                    } else {
+                        revert_reason
+                    });
+                }
+                // proptest gives up once too many inputs were rejected
+                Some(TestError::Abort(_)) => {
+                    rejected = true;
+                    reason = Some(rejected_reason(&func.name, config.max_global_rejects as usize));
+                }
+                None => {}
+            }
+        }
+
+        Ok(TestResult {
+            success: reason.is_none(),
+            reason,
+            timed_out: evm.timed_out(),
+            gas_used: cases.median_gas(),
+            counterexample,
+            logs,
+            kind: TestKind::Fuzz(cases),
+            traces,
+            identified_contracts,
+            cheatcode_usage: evm.state().cheatcode_usage.clone(),
+            storage_reads: evm.state().storage_reads,
+            storage_writes: evm.state().storage_writes,
+            duration: start.elapsed(),
+            rejected,
+            coverage: evm.state().coverage.as_ref().map(|c| c.borrow().clone()).unwrap_or_default(),
+            contract_address: address,
+            deploy_gas,
+            setup_gas,
+            ..Default::default()
         })
     }
 
@@ -529,8 +1698,47 @@ impl<'a, B: Backend + Clone + Send + Sync> ContractRunner<'a, B> {
     }
 }
 
+/// The state of the EVM after `setUp()`, which `--isolate` restores before every unit test
+struct SetupSnapshot<'a, B> {
+    setup_gas: u64,
+    logs: Vec<String>,
+    structured_logs: Vec<StructuredLog>,
+    state: CheatcodeStackState<'a, B>,
+}
+
+/// The message of a failed `setUp()`, as the revert `reason` of the test if `reverted` is set,
+/// or as its `error` otherwise, depending on whether the setup reverted or the EVM errored
+fn setup_failure(err: &eyre::Report, reverted: bool) -> Option<String> {
+    let is_revert = matches!(err.downcast_ref::<EvmError>(), Some(EvmError::Execution { .. }));
+    (is_revert == reverted).then(|| "Setup failed: ".to_string() + &err.to_string())
+}
+
+/// The raw return data of a failed `setUp()`, if it reverted
+fn setup_revert_data(err: &eyre::Report) -> Option<Bytes> {
+    match err.downcast_ref::<EvmError>() {
+        Some(EvmError::Execution { data, .. }) => Some(data.clone()),
+        _ => None,
+    }
+}
+
+/// The reason an `expectRevert` expected, as a string if it is one or hex encoded otherwise
+fn expected_revert_reason(expected: &[u8]) -> String {
+    match std::str::from_utf8(expected) {
+        Ok(reason) => reason.to_string(),
+        Err(_) => foundry_utils::decode_revert(expected)
+            .unwrap_or_else(|_| format!("0x{}", hex::encode(expected))),
+    }
+}
+
+/// The reason of a fuzz or invariant test which proptest gave up on because too many inputs were
+/// rejected, with the number of rejected inputs
+fn rejected_reason(func_name: &str, rejects: usize) -> String {
+    format!("{} gave up after {} inputs were rejected; loosen your assumptions", func_name, rejects)
+}
+
 // Helper functions for getting the revert status for a `ReturnReason` without having
 // to specify the full EVM signature
+
 fn is_fail<S: Clone, E: Evm<S> + evm_adapters::Evm<S, ReturnReason = T>, T>(
     _evm: &mut E,
     status: T,
@@ -538,6 +1746,13 @@ fn is_fail<S: Clone, E: Evm<S> + evm_adapters::Evm<S, ReturnReason = T>, T>(
     <E as evm_adapters::Evm<S>>::is_fail(&status)
 }
 
+fn is_out_of_gas<S: Clone, E: Evm<S> + evm_adapters::Evm<S, ReturnReason = T>, T>(
+    _evm: &mut E,
+    status: &T,
+) -> bool {
+    <E as evm_adapters::Evm<S>>::is_out_of_gas(status)
+}
+
 fn revert<S: Clone, E: Evm<S> + evm_adapters::Evm<S, ReturnReason = T>, T>(_evm: &E) -> T {
     <E as evm_adapters::Evm<S>>::revert()
 }
@@ -586,6 +1801,38 @@ mod tests {
             assert!(results["testGreeting(string,string)"].success);
         }
 
+        #[test]
+        fn test_expected_revert() {
+            let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+
+            let (_, code, _) = compiled.into_parts_or_default();
+            let runner = runner(compiled.abi.as_ref().unwrap(), code);
+
+            let results = runner.run_tests(&Filter::new("Greeting", ".*"), None, None).unwrap();
+            let failing = &results["testFailGreeting()"];
+            assert!(failing.success);
+            assert!(failing.expected_revert);
+            assert_eq!(failing.expected_reason.as_deref(), Some("not equal to `hi`"));
+            assert!(!results["testGreeting()"].expected_revert);
+        }
+
+        #[test]
+        fn test_isolate() {
+            let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+            let (_, code, _) = compiled.into_parts_or_default();
+            let abi = compiled.abi.as_ref().unwrap();
+            let filter = Filter::new("Greeting", ".*");
+
+            let fresh = runner(abi, code.clone()).run_tests(&filter, None, None).unwrap();
+            let isolated = runner(abi, code).isolate(true).run_tests(&filter, None, None).unwrap();
+            assert_eq!(fresh.keys().collect::<Vec<_>>(), isolated.keys().collect::<Vec<_>>());
+            for (name, result) in isolated {
+                assert_eq!(result.success, fresh[&name].success);
+                assert_eq!(result.setup_gas, fresh[&name].setup_gas);
+                assert_eq!(result.gas_used, fresh[&name].gas_used);
+            }
+        }
+
         #[test]
         fn test_fuzzing_counterexamples() {
             let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
@@ -603,6 +1850,107 @@ mod tests {
             }
         }
 
+        #[test]
+        fn test_fuzz_counterexample_trace() {
+            let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+            let (_, code, _) = compiled.into_parts_or_default();
+            let runner = runner(compiled.abi.as_ref().unwrap(), code).force_tracing(true);
+
+            let mut cfg = FuzzConfig::default();
+            cfg.failure_persistence = None;
+            let fuzzer = TestRunner::new(cfg);
+            let func = get_func("function testFuzzFreshState(uint256 x) public").unwrap();
+            let res = runner.run_fuzz_test(&func, true, fuzzer, Some(&BTreeMap::new())).unwrap();
+            assert!(!res.success);
+
+            // the passing cases tried while shrinking would let the counterexample pass if it
+            // was replayed on their state
+            let traces = res.traces.expect("no traces");
+            let test_trace = traces.last().unwrap();
+            assert!(!test_trace.arena[test_trace.entry].trace.success);
+        }
+
+        #[test]
+        fn test_fuzz_retries() {
+            let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+            let (_, code, _) = compiled.into_parts_or_default();
+            let runner = runner(compiled.abi.as_ref().unwrap(), code).fuzz_retries(64, Some(7));
+
+            let mut cfg = FuzzConfig::default();
+            cfg.failure_persistence = None;
+            cfg.cases = 1;
+            let filter = Filter::new("testFuzzFlaky", ".*");
+            // the first run fails half of the time, and one of its retries passes
+            let res = (0..32)
+                .map(|_| {
+                    let fuzzer = TestRunner::new(cfg.clone());
+                    runner.run_tests(&filter, Some(fuzzer), None).unwrap()
+                })
+                .map(|mut results| results.remove("testFuzzFlaky(uint256)").unwrap())
+                .find(|res| res.flaky)
+                .expect("never failed first");
+            assert!(res.success);
+            assert!(res.retries > 0);
+            // the retries are seeded with the fuzz seed and their number
+            assert_eq!(res.flaky_seed, Some(7 + res.retries as u64));
+            assert_eq!(res.reason.as_deref(), Some("odd"));
+            assert!(res.counterexample.is_some());
+        }
+
+        #[test]
+        fn test_retries_of_fuzz_tests_use_fresh_fuzzers() {
+            let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+            let (_, code, _) = compiled.into_parts_or_default();
+            let runner = runner(compiled.abi.as_ref().unwrap(), code)
+                .retries(64, None)
+                .fuzz_retries(0, Some(7));
+
+            let mut cfg = FuzzConfig::default();
+            cfg.failure_persistence = None;
+            cfg.cases = 1;
+            let filter = Filter::new("testFuzzFlaky", ".*");
+            // the first run fails half of the time, retrying with the same fuzzer would fail again
+            let res = (0..32)
+                .map(|_| {
+                    let fuzzer = TestRunner::new(cfg.clone());
+                    runner.run_tests(&filter, Some(fuzzer), None).unwrap()
+                })
+                .map(|mut results| results.remove("testFuzzFlaky(uint256)").unwrap())
+                .find(|res| res.retries > 0)
+                .expect("never failed first");
+            assert!(res.success);
+            assert!(!res.flaky);
+        }
+
+        #[test]
+        fn test_fuzz_assume() {
+            let compiled = COMPILED.find("AssumeTest").expect("could not find contract");
+            let (_, code, _) = compiled.into_parts_or_default();
+            let runner = runner(compiled.abi.as_ref().unwrap(), code);
+
+            let mut cfg = FuzzConfig::default();
+            cfg.failure_persistence = None;
+            cfg.cases = 64;
+            cfg.max_global_rejects = 200;
+            let fuzzer = TestRunner::new(cfg);
+            let results = runner.run_tests(&Filter::new(".*", ".*"), Some(fuzzer), None).unwrap();
+
+            // the odd inputs are rejected instead of failing the test
+            let even = &results["testFuzzAssumeEven(uint256)"];
+            assert!(even.success);
+            assert!(!even.rejected);
+            assert!(even.rejects > 0);
+
+            let never = &results["testFuzzAssumeNever(uint256)"];
+            assert!(!never.success);
+            assert!(never.rejected);
+            assert_eq!(never.rejects, 200);
+            let reason = never.reason.as_deref().unwrap();
+            assert!(
+                reason.starts_with("testFuzzAssumeNever gave up after 200 inputs were rejected")
+            );
+        }
+
         #[test]
         fn test_fuzzing_ok() {
             let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
@@ -654,6 +2002,33 @@ mod tests {
             let product_without_shrinking = args[0].saturating_mul(args[1]);
             assert!(product_without_shrinking > product_with_shrinking.into());
         }
+
+        #[test]
+        fn test_fuzz_deterministic_shrink() {
+            let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+            let (_, code, _) = compiled.into_parts_or_default();
+            let runner =
+                runner(compiled.abi.as_ref().unwrap(), code).deterministic_shrink_seed(Some(7));
+
+            let func = get_func("function testShrinking(uint256 x, uint256 y) public").unwrap();
+            let counterexamples = (0..2)
+                .map(|seed| {
+                    let mut cfg = FuzzConfig::default();
+                    cfg.failure_persistence = None;
+                    // the fuzzer's own rng is replaced by one seeded from the shrink seed
+                    let mut bytes = [0u8; 32];
+                    bytes[0] = seed;
+                    let fuzzer = TestRunner::new_with_rng(
+                        cfg,
+                        TestRng::from_seed(RngAlgorithm::ChaCha, &bytes),
+                    );
+                    let res = runner.run_fuzz_test(&func, true, fuzzer, None).unwrap();
+                    assert!(!res.success);
+                    res.counterexample.unwrap().calldata
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(counterexamples[0], counterexamples[1]);
+        }
     }
 
     pub fn test_runner(compiled: CompactContractRef) {
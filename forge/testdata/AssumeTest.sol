@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity 0.8.0;
+
+import "../../evm-adapters/testdata/DsTest.sol";
+
+interface Hevm {
+    function assume(bool) external;
+}
+
+contract AssumeTest is DSTest {
+    Hevm constant hevm = Hevm(HEVM_ADDRESS);
+
+    // rejects about half of its inputs, and passes for all others
+    function testFuzzAssumeEven(uint256 x) public {
+        hevm.assume(x % 2 == 0);
+        require(x % 2 == 0, "odd");
+    }
+
+    // rejects every input, so the fuzzer gives up
+    function testFuzzAssumeNever(uint256 x) public {
+        hevm.assume(x != x);
+    }
+}
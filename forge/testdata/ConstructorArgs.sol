@@ -0,0 +1,18 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.8.1;
+
+contract ConstructorArgsTest {
+    uint256 public value;
+    address public owner;
+
+    constructor(uint256 _value, address _owner) {
+        require(_value != 0, "value is required");
+        value = _value;
+        owner = _owner;
+    }
+
+    function testValue() public view {
+        require(value == 42, "wrong value");
+        require(owner == address(0x1234), "wrong owner");
+    }
+}
@@ -48,6 +48,20 @@ contract GreeterTest is GreeterTestSetup {
         require(x[1] / x[1] == 0);
     }
 
+    // fails for x >= 10 on the state after setUp(), but the passing cases leave a greeting behind
+    function testFuzzFreshState(uint256 x) public {
+        if (x < 10) {
+            greeter.greet("passed");
+            return;
+        }
+        require(bytes(greeter.greeting()).length != 0, "fresh state");
+    }
+
+    // fails for half of the inputs, so a single case passes or fails by chance
+    function testFuzzFlaky(uint256 x) public {
+        require(x % 2 == 0, "odd");
+    }
+
     function testFuzzVariableArray(uint256[] memory x) public {
         require(x[0] == x[1]);
     }
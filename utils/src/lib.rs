@@ -62,55 +62,47 @@ pub fn remove_extra_costs(gas: U256, calldata: &[u8]) -> U256 {
     gas - calldata_cost - BASE_TX_COST
 }
 
+/// Returns the revert reason `decode_revert` produces for a solidity builtin `Panic(uint256)` with
+/// the given code
+pub fn panic_reason(code: u8) -> Option<&'static str> {
+    // ref: https://soliditydeveloper.com/solidity-0.8
+    Some(match code {
+        // assert
+        1 => "Assertion violated",
+        // safemath over/underflow
+        17 => "Arithmetic over/underflow",
+        // divide by 0
+        18 => "Division or modulo by 0",
+        // conversion into non-existent enum type
+        33 => "Conversion into non-existent enum type",
+        // incorrectly encoded storage byte array
+        34 => "Incorrectly encoded storage byte array",
+        // pop() on empty array
+        49 => "`pop()` on empty array",
+        // index out of bounds
+        50 => "Index out of bounds",
+        // allocating too much memory or creating too large array
+        65 => "Memory allocation overflow",
+        // calling a zero initialized variable of internal function type
+        81 => "Calling a zero initialized variable of internal function type",
+        _ => return None,
+    })
+}
+
+/// Returns `true` if the revert reason was decoded from a solidity builtin `Panic(uint256)`
+pub fn is_panic_reason(reason: &str) -> bool {
+    (0..=u8::MAX).any(|code| panic_reason(code) == Some(reason))
+}
+
 /// Given an ABI encoded error string with the function signature `Error(string)`, it decodes
 /// it and returns the revert error message.
 pub fn decode_revert(error: &[u8]) -> Result<String> {
     if error.len() >= 4 {
         match error[0..4] {
             // keccak(Panic(uint256))
-            [78, 72, 123, 113] => {
-                // ref: https://soliditydeveloper.com/solidity-0.8
-                match error[error.len() - 1] {
-                    1 => {
-                        // assert
-                        Ok("Assertion violated".to_string())
-                    }
-                    17 => {
-                        // safemath over/underflow
-                        Ok("Arithmetic over/underflow".to_string())
-                    }
-                    18 => {
-                        // divide by 0
-                        Ok("Division or modulo by 0".to_string())
-                    }
-                    33 => {
-                        // conversion into non-existent enum type
-                        Ok("Conversion into non-existent enum type".to_string())
-                    }
-                    34 => {
-                        // incorrectly encoded storage byte array
-                        Ok("Incorrectly encoded storage byte array".to_string())
-                    }
-                    49 => {
-                        // pop() on empty array
-                        Ok("`pop()` on empty array".to_string())
-                    }
-                    50 => {
-                        // index out of bounds
-                        Ok("Index out of bounds".to_string())
-                    }
-                    65 => {
-                        // allocating too much memory or creating too large array
-                        Ok("Memory allocation overflow".to_string())
-                    }
-                    81 => {
-                        // calling a zero initialized variable of internal function type
-                        Ok("Calling a zero initialized variable of internal function type"
-                            .to_string())
-                    }
-                    _ => Err(eyre::Error::msg("Unsupported solidity builtin panic")),
-                }
-            }
+            [78, 72, 123, 113] => panic_reason(error[error.len() - 1])
+                .map(str::to_string)
+                .ok_or_else(|| eyre::Error::msg("Unsupported solidity builtin panic")),
             // keccak(Error(string))
             [8, 195, 121, 160] => {
                 if let Ok(decoded) = abi::decode(&[abi::ParamType::String], &error[4..]) {
@@ -602,6 +594,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_decode_panic() {
+        // Panic(uint256) with code 0x11
+        let mut data = vec![78, 72, 123, 113];
+        data.extend_from_slice(&[0u8; 31]);
+        data.push(17);
+        let reason = decode_revert(&data).unwrap();
+        assert_eq!(reason, "Arithmetic over/underflow");
+        assert!(is_panic_reason(&reason));
+        assert!(!is_panic_reason("not enough balance"));
+    }
+
     #[tokio::test]
     async fn test_fourbyte() {
         let sigs = fourbyte("0xa9059cbb").await.unwrap();